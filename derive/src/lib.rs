@@ -0,0 +1,144 @@
+//! `#[derive(Lexer)]` for `smpl_parser`, enabled by its `derive` feature.
+
+use proc_macro::{Delimiter, TokenStream, TokenTree};
+
+struct Variant {
+    name : String,
+    field : Option<String>,
+    patterns : Vec<(&'static str, String)>,
+}
+
+/// Implements `smpl_parser::Lexer` for an enum whose variants are tagged with the patterns
+/// that lex them: `#[token("mov")]` for literal text and `#[regex("[0-9]+")]` for a regular
+/// expression. A variant may have several patterns, and those without any are never lexed. A
+/// variant with a single field is built by parsing the matched text with `FromStr`.
+#[proc_macro_derive(Lexer, attributes(token, regex))]
+pub fn derive_lexer(input : TokenStream) -> TokenStream {
+    match parse_enum(input) {
+        Ok((name, variants)) => generate(&name, &variants),
+        Err(message) => format!("::core::compile_error!({message:?});").parse().unwrap(),
+    }
+}
+
+fn parse_enum(input : TokenStream) -> Result<(String, Vec<Variant>), String> {
+    let mut toks = input.into_iter().peekable();
+    loop {
+        match toks.next() {
+            Some(TokenTree::Ident(ident)) if ident.to_string() == "enum" => break,
+            Some(TokenTree::Ident(ident)) if ident.to_string() == "struct" || ident.to_string() == "union" =>
+                return Err("`Lexer` can only be derived for enums".to_string()),
+            Some(_) => (),
+            None => return Err("expected an enum".to_string()),
+        }
+    }
+
+    let name = match toks.next() {
+        Some(TokenTree::Ident(ident)) => ident.to_string(),
+        _ => return Err("expected the name of the enum".to_string()),
+    };
+    let body = match toks.next() {
+        Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace => group.stream(),
+        Some(TokenTree::Punct(punct)) if punct.as_char() == '<' =>
+            return Err("`Lexer` cannot be derived for generic enums".to_string()),
+        _ => return Err("expected the variants of the enum".to_string()),
+    };
+
+    let mut variants = Vec::new();
+    let mut toks = body.into_iter().peekable();
+    while toks.peek().is_some() {
+        let mut patterns = Vec::new();
+        while matches!(toks.peek(), Some(TokenTree::Punct(punct)) if punct.as_char() == '#') {
+            toks.next();
+            let Some(TokenTree::Group(attr)) = toks.next() else { return Err("expected an attribute".to_string()) };
+            let mut attr = attr.stream().into_iter();
+            let kind = match attr.next() {
+                Some(TokenTree::Ident(ident)) if ident.to_string() == "token" => "Token",
+                Some(TokenTree::Ident(ident)) if ident.to_string() == "regex" => "Regex",
+                _ => continue,
+            };
+            let pattern = match attr.next() {
+                Some(TokenTree::Group(args)) if args.delimiter() == Delimiter::Parenthesis => {
+                    let mut args = args.stream().into_iter();
+                    match (args.next(), args.next()) {
+                        (Some(TokenTree::Literal(lit)), None) if lit.to_string().ends_with('"') => lit.to_string(),
+                        _ => return Err(format!("expected a string literal in `#[{}(..)]`", kind.to_lowercase())),
+                    }
+                },
+                _ => return Err(format!("expected `#[{}(\"..\")]`", kind.to_lowercase())),
+            };
+            patterns.push((kind, pattern));
+        }
+
+        let name = match toks.next() {
+            Some(TokenTree::Ident(ident)) => ident.to_string(),
+            _ => return Err("expected a variant".to_string()),
+        };
+        let field = match toks.peek() {
+            Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Parenthesis => {
+                // Drop the field's attributes and a trailing comma
+                let mut field : Vec<_> = group.stream().into_iter().collect();
+                while let [TokenTree::Punct(punct), TokenTree::Group(_), ..] = &field[..] {
+                    if punct.as_char() != '#' {
+                        break;
+                    }
+                    field.drain(..2);
+                }
+                if matches!(field.last(), Some(TokenTree::Punct(punct)) if punct.as_char() == ',') {
+                    field.pop();
+                }
+                if field.is_empty() || field.iter().any(|tok| matches!(tok, TokenTree::Punct(punct) if punct.as_char() == ',')) {
+                    return Err(format!("variant `{name}` must have no fields or a single one"));
+                }
+                toks.next();
+                Some(field.into_iter().collect::<TokenStream>().to_string())
+            },
+            Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace =>
+                return Err(format!("variant `{name}` must have no fields or a single unnamed one")),
+            _ => None,
+        };
+
+        // Skip a discriminant
+        for tok in toks.by_ref() {
+            if matches!(tok, TokenTree::Punct(punct) if punct.as_char() == ',') {
+                break;
+            }
+        }
+        variants.push(Variant { name, field, patterns });
+    }
+    Ok((name, variants))
+}
+
+fn generate(name : &str, variants : &[Variant]) -> TokenStream {
+    let mut patterns = String::new();
+    let mut arms = String::new();
+    let mut index = 0;
+    for variant in variants {
+        for (kind, pattern) in &variant.patterns {
+            patterns += &format!("::smpl_parser::LexPattern::{kind}({pattern}), ");
+            arms += &match &variant.field {
+                Some(field) => format!(
+                    "{index} => ::core::str::FromStr::from_str(text).ok().map(|value : {field}| Self::{}(value)),\n",
+                    variant.name,
+                ),
+                None => format!("{index} => ::core::option::Option::Some(Self::{}),\n", variant.name),
+            };
+            index += 1;
+        }
+    }
+
+    format!("
+        impl ::smpl_parser::Lexer for {name} {{
+            fn patterns() -> &'static [::smpl_parser::LexPattern] {{
+                &[{patterns}]
+            }}
+
+            fn from_match(index : usize, text : &str) -> ::core::option::Option<Self> {{
+                let _ = text;
+                match index {{
+                    {arms}
+                    _ => ::core::option::Option::None,
+                }}
+            }}
+        }}
+    ").parse().unwrap()
+}
@@ -0,0 +1,78 @@
+/* The C interface of smpl_parser, built with the `ffi` feature. See src/ffi.rs. */
+
+#ifndef SMPL_PARSER_H
+#define SMPL_PARSER_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+enum {
+    SMPL_IDENT = 0,
+    SMPL_PUNCT = 1,
+    SMPL_COMMENT = 2,
+    SMPL_GROUP = 3,
+    SMPL_NEWLINE = 4,
+    SMPL_STRING = 5,
+    SMPL_CHAR = 6,
+    SMPL_NUMBER = 7,
+    SMPL_LABEL = 8,
+    SMPL_DIRECTIVE = 9,
+    SMPL_IMMEDIATE = 10,
+    SMPL_REGISTER = 11,
+    SMPL_EOF = 12,
+    SMPL_DOC_COMMENT = 13,
+    SMPL_FLOAT = 14,
+    SMPL_KEYWORD = 15,
+    SMPL_OP = 16,
+};
+
+/* Set in `flags` of a group the tokenizer closed itself, recovering from an error. */
+#define SMPL_RECOVERED 1u
+
+/* A token of the flattened tree: a group is followed by its tokens one level deeper, and an
+   immediate by the token after its prefix. Strings are UTF-8, not NUL-terminated, and live
+   as long as the result. */
+typedef struct SmplToken {
+    uint32_t kind;
+    uint32_t flags;
+    uint32_t depth;
+    size_t start;
+    size_t end;
+    size_t descendants;
+    int64_t number; /* The bits of a double for SMPL_FLOAT */
+    const uint8_t *text;
+    size_t text_len;
+} SmplToken;
+
+/* Severity is 0 for an error, 1 for a warning and 2 for a note; `code` is NULL without one. */
+typedef struct SmplDiagnostic {
+    uint32_t severity;
+    size_t start;
+    size_t end;
+    const uint8_t *code;
+    size_t code_len;
+    const uint8_t *message;
+    size_t message_len;
+} SmplDiagnostic;
+
+typedef struct SmplLexResult SmplLexResult;
+
+/* Tokenizes `len` bytes of UTF-8 at `src`; a NULL `src` or a `len` of 0 is an empty input. */
+SmplLexResult *smpl_lex(const uint8_t *src, size_t len);
+/* Frees a result of `smpl_lex`; freeing NULL does nothing. */
+void smpl_lex_free(SmplLexResult *result);
+size_t smpl_lex_token_count(const SmplLexResult *result);
+bool smpl_lex_token(const SmplLexResult *result, size_t index, SmplToken *out);
+size_t smpl_lex_diagnostic_count(const SmplLexResult *result);
+bool smpl_lex_diagnostic(const SmplLexResult *result, size_t index, SmplDiagnostic *out);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,146 @@
+"""Python bindings to the smpl_parser tokenizer, over its C interface.
+
+Build the shared library with the `ffi` feature, e.g.
+
+    cargo rustc --release --lib --features ffi --crate-type cdylib
+
+then point `SMPL_PARSER_LIB` at it (`target/release/libsmpl_parser.so` on Linux), or pass its
+path to `load`. Tokens mirror `smpl_parser::Token`, with groups and immediates holding the
+tokens in them.
+"""
+
+import ctypes
+import os
+import struct
+from dataclasses import dataclass, field
+from typing import List, Optional, Union
+
+IDENT, PUNCT, COMMENT, GROUP, NEWLINE, STRING, CHAR, NUMBER, LABEL, DIRECTIVE, IMMEDIATE, REGISTER, EOF, DOC_COMMENT, FLOAT, KEYWORD, OP = range(17)
+
+KINDS = ["ident", "punct", "comment", "group", "newline", "string", "char", "number", "label", "directive", "immediate", "register", "eof", "doc-comment", "float", "keyword", "op"]
+
+RECOVERED = 1
+
+SEVERITIES = ["error", "warning", "note"]
+
+
+class _Token(ctypes.Structure):
+    _fields_ = [
+        ("kind", ctypes.c_uint32),
+        ("flags", ctypes.c_uint32),
+        ("depth", ctypes.c_uint32),
+        ("start", ctypes.c_size_t),
+        ("end", ctypes.c_size_t),
+        ("descendants", ctypes.c_size_t),
+        ("number", ctypes.c_int64),
+        ("text", ctypes.POINTER(ctypes.c_uint8)),
+        ("text_len", ctypes.c_size_t),
+    ]
+
+
+class _Diagnostic(ctypes.Structure):
+    _fields_ = [
+        ("severity", ctypes.c_uint32),
+        ("start", ctypes.c_size_t),
+        ("end", ctypes.c_size_t),
+        ("code", ctypes.POINTER(ctypes.c_uint8)),
+        ("code_len", ctypes.c_size_t),
+        ("message", ctypes.POINTER(ctypes.c_uint8)),
+        ("message_len", ctypes.c_size_t),
+    ]
+
+
+@dataclass
+class Token:
+    """A token and its byte span. `value` is the text, or the value of a number or float; a
+    group has its opening delimiter as `value` and its tokens in `tokens`, and an immediate the
+    token after its prefix as the only one."""
+
+    kind: str
+    start: int
+    end: int
+    value: Union[str, int, float, None] = None
+    tokens: List["Token"] = field(default_factory=list)
+    recovered: bool = False
+
+
+@dataclass
+class Diagnostic:
+    severity: str
+    code: Optional[str]
+    message: str
+    start: int
+    end: int
+
+
+_lib = None
+
+
+def load(path=None):
+    """Loads the shared library from `path`, or else `SMPL_PARSER_LIB`."""
+    global _lib
+    lib = ctypes.CDLL(path or os.environ["SMPL_PARSER_LIB"])
+    lib.smpl_lex.restype = ctypes.c_void_p
+    lib.smpl_lex.argtypes = [ctypes.c_char_p, ctypes.c_size_t]
+    lib.smpl_lex_free.argtypes = [ctypes.c_void_p]
+    for name in ["smpl_lex_token_count", "smpl_lex_diagnostic_count"]:
+        getattr(lib, name).restype = ctypes.c_size_t
+        getattr(lib, name).argtypes = [ctypes.c_void_p]
+    lib.smpl_lex_token.restype = ctypes.c_bool
+    lib.smpl_lex_token.argtypes = [ctypes.c_void_p, ctypes.c_size_t, ctypes.POINTER(_Token)]
+    lib.smpl_lex_diagnostic.restype = ctypes.c_bool
+    lib.smpl_lex_diagnostic.argtypes = [ctypes.c_void_p, ctypes.c_size_t, ctypes.POINTER(_Diagnostic)]
+    _lib = lib
+    return lib
+
+
+def _text(ptr, length):
+    return ctypes.string_at(ptr, length).decode("utf-8") if ptr else None
+
+
+def _build(flat, i):
+    """The token at `flat[i]` with the tokens in it, and the index after them."""
+    tok = flat[i]
+    if tok.kind == NUMBER:
+        value = tok.number
+    elif tok.kind == FLOAT:
+        value = struct.unpack("<d", struct.pack("<q", tok.number))[0]
+    elif tok.kind == GROUP:
+        value = chr(tok.number)
+    elif tok.kind in (NEWLINE, EOF, IMMEDIATE):
+        value = None
+    else:
+        value = _text(tok.text, tok.text_len)
+    token = Token(KINDS[tok.kind], tok.start, tok.end, value, recovered=bool(tok.flags & RECOVERED))
+    end, i = i + 1 + tok.descendants, i + 1
+    while i < end:
+        child, i = _build(flat, i)
+        token.tokens.append(child)
+    return token, end
+
+
+def tokenize(code):
+    """The tokens and diagnostics of `code`, tokenized with the default config. Texts are
+    decoded before the result owning them is freed."""
+    lib = _lib or load()
+    src = code.encode("utf-8")
+    result = lib.smpl_lex(src, len(src))
+    try:
+        flat = []
+        for i in range(lib.smpl_lex_token_count(result)):
+            tok = _Token()
+            lib.smpl_lex_token(result, i, ctypes.byref(tok))
+            flat.append(tok)
+        tokens, i = [], 0
+        while i < len(flat):
+            token, i = _build(flat, i)
+            tokens.append(token)
+
+        diagnostics = []
+        for i in range(lib.smpl_lex_diagnostic_count(result)):
+            diag = _Diagnostic()
+            lib.smpl_lex_diagnostic(result, i, ctypes.byref(diag))
+            diagnostics.append(Diagnostic(SEVERITIES[diag.severity], _text(diag.code, diag.code_len), _text(diag.message, diag.message_len), diag.start, diag.end))
+        return tokens, diagnostics
+    finally:
+        lib.smpl_lex_free(result)
@@ -0,0 +1,135 @@
+//! Checking that delimiters balance without lexing, for editors to give instant feedback.
+
+use core::fmt;
+
+use crate::prelude::*;
+use crate::{Delimiter, Diagnostic, DiagnosticKind, Edit, HasSpan, Span};
+
+/// The first delimiter that doesn't balance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BalanceError {
+    /// The group opened at `span` is never closed.
+    Unclosed { open : char, span : Span },
+
+    /// The delimiter at `span` closes no group.
+    UnexpectedClose { close : char, span : Span },
+
+    /// The delimiter at `span` closes the group opened at `open`, which it doesn't match.
+    Mismatched { expected : char, found : char, span : Span, open : Span },
+}
+
+impl HasSpan for BalanceError {
+    fn span(&self) -> Span {
+        match self {
+            BalanceError::Unclosed { span, .. } | BalanceError::UnexpectedClose { span, .. } | BalanceError::Mismatched { span, .. } => *span,
+        }
+    }
+}
+
+/// The diagnostic the tokenizer reports for the same problem, without the fix for an unclosed group.
+impl From<BalanceError> for Diagnostic {
+    fn from(err : BalanceError) -> Self {
+        match err {
+            BalanceError::Unclosed { open, span } => Diagnostic::error(span, DiagnosticKind::UnclosedDelimiter(open)),
+            BalanceError::UnexpectedClose { close, span } => Diagnostic::error(span, DiagnosticKind::UnexpectedCloseDelimiter(close)),
+            BalanceError::Mismatched { expected, found, span, open } => Diagnostic::error(span, DiagnosticKind::MismatchedDelimiter { expected, found })
+                .with_note(Diagnostic::note(open, DiagnosticKind::UnclosedDelimiter(Delimiter::from_close(expected).unwrap().open())))
+                .with_suggestion(Edit::new(span, expected.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for BalanceError {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}", Diagnostic::from(self.clone()).message(), self.span())
+    }
+}
+
+impl core::error::Error for BalanceError {}
+
+/// The offset past the string or char literal opened by the quote at `i`, which ends at a line
+/// break or the end of input like in the tokenizer.
+pub(crate) fn literal_end(bytes : &[u8], i : usize) -> usize {
+    let quote = bytes[i];
+    let mut j = i + 1;
+    let mut chars = 0;
+    while j < bytes.len() && !matches!(bytes[j], b'\n' | b'\r') {
+        match bytes[j] {
+            c if c == quote && (quote == b'"' || chars > 0 || bytes.get(j + 1) != Some(&b'\'')) => return j + 1,
+            b'\\' if bytes.get(j + 1).is_some_and(|c| !matches!(c, b'\n' | b'\r')) => j += 2,
+            _ => j += 1,
+        }
+        // Skips the rest of a multi-byte char
+        while bytes.get(j).is_some_and(|c| c & 0xC0 == 0x80) {
+            j += 1;
+        }
+        chars += 1;
+        if quote == b'\'' && bytes.get(j) != Some(&b'\'') {
+            return j;
+        }
+    }
+    j
+}
+
+/// Checks that the delimiters of `code` balance, skipping strings, chars and comments but
+/// building no tokens, so it is much faster than tokenizing.
+pub fn check_balanced(code : &str) -> Result<(), BalanceError> {
+    let bytes = code.as_bytes();
+    let mut open : Vec<(char, usize)> = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = char::from(bytes[i]);
+        match bytes[i] {
+            b'(' | b'[' | b'{' => open.push((c, i)),
+            b')' | b']' | b'}' => match open.pop() {
+                Some((delimiter, _)) if Delimiter::from_open(delimiter) == Delimiter::from_close(c) => (),
+                Some((delimiter, at)) => return Err(BalanceError::Mismatched {
+                    expected: Delimiter::from_open(delimiter).unwrap().close(),
+                    found: c,
+                    span: Span::new(i, i + 1),
+                    open: Span::new(at, at + 1),
+                }),
+                None => return Err(BalanceError::UnexpectedClose { close: c, span: Span::new(i, i + 1) }),
+            },
+            b'"' | b'\'' => {
+                i = literal_end(bytes, i);
+                continue;
+            },
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i = code[i..].find(['\n', '\r']).map_or(bytes.len(), |end| i + end);
+                continue;
+            },
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = code[i + 2..].find("*/").map_or(bytes.len(), |end| i + end + 4);
+                continue;
+            },
+            _ => (),
+        }
+        i += 1;
+    }
+
+    match open.pop() {
+        Some((open, at)) => Err(BalanceError::Unclosed { open, span: Span::new(at, at + 1) }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tokenize_with_diagnostics;
+
+    #[test]
+    fn balanced() {
+        assert_eq!(check_balanced("a (b [c] {d}) \"(\\\"\" ')' '\\'' ''' '' // {\n /* ( */ x"), Ok(()));
+        assert_eq!(check_balanced("\"é(\" 'é' ["), Err(BalanceError::Unclosed { open: '[', span: Span::new(11, 12) }));
+        assert_eq!(check_balanced("a)"), Err(BalanceError::UnexpectedClose { close: ')', span: Span::new(1, 2) }));
+        assert_eq!(check_balanced("{ \"}\n"), Err(BalanceError::Unclosed { open: '{', span: Span::new(0, 1) }));
+        assert_eq!(check_balanced("x /* ) *"), Ok(()));
+
+        let err = check_balanced("{(a]}").unwrap_err();
+        assert_eq!(err, BalanceError::Mismatched { expected: ')', found: ']', span: Span::new(3, 4), open: Span::new(1, 2) });
+        assert_eq!(err.to_string(), "mismatched closing delimiter: expected `)`, found `]` at 3..4");
+        assert!(tokenize_with_diagnostics("{(a]}").1.contains(&Diagnostic::from(err)));
+    }
+}
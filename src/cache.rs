@@ -0,0 +1,196 @@
+use alloc::collections::BTreeMap;
+use core::hash::Hasher;
+
+use crate::prelude::*;
+use crate::{Spanned, Token, structural_eq};
+
+/// 64-bit FNV-1a, used because it is deterministic across runs and platforms.
+pub(crate) struct Fnv(u64);
+
+impl Fnv {
+    pub(crate) fn new() -> Self {
+        Fnv(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes : &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ u64::from(*byte)).wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+/// Version of the encoding hashed by `fingerprint`, changed whenever the encoding does.
+pub const FINGERPRINT_VERSION : u32 = 1;
+
+/// Feeds a span-free encoding of `toks` to `h`, with fixed-width little-endian integers so
+/// that it is the same on every platform.
+fn hash_tokens<T : AsRef<Token>>(toks : &[T], skip_trivia : bool, h : &mut impl Hasher) {
+    let str = |h : &mut dyn Hasher, s : &str| {
+        h.write(&(s.len() as u64).to_le_bytes());
+        h.write(s.as_bytes());
+    };
+
+    for tok in toks.iter().map(AsRef::as_ref).filter(|tok| !(skip_trivia && tok.is_trivia())) {
+        match tok {
+            Token::Ident(s) => { h.write(&[0]); str(h, s) },
+            Token::Punct(c) => { h.write(&[1]); h.write(&u32::from(*c).to_le_bytes()) },
+            Token::Comment(s) => { h.write(&[2]); str(h, s) },
+            Token::Group(g) => {
+                h.write(&[3, g.delimiter as u8]);
+                hash_tokens(&g.tokens, skip_trivia, h);
+                h.write(&[0xff]);
+            },
+            Token::Newline => h.write(&[4]),
+            Token::String(s) => { h.write(&[5]); str(h, s) },
+            Token::Char(c) => { h.write(&[6]); h.write(&u32::from(*c).to_le_bytes()) },
+            Token::Number(n) => { h.write(&[7]); h.write(&n.to_le_bytes()) },
+            Token::Label(s) => { h.write(&[8]); str(h, s) },
+            Token::Directive(s) => { h.write(&[9]); str(h, s) },
+            Token::Register(s) => { h.write(&[11]); str(h, s) },
+            Token::Eof => h.write(&[12]),
+            Token::DocComment(s) => { h.write(&[13]); str(h, s) },
+            Token::Float(f) => { h.write(&[14]); h.write(&f.0.to_bits().to_le_bytes()) },
+            Token::Keyword(s) => { h.write(&[15]); str(h, s) },
+            Token::Op(s) => { h.write(&[16]); str(h, s) },
+            Token::Immediate(tok) => { h.write(&[10]); hash_tokens(core::slice::from_ref(&**tok), skip_trivia, h) },
+        }
+    }
+}
+
+/// A 64-bit hash of the content of a token tree, for change detection: it ignores spans and
+/// comments, and is the same across runs and platforms for a given `FINGERPRINT_VERSION`.
+pub fn fingerprint<T : AsRef<Token>>(toks : &[T]) -> u64 {
+    let mut h = Fnv::new();
+    h.write(&FINGERPRINT_VERSION.to_le_bytes());
+    hash_tokens(toks, true, &mut h);
+    h.finish()
+}
+
+struct Entry<T> {
+    toks : Vec<Spanned<Token>>,
+    value : T,
+    used : bool,
+}
+
+/// Memoizes the results of parsing token ranges, so that after re-lexing an edited file only the
+/// productions whose tokens actually changed get parsed again.
+///
+/// Ranges are looked up by their tokens alone, ignoring spans: a production that merely moved
+/// because of an edit above it is still a hit. Cached values should therefore not depend on
+/// absolute positions, or be re-based by the caller.
+///
+/// Use one cache per production, and call `sweep` after each full pass to drop the entries
+/// that pass didn't use.
+pub struct ParseCache<T> {
+    entries : BTreeMap<u64, Vec<Entry<T>>>,
+    hits : usize,
+    misses : usize,
+}
+
+impl<T> Default for ParseCache<T> {
+    fn default() -> Self {
+        Self { entries: BTreeMap::new(), hits: 0, misses: 0 }
+    }
+}
+
+impl<T> ParseCache<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the cached result for `toks`, calling `parse` to compute it on a miss.
+    pub fn get_or_parse(&mut self, toks : &[Spanned<Token>], parse : impl FnOnce(&[Spanned<Token>]) -> T) -> &T {
+        let mut h = Fnv::new();
+        hash_tokens(toks, false, &mut h);
+        let bucket = self.entries.entry(h.finish()).or_default();
+
+        let i = match bucket.iter().position(|entry| structural_eq(&entry.toks, toks)) {
+            Some(i) => {
+                self.hits += 1;
+                i
+            },
+            None => {
+                self.misses += 1;
+                bucket.push(Entry { toks: toks.to_vec(), value: parse(toks), used: false });
+                bucket.len() - 1
+            },
+        };
+
+        bucket[i].used = true;
+        &bucket[i].value
+    }
+
+    /// Drops the entries that weren't looked up since the previous sweep.
+    pub fn sweep(&mut self) {
+        self.entries.retain(|_, bucket| {
+            bucket.retain(|entry| entry.used);
+            bucket.iter_mut().for_each(|entry| entry.used = false);
+            !bucket.is_empty()
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of lookups answered from, respectively not found in, the cache.
+    pub fn stats(&self) -> (usize, usize) {
+        (self.hits, self.misses)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{TokenizerConfig, tokenize, tokenize_spanned};
+
+    fn lines(code : &str) -> Vec<Vec<Spanned<Token>>> {
+        let config = TokenizerConfig { newlines: true, ..Default::default() };
+        let (toks, _) = tokenize_spanned(&config, code);
+        toks.split(|tok| tok.value == Token::Newline).map(<[_]>::to_vec).collect()
+    }
+
+    #[test]
+    fn incremental() {
+        let mut cache = ParseCache::new();
+        let mut parse = |code| -> Vec<usize> {
+            lines(code).iter().map(|line| *cache.get_or_parse(line, |toks| toks.len())).collect()
+        };
+
+        assert_eq!(parse("mov r0, (1 2)\nnop"), vec![4, 1]);
+        assert_eq!(parse("jmp x\n\nmov r0, (1 2)\nnop"), vec![2, 0, 4, 1]);
+        assert_eq!(parse("mov r0, (1 3)\nnop"), vec![4, 1]);
+        assert_eq!(cache.stats(), (3, 5));
+
+        cache.sweep();
+        assert_eq!(cache.len(), 5);
+        cache.get_or_parse(&lines("nop")[0], |_| unreachable!());
+        cache.sweep();
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn fingerprints() {
+        let toks = tokenize("mov r0, [sp] /* spill */ \"s\" 'c'");
+        assert_eq!(fingerprint(&toks), fingerprint(&tokenize("mov r0,[sp] \"s\" 'c'")));
+        assert_ne!(fingerprint(&toks), fingerprint(&tokenize("mov r0, (sp) \"s\" 'c'")));
+        assert_ne!(fingerprint(&tokenize("a b")), fingerprint(&tokenize("ab")));
+
+        // Pinned, since it must not change without bumping the version
+        assert_eq!(fingerprint(&toks), 0x1501a7d6dc03425a);
+    }
+}
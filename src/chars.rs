@@ -0,0 +1,82 @@
+//! Helpers for lexing with a `Scanner<char>`, the building blocks of the tokenizer's own
+//! matchers, for custom matchers to lex the same way.
+
+use crate::prelude::*;
+use crate::{Scanner, Span, Spanned};
+
+/// Skips whitespace, including line breaks, returning whether there was any.
+pub fn skip_whitespace(scanner : &mut Scanner<char>) -> bool {
+    !scanner.take_while(|c| c.is_whitespace()).is_empty()
+}
+
+/// Takes an identifier: a letter or `_` followed by letters, digits and `_`.
+pub fn take_ident(scanner : &mut Scanner<char>) -> Option<String> {
+    scanner.test(|c| c.is_alphabetic() || *c == '_')
+        .then(|| scanner.take_while(|c| c.is_alphanumeric() || *c == '_').into_iter().collect())
+}
+
+/// Takes the digits of `radix`, if there are any, without a sign or prefix.
+///
+/// Panics if `radix` is not between 2 and 36.
+pub fn take_digits(scanner : &mut Scanner<char>, radix : u32) -> Option<String> {
+    assert!((2..=36).contains(&radix), "invalid radix {radix}");
+    let digits : String = scanner.take_while(|c| c.is_digit(radix)).into_iter().collect();
+    (!digits.is_empty()).then_some(digits)
+}
+
+/// Takes the rest of the line, leaving the line break.
+pub fn take_line(scanner : &mut Scanner<char>) -> String {
+    scanner.take_while(|c| !(*c == '\n' || *c == '\r')).into_iter().collect()
+}
+
+/// Like `take_line`, with the span of the text in the scanner's offsets.
+pub fn take_until_newline(scanner : &mut Scanner<char>) -> Spanned<String> {
+    let start = scanner.offset();
+    let line = take_line(scanner);
+    Spanned::new(line, Span::new(start, scanner.offset()))
+}
+
+/// Takes the rest of the line and its line break, `\n`, `\r\n` or `\r`, if any, e.g. to
+/// recover from an error at the start of the next line.
+pub fn take_through_newline(scanner : &mut Scanner<char>) -> Spanned<String> {
+    let mut line = take_until_newline(scanner);
+    if let Some(c) = scanner.take(|c| *c == '\r') {
+        line.value.push(c);
+    }
+    if let Some(c) = scanner.take(|c| *c == '\n') {
+        line.value.push(c);
+    }
+    line.span.end = scanner.offset();
+    line
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn helpers() {
+        let mut scanner = Scanner::new(" \n\t_läbel2 ff7g // rest\r\nnext".chars().collect());
+        assert!(skip_whitespace(&mut scanner));
+        assert!(!skip_whitespace(&mut scanner));
+        assert_eq!(take_ident(&mut scanner).as_deref(), Some("_läbel2"));
+        assert_eq!(take_ident(&mut scanner), None);
+
+        skip_whitespace(&mut scanner);
+        assert_eq!(take_digits(&mut scanner, 10), None);
+        assert_eq!(take_digits(&mut scanner, 16).as_deref(), Some("ff7"));
+        assert_eq!(take_line(&mut scanner), "g // rest");
+        assert_eq!(scanner.peek(), Some(&'\r'));
+    }
+
+    #[test]
+    fn lines() {
+        let mut scanner = Scanner::with_width("#!é\r\nnext\n\rlast".chars().collect(), |c| c.len_utf8());
+        assert_eq!(take_through_newline(&mut scanner), Spanned::new("#!é\r\n".to_string(), Span::new(0, 6)));
+        assert_eq!(take_until_newline(&mut scanner), Spanned::new("next".to_string(), Span::new(6, 10)));
+        assert_eq!(take_until_newline(&mut scanner), Spanned::new(String::new(), Span::new(10, 10)));
+        assert_eq!(take_through_newline(&mut scanner).value, "\n");
+        assert_eq!(take_through_newline(&mut scanner).value, "\r");
+        assert_eq!(take_through_newline(&mut scanner), Spanned::new("last".to_string(), Span::new(12, 16)));
+    }
+}
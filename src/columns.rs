@@ -0,0 +1,199 @@
+//! Re-emission of token lines as column-aligned text, e.g. for assembly listings.
+
+use crate::prelude::*;
+use crate::{Line, Spanned, Token, TokenizerConfig};
+
+/// Formats lines of tokens into four columns: labels, mnemonic, operands and comment.
+///
+/// `Token::Label`s go in the first column, the next identifier or directive is the mnemonic,
+/// the comments ending a line go in the comment column and everything else is an operand. A
+/// line of only comments is not aligned. Columns are 0-based char positions; text that overruns
+/// its column pushes the next one right by a single space.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnFormatter {
+    pub mnemonic : usize,
+    pub operands : usize,
+    pub comment : usize,
+}
+
+impl Default for ColumnFormatter {
+    fn default() -> Self {
+        Self { mnemonic: 8, operands: 16, comment: 40 }
+    }
+}
+
+impl ColumnFormatter {
+    /// Formats `lines`, e.g. from `tokenize_lines`, given the `config` they were lexed with to
+    /// re-emit directives, immediates and doc comments with the right prefix.
+    pub fn format(&self, config : &TokenizerConfig, lines : &[Line]) -> String {
+        let mut out = String::new();
+        for line in lines {
+            self.format_line(config, line, &mut out);
+            out.push('\n');
+        }
+        out
+    }
+
+    fn format_line(&self, config : &TokenizerConfig, toks : &[Spanned<Token>], out : &mut String) {
+        let comments = toks.iter().rev().take_while(|tok| matches!(tok.value, Token::Comment(_) | Token::DocComment(_))).count();
+        let (toks, comments) = toks.split_at(toks.len() - comments);
+
+        let labels = toks.iter().take_while(|tok| matches!(tok.value, Token::Label(_))).count();
+        let (labels, rest) = toks.split_at(labels);
+        let (mnemonic, operands) = match rest.split_first() {
+            Some((first, operands)) if matches!(first.value, Token::Ident(_) | Token::Directive(_)) => (Some(first), operands),
+            _ => (None, rest),
+        };
+
+        let start = out.len();
+        let pad = |out : &mut String, column : usize| {
+            let width = out[start..].chars().count();
+            let spaces = if width == 0 { column } else { column.saturating_sub(width).max(1) };
+            out.extend(core::iter::repeat_n(' ', spaces));
+        };
+        let column = |out : &mut String, column : usize, toks : &[Spanned<Token>]| {
+            if !toks.is_empty() {
+                pad(out, column);
+                write_tokens(config, toks, false, out);
+            }
+        };
+
+        column(out, 0, labels);
+        column(out, self.mnemonic, mnemonic.map_or(&[][..], core::slice::from_ref));
+        column(out, self.operands, operands);
+
+        // The last comment can be a line comment, whichever kind it was
+        if let Some((last, comments)) = comments.split_last() {
+            pad(out, if out.len() > start { self.comment } else { 0 });
+            write_tokens(config, comments, false, out);
+            if !comments.is_empty() {
+                out.push(' ');
+            }
+            match &last.value {
+                Token::Comment(text) if fits_line(text) => out.push_str(&format!("//{text}")),
+                tok => write_token(config, tok, false, out),
+            }
+        }
+    }
+}
+
+/// The source of `toks`, lexed with `config`: each token written the way `config` lexes it,
+/// separated by spaces except before `,` and `:`, and by a line break after a comment of a
+/// single line, which is written as a line comment. Tokenizing it again gives the same tokens,
+/// which `testing::verify_roundtrip` checks, but not the same spans.
+pub fn to_source(config : &TokenizerConfig, toks : &[Spanned<Token>]) -> String {
+    let mut out = String::new();
+    write_tokens(config, toks, true, &mut out);
+    out
+}
+
+/// Whether a comment can be written as a line comment, which unlike a block one can hold `*/`.
+fn fits_line(text : &str) -> bool {
+    !text.contains(['\n', '\r'])
+}
+
+/// Whether `tok` is written as a line comment, or is a doc comment, with `lines`.
+fn ends_line(tok : &Token, lines : bool) -> bool {
+    lines && (matches!(tok, Token::Comment(text) if fits_line(text)) || matches!(tok, Token::DocComment(_)))
+}
+
+/// Writes tokens as source, separated by spaces except before `,` and `:`. With `lines`,
+/// comments that fit on a line are written as line comments, each followed by a line break
+/// unless a `Token::Newline` is next.
+fn write_tokens(config : &TokenizerConfig, toks : &[Spanned<Token>], lines : bool, out : &mut String) {
+    for (i, tok) in toks.iter().enumerate() {
+        if i > 0 && ends_line(&toks[i - 1].value, lines) {
+            if tok.value != Token::Newline {
+                out.push('\n');
+            }
+        } else if i > 0 && !matches!(tok.value, Token::Punct(',' | ':')) {
+            out.push(' ');
+        }
+        write_token(config, &tok.value, lines, out);
+    }
+}
+
+fn write_token(config : &TokenizerConfig, tok : &Token, lines : bool, out : &mut String) {
+    let quoted = |out : &mut String, s : &str, quote : char| {
+        out.push(quote);
+        for c in s.chars() {
+            if c == quote || c == '\\' {
+                out.push('\\');
+            }
+            out.push(c);
+        }
+        out.push(quote);
+    };
+
+    match tok {
+        Token::Ident(name) | Token::Register(name) | Token::Keyword(name) | Token::Op(name) => out.push_str(name),
+        Token::Punct(c) => out.push(*c),
+        Token::Comment(text) if lines && fits_line(text) => out.push_str(&format!("//{text}")),
+        Token::Comment(text) => out.push_str(&format!("/*{text}*/")),
+        Token::DocComment(text) => {
+            out.push_str(config.doc_comments.first().map_or("///", String::as_str));
+            out.push_str(text);
+        },
+        Token::Group(group) => {
+            out.push(group.delimiter.open());
+            write_tokens(config, &group.tokens, lines, out);
+            if group.tokens.last().is_some_and(|tok| ends_line(&tok.value, lines)) {
+                out.push('\n');
+            }
+            out.push(group.delimiter.close());
+        },
+        Token::Newline => out.push('\n'),
+        Token::String(value) => quoted(out, value, '"'),
+        Token::Char(value) => quoted(out, &value.to_string(), '\''),
+        Token::Number(value) => out.push_str(&value.to_string()),
+        // Debug keeps a fraction or exponent, e.g. `1.0`, so that it lexes as a float again
+        Token::Float(value) => out.push_str(&format!("{:?}", value.0)),
+        Token::Label(name) => {
+            out.push_str(name);
+            out.push(':');
+        },
+        Token::Directive(name) => {
+            out.push(config.directive_sigil.unwrap_or('.'));
+            out.push_str(name);
+        },
+        Token::Immediate(tok) => {
+            out.push(config.immediate_prefixes.first().copied().unwrap_or('#'));
+            write_token(config, tok, lines, out);
+        },
+        Token::Eof => (),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{tokenize_lines, tokenize_spanned};
+
+    #[test]
+    fn columns() {
+        let config = TokenizerConfig {
+            labels: true,
+            directive_sigil: Some('.'),
+            immediate_prefixes: vec!['#'],
+            ..Default::default()
+        };
+        let code = "start: mov r0,#4 // init\n  .word 1,2 /* a */ /* b */\n// done\na_very_long_label: b [sp, -4] \"s\\\"\"";
+        let (lines, _) = tokenize_lines(&config, code);
+
+        let formatter = ColumnFormatter { mnemonic: 8, operands: 14, comment: 24 };
+        assert_eq!(formatter.format(&config, &lines), [
+            "start:  mov   r0, #4    // init",
+            "        .word 1, 2      /* a */ // b ",
+            "// done",
+            "a_very_long_label: b [sp, -4] \"s\\\"\"",
+            "",
+        ].join("\n"));
+    }
+    #[test]
+    fn source() {
+        let config = TokenizerConfig { newlines: true, ..Default::default() };
+        let source = |code| to_source(&config, &tokenize_spanned(&config, code).0);
+        assert_eq!(source("a // x */ y\nb /* c\n*/ d"), "a // x */ y\n b /* c\n*/ d");
+        assert_eq!(source("(a /* b */) c"), "(a // b \n) c");
+    }
+}
@@ -0,0 +1,213 @@
+//! Parser combinators over `TokenScanner`, for grammars written as Rust values rather than
+//! with the `grammar!` macro, e.g. to build them at run time or share pieces between them:
+//!
+//! ```
+//! # use smpl_parser::{Delimiter, TokenScanner, TokenizerConfig, tokenize_spanned};
+//! use smpl_parser::combinator::*;
+//!
+//! #[derive(Debug, PartialEq)]
+//! enum Operand {
+//!     Reg(String),
+//!     Imm(i64),
+//!     Mem(String, i64),
+//! }
+//!
+//! let (toks, _) = tokenize_spanned(&TokenizerConfig::default(), "ldr r0, [sp + 8]");
+//! let operand = || choice((
+//!     map(ident(), |name| Operand::Reg(name.to_string())),
+//!     map(number(), Operand::Imm),
+//!     group(Delimiter::Bracket, map(
+//!         (ident(), optional(map((just('+'), number()), |(_, offset)| offset))),
+//!         |(base, offset)| Operand::Mem(base.to_string(), offset.unwrap_or(0)),
+//!     )),
+//! ));
+//! let instr = (ident(), separated_by(operand(), ','), end());
+//! let (mnemonic, operands, ()) = instr.parse(&mut TokenScanner::new(&toks)).unwrap();
+//! assert_eq!(mnemonic, "ldr");
+//! assert_eq!(operands, [Operand::Reg("r0".into()), Operand::Mem("sp".into(), 8)]);
+//! ```
+//!
+//! Parsers skip trivia before each token. When every alternative of a `choice` fails, its error
+//! is that of the one that got furthest, with what each alternative that failed there expected.
+//!
+//! They only parse a `TokenScanner`, over spanned tokens; there is no implementation for a
+//! `Scanner<Token>`, which has neither spans nor cheap forks to backtrack with.
+
+use crate::grammar::{furthest, many, optional as grammar_optional, separated};
+use crate::prelude::*;
+use crate::{Delimiter, Diagnostic, Token, TokenScanner, TokenStr};
+
+pub type PResult<T> = Result<T, Box<Diagnostic>>;
+
+/// Parses a `T` from a scanner, leaving it after the tokens of the `T`. Implemented by the
+/// closures taking a scanner, by the values of this module, and by tuples of parsers, which
+/// parse their items in sequence.
+pub trait Parser<'a, T> {
+    fn parse(&self, input : &mut TokenScanner<'a>) -> PResult<T>;
+}
+
+impl<'a, T, F : Fn(&mut TokenScanner<'a>) -> PResult<T>> Parser<'a, T> for F {
+    fn parse(&self, input : &mut TokenScanner<'a>) -> PResult<T> {
+        self(input)
+    }
+}
+
+macro_rules! impl_sequence {
+    ($(($($p:ident $t:ident),*)),*) => {$(
+        #[allow(non_snake_case)]
+        impl<'a, $($t, $p : Parser<'a, $t>),*> Parser<'a, ($($t,)*)> for ($($p,)*) {
+            fn parse(&self, input : &mut TokenScanner<'a>) -> PResult<($($t,)*)> {
+                let ($($p,)*) = self;
+                Ok(($($p.parse(input)?,)*))
+            }
+        }
+    )*};
+}
+
+impl_sequence!((A TA, B TB), (A TA, B TB, C TC), (A TA, B TB, C TC, D TD), (A TA, B TB, C TC, D TD, E TE));
+
+/// Parsers to try in order, for `choice`: tuples of parsers of the same type.
+pub trait Alternatives<'a, T> {
+    fn parse_first(&self, input : &mut TokenScanner<'a>) -> PResult<T>;
+}
+
+macro_rules! impl_alternatives {
+    ($(($($p:ident),*)),*) => {$(
+        #[allow(non_snake_case)]
+        impl<'a, T, $($p : Parser<'a, T>),*> Alternatives<'a, T> for ($($p,)*) {
+            fn parse_first(&self, input : &mut TokenScanner<'a>) -> PResult<T> {
+                let ($($p,)*) = self;
+                let mut err = None;
+                $(
+                    let mut fork = *input;
+                    match $p.parse(&mut fork) {
+                        Ok(value) => {
+                            *input = fork;
+                            return Ok(value);
+                        },
+                        Err(e) => err = Some(furthest(err, e)),
+                    }
+                )*
+                Err(err.unwrap())
+            }
+        }
+    )*};
+}
+
+impl_alternatives!((A, B), (A, B, C), (A, B, C, D), (A, B, C, D, E), (A, B, C, D, E, F), (A, B, C, D, E, F, G), (A, B, C, D, E, F, G, H));
+
+/// The punctuation `c`.
+pub fn just<'a>(c : char) -> impl Parser<'a, ()> {
+    move |input : &mut TokenScanner<'a>| input.expect_punct(c)
+}
+
+/// The identifier or `Token::Keyword` `name`.
+pub fn keyword<'a>(name : &str) -> impl Parser<'a, ()> {
+    let name = name.to_string();
+    move |input : &mut TokenScanner<'a>| input.expect_keyword(&name)
+}
+
+/// A token equal to `tok`, which shouldn't be a group, as those are compared with their spans.
+pub fn token<'a>(tok : Token) -> impl Parser<'a, ()> {
+    let expected = tok.describe();
+    move |input : &mut TokenScanner<'a>| input.expect(&expected, |found| (*found == tok).then_some(()))
+}
+
+pub fn ident<'a>() -> impl Parser<'a, TokenStr> {
+    |input : &mut TokenScanner<'a>| input.expect_ident()
+}
+
+/// A number, which the tokenizer saturates to the range of `i64`, reporting
+/// `DiagnosticKind::NumberOutOfRange`, rather than wrapping.
+pub fn number<'a>() -> impl Parser<'a, i64> {
+    |input : &mut TokenScanner<'a>| input.expect_number()
+}
+
+/// A string, char, number or float.
+pub fn literal<'a>() -> impl Parser<'a, Token> {
+    |input : &mut TokenScanner<'a>| input.expect("a literal", |tok| {
+        matches!(tok, Token::String(_) | Token::Char(_) | Token::Number(_) | Token::Float(_)).then(|| tok.clone())
+    })
+}
+
+/// A group delimited by `delimiter` whose tokens are exactly what `p` parses.
+pub fn group<'a, T>(delimiter : Delimiter, p : impl Parser<'a, T>) -> impl Parser<'a, T> {
+    move |input : &mut TokenScanner<'a>| {
+        let mut inner = input.expect_group(delimiter)?;
+        let value = p.parse(&mut inner)?;
+        inner.expect_end()?;
+        Ok(value)
+    }
+}
+
+/// Nothing but trivia left, e.g. at the end of a line or group.
+pub fn end<'a>() -> impl Parser<'a, ()> {
+    |input : &mut TokenScanner<'a>| input.expect_end()
+}
+
+/// What `p` parses, or `None` if it fails without getting past the first token.
+pub fn optional<'a, T>(p : impl Parser<'a, T>) -> impl Parser<'a, Option<T>> {
+    move |input : &mut TokenScanner<'a>| grammar_optional(input, |input| p.parse(input))
+}
+
+/// What `p` parses, as many times as it does. Like with `optional`, failing past the first
+/// token fails the repetition.
+pub fn repeated<'a, T>(p : impl Parser<'a, T>) -> impl Parser<'a, Vec<T>> {
+    move |input : &mut TokenScanner<'a>| many(input, 0, |input| p.parse(input))
+}
+
+/// Zero or more of what `p` parses, separated by the punctuation `sep`, with no trailing one.
+pub fn separated_by<'a, T>(p : impl Parser<'a, T>, sep : char) -> impl Parser<'a, Vec<T>> {
+    move |input : &mut TokenScanner<'a>| separated(input, sep, |input| p.parse(input))
+}
+
+/// The value of the first of `alternatives` that parses, each tried from the same token.
+pub fn choice<'a, T>(alternatives : impl Alternatives<'a, T>) -> impl Parser<'a, T> {
+    move |input : &mut TokenScanner<'a>| alternatives.parse_first(input)
+}
+
+/// What `p` parses, passed through `f`.
+pub fn map<'a, T, U>(p : impl Parser<'a, T>, f : impl Fn(T) -> U) -> impl Parser<'a, U> {
+    move |input : &mut TokenScanner<'a>| p.parse(input).map(&f)
+}
+
+/// What `p` parses, passed through `f`, which can fail, e.g. for a number out of range.
+pub fn try_map<'a, T, U>(p : impl Parser<'a, T>, f : impl Fn(T) -> PResult<U>) -> impl Parser<'a, U> {
+    move |input : &mut TokenScanner<'a>| p.parse(input).and_then(&f)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{DiagnosticKind, Spanned, TokenizerConfig, tokenize_spanned};
+
+    fn toks(code : &str) -> Vec<Spanned<Token>> {
+        tokenize_spanned(&TokenizerConfig::default(), code).0
+    }
+
+    /// Sums like `1 + (2 + x)`, counting the identifiers as 0.
+    fn sum<'a>(input : &mut TokenScanner<'a>) -> PResult<i64> {
+        let term = || choice((number(), map(ident(), |_| 0), group(Delimiter::Paren, sum)));
+        let (first, rest) = (term(), repeated(map((just('+'), term()), |(_, n)| n))).parse(input)?;
+        Ok(first + rest.iter().sum::<i64>())
+    }
+
+    #[test]
+    fn combinators() {
+        let toks = toks("1 + (2 + x + (3)) ; 4, 5 \"a\"");
+        let mut input = TokenScanner::new(&toks);
+        assert_eq!(sum(&mut input), Ok(6));
+        assert_eq!(just(';').parse(&mut input), Ok(()));
+        assert_eq!(separated_by(number(), ',').parse(&mut input), Ok(vec![4, 5]));
+        assert_eq!((literal(), end()).parse(&mut input), Ok((Token::String("a".into()), ())));
+
+        let toks = self::toks("1 + , mov");
+        let err = (sum, end()).parse(&mut TokenScanner::new(&toks)).unwrap_err();
+        assert_eq!(err.message(), "expected a number or an identifier or `(`, found `,`");
+        let mut input = TokenScanner::new(&toks[3..]);
+        assert_eq!(choice((map(keyword("nop"), |_| 0), map(token(Token::Ident("mov".into())), |_| 1))).parse(&mut input), Ok(1));
+        let toks = self::toks("12");
+        let small = try_map(number(), |n| if n < 10 { Ok(n) } else { Err(Box::new(Diagnostic::error(toks[0].span, DiagnosticKind::Custom("big".into())))) });
+        assert_eq!(small.parse(&mut TokenScanner::new(&toks)).unwrap_err().message(), "big");
+    }
+}
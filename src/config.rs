@@ -0,0 +1,187 @@
+use crate::prelude::*;
+use crate::NumberType;
+
+/// What the tokenizer does after an error.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Recovery {
+    /// Keep lexing right after the error, reporting every problem found.
+    #[default]
+    Continue,
+
+    /// Discard the tokens of the line containing the error and resume on the next line,
+    /// reporting only the first diagnostic of that line. Intended for line-oriented grammars.
+    SkipLine,
+}
+
+/// What the tokenizer does with raw control characters, like tabs and NUL, in string and char
+/// literals.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ControlCharPolicy {
+    #[default]
+    Allow,
+
+    /// Accept them, with a warning for each.
+    Warn,
+
+    /// Report an error for each, keeping them in the literal.
+    Error,
+}
+
+/// How the tokenizer reads a decimal number with leading zeros, like `010`, which dialects
+/// disagree on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LeadingZeroPolicy {
+    /// Read it as decimal, with a warning.
+    #[default]
+    Decimal,
+
+    /// Read it as octal, like C, reporting an error for the digits 8 and 9.
+    Octal,
+
+    /// Report an error, reading it as decimal.
+    Error,
+}
+
+/// How the tokenizer normalizes identifiers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Normalization {
+    /// Keep identifiers as written.
+    #[default]
+    None,
+
+    /// Normalize identifiers to Unicode NFC, so that composed and decomposed spellings of the
+    /// same identifier give equal tokens. Combining marks continue identifiers.
+    Nfc,
+
+    /// Like `Nfc`, also warning about each identifier that wasn't in NFC.
+    NfcWarn,
+}
+
+/// Bounds on what the tokenizer accepts, for untrusted input. Past a bound it reports an error:
+/// input that is too large is not lexed, lexing stops at the token past `max_tokens`, and
+/// longer strings and identifiers are truncated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub max_input_bytes : Option<usize>,
+
+    /// The most tokens, counting those inside groups and groups themselves.
+    pub max_tokens : Option<usize>,
+
+    /// The most bytes in the value of a string literal.
+    pub max_string_len : Option<usize>,
+
+    /// The most bytes in an identifier, including labels, directives and registers.
+    pub max_ident_len : Option<usize>,
+
+    /// How many steps, chars consumed including those looked at again, lexing can take before
+    /// it stops with an error, bounding its time whatever the matchers do. The token lexed
+    /// when the fuel ran out can be cut short.
+    pub fuel : Option<u64>,
+}
+
+/// Options for `tokenize_with`. `Default` gives the behaviour of `tokenize`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TokenizerConfig {
+    /// Recover from malformed groups by synthesizing the missing closing delimiter:
+    /// a group closed by the wrong delimiter is closed at that point (leaving the delimiter
+    /// to an enclosing group) instead of the wrong delimiter being discarded. Also makes
+    /// Unicode bidirectional control characters, which enable "Trojan Source" attacks,
+    /// warnings rather than errors.
+    pub lenient : bool,
+
+    /// Emit `Token::Newline` for line breaks instead of treating them as whitespace.
+    pub newlines : bool,
+
+    /// End the tokens with a `Token::Eof`.
+    pub eof : bool,
+
+    /// Lex an identifier directly followed by `:` at the start of a line as a `Token::Label`.
+    pub labels : bool,
+
+    /// The char, e.g. `.` or `%`, that makes an identifier it directly precedes a `Token::Directive`.
+    pub directive_sigil : Option<char>,
+
+    /// Chars, e.g. `#` or `$`, that make a number or identifier they directly precede a `Token::Immediate`.
+    pub immediate_prefixes : Vec<char>,
+
+    /// Names of the identifiers to lex as `Token::Register`s, where `r0..r15` stands for `r0`
+    /// through `r15`.
+    pub registers : Vec<String>,
+
+    /// Prefixes, e.g. `///` or `;;`, of the line comments to lex as `Token::DocComment`s.
+    pub doc_comments : Vec<String>,
+
+    /// Prefixes, e.g. `;` or `#`, of the line comments to lex besides `//` ones. Doc comments
+    /// take precedence, so `;;` can start doc comments while `;` starts plain ones.
+    pub line_comments : Vec<String>,
+
+    /// Drop comments instead of lexing them as `Token::Comment` trivia. Doc comments are kept.
+    pub skip_comments : bool,
+
+    /// Report an error for each non-ASCII char outside string and char literals, including in
+    /// comments, for toolchains that must all read the source the same way.
+    pub ascii_only : bool,
+
+    /// Merge string literals separated only by whitespace into one `Token::String` spanning
+    /// them all, like C does, e.g. `"ab" "cd"` into `"abcd"`. With `newlines`, only those on
+    /// the same line are merged.
+    pub concat_strings : bool,
+
+    /// Lex decimal numbers with a fraction or an exponent, e.g. `1.5` or `2e-3`, as
+    /// `Token::Float`s rather than a number, a `.` and another number.
+    pub floats : bool,
+
+    /// Allow `_` in numbers after their first digit or prefix, e.g. `1_000` or `0xffff_0000`.
+    pub digit_separators : bool,
+
+    /// Accept the escapes `\n`, `\r`, `\t`, `\\`, `\0`, `\'`, `\"` and `\u{...}` in string and
+    /// char literals, rather than only that of their quote.
+    pub escapes : bool,
+
+    /// The identifiers to lex as `Token::Keyword`s, e.g. `if` or `macro`.
+    pub keywords : Vec<String>,
+
+    /// The operators of several chars, e.g. `==` or `->`, to lex as `Token::Op`s rather than
+    /// `Token::Punct`s, the longest one that matches first.
+    pub operators : Vec<String>,
+
+    /// Lex a `-` right after an identifier, a number or the end of a group, e.g. in `a-1`, as a
+    /// `Token::Punct` rather than the sign of a number. After whitespace, as in `a -1`, it is
+    /// still a sign, and so is one before a digit elsewhere, as in `(-1)`.
+    pub binary_minus : bool,
+
+    pub recovery : Recovery,
+
+    pub limits : Limits,
+
+    pub normalization : Normalization,
+
+    pub control_chars : ControlCharPolicy,
+
+    pub leading_zeros : LeadingZeroPolicy,
+
+    /// The type numbers must fit, e.g. `NumberType::of::<u16>()` for a 16-bit target, reporting
+    /// an error for those that don't. As `Token::Number` holds an `i64`, numbers that fit the
+    /// type but not an `i64` are reported too, and saturated; `parse_number` their lexemes for
+    /// the exact values, e.g. of a `u64` past `i64::MAX`.
+    pub number_type : Option<NumberType>,
+}
+
+impl TokenizerConfig {
+    /// Whether `name` is one of `registers`.
+    pub fn is_register(&self, name : &str) -> bool {
+        self.registers.iter().any(|register| match register.split_once("..") {
+            Some((first, last)) => {
+                let prefix = first.trim_end_matches(|c : char| c.is_ascii_digit());
+                let number = |s : &str| s.strip_prefix(prefix)
+                    .filter(|n| n.chars().all(|c| c.is_ascii_digit()) && (n.len() == 1 || !n.starts_with('0')))?
+                    .parse::<u64>().ok();
+                match (number(first), number(last), number(name)) {
+                    (Some(first), Some(last), Some(n)) => (first..=last).contains(&n),
+                    _ => false,
+                }
+            },
+            None => register == name,
+        })
+    }
+}
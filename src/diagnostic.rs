@@ -0,0 +1,410 @@
+use core::fmt;
+
+use crate::prelude::*;
+use crate::{HasSpan, Span, suggest};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// The input is malformed; the produced tokens are a best effort.
+    Error,
+
+    /// The input is accepted, but probably not what was intended.
+    Warning,
+
+    /// Extra information attached to another diagnostic.
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        })
+    }
+}
+
+/// A textual replacement in the source: replace `span` with `replacement`.
+/// An empty span inserts, an empty replacement deletes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Edit {
+    pub span : Span,
+    pub replacement : String,
+}
+
+impl Edit {
+    pub fn new(span : Span, replacement : impl Into<String>) -> Self {
+        Self { span, replacement: replacement.into() }
+    }
+
+    pub fn insert(offset : usize, text : impl Into<String>) -> Self {
+        Self::new(Span::empty(offset), text)
+    }
+
+    pub fn delete(span : Span) -> Self {
+        Self::new(span, "")
+    }
+}
+
+/// What a diagnostic is about, with the parameters needed to describe it.
+/// The text is produced separately by a `MessageFormatter`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiagnosticKind {
+    LeadingZeros,
+    ConfusableIdentifier { ident : String, found : char, lookalike : char },
+    UnknownEscape(char),
+    UnterminatedString,
+    UnterminatedChar,
+    EmptyChar,
+    LiteralAbandoned,
+    UnclosedDelimiter(char),
+    UnexpectedCloseDelimiter(char),
+    MismatchedDelimiter { expected : char, found : char },
+    UnknownKeyword(String),
+    DidYouMean(String),
+    IncludeNotFound(String),
+    IncludeCycle(String),
+    ExpectedIncludePath,
+    IncludedFrom(String),
+    MacroArity { name : String, expected : usize, found : usize },
+    MacroRecursionLimit(String),
+    ExpectedCondition,
+    UnmatchedConditional,
+    DuplicateElse,
+    UnterminatedConditional,
+    ExpandedFrom(String),
+    ExpectedSelector,
+    ExpectedOperand,
+    ExpectedOperator,
+    UndefinedSymbol(String),
+    ArithmeticOverflow,
+    DivisionByZero,
+    ExpectedInteger,
+    UnexpectedChar(char),
+    InvalidTokenValue(String),
+    ExpectedToken { expected : String, found : String },
+    UndefinedRule(String),
+    DuplicateRule(String),
+    LeftRecursion(String),
+    InputTooLarge { limit : usize, found : usize },
+    TooManyTokens(usize),
+    StringTooLong(usize),
+    IdentifierTooLong(usize),
+    UnnormalizedIdentifier(String),
+    MixedScriptIdentifier { ident : String, first : String, second : String },
+    BidiControl(char),
+    ControlCharInLiteral(char),
+    LimitExceeded(String),
+    MixedIndentation,
+    TrailingWhitespace,
+    InconsistentHexCase { uppercase : bool },
+    InvalidDigit { digit : char, radix : u32 },
+    NonAsciiChar(char),
+    NumberOutOfRange(String),
+    UnterminatedComment,
+    MissingDigits { radix : u32 },
+    ReadError(String),
+    InvalidUnicodeEscape,
+
+    /// A free-form message, not translatable.
+    Custom(String),
+}
+
+impl DiagnosticKind {
+    /// Stable identifier of the kind of problem (e.g. `"unknown-escape"`), for tools.
+    pub fn code(&self) -> Option<&'static str> {
+        Some(match self {
+            DiagnosticKind::LeadingZeros => "leading-zeros",
+            DiagnosticKind::ConfusableIdentifier { .. } => "confusable-identifier",
+            DiagnosticKind::UnknownEscape(_) => "unknown-escape",
+            DiagnosticKind::UnterminatedString => "unterminated-string",
+            DiagnosticKind::UnterminatedChar => "unterminated-char",
+            DiagnosticKind::EmptyChar => "empty-char",
+            DiagnosticKind::LiteralAbandoned => "literal-abandoned",
+            DiagnosticKind::UnclosedDelimiter(_) => "unclosed-delimiter",
+            DiagnosticKind::UnexpectedCloseDelimiter(_) => "unexpected-close-delimiter",
+            DiagnosticKind::MismatchedDelimiter { .. } => "mismatched-delimiter",
+            DiagnosticKind::UnknownKeyword(_) => "unknown-keyword",
+            DiagnosticKind::DidYouMean(_) => "did-you-mean",
+            DiagnosticKind::IncludeNotFound(_) => "include-not-found",
+            DiagnosticKind::IncludeCycle(_) => "include-cycle",
+            DiagnosticKind::ExpectedIncludePath => "expected-include-path",
+            DiagnosticKind::IncludedFrom(_) => "included-from",
+            DiagnosticKind::MacroArity { .. } => "macro-arity",
+            DiagnosticKind::MacroRecursionLimit(_) => "macro-recursion-limit",
+            DiagnosticKind::ExpectedCondition => "expected-condition",
+            DiagnosticKind::UnmatchedConditional => "unmatched-conditional",
+            DiagnosticKind::DuplicateElse => "duplicate-else",
+            DiagnosticKind::UnterminatedConditional => "unterminated-conditional",
+            DiagnosticKind::ExpandedFrom(_) => "expanded-from",
+            DiagnosticKind::ExpectedSelector => "expected-selector",
+            DiagnosticKind::ExpectedOperand => "expected-operand",
+            DiagnosticKind::ExpectedOperator => "expected-operator",
+            DiagnosticKind::UndefinedSymbol(_) => "undefined-symbol",
+            DiagnosticKind::ArithmeticOverflow => "arithmetic-overflow",
+            DiagnosticKind::DivisionByZero => "division-by-zero",
+            DiagnosticKind::ExpectedInteger => "expected-integer",
+            DiagnosticKind::UnexpectedChar(_) => "unexpected-char",
+            DiagnosticKind::InvalidTokenValue(_) => "invalid-token-value",
+            DiagnosticKind::ExpectedToken { .. } => "expected-token",
+            DiagnosticKind::UndefinedRule(_) => "undefined-rule",
+            DiagnosticKind::DuplicateRule(_) => "duplicate-rule",
+            DiagnosticKind::LeftRecursion(_) => "left-recursion",
+            DiagnosticKind::InputTooLarge { .. } => "input-too-large",
+            DiagnosticKind::TooManyTokens(_) => "too-many-tokens",
+            DiagnosticKind::StringTooLong(_) => "string-too-long",
+            DiagnosticKind::IdentifierTooLong(_) => "identifier-too-long",
+            DiagnosticKind::UnnormalizedIdentifier(_) => "unnormalized-identifier",
+            DiagnosticKind::MixedScriptIdentifier { .. } => "mixed-script-identifier",
+            DiagnosticKind::BidiControl(_) => "bidi-control",
+            DiagnosticKind::ControlCharInLiteral(_) => "control-char-in-literal",
+            DiagnosticKind::LimitExceeded(_) => "limit-exceeded",
+            DiagnosticKind::MixedIndentation => "mixed-indentation",
+            DiagnosticKind::TrailingWhitespace => "trailing-whitespace",
+            DiagnosticKind::InconsistentHexCase { .. } => "inconsistent-hex-case",
+            DiagnosticKind::InvalidDigit { .. } => "invalid-digit",
+            DiagnosticKind::NonAsciiChar(_) => "non-ascii-char",
+            DiagnosticKind::NumberOutOfRange(_) => "number-out-of-range",
+            DiagnosticKind::UnterminatedComment => "unterminated-comment",
+            DiagnosticKind::MissingDigits { .. } => "missing-digits",
+            DiagnosticKind::ReadError(_) => "read-error",
+            DiagnosticKind::InvalidUnicodeEscape => "invalid-unicode-escape",
+            DiagnosticKind::Custom(_) => return None,
+        })
+    }
+}
+
+impl From<String> for DiagnosticKind {
+    fn from(message : String) -> Self {
+        DiagnosticKind::Custom(message)
+    }
+}
+
+impl From<&str> for DiagnosticKind {
+    fn from(message : &str) -> Self {
+        DiagnosticKind::Custom(message.to_string())
+    }
+}
+
+/// Turns a `DiagnosticKind` into text, e.g. in the user's language.
+pub trait MessageFormatter {
+    fn message(&self, kind : &DiagnosticKind) -> String;
+}
+
+/// The built-in English messages.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultFormatter;
+
+impl MessageFormatter for DefaultFormatter {
+    fn message(&self, kind : &DiagnosticKind) -> String {
+        match kind {
+            DiagnosticKind::LeadingZeros => "number has leading zeros".to_string(),
+            DiagnosticKind::ConfusableIdentifier { ident, found, lookalike } => format!(
+                "identifier `{ident}` uses confusable character `{found}` (U+{:04X}), which looks like `{lookalike}`",
+                *found as u32,
+            ),
+            DiagnosticKind::UnknownEscape(c) => format!("unknown escape sequence `\\{c}`"),
+            DiagnosticKind::UnterminatedString => "unterminated string literal".to_string(),
+            DiagnosticKind::UnterminatedChar => "unterminated character literal".to_string(),
+            DiagnosticKind::EmptyChar => "empty character literal".to_string(),
+            DiagnosticKind::LiteralAbandoned => "literal ends here".to_string(),
+            DiagnosticKind::UnclosedDelimiter(c) => format!("unclosed delimiter `{c}`"),
+            DiagnosticKind::UnexpectedCloseDelimiter(c) => format!("unexpected closing delimiter `{c}`"),
+            DiagnosticKind::MismatchedDelimiter { expected, found } =>
+                format!("mismatched closing delimiter: expected `{expected}`, found `{found}`"),
+            DiagnosticKind::UnknownKeyword(found) => format!("unknown keyword `{found}`"),
+            DiagnosticKind::DidYouMean(keyword) => format!("did you mean `{keyword}`?"),
+            DiagnosticKind::IncludeNotFound(name) => format!("cannot find included file `{name}`"),
+            DiagnosticKind::IncludeCycle(name) => format!("`{name}` includes itself"),
+            DiagnosticKind::ExpectedIncludePath => "expected a file name in quotes".to_string(),
+            DiagnosticKind::IncludedFrom(name) => format!("`{name}` included from here"),
+            DiagnosticKind::MacroArity { name, expected, found } => format!("macro `{name}` takes {expected} argument(s) but {found} were given"),
+            DiagnosticKind::MacroRecursionLimit(name) => format!("recursion limit reached while expanding macro `{name}`"),
+            DiagnosticKind::ExpectedCondition => "expected a symbol or number as condition".to_string(),
+            DiagnosticKind::UnmatchedConditional => "no conditional section is open here".to_string(),
+            DiagnosticKind::DuplicateElse => "conditional section already has an else branch".to_string(),
+            DiagnosticKind::UnterminatedConditional => "conditional section is never closed".to_string(),
+            DiagnosticKind::ExpandedFrom(name) => format!("expanded from macro `{name}`"),
+            DiagnosticKind::ExpectedSelector => "expected a token selector such as `Ident` or `Group(Brace)`".to_string(),
+            DiagnosticKind::ExpectedOperand => "expected a number, symbol or parenthesized expression".to_string(),
+            DiagnosticKind::ExpectedOperator => "expected an operator".to_string(),
+            DiagnosticKind::UndefinedSymbol(name) => format!("undefined symbol `{name}`"),
+            DiagnosticKind::ArithmeticOverflow => "arithmetic overflow".to_string(),
+            DiagnosticKind::DivisionByZero => "division by zero".to_string(),
+            DiagnosticKind::ExpectedInteger => "expected an integer, found a float".to_string(),
+            DiagnosticKind::UnexpectedChar(c) => format!("unexpected character `{c}`"),
+            DiagnosticKind::InvalidTokenValue(text) => format!("invalid value `{text}` for this token"),
+            DiagnosticKind::ExpectedToken { expected, found } => format!("expected {expected}, found {found}"),
+            DiagnosticKind::UndefinedRule(name) => format!("undefined rule `{name}`"),
+            DiagnosticKind::DuplicateRule(name) => format!("rule `{name}` is defined more than once"),
+            DiagnosticKind::LeftRecursion(name) => format!("rule `{name}` is left-recursive"),
+            DiagnosticKind::InputTooLarge { limit, found } => format!("input of {found} bytes exceeds the limit of {limit} bytes"),
+            DiagnosticKind::TooManyTokens(limit) => format!("input exceeds the limit of {limit} tokens"),
+            DiagnosticKind::StringTooLong(limit) => format!("string literal exceeds the limit of {limit} bytes"),
+            DiagnosticKind::IdentifierTooLong(limit) => format!("identifier exceeds the limit of {limit} bytes"),
+            DiagnosticKind::UnnormalizedIdentifier(ident) => format!("identifier `{ident}` is not in Unicode NFC form"),
+            DiagnosticKind::MixedScriptIdentifier { ident, first, second } => format!("identifier `{ident}` mixes {first} and {second} letters"),
+            DiagnosticKind::BidiControl(c) => format!("bidirectional control character U+{:04X} can make code display differently from how it is lexed", u32::from(*c)),
+            DiagnosticKind::ControlCharInLiteral(c) => format!("control character U+{:04X} in a literal", u32::from(*c)),
+            DiagnosticKind::LimitExceeded(limit) => format!("exceeded the {limit} limit"),
+            DiagnosticKind::MixedIndentation => "indentation mixes tabs and spaces".to_string(),
+            DiagnosticKind::TrailingWhitespace => "trailing whitespace".to_string(),
+            DiagnosticKind::InconsistentHexCase { uppercase } => format!("hex digits should be {}, like those of the first hex number", if *uppercase { "uppercase" } else { "lowercase" }),
+            DiagnosticKind::InvalidDigit { digit, radix } => format!("invalid digit `{digit}` in a base {radix} number"),
+            DiagnosticKind::NonAsciiChar(c) => format!("non-ASCII character `{c}` (U+{:04X})", u32::from(*c)),
+            DiagnosticKind::NumberOutOfRange(ty) => format!("number literal out of range for `{ty}`"),
+            DiagnosticKind::UnterminatedComment => "unterminated block comment".to_string(),
+            DiagnosticKind::MissingDigits { radix } => format!("expected base {radix} digits after the prefix"),
+            DiagnosticKind::ReadError(err) => format!("could not read the input: {err}"),
+            DiagnosticKind::InvalidUnicodeEscape => "invalid unicode escape, expected `\\u{...}` with the hex digits of a char".to_string(),
+            DiagnosticKind::Custom(message) => message.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity : Severity,
+    pub kind : DiagnosticKind,
+    pub span : Span,
+
+    /// Edits that together fix the problem, if an obvious fix exists.
+    pub suggestions : Vec<Edit>,
+
+    /// Additional context, usually with `Severity::Note`.
+    pub notes : Vec<Diagnostic>,
+}
+
+impl Diagnostic {
+    pub fn new(severity : Severity, span : Span, kind : impl Into<DiagnosticKind>) -> Self {
+        Self { severity, kind: kind.into(), span, suggestions: Vec::new(), notes: Vec::new() }
+    }
+
+    pub fn code(&self) -> Option<&'static str> {
+        self.kind.code()
+    }
+
+    /// The message in English, see `message_with`.
+    pub fn message(&self) -> String {
+        self.message_with(&DefaultFormatter)
+    }
+
+    pub fn message_with(&self, formatter : &dyn MessageFormatter) -> String {
+        formatter.message(&self.kind)
+    }
+
+    /// An error for `found` at `span` where one of `expected` was required, with a
+    /// "did you mean" note and fix if one of them is a likely typo of `found`.
+    pub fn unknown_keyword<'a>(span : Span, found : &str, expected : impl IntoIterator<Item = &'a str>) -> Self {
+        let diagnostic = Self::error(span, DiagnosticKind::UnknownKeyword(found.to_string()));
+        match suggest::did_you_mean(found, expected) {
+            Some(keyword) => diagnostic
+                .with_note(Self::note(span, DiagnosticKind::DidYouMean(keyword.to_string())))
+                .with_suggestion(Edit::new(span, keyword)),
+            None => diagnostic,
+        }
+    }
+
+    pub fn with_suggestion(mut self, edit : Edit) -> Self {
+        self.suggestions.push(edit);
+        self
+    }
+
+    pub fn with_note(mut self, note : Diagnostic) -> Self {
+        self.notes.push(note);
+        self
+    }
+
+    pub fn error(span : Span, kind : impl Into<DiagnosticKind>) -> Self {
+        Self::new(Severity::Error, span, kind)
+    }
+
+    pub fn warning(span : Span, kind : impl Into<DiagnosticKind>) -> Self {
+        Self::new(Severity::Warning, span, kind)
+    }
+
+    pub fn note(span : Span, kind : impl Into<DiagnosticKind>) -> Self {
+        Self::new(Severity::Note, span, kind)
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} at {}", self.severity, self.message(), self.span)?;
+        for note in &self.notes {
+            write!(f, "\n  {note}")?;
+        }
+        Ok(())
+    }
+}
+
+impl HasSpan for Diagnostic {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+/// Sorts `diagnostics` into source order: by file and span, then severity, code and message, so that
+/// output is reproducible no matter the order in which they were emitted.
+/// The sort is stable and leaves each diagnostic's notes untouched.
+pub fn sort_diagnostics(diagnostics : &mut [Diagnostic]) {
+    diagnostics.sort_by_cached_key(|d| (d.span, d.severity, d.code(), d.message()));
+}
+
+/// Sorts `diagnostics` like `sort_diagnostics` and removes exact duplicates.
+pub fn normalize_diagnostics(diagnostics : &mut Vec<Diagnostic>) {
+    sort_diagnostics(diagnostics);
+    diagnostics.dedup();
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unknown_keyword() {
+        let span = Span::new(4, 7);
+        let diagnostic = Diagnostic::unknown_keyword(span, "mvo", ["nop", "mov"]);
+        assert_eq!(diagnostic.to_string(), "error: unknown keyword `mvo` at 4..7\n  note: did you mean `mov`? at 4..7");
+        assert_eq!(diagnostic.suggestions, vec![Edit::new(span, "mov")]);
+
+        let diagnostic = Diagnostic::unknown_keyword(span, "xyz", ["nop", "mov"]);
+        assert!(diagnostic.notes.is_empty() && diagnostic.suggestions.is_empty());
+    }
+
+    #[test]
+    fn formatter() {
+        struct Spanish;
+        impl MessageFormatter for Spanish {
+            fn message(&self, kind : &DiagnosticKind) -> String {
+                match kind {
+                    DiagnosticKind::UnknownKeyword(found) => format!("palabra clave desconocida `{found}`"),
+                    _ => DefaultFormatter.message(kind),
+                }
+            }
+        }
+
+        let diagnostic = Diagnostic::unknown_keyword(Span::new(0, 3), "mvo", ["mov"]);
+        assert_eq!(diagnostic.code(), Some("unknown-keyword"));
+        assert_eq!(diagnostic.message(), "unknown keyword `mvo`");
+        assert_eq!(diagnostic.message_with(&Spanish), "palabra clave desconocida `mvo`");
+        assert_eq!(Diagnostic::error(Span::empty(0), "custom").code(), None);
+    }
+
+    #[test]
+    fn ordering() {
+        let a = Diagnostic::warning(Span::new(0, 3), DiagnosticKind::LeadingZeros);
+        let b = Diagnostic::error(Span::new(4, 5), "b");
+        let c = Diagnostic::warning(Span::new(4, 5), "a");
+        let d = Diagnostic::warning(Span::new(4, 5), "b");
+
+        let mut diagnostics = vec![d.clone(), c.clone(), b.clone(), a.clone(), d.clone()];
+        normalize_diagnostics(&mut diagnostics);
+        assert_eq!(diagnostics, vec![a, b, c, d]);
+    }
+}
@@ -0,0 +1,314 @@
+//! An on-disk cache of token trees, so that building an unchanged project again skips lexing.
+
+use std::fs;
+use std::hash::Hasher;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use crate::cache::Fnv;
+use crate::{Delimiter, Diagnostic, FileId, Float, Group, Span, Spanned, Token, TokenizerConfig, token::tokenize_file};
+
+/// Version of the encoding of cache entries, changed whenever it or lexing does.
+const CACHE_VERSION : u32 = 2;
+
+/// A directory of the tokens of sources, each in a file named after hashes of the source and
+/// of the `TokenizerConfig` it was lexed with, so that changing either misses the cache.
+///
+/// Entries hold their source, so that one whose hash collides with another's is missed rather
+/// than returning its tokens, and are written to a temporary file renamed into place, so that
+/// readers never see half of one. Only sources that lexed without diagnostics are stored, so
+/// that others are lexed again to report them. With a size limit, the entries used least
+/// recently are removed past it.
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    dir : PathBuf,
+    max_bytes : Option<u64>,
+}
+
+impl DiskCache {
+    /// A cache in `dir`, which is created when storing the first entry.
+    pub fn new(dir : impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into(), max_bytes: None }
+    }
+
+    /// Limits the size of the entries to `max_bytes`.
+    pub fn max_bytes(mut self, max_bytes : u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    fn path(&self, config : &TokenizerConfig, code : &str) -> PathBuf {
+        let mut config_hash = Fnv::new();
+        config_hash.write(format!("{CACHE_VERSION} {config:?}").as_bytes());
+        let mut code_hash = Fnv::new();
+        code_hash.write(code.as_bytes());
+        self.dir.join(format!("{:016x}-{:016x}.tok", code_hash.finish(), config_hash.finish()))
+    }
+
+    /// The tokens of `code` lexed with `config`, with spans in `file`, if they are cached.
+    pub fn get(&self, config : &TokenizerConfig, file : FileId, code : &str) -> Option<Vec<Spanned<Token>>> {
+        let path = self.path(config, code);
+        let bytes = fs::read(&path).ok()?;
+        let mut decoder = Decoder { bytes: &bytes, file };
+        let len = decoder.usize()?;
+        if decoder.take(len)? != code.as_bytes() {
+            return None;
+        }
+        let toks = decoder.tokens()?;
+        // Marks it used, for the size limit; failing to only makes it likelier to go first
+        let _ = fs::File::options().append(true).open(&path).and_then(|entry| entry.set_modified(SystemTime::now()));
+        Some(toks)
+    }
+
+    /// Stores `toks` as the tokens of `code` lexed with `config`, then removes the entries used
+    /// least recently past the size limit.
+    pub fn put(&self, config : &TokenizerConfig, code : &str, toks : &[Spanned<Token>]) -> io::Result<()> {
+        static WRITES : AtomicU64 = AtomicU64::new(0);
+
+        let mut bytes = Vec::new();
+        encode_str(code, &mut bytes);
+        encode_tokens(toks, &mut bytes);
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(config, code);
+        // Unique among the processes and threads writing to the cache
+        let temp = path.with_extension(format!("tmp-{}-{}", std::process::id(), WRITES.fetch_add(1, Ordering::Relaxed)));
+        fs::write(&temp, bytes)?;
+        if let Err(err) = fs::rename(&temp, &path) {
+            let _ = fs::remove_file(&temp);
+            return Err(err);
+        }
+        self.trim()
+    }
+
+    /// Removes the entry of `code` lexed with `config`, if any.
+    pub fn invalidate(&self, config : &TokenizerConfig, code : &str) -> io::Result<()> {
+        match fs::remove_file(self.path(config, code)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /// Removes every entry.
+    pub fn clear(&self) -> io::Result<()> {
+        for (path, ..) in self.entries()? {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// The total size of the entries, in bytes.
+    pub fn size(&self) -> io::Result<u64> {
+        Ok(self.entries()?.iter().map(|(_, len, _)| len).sum())
+    }
+
+    fn entries(&self) -> io::Result<Vec<(PathBuf, u64, SystemTime)>> {
+        let dir = match fs::read_dir(&self.dir) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            dir => dir?,
+        };
+        let mut entries = Vec::new();
+        for entry in dir {
+            let entry = entry?;
+            let meta = entry.metadata()?;
+            if meta.is_file() && entry.path().extension().is_some_and(|ext| ext == "tok") {
+                entries.push((entry.path(), meta.len(), meta.modified()?));
+            }
+        }
+        Ok(entries)
+    }
+
+    fn trim(&self) -> io::Result<()> {
+        let Some(max_bytes) = self.max_bytes else { return Ok(()) };
+        let mut entries = self.entries()?;
+        entries.sort_by_key(|(_, _, modified)| *modified);
+        let mut size : u64 = entries.iter().map(|(_, len, _)| len).sum();
+        for (path, len, _) in entries {
+            if size <= max_bytes {
+                break;
+            }
+            fs::remove_file(path)?;
+            size -= len;
+        }
+        Ok(())
+    }
+
+    /// Like `tokenize_spanned` with spans in `file`, but from the cache when `code` is in it,
+    /// storing the tokens otherwise. Failing to store them is ignored, as they are lexed anyway.
+    pub fn tokenize(&self, config : &TokenizerConfig, file : FileId, code : &str) -> (Vec<Spanned<Token>>, Vec<Diagnostic>) {
+        if let Some(toks) = self.get(config, file, code) {
+            return (toks, Vec::new());
+        }
+        let (toks, diagnostics) = tokenize_file(config, file, code);
+        if diagnostics.is_empty() {
+            let _ = self.put(config, code, &toks);
+        }
+        (toks, diagnostics)
+    }
+}
+
+fn encode_str(s : &str, out : &mut Vec<u8>) {
+    out.extend((s.len() as u64).to_le_bytes());
+    out.extend(s.as_bytes());
+}
+
+fn encode_token(tok : &Token, out : &mut Vec<u8>) {
+    match tok {
+        Token::Ident(s) => { out.push(0); encode_str(s, out) },
+        Token::Punct(c) => { out.push(1); out.extend(u32::from(*c).to_le_bytes()) },
+        Token::Comment(s) => { out.push(2); encode_str(s, out) },
+        Token::Group(g) => {
+            out.extend([3, g.delimiter as u8, u8::from(g.recovered)]);
+            encode_tokens(&g.tokens, out);
+        },
+        Token::Newline => out.push(4),
+        Token::String(s) => { out.push(5); encode_str(s, out) },
+        Token::Char(c) => { out.push(6); out.extend(u32::from(*c).to_le_bytes()) },
+        Token::Number(n) => { out.push(7); out.extend(n.to_le_bytes()) },
+        Token::Label(s) => { out.push(8); encode_str(s, out) },
+        Token::Directive(s) => { out.push(9); encode_str(s, out) },
+        Token::Immediate(tok) => { out.push(10); encode_token(tok, out) },
+        Token::Register(s) => { out.push(11); encode_str(s, out) },
+        Token::Eof => out.push(12),
+        Token::DocComment(s) => { out.push(13); encode_str(s, out) },
+        Token::Float(f) => { out.push(14); out.extend(f.0.to_bits().to_le_bytes()) },
+        Token::Keyword(s) => { out.push(15); encode_str(s, out) },
+        Token::Op(s) => { out.push(16); encode_str(s, out) },
+    }
+}
+
+fn encode_tokens(toks : &[Spanned<Token>], out : &mut Vec<u8>) {
+    out.extend((toks.len() as u64).to_le_bytes());
+    for tok in toks {
+        out.extend((tok.span.start as u64).to_le_bytes());
+        out.extend((tok.span.end as u64).to_le_bytes());
+        encode_token(&tok.value, out);
+    }
+}
+
+/// Reads what `encode_tokens` wrote, failing on anything else, e.g. a truncated entry.
+struct Decoder<'a> {
+    bytes : &'a [u8],
+    file : FileId,
+}
+
+impl Decoder<'_> {
+    fn take(&mut self, n : usize) -> Option<&[u8]> {
+        let (taken, rest) = self.bytes.split_at_checked(n)?;
+        self.bytes = rest;
+        Some(taken)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    fn usize(&mut self) -> Option<usize> {
+        self.u64()?.try_into().ok()
+    }
+
+    fn char(&mut self) -> Option<char> {
+        char::from_u32(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn str(&mut self) -> Option<String> {
+        let len = self.usize()?;
+        String::from_utf8(self.take(len)?.to_vec()).ok()
+    }
+
+    fn token(&mut self) -> Option<Token> {
+        Some(match self.u8()? {
+            0 => Token::Ident(self.str()?.into()),
+            1 => Token::Punct(self.char()?),
+            2 => Token::Comment(self.str()?.into()),
+            3 => {
+                let delimiter = *Delimiter::ALL.get(usize::from(self.u8()?))?;
+                let recovered = self.u8()? != 0;
+                Token::from(Group { delimiter, tokens: self.tokens()?, recovered })
+            },
+            4 => Token::Newline,
+            5 => Token::String(self.str()?.into()),
+            6 => Token::Char(self.char()?),
+            7 => Token::Number(self.u64()? as i64),
+            8 => Token::Label(self.str()?.into()),
+            9 => Token::Directive(self.str()?.into()),
+            10 => Token::Immediate(Box::new(self.token()?)),
+            11 => Token::Register(self.str()?.into()),
+            12 => Token::Eof,
+            13 => Token::DocComment(self.str()?.into()),
+            14 => Token::Float(Float(f64::from_bits(self.u64()?))),
+            15 => Token::Keyword(self.str()?.into()),
+            16 => Token::Op(self.str()?.into()),
+            _ => return None,
+        })
+    }
+
+    fn tokens(&mut self) -> Option<Vec<Spanned<Token>>> {
+        let len = self.usize()?;
+        // Bounded by the bytes left, so that a corrupt length can't allocate much
+        let mut toks = Vec::with_capacity(len.min(self.bytes.len()));
+        for _ in 0..len {
+            let (start, end) = (self.usize()?, self.usize()?);
+            if start > end {
+                return None;
+            }
+            let span = Span::new(start, end).with_file(self.file);
+            toks.push(Spanned::new(self.token()?, span));
+        }
+        Some(toks)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tokenize_spanned;
+
+    #[test]
+    fn disk_cache() {
+        let dir = std::env::temp_dir().join(format!("smpl_parser_disk_cache_{}", std::process::id()));
+        let cache = DiskCache::new(&dir);
+        let config = TokenizerConfig { immediate_prefixes: vec!['#'], ..Default::default() };
+        let code = "mov r0, [#-4, 'c'] // spill\n\"s\"";
+        assert!(cache.get(&config, FileId::default(), code).is_none());
+
+        let (toks, diagnostics) = cache.tokenize(&config, FileId(1), code);
+        assert!(diagnostics.is_empty());
+        assert_eq!(cache.get(&config, FileId(1), code), Some(toks.clone()));
+        assert_eq!(cache.tokenize(&config, FileId(1), code).0, toks);
+        assert!(cache.get(&TokenizerConfig::default(), FileId(1), code).is_none());
+        assert!(cache.get(&config, FileId(1), "mov r1").is_none());
+
+        // An entry found for another source of the same length, as on a hash collision, misses
+        let other = "mov r1, [#-4, 'c'] // spill\n\"s\"";
+        fs::copy(cache.path(&config, code), cache.path(&config, other)).unwrap();
+        assert!(cache.get(&config, FileId(1), other).is_none());
+        cache.invalidate(&config, other).unwrap();
+
+        // Not stored, to report the diagnostics again
+        assert_eq!(cache.tokenize(&config, FileId(1), "\"open").1.len(), 1);
+        assert_eq!(cache.entries().unwrap().len(), 1);
+
+        cache.invalidate(&config, code).unwrap();
+        assert!(cache.get(&config, FileId(1), code).is_none());
+
+        let codes = ["a", "b b", "c c c"];
+        for code in codes {
+            cache.put(&config, code, &tokenize_spanned(&config, code).0).unwrap();
+        }
+        let size = cache.size().unwrap();
+        let cache = cache.max_bytes(size - 1);
+        cache.put(&config, "d", &tokenize_spanned(&config, "d").0).unwrap();
+        assert!(cache.size().unwrap() < size);
+        assert!(cache.get(&config, FileId(1), "d").is_some());
+
+        cache.clear().unwrap();
+        assert_eq!(cache.size().unwrap(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,531 @@
+//! Grammars loaded at runtime from an EBNF-like description, interpreted against tokens into
+//! generic parse trees.
+
+use alloc::collections::BTreeMap;
+use core::fmt;
+
+use crate::prelude::*;
+use crate::grammar::furthest;
+use crate::{instrument, trace};
+use crate::{
+    Delimiter, Diagnostic, DiagnosticKind, Span, Spanned, Token, TokenScanner, TokenizerConfig, Trace,
+    tokenize_spanned,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Ident,
+    Number,
+    String,
+    Char,
+    Label,
+    Directive,
+    Register,
+    Float,
+    Op,
+}
+
+impl Kind {
+    const NAMES : [(&'static str, Kind); 9] = [
+        ("IDENT", Kind::Ident),
+        ("NUMBER", Kind::Number),
+        ("STRING", Kind::String),
+        ("CHAR", Kind::Char),
+        ("LABEL", Kind::Label),
+        ("DIRECTIVE", Kind::Directive),
+        ("REGISTER", Kind::Register),
+        ("FLOAT", Kind::Float),
+        ("OP", Kind::Op),
+    ];
+
+    fn matches(self, tok : &Token) -> bool {
+        matches!((self, tok),
+            (Kind::Ident, Token::Ident(_)) | (Kind::Number, Token::Number(_)) | (Kind::String, Token::String(_))
+            | (Kind::Char, Token::Char(_)) | (Kind::Label, Token::Label(_)) | (Kind::Directive, Token::Directive(_))
+            | (Kind::Register, Token::Register(_)) | (Kind::Float, Token::Float(_))
+            | (Kind::Op, Token::Op(_)))
+    }
+
+    fn expected(self) -> &'static str {
+        match self {
+            Kind::Ident => "an identifier",
+            Kind::Number => "a number",
+            Kind::String => "a string",
+            Kind::Char => "a char",
+            Kind::Label => "a label",
+            Kind::Directive => "a directive",
+            Kind::Register => "a register",
+            Kind::Float => "a float",
+            Kind::Op => "an operator",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Seq(Vec<Expr>),
+    Alt(Vec<Expr>),
+    Optional(Box<Expr>),
+    Repeat(Box<Expr>, usize),
+    Rule(usize),
+    Kind(Kind),
+    Keyword(String),
+    Punct(char),
+
+    /// Punctuation of several chars, matching a `Token::Op` of them or their `Token::Punct`s.
+    Op(String),
+    Number(i64),
+    Group(Delimiter, Box<Expr>),
+}
+
+/// A rule reference or a terminal, before groups are formed from delimiter terminals.
+enum Item {
+    Expr(Expr),
+    Open(Delimiter, Span),
+    Close(Delimiter, Span),
+}
+
+/// A grammar interpreted at runtime, e.g. loaded from a file by a tool.
+///
+/// The description is a list of rules `name = expr ;`, where expressions are alternatives
+/// separated by `|` of sequences of `[optional]`, `{repeated}`, `(grouped)` expressions,
+/// others followed by `?`, `*` or `+`, rule names, the token kinds `IDENT`, `NUMBER`, `STRING`,
+/// `CHAR`, `LABEL`, `DIRECTIVE`, `REGISTER`, `FLOAT` and `OP`, and quoted terminals matching a
+/// keyword or punctuation, which is an operator or its chars, or numbers matching themselves. Terminal delimiters match a group, so
+/// `"[" REGISTER "]"` matches `[r0]`. Comments are allowed.
+///
+/// Alternatives are tried in order and repetitions are greedy, backtracking over what failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grammar {
+    rules : Vec<(String, Expr)>,
+}
+
+struct Loader {
+    names : BTreeMap<String, usize>,
+    uses : Vec<(String, Span)>,
+}
+
+impl Loader {
+    fn alt(&mut self, input : &mut TokenScanner<'_>) -> Result<Expr, Box<Diagnostic>> {
+        let mut alts = vec![self.seq(input)?];
+        while input.expect_punct('|').is_ok() {
+            alts.push(self.seq(input)?);
+        }
+        Ok(if alts.len() == 1 { alts.pop().unwrap() } else { Expr::Alt(alts) })
+    }
+
+    fn seq(&mut self, input : &mut TokenScanner<'_>) -> Result<Expr, Box<Diagnostic>> {
+        let mut items = Vec::new();
+        loop {
+            input.skip_trivia();
+            match input.peek().map(|tok| &tok.value) {
+                None | Some(Token::Punct('|' | ';')) => break,
+                _ => items.push(self.item(input)?),
+            }
+        }
+
+        // Delimiter terminals enclose the items between them in a group
+        let mut stack = vec![(None, Vec::new())];
+        for item in items {
+            match item {
+                Item::Expr(expr) => stack.last_mut().unwrap().1.push(expr),
+                Item::Open(delimiter, span) => stack.push((Some((delimiter, span)), Vec::new())),
+                Item::Close(delimiter, span) => match stack.pop().unwrap() {
+                    (Some((open, _)), exprs) if open == delimiter => {
+                        stack.last_mut().unwrap().1.push(Expr::Group(delimiter, Box::new(Expr::Seq(exprs))));
+                    },
+                    (Some((open, _)), _) => return Err(Box::new(Diagnostic::error(span, DiagnosticKind::MismatchedDelimiter {
+                        expected: open.close(),
+                        found: delimiter.close(),
+                    }))),
+                    (None, _) => return Err(Box::new(Diagnostic::error(span, DiagnosticKind::UnexpectedCloseDelimiter(delimiter.close())))),
+                },
+            }
+        }
+        match stack.pop().unwrap() {
+            (None, exprs) => Ok(Expr::Seq(exprs)),
+            (Some((open, span)), _) => Err(Box::new(Diagnostic::error(span, DiagnosticKind::UnclosedDelimiter(open.open())))),
+        }
+    }
+
+    fn item(&mut self, input : &mut TokenScanner<'_>) -> Result<Item, Box<Diagnostic>> {
+        let tok = input.pop().unwrap();
+        let mut expr = match &tok.value {
+            Token::Ident(name) => match Kind::NAMES.iter().find(|(kind, _)| name == *kind) {
+                Some((_, kind)) => Expr::Kind(*kind),
+                None => {
+                    self.uses.push((name.to_string(), tok.span));
+                    Expr::Rule(self.uses.len() - 1)
+                },
+            },
+            Token::String(text) => {
+                let mut chars = text.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) if Delimiter::is_open(c) => return Ok(Item::Open(Delimiter::from_open(c).unwrap(), tok.span)),
+                    (Some(c), None) if Delimiter::is_close(c) => return Ok(Item::Close(Delimiter::from_close(c).unwrap(), tok.span)),
+                    (Some(c), _) if c.is_alphabetic() || c == '_' => Expr::Keyword(text.to_string()),
+                    (Some(c), second) if !text.chars().any(|c| c.is_alphanumeric() || c.is_whitespace() || Delimiter::is_open(c) || Delimiter::is_close(c)) => {
+                        match second {
+                            Some(_) => Expr::Op(text.to_string()),
+                            None => Expr::Punct(c),
+                        }
+                    },
+                    _ => return Err(Box::new(Diagnostic::error(tok.span, DiagnosticKind::ExpectedToken {
+                        expected: "a keyword or punctuation".to_string(),
+                        found: tok.value.describe(),
+                    }))),
+                }
+            },
+            Token::Number(n) => Expr::Number(*n),
+            Token::Group(group) => {
+                let mut inner = TokenScanner::new(&group.tokens);
+                let expr = self.alt(&mut inner)?;
+                inner.expect_end()?;
+                match group.delimiter {
+                    Delimiter::Paren => expr,
+                    Delimiter::Bracket => Expr::Optional(Box::new(expr)),
+                    Delimiter::Brace => Expr::Repeat(Box::new(expr), 0),
+                }
+            },
+            _ => {
+                input.pos -= 1;
+                return Err(input.error("a rule, token kind or terminal"));
+            },
+        };
+
+        loop {
+            expr = if input.expect_punct('?').is_ok() {
+                Expr::Optional(Box::new(expr))
+            } else if input.expect_punct('*').is_ok() {
+                Expr::Repeat(Box::new(expr), 0)
+            } else if input.expect_punct('+').is_ok() {
+                Expr::Repeat(Box::new(expr), 1)
+            } else {
+                break Ok(Item::Expr(expr))
+            };
+        }
+    }
+
+    /// Replaces the indices into `uses` of rule references with indices of rules.
+    fn resolve(&self, expr : &mut Expr) -> Result<(), Box<Diagnostic>> {
+        match expr {
+            Expr::Seq(exprs) | Expr::Alt(exprs) => exprs.iter_mut().try_for_each(|expr| self.resolve(expr)),
+            Expr::Optional(expr) | Expr::Repeat(expr, _) | Expr::Group(_, expr) => self.resolve(expr),
+            Expr::Rule(index) => {
+                let (name, span) = &self.uses[*index];
+                *index = *self.names.get(name).ok_or_else(|| Box::new(Diagnostic::error(*span, DiagnosticKind::UndefinedRule(name.clone()))))?;
+                Ok(())
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Grammar {
+    /// Loads a grammar from its description, failing on the first error.
+    pub fn parse(text : &str) -> Result<Self, Box<Diagnostic>> {
+        let (toks, diagnostics) = tokenize_spanned(&TokenizerConfig::default(), text);
+        if let Some(diagnostic) = diagnostics.into_iter().find(Diagnostic::is_error) {
+            return Err(Box::new(diagnostic));
+        }
+
+        let mut loader = Loader { names: BTreeMap::new(), uses: Vec::new() };
+        let mut rules = Vec::new();
+        let mut input = TokenScanner::new(&toks);
+        while input.expect_end().is_err() {
+            let span = input.span();
+            let name = input.expect_ident()?;
+            if loader.names.insert(name.to_string(), rules.len()).is_some() {
+                return Err(Box::new(Diagnostic::error(span, DiagnosticKind::DuplicateRule(name.to_string()))));
+            }
+            input.expect_punct('=')?;
+            let expr = loader.alt(&mut input)?;
+            input.expect_punct(';')?;
+            rules.push((name.to_string(), expr));
+        }
+
+        for (_, expr) in &mut rules {
+            loader.resolve(expr)?;
+        }
+        Ok(Self { rules })
+    }
+
+    /// The names of the rules, in order of definition.
+    pub fn rules(&self) -> impl Iterator<Item = &str> {
+        self.rules.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Parses all of `toks` as the rule `start`.
+    pub fn parse_tokens(&self, start : &str, toks : &[Spanned<Token>]) -> Result<ParseNode, Box<Diagnostic>> {
+        self.parse_scanner(start, TokenScanner::new(toks))
+    }
+
+    /// Like `parse_tokens`, recording each rule attempted and each backtrack in `trace`.
+    pub fn parse_tokens_traced(&self, start : &str, toks : &[Spanned<Token>], trace : &Trace) -> Result<ParseNode, Box<Diagnostic>> {
+        self.parse_scanner(start, TokenScanner::new(toks).with_trace(trace))
+    }
+
+    fn parse_scanner(&self, start : &str, input : TokenScanner<'_>) -> Result<ParseNode, Box<Diagnostic>> {
+        let measure = instrument::start("parse");
+        let res = self.interpret(start, input);
+        measure.finish(input.toks.len(), usize::from(res.is_err()));
+        res
+    }
+
+    fn interpret(&self, start : &str, mut input : TokenScanner<'_>) -> Result<ParseNode, Box<Diagnostic>> {
+        let Some(rule) = self.rules.iter().position(|(name, _)| name == start) else {
+            return Err(Box::new(Diagnostic::error(input.span(), DiagnosticKind::UndefinedRule(start.to_string()))));
+        };
+
+        let mut interpreter = Interpreter { grammar: self, error: None, fatal: None, active: Vec::new() };
+        let mut children = Vec::new();
+        let res = interpreter.eval(&Expr::Rule(rule), &mut input, &mut children);
+        if let Some(fatal) = interpreter.fatal {
+            return Err(fatal);
+        }
+        match res {
+            true => match input.expect_end() {
+                Ok(()) => match children.pop() {
+                    Some(ParseChild::Node(node)) => Ok(node),
+                    _ => unreachable!(),
+                },
+                Err(err) => Err(furthest(interpreter.error, err)),
+            },
+            false => Err(interpreter.error.unwrap()),
+        }
+    }
+}
+
+struct Interpreter<'g> {
+    grammar : &'g Grammar,
+
+    /// The error of the failure that got furthest, and one that stops parsing.
+    error : Option<Box<Diagnostic>>,
+    fatal : Option<Box<Diagnostic>>,
+
+    /// The rules being parsed, with the tokens and position they started at.
+    active : Vec<(usize, *const Spanned<Token>, usize)>,
+}
+
+impl Interpreter<'_> {
+    fn fail(&mut self, err : Box<Diagnostic>) -> bool {
+        self.error = Some(furthest(self.error.take(), err));
+        false
+    }
+
+    fn terminal(&mut self, input : &mut TokenScanner<'_>, children : &mut Vec<ParseChild>, expected : &str, cb : impl FnOnce(&Token) -> bool) -> bool {
+        match input.expect(expected, |tok| cb(tok).then_some(())) {
+            Ok(()) => {
+                children.push(ParseChild::Token(input.toks[input.pos - 1].clone()));
+                true
+            },
+            Err(err) => self.fail(err),
+        }
+    }
+
+    fn eval(&mut self, expr : &Expr, input : &mut TokenScanner<'_>, children : &mut Vec<ParseChild>) -> bool {
+        if self.fatal.is_some() {
+            return false;
+        }
+        match expr {
+            Expr::Seq(exprs) => exprs.iter().all(|expr| self.eval(expr, input, children)),
+            Expr::Alt(alts) => alts.iter().any(|alt| self.attempt(alt, input, children)),
+            Expr::Optional(expr) => {
+                self.attempt(expr, input, children);
+                true
+            },
+            Expr::Repeat(expr, min) => {
+                let mut count = 0;
+                loop {
+                    let pos = input.pos;
+                    if !self.attempt(expr, input, children) {
+                        break count >= *min;
+                    }
+                    count += 1;
+                    if input.pos == pos {
+                        break true;
+                    }
+                }
+            },
+            Expr::Rule(rule) => {
+                input.skip_trivia();
+                let key = (*rule, input.toks.as_ptr(), input.pos);
+                let (name, expr) = &self.grammar.rules[*rule];
+                if self.active.contains(&key) {
+                    self.fatal = Some(Box::new(Diagnostic::error(input.span(), DiagnosticKind::LeftRecursion(name.clone()))));
+                    return false;
+                }
+
+                self.active.push(key);
+                trace::enter(input, name);
+                let start = input.span();
+                let mut nodes = Vec::new();
+                let res = self.eval(expr, input, &mut nodes);
+                self.active.pop();
+                if let Some(trace) = input.trace {
+                    trace.exit(name, res);
+                }
+                if res {
+                    let span = nodes.iter().map(ParseChild::span).reduce(Span::join).unwrap_or(Span { end: start.start, ..start });
+                    children.push(ParseChild::Node(ParseNode { rule: name.clone(), children: nodes, span }));
+                }
+                res
+            },
+            Expr::Kind(kind) => self.terminal(input, children, kind.expected(), |tok| kind.matches(tok)),
+            Expr::Keyword(keyword) => self.terminal(input, children, &format!("`{keyword}`"), |tok| matches!(tok, Token::Ident(name) | Token::Keyword(name) if name == keyword)),
+            Expr::Punct(c) => self.terminal(input, children, &format!("`{c}`"), |tok| *tok == Token::Punct(*c)),
+            Expr::Op(op) => {
+                input.skip_trivia();
+                match input.peek() {
+                    Some(tok) if matches!(&tok.value, Token::Op(found) if found == op) => self.terminal(input, children, &format!("`{op}`"), |_| true),
+                    _ => op.chars().all(|c| self.eval(&Expr::Punct(c), input, children)),
+                }
+            },
+            Expr::Number(n) => self.terminal(input, children, &format!("`{n}`"), |tok| *tok == Token::Number(*n)),
+            Expr::Group(delimiter, expr) => {
+                let mut inner = match input.expect_group(*delimiter) {
+                    Ok(inner) => inner,
+                    Err(err) => return self.fail(err),
+                };
+                self.eval(expr, &mut inner, children) && match inner.expect_end() {
+                    Ok(()) => true,
+                    Err(err) => self.fail(err),
+                }
+            },
+        }
+    }
+
+    /// Evaluates `expr`, leaving `input` and `children` as they were if it fails.
+    fn attempt(&mut self, expr : &Expr, input : &mut TokenScanner<'_>, children : &mut Vec<ParseChild>) -> bool {
+        let (fork, len) = (*input, children.len());
+        let res = self.eval(expr, input, children);
+        if !res {
+            if let Some(err) = &self.error {
+                trace::backtrack(input, err);
+            }
+            *input = fork;
+            children.truncate(len);
+        }
+        res
+    }
+}
+
+/// A node of the tree `Grammar::parse_tokens` produces: a rule and what it matched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseNode {
+    pub rule : String,
+    pub children : Vec<ParseChild>,
+
+    /// From the first token to the last, or empty if the rule matched nothing.
+    pub span : Span,
+}
+
+/// One of the rules or tokens a rule matched. Groups are not children, their contents are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseChild {
+    Node(ParseNode),
+    Token(Spanned<Token>),
+}
+
+impl ParseChild {
+    pub fn span(&self) -> Span {
+        match self {
+            ParseChild::Node(node) => node.span,
+            ParseChild::Token(tok) => tok.span,
+        }
+    }
+}
+
+/// As an S-expression, e.g. `(instr mov (operand r0))`.
+impl fmt::Display for ParseNode {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}", self.rule)?;
+        for child in &self.children {
+            match child {
+                ParseChild::Node(node) => write!(f, " {node}")?,
+                ParseChild::Token(tok) => match &tok.value {
+                    Token::Ident(name) | Token::Label(name) | Token::Directive(name) | Token::Register(name)
+                    | Token::Keyword(name) | Token::Op(name) => write!(f, " {name}")?,
+                    Token::Punct(c) => write!(f, " {c}")?,
+                    Token::Number(n) => write!(f, " {n}")?,
+                    Token::Float(n) => write!(f, " {n}")?,
+                    Token::String(value) => write!(f, " {value:?}")?,
+                    Token::Char(c) => write!(f, " {c:?}")?,
+                    tok => write!(f, " <{}>", tok.describe())?,
+                },
+            }
+        }
+        write!(f, ")")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const GRAMMAR : &str = r##"
+        // A tiny assembly language
+        program = { line } ;
+        line = LABEL? instr ";" ;
+        instr = IDENT [ operand ("," operand)* ] ;
+        operand = REGISTER | "#" NUMBER | "[" REGISTER ["," NUMBER] "]" | "<<" NUMBER ;
+    "##;
+
+    fn run(code : &str) -> Result<String, String> {
+        let grammar = Grammar::parse(GRAMMAR).unwrap();
+        let config = TokenizerConfig { labels: true, registers: vec!["r0..r7".to_string()], ..Default::default() };
+        let (toks, _) = tokenize_spanned(&config, code);
+        grammar.parse_tokens("program", &toks).map(|node| node.to_string()).map_err(|err| err.message())
+    }
+
+    #[test]
+    fn interpret() {
+        assert_eq!(
+            run("loop: ld r0, [r1, 4]; nop /* no-op */;").unwrap(),
+            "(program (line loop (instr ld (operand r0) , (operand r1 , 4)) ;) (line (instr nop) ;))",
+        );
+        assert_eq!(run("lsl r0, <<2;").unwrap(), "(program (line (instr lsl (operand r0) , (operand < < 2)) ;))");
+        assert_eq!(run("").unwrap(), "(program)");
+
+        let grammar = Grammar::parse("data = \"db\" FLOAT { \",\" FLOAT } ;").unwrap();
+        let config = TokenizerConfig { keywords: vec!["db".to_string()], floats: true, ..Default::default() };
+        let (toks, _) = tokenize_spanned(&config, "db 1.5, 2e3");
+        assert_eq!(grammar.parse_tokens("data", &toks).unwrap().to_string(), "(data db 1.5 , 2000)");
+
+        // `<<` matches an operator as well as its chars
+        let grammar = Grammar::parse(GRAMMAR).unwrap();
+        let config = TokenizerConfig { operators: vec!["<<".to_string()], registers: vec!["r0..r7".to_string()], ..Default::default() };
+        let (toks, _) = tokenize_spanned(&config, "lsl r0, <<2;");
+        assert_eq!(grammar.parse_tokens("program", &toks).unwrap().to_string(), "(program (line (instr lsl (operand r0) , (operand << 2)) ;))");
+        let grammar = Grammar::parse("a = OP IDENT ;").unwrap();
+        assert_eq!(grammar.parse_tokens("a", &toks[2..]).unwrap_err().message(), "expected an operator, found `,`");
+
+        assert_eq!(run("ld r0, [r1 4];"), Err("expected `,` or `]`, found number 4".to_string()));
+        assert_eq!(run("ld r0, x;"), Err("expected a register or `#` or `[` or `<`, found identifier `x`".to_string()));
+        assert_eq!(run("nop"), Err("expected a register or `#` or `[` or `<` or `;`, found the end of input".to_string()));
+    }
+
+    #[test]
+    fn errors() {
+        let message = |text : &str| Grammar::parse(text).unwrap_err().message();
+        assert_eq!(message("a = b ;"), "undefined rule `b`");
+        assert_eq!(message("a = IDENT ; a = NUMBER ;"), "rule `a` is defined more than once");
+        assert_eq!(message("a = \"(\" IDENT ;"), "unclosed delimiter `(`");
+        assert_eq!(message("a = IDENT"), "expected `;`, found the end of input");
+
+        let grammar = Grammar::parse("expr = expr \"+\" NUMBER | NUMBER ;").unwrap();
+        let (toks, _) = tokenize_spanned(&TokenizerConfig::default(), "1 + 2");
+        assert_eq!(grammar.parse_tokens("expr", &toks).unwrap_err().message(), "rule `expr` is left-recursive");
+        assert_eq!(grammar.parse_tokens("nope", &toks).unwrap_err().message(), "undefined rule `nope`");
+        assert!(grammar.rules().eq(["expr"]));
+    }
+
+    #[test]
+    fn traced() {
+        let grammar = Grammar::parse("a = \"x\" | b ; b = IDENT ;").unwrap();
+        let (toks, _) = tokenize_spanned(&TokenizerConfig::default(), "y");
+        let trace = Trace::new();
+        grammar.parse_tokens_traced("a", &toks, &trace).unwrap();
+        assert_eq!(trace.to_string(), "a at 0..1\n  backtrack: expected `x`, found identifier `y`\n  b at 0..1\n  b matched\na matched\n");
+    }
+}
@@ -0,0 +1,268 @@
+//! Evaluation of constant expressions, such as the operands of assembler instructions.
+
+use crate::prelude::*;
+use crate::{Delimiter, Diagnostic, DiagnosticKind, Span, Spanned, Token};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Or,
+    Xor,
+    And,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+impl Op {
+    fn precedence(self) -> u8 {
+        match self {
+            Op::Or => 1,
+            Op::Xor => 2,
+            Op::And => 3,
+            Op::Shl | Op::Shr => 4,
+            Op::Add | Op::Sub => 5,
+            Op::Mul | Op::Div | Op::Rem => 6,
+        }
+    }
+
+    /// Applies the operator to integers, or to floats if either operand is one.
+    fn apply(self, a : Value, b : Value, span : Span) -> Result<Value, Box<Diagnostic>> {
+        match (a, b) {
+            (Value::Int(a), Value::Int(b)) => self.apply_int(a, b, span).map(Value::Int),
+            (a, b) => self.apply_float(a.as_f64(), b.as_f64(), span).map(Value::Float),
+        }
+    }
+
+    fn apply_int(self, a : i64, b : i64, span : Span) -> Result<i64, Box<Diagnostic>> {
+        let shift = || u32::try_from(b).ok().filter(|b| *b < 64);
+        let res = match self {
+            Op::Or => Some(a | b),
+            Op::Xor => Some(a ^ b),
+            Op::And => Some(a & b),
+            Op::Shl => shift().and_then(|b| a.checked_shl(b)),
+            Op::Shr => shift().and_then(|b| a.checked_shr(b)),
+            Op::Add => a.checked_add(b),
+            Op::Sub => a.checked_sub(b),
+            Op::Mul => a.checked_mul(b),
+            Op::Div | Op::Rem if b == 0 => return Err(Box::new(Diagnostic::error(span, DiagnosticKind::DivisionByZero))),
+            Op::Div => a.checked_div(b),
+            Op::Rem => a.checked_rem(b),
+        };
+        res.ok_or_else(|| Box::new(Diagnostic::error(span, DiagnosticKind::ArithmeticOverflow)))
+    }
+
+    fn apply_float(self, a : f64, b : f64, span : Span) -> Result<f64, Box<Diagnostic>> {
+        let res = match self {
+            Op::Or | Op::Xor | Op::And | Op::Shl | Op::Shr => return Err(Box::new(Diagnostic::error(span, DiagnosticKind::ExpectedInteger))),
+            Op::Div | Op::Rem if b == 0.0 => return Err(Box::new(Diagnostic::error(span, DiagnosticKind::DivisionByZero))),
+            Op::Add => a + b,
+            Op::Sub => a - b,
+            Op::Mul => a * b,
+            Op::Div => a / b,
+            Op::Rem => a % b,
+        };
+        match res.is_finite() {
+            true => Ok(res),
+            false => Err(Box::new(Diagnostic::error(span, DiagnosticKind::ArithmeticOverflow))),
+        }
+    }
+}
+
+/// The value of an expression: an integer, or a float if one takes part in it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+}
+
+impl Value {
+    pub fn as_f64(self) -> f64 {
+        match self {
+            Value::Int(n) => n as f64,
+            Value::Float(f) => f,
+        }
+    }
+}
+
+struct Evaluator<'a, F> {
+    toks : Vec<&'a Spanned<Token>>,
+    pos : usize,
+    end : Span,
+    lookup : &'a F,
+
+    /// Whether the current token is a negative number to read as a `-` followed by its absolute value.
+    split_minus : bool,
+}
+
+impl<'a, F : Fn(&str) -> Option<i64>> Evaluator<'a, F> {
+    fn new(toks : &'a [Spanned<Token>], end : Span, lookup : &'a F) -> Self {
+        let toks = toks.iter().filter(|tok| !tok.is_trivia()).collect();
+        Self { toks, pos: 0, end, lookup, split_minus: false }
+    }
+
+    fn peek(&self) -> Option<&'a Spanned<Token>> {
+        self.toks.get(self.pos).copied()
+    }
+
+    fn span(&self) -> Span {
+        self.peek().map_or(self.end, |tok| tok.span)
+    }
+
+    fn error(&self, kind : DiagnosticKind) -> Box<Diagnostic> {
+        Box::new(Diagnostic::error(self.span(), kind))
+    }
+
+    /// The binary operator at the current position and the number of tokens it takes.
+    fn operator(&self) -> Option<(Op, usize)> {
+        let tok = self.peek()?;
+        let next = self.toks.get(self.pos + 1).filter(|next| next.span.start == tok.span.end);
+        Some(match (&tok.value, next.map(|next| &next.value)) {
+            (Token::Punct('|'), _) => (Op::Or, 1),
+            (Token::Punct('^'), _) => (Op::Xor, 1),
+            (Token::Punct('&'), _) => (Op::And, 1),
+            (Token::Punct('<'), Some(Token::Punct('<'))) => (Op::Shl, 2),
+            (Token::Punct('>'), Some(Token::Punct('>'))) => (Op::Shr, 2),
+            (Token::Punct('+'), _) => (Op::Add, 1),
+            (Token::Punct('-'), _) => (Op::Sub, 1),
+            (Token::Number(n), _) if *n < 0 => (Op::Sub, 0), // `a -1` lexes as `a` and `-1`
+            (Token::Float(f), _) if f.0.is_sign_negative() => (Op::Sub, 0),
+            (Token::Punct('*'), _) => (Op::Mul, 1),
+            (Token::Punct('/'), _) => (Op::Div, 1),
+            (Token::Punct('%'), _) => (Op::Rem, 1),
+            _ => return None,
+        })
+    }
+
+    fn expr(&mut self, precedence : u8) -> Result<Value, Box<Diagnostic>> {
+        let mut value = self.unary()?;
+        while let Some((op, len)) = self.operator().filter(|(op, _)| op.precedence() >= precedence) {
+            let span = self.span();
+            self.pos += len;
+            self.split_minus = len == 0;
+            let rhs = self.expr(op.precedence() + 1)?;
+            value = op.apply(value, rhs, span)?;
+        }
+        Ok(value)
+    }
+
+    fn unary(&mut self) -> Result<Value, Box<Diagnostic>> {
+        let span = self.span();
+        let op = match self.peek().map(|tok| &tok.value) {
+            Some(Token::Punct(c @ ('-' | '~' | '+'))) if !self.split_minus => *c,
+            _ => return self.primary(),
+        };
+        self.pos += 1;
+        let value = self.unary()?;
+        let error = |kind| Box::new(Diagnostic::error(span, kind));
+        match (op, value) {
+            ('-', Value::Int(n)) => n.checked_neg().map(Value::Int).ok_or_else(|| error(DiagnosticKind::ArithmeticOverflow)),
+            ('-', Value::Float(f)) => Ok(Value::Float(-f)),
+            ('~', Value::Int(n)) => Ok(Value::Int(!n)),
+            ('~', Value::Float(_)) => Err(error(DiagnosticKind::ExpectedInteger)),
+            _ => Ok(value),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Value, Box<Diagnostic>> {
+        let Some(tok) = self.peek() else { return Err(self.error(DiagnosticKind::ExpectedOperand)) };
+        let value = match &tok.value {
+            Token::Number(n) if self.split_minus => Value::Int(n.checked_neg().ok_or_else(|| self.error(DiagnosticKind::ArithmeticOverflow))?),
+            Token::Float(f) if self.split_minus => Value::Float(-f.0),
+            Token::Group(group) if group.delimiter == Delimiter::Paren => {
+                let end = Span::new(tok.span.end - 1, tok.span.end).with_file(tok.span.file);
+                eval_in(&group.tokens, end, self.lookup)?
+            },
+            tok => self.operand(tok)?,
+        };
+        self.split_minus = false;
+        self.pos += 1;
+        Ok(value)
+    }
+
+    fn operand(&self, tok : &Token) -> Result<Value, Box<Diagnostic>> {
+        match tok {
+            Token::Number(n) => Ok(Value::Int(*n)),
+            Token::Float(f) => Ok(Value::Float(f.0)),
+            Token::Ident(name) => (self.lookup)(name).map(Value::Int).ok_or_else(|| self.error(DiagnosticKind::UndefinedSymbol(name.to_string()))),
+            Token::Immediate(tok) => self.operand(tok),
+            _ => Err(self.error(DiagnosticKind::ExpectedOperand)),
+        }
+    }
+}
+
+fn eval_in<F : Fn(&str) -> Option<i64>>(toks : &[Spanned<Token>], end : Span, lookup : &F) -> Result<Value, Box<Diagnostic>> {
+    let mut evaluator = Evaluator::new(toks, end, lookup);
+    let value = evaluator.expr(0)?;
+    match evaluator.peek() {
+        Some(_) => Err(evaluator.error(DiagnosticKind::ExpectedOperator)),
+        None => Ok(value),
+    }
+}
+
+/// Evaluates a constant expression, e.g. `(BASE + 4*idx) & ~3`, calling `lookup` for the
+/// integer value of each identifier.
+///
+/// Supports parentheses, unary `-`, `+` and `~`, and the binary `* / % + - << >> & ^ |` with C
+/// precedence; comments are ignored. Numbers are `i64`s and floats, from
+/// `TokenizerConfig::floats`, `f64`s: an operation with a float operand promotes the other one
+/// and gives a float, but `~`, shifts and bitwise operators only take integers. Overflow,
+/// including shifts by 64 or more and floats becoming infinite, and division by zero are errors,
+/// as is anything else in the expression.
+pub fn eval(toks : &[Spanned<Token>], lookup : impl Fn(&str) -> Option<i64>) -> Result<Value, Box<Diagnostic>> {
+    let end = toks.last().map_or(Span::default(), |tok| Span::empty(tok.span.end).with_file(tok.span.file));
+    eval_in(toks, end, &lookup)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{TokenizerConfig, tokenize_spanned};
+
+    fn run(code : &str) -> Result<Value, (&'static str, Span)> {
+        let config = TokenizerConfig { immediate_prefixes: vec!['#'], floats: true, ..Default::default() };
+        let (toks, _) = tokenize_spanned(&config, code);
+        let symbols = |name : &str| match name {
+            "BASE" => Some(0x1000),
+            "idx" => Some(3),
+            _ => None,
+        };
+        eval(&toks, symbols).map_err(|diag| (diag.code().unwrap(), diag.span))
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(run("(BASE + 4*idx)"), Ok(Value::Int(0x100c)));
+        assert_eq!(run("1 + 2 * 3 - 4 /* four */"), Ok(Value::Int(3)));
+        assert_eq!(run("10-4-3"), Ok(Value::Int(3)));
+        assert_eq!(run("2 * -3 + -(1 << 4) % 5"), Ok(Value::Int(-7)));
+        assert_eq!(run("~0 & 0xff | 1 << 8 ^ 3"), Ok(Value::Int(0x1ff)));
+        assert_eq!(run("#BASE >> 12"), Ok(Value::Int(1)));
+    }
+
+    #[test]
+    fn floats() {
+        assert_eq!(run("1.5 + 2"), Ok(Value::Float(3.5)));
+        assert_eq!(run("idx / 2.0 -0.5 * -(2)"), Ok(Value::Float(2.5)));
+        assert_eq!(run("7 / 2 * 1.0"), Ok(Value::Float(3.0)));
+        assert_eq!(run("1.5 | 1"), Err(("expected-integer", Span::new(4, 5))));
+        assert_eq!(run("~1.5"), Err(("expected-integer", Span::new(0, 1))));
+        assert_eq!(run("1.0 / 0"), Err(("division-by-zero", Span::new(4, 5))));
+        assert_eq!(run("1e300 * 1e300"), Err(("arithmetic-overflow", Span::new(6, 7))));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(run("1 / (idx - 3)"), Err(("division-by-zero", Span::new(2, 3))));
+        assert_eq!(run("0x7fffffffffffffff + 1"), Err(("arithmetic-overflow", Span::new(19, 20))));
+        assert_eq!(run("1 << 64"), Err(("arithmetic-overflow", Span::new(2, 3))));
+        assert_eq!(run("BASE + nope"), Err(("undefined-symbol", Span::new(7, 11))));
+        assert_eq!(run("(1 +)"), Err(("expected-operand", Span::new(4, 5))));
+        assert_eq!(run("1 +"), Err(("expected-operand", Span::new(3, 3))));
+        assert_eq!(run("1 2"), Err(("expected-operator", Span::new(2, 3))));
+        assert_eq!(run("[1]"), Err(("expected-operand", Span::new(0, 3))));
+    }
+}
@@ -0,0 +1,294 @@
+//! A C interface to the tokenizer, with the `ffi` feature, declared by `include/smpl_parser.h`.
+//!
+//! `smpl_lex` tokenizes a buffer into a result that is read with the other functions and freed
+//! with `smpl_lex_free`. The token tree is flattened in source order: a group is followed by its
+//! tokens one level deeper, and an immediate by the token after its prefix. Strings point into
+//! the result, are UTF-8 and aren't NUL-terminated.
+
+use core::ptr;
+
+use crate::prelude::*;
+use crate::{Diagnostic, Severity, Span, Spanned, Token, TokenizerConfig, tokenize_spanned};
+
+pub const SMPL_IDENT : u32 = 0;
+pub const SMPL_PUNCT : u32 = 1;
+pub const SMPL_COMMENT : u32 = 2;
+pub const SMPL_GROUP : u32 = 3;
+pub const SMPL_NEWLINE : u32 = 4;
+pub const SMPL_STRING : u32 = 5;
+pub const SMPL_CHAR : u32 = 6;
+pub const SMPL_NUMBER : u32 = 7;
+pub const SMPL_LABEL : u32 = 8;
+pub const SMPL_DIRECTIVE : u32 = 9;
+pub const SMPL_IMMEDIATE : u32 = 10;
+pub const SMPL_REGISTER : u32 = 11;
+pub const SMPL_EOF : u32 = 12;
+pub const SMPL_DOC_COMMENT : u32 = 13;
+pub const SMPL_FLOAT : u32 = 14;
+pub const SMPL_KEYWORD : u32 = 15;
+pub const SMPL_OP : u32 = 16;
+
+/// Set in `SmplToken::flags` of a group the tokenizer closed itself, recovering from an error.
+pub const SMPL_RECOVERED : u32 = 1;
+
+/// A token of the flattened tree.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SmplToken {
+    /// One of the `SMPL_*` kinds.
+    pub kind : u32,
+    pub flags : u32,
+    /// How many groups and immediates the token is in.
+    pub depth : u32,
+    pub start : usize,
+    pub end : usize,
+    /// How many of the tokens after this one are in it, to skip a group.
+    pub descendants : usize,
+    /// The value of a number, the bits of a float, or the code point of a punct, char or group's
+    /// opening delimiter.
+    pub number : i64,
+    /// The text of an identifier, comment, string, char, punct, label, directive or register.
+    pub text : *const u8,
+    pub text_len : usize,
+}
+
+/// A diagnostic of tokenizing, without its notes and suggestions.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SmplDiagnostic {
+    /// 0 for an error, 1 for a warning and 2 for a note.
+    pub severity : u32,
+    pub start : usize,
+    pub end : usize,
+    /// The code of the diagnostic, or null if it has none.
+    pub code : *const u8,
+    pub code_len : usize,
+    pub message : *const u8,
+    pub message_len : usize,
+}
+
+struct Item {
+    kind : u32,
+    flags : u32,
+    depth : u32,
+    span : Span,
+    descendants : usize,
+    number : i64,
+    text : String,
+}
+
+/// The tokens and diagnostics of a buffer, opaque to C.
+pub struct SmplLexResult {
+    items : Vec<Item>,
+    diagnostics : Vec<(Diagnostic, String)>,
+}
+
+fn flatten(tok : &Token, span : Span, depth : u32, items : &mut Vec<Item>) {
+    let (kind, number, text) = match tok {
+        Token::Ident(s) => (SMPL_IDENT, 0, s.to_string()),
+        Token::Punct(c) => (SMPL_PUNCT, i64::from(u32::from(*c)), c.to_string()),
+        Token::Comment(s) => (SMPL_COMMENT, 0, s.to_string()),
+        Token::Group(group) => (SMPL_GROUP, i64::from(u32::from(group.delimiter.open())), String::new()),
+        Token::Newline => (SMPL_NEWLINE, 0, String::new()),
+        Token::String(s) => (SMPL_STRING, 0, s.to_string()),
+        Token::Char(c) => (SMPL_CHAR, i64::from(u32::from(*c)), c.to_string()),
+        Token::Number(n) => (SMPL_NUMBER, *n, String::new()),
+        Token::Label(s) => (SMPL_LABEL, 0, s.to_string()),
+        Token::Directive(s) => (SMPL_DIRECTIVE, 0, s.to_string()),
+        Token::Immediate(_) => (SMPL_IMMEDIATE, 0, String::new()),
+        Token::Register(s) => (SMPL_REGISTER, 0, s.to_string()),
+        Token::Eof => (SMPL_EOF, 0, String::new()),
+        Token::DocComment(s) => (SMPL_DOC_COMMENT, 0, s.to_string()),
+        Token::Float(f) => (SMPL_FLOAT, f.0.to_bits() as i64, String::new()),
+        Token::Keyword(s) => (SMPL_KEYWORD, 0, s.to_string()),
+        Token::Op(s) => (SMPL_OP, 0, s.to_string()),
+    };
+    let flags = match tok {
+        Token::Group(group) if group.recovered => SMPL_RECOVERED,
+        _ => 0,
+    };
+    let i = items.len();
+    items.push(Item { kind, flags, depth, span, descendants: 0, number, text });
+    match tok {
+        Token::Group(group) => for tok in &group.tokens {
+            flatten(&tok.value, tok.span, depth + 1, items);
+        },
+        // The token after the prefix has no span of its own, so it gets the immediate's
+        Token::Immediate(tok) => flatten(tok, span, depth + 1, items),
+        _ => (),
+    }
+    items[i].descendants = items.len() - i - 1;
+}
+
+impl SmplLexResult {
+    fn new(toks : &[Spanned<Token>], diagnostics : Vec<Diagnostic>) -> Self {
+        let mut items = Vec::new();
+        for tok in toks {
+            flatten(&tok.value, tok.span, 0, &mut items);
+        }
+        let diagnostics = diagnostics.into_iter().map(|diag| {
+            let message = diag.message();
+            (diag, message)
+        }).collect();
+        Self { items, diagnostics }
+    }
+}
+
+/// Tokenizes the `len` bytes of UTF-8 at `src` with the default config, invalid sequences being
+/// replaced by U+FFFD. A null `src` or a `len` of 0 is an empty input.
+///
+/// # Safety
+///
+/// `src` must point to `len` readable bytes, unless it is null or `len` is 0.
+#[no_mangle]
+pub unsafe extern "C" fn smpl_lex(src : *const u8, len : usize) -> *mut SmplLexResult {
+    let bytes = if src.is_null() || len == 0 { &[][..] } else { core::slice::from_raw_parts(src, len) };
+    let code = String::from_utf8_lossy(bytes);
+    let (toks, diagnostics) = tokenize_spanned(&TokenizerConfig::default(), &code);
+    Box::into_raw(Box::new(SmplLexResult::new(&toks, diagnostics)))
+}
+
+/// Frees a result of `smpl_lex`, and the strings it points to. Freeing null does nothing.
+///
+/// # Safety
+///
+/// `result` must be null or come from `smpl_lex`, and not be freed already.
+#[no_mangle]
+pub unsafe extern "C" fn smpl_lex_free(result : *mut SmplLexResult) {
+    if !result.is_null() {
+        drop(Box::from_raw(result));
+    }
+}
+
+/// The number of tokens of the flattened tree.
+///
+/// # Safety
+///
+/// `result` must be a live result of `smpl_lex`.
+#[no_mangle]
+pub unsafe extern "C" fn smpl_lex_token_count(result : *const SmplLexResult) -> usize {
+    let result = &*result;
+    result.items.len()
+}
+
+/// Writes the token at `index` to `out`, returning false if there are fewer tokens.
+///
+/// # Safety
+///
+/// `result` must be a live result of `smpl_lex`, and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn smpl_lex_token(result : *const SmplLexResult, index : usize, out : *mut SmplToken) -> bool {
+    let result = &*result;
+    let Some(item) = result.items.get(index) else { return false };
+    out.write(SmplToken {
+        kind: item.kind,
+        flags: item.flags,
+        depth: item.depth,
+        start: item.span.start,
+        end: item.span.end,
+        descendants: item.descendants,
+        number: item.number,
+        text: item.text.as_ptr(),
+        text_len: item.text.len(),
+    });
+    true
+}
+
+/// The number of diagnostics.
+///
+/// # Safety
+///
+/// `result` must be a live result of `smpl_lex`.
+#[no_mangle]
+pub unsafe extern "C" fn smpl_lex_diagnostic_count(result : *const SmplLexResult) -> usize {
+    let result = &*result;
+    result.diagnostics.len()
+}
+
+/// Writes the diagnostic at `index` to `out`, returning false if there are fewer diagnostics.
+///
+/// # Safety
+///
+/// `result` must be a live result of `smpl_lex`, and `out` writable.
+#[no_mangle]
+pub unsafe extern "C" fn smpl_lex_diagnostic(result : *const SmplLexResult, index : usize, out : *mut SmplDiagnostic) -> bool {
+    let result = &*result;
+    let Some((diag, message)) = result.diagnostics.get(index) else { return false };
+    let (code, code_len) = diag.code().map_or((ptr::null(), 0), |code| (code.as_ptr(), code.len()));
+    out.write(SmplDiagnostic {
+        severity: match diag.severity {
+            Severity::Error => 0,
+            Severity::Warning => 1,
+            Severity::Note => 2,
+        },
+        start: diag.span.start,
+        end: diag.span.end,
+        code,
+        code_len,
+        message: message.as_ptr(),
+        message_len: message.len(),
+    });
+    true
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    unsafe fn text<'a>(ptr : *const u8, len : usize) -> &'a str {
+        core::str::from_utf8(core::slice::from_raw_parts(ptr, len)).unwrap()
+    }
+
+    #[test]
+    fn lex() {
+        let code = "mov (a, 'b') \"x";
+        unsafe {
+            let result = smpl_lex(code.as_ptr(), code.len());
+            let mut tok = core::mem::MaybeUninit::uninit();
+            let mut found = Vec::new();
+            for i in 0..smpl_lex_token_count(result) {
+                assert!(smpl_lex_token(result, i, tok.as_mut_ptr()));
+                let tok = tok.assume_init();
+                found.push((tok.kind, tok.depth, tok.descendants, tok.number, text(tok.text, tok.text_len)));
+            }
+            assert!(!smpl_lex_token(result, found.len(), tok.as_mut_ptr()));
+            assert_eq!(found, [
+                (SMPL_IDENT, 0, 0, 0, "mov"),
+                (SMPL_GROUP, 0, 3, '(' as i64, ""),
+                (SMPL_IDENT, 1, 0, 0, "a"),
+                (SMPL_PUNCT, 1, 0, ',' as i64, ","),
+                (SMPL_CHAR, 1, 0, 'b' as i64, "b"),
+                (SMPL_STRING, 0, 0, 0, "x"),
+            ]);
+
+            assert_eq!(smpl_lex_diagnostic_count(result), 1);
+            let mut diag = core::mem::MaybeUninit::uninit();
+            assert!(smpl_lex_diagnostic(result, 0, diag.as_mut_ptr()));
+            let diag = diag.assume_init();
+            assert_eq!((diag.severity, text(diag.code, diag.code_len)), (0, "unterminated-string"));
+            assert_eq!(text(diag.message, diag.message_len), "unterminated string literal");
+            smpl_lex_free(result);
+        }
+    }
+
+    #[test]
+    fn null() {
+        unsafe {
+            let result = smpl_lex(core::ptr::null(), 0);
+            assert_eq!((smpl_lex_token_count(result), smpl_lex_diagnostic_count(result)), (0, 0));
+            smpl_lex_free(result);
+            smpl_lex_free(core::ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn header() {
+        let header = include_str!("../include/smpl_parser.h");
+        for name in ["smpl_lex", "smpl_lex_free", "smpl_lex_token_count", "smpl_lex_token", "smpl_lex_diagnostic_count", "smpl_lex_diagnostic"] {
+            assert!([" ", "*"].iter().any(|before| header.contains(&format!("{before}{name}("))), "{name} is not declared");
+        }
+        for (name, value) in [("SMPL_IDENT", SMPL_IDENT), ("SMPL_GROUP", SMPL_GROUP), ("SMPL_IMMEDIATE", SMPL_IMMEDIATE), ("SMPL_DOC_COMMENT", SMPL_DOC_COMMENT)] {
+            assert!(header.contains(&format!("{name} = {value}")), "{name} is not declared");
+        }
+    }
+}
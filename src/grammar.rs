@@ -0,0 +1,353 @@
+//! The `grammar!` macro, generating `Parse` implementations from BNF-like productions.
+
+use crate::prelude::*;
+use crate::{Diagnostic, DiagnosticKind, TokenScanner};
+
+type PResult<T> = Result<T, Box<Diagnostic>>;
+
+/// Implements `Parse` for types from productions written roughly in BNF:
+///
+/// ```
+/// # use smpl_parser::{Parse, TokenScanner, TokenStr, TokenizerConfig, grammar, tokenize_spanned};
+/// #[derive(Debug, PartialEq)]
+/// enum Operand {
+///     Reg(TokenStr),
+///     Imm(i64),
+///     Mem(TokenStr),
+/// }
+///
+/// struct Instr {
+///     mnemonic : TokenStr,
+///     operands : Vec<Operand>,
+/// }
+///
+/// grammar! {
+///     Instr = mnemonic:ident operands:Operand % ',' => Instr { mnemonic, operands };
+///     Operand = {
+///         r:reg => Operand::Reg(r),
+///         '#' n:num => Operand::Imm(n),
+///         bracket(base:reg) => Operand::Mem(base),
+///     }
+/// }
+///
+/// let config = TokenizerConfig { registers: vec!["r0..r15".to_string()], ..Default::default() };
+/// let (toks, _) = tokenize_spanned(&config, "ldr r0, [r1]");
+/// let instr : Instr = TokenScanner::new(&toks).parse().unwrap();
+/// assert_eq!(instr.operands, [Operand::Reg("r0".into()), Operand::Mem("r1".into())]);
+/// ```
+///
+/// A production is `Type = items => value;`, or `Type = { items => value, .. }` to try
+/// alternatives in order, backtracking over the failed ones. Items are `ident`, `label`,
+/// `directive`, `reg`, `str`, `num` and `char` tokens, a char literal for that punctuation or a
+/// string literal for that keyword, a type implementing `Parse` (written `<Type>` unless it is
+/// a single identifier), or `paren(items)`, `bracket(items)` or `brace(items)` for a group that
+/// must contain exactly `items`. `name:item` binds the value of an item for `value`; items
+/// other than groups can be followed by `?`, `*`, `+` or `% ','` for an optional item, zero or
+/// more, one or more, or zero or more separated by a punctuation with no trailing one.
+///
+/// When all alternatives fail, the error is that of the one that got furthest.
+#[macro_export]
+macro_rules! grammar {
+    () => {};
+    ($ty:ty = { $($arms:tt)* } $($rest:tt)*) => {
+        $crate::__grammar!(@arms $ty [] [] $($arms)*);
+        $crate::grammar!($($rest)*);
+    };
+    ($ty:ty = $($rest:tt)*) => {
+        $crate::__grammar!(@single $ty [] $($rest)*);
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __grammar {
+    (@single $ty:ty [$($items:tt)*] => $value:expr ; $($rest:tt)*) => {
+        $crate::__grammar!(@impl $ty [[$($items)*] ($value)]);
+        $crate::grammar!($($rest)*);
+    };
+    (@single $ty:ty [$($items:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::__grammar!(@single $ty [$($items)* $next] $($rest)*);
+    };
+
+    (@arms $ty:ty [$($done:tt)*] []) => {
+        $crate::__grammar!(@impl $ty $($done)*);
+    };
+    (@arms $ty:ty [$($done:tt)*] [$($items:tt)*] => $value:expr $(, $($rest:tt)*)?) => {
+        $crate::__grammar!(@arms $ty [$($done)* [[$($items)*] ($value)]] [] $($($rest)*)?);
+    };
+    (@arms $ty:ty [$($done:tt)*] [$($items:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::__grammar!(@arms $ty [$($done)*] [$($items)* $next] $($rest)*);
+    };
+
+    (@impl $ty:ty $([[$($items:tt)*] ($value:expr)])+) => {
+        impl $crate::Parse for $ty {
+            fn parse(input : &mut $crate::TokenScanner<'_>) -> ::core::result::Result<Self, $crate::__Box<$crate::Diagnostic>> {
+                let rule = ::core::stringify!($ty);
+                $crate::__trace_enter(input, rule);
+                let mut error = ::core::option::Option::None;
+                $(
+                    let mut fork = *input;
+                    match $crate::__grammar_one(&mut fork, |input| {
+                        $crate::__grammar!(@seq input $($items)*);
+                        ::core::result::Result::Ok($value)
+                    }) {
+                        ::core::result::Result::Ok(value) => {
+                            *input = fork;
+                            return $crate::__trace_exit(input, rule, ::core::result::Result::Ok(value));
+                        },
+                        ::core::result::Result::Err(err) => {
+                            $crate::__trace_backtrack(input, &err);
+                            error = ::core::option::Option::Some($crate::__grammar_furthest(error, err));
+                        },
+                    }
+                )+
+                $crate::__trace_exit(input, rule, ::core::result::Result::Err(error.unwrap()))
+            }
+        }
+    };
+
+    // Items
+    (@seq $input:ident) => {};
+    (@seq $input:ident paren($($inner:tt)*) $($rest:tt)*) => {
+        $crate::__grammar!(@group $input Paren [$($inner)*] $($rest)*);
+    };
+    (@seq $input:ident bracket($($inner:tt)*) $($rest:tt)*) => {
+        $crate::__grammar!(@group $input Bracket [$($inner)*] $($rest)*);
+    };
+    (@seq $input:ident brace($($inner:tt)*) $($rest:tt)*) => {
+        $crate::__grammar!(@group $input Brace [$($inner)*] $($rest)*);
+    };
+    (@seq $input:ident $b:ident : $($rest:tt)*) => {
+        $crate::__grammar!(@item $input [$b] $($rest)*);
+    };
+    (@seq $input:ident $($rest:tt)*) => {
+        $crate::__grammar!(@item $input [_] $($rest)*);
+    };
+
+    // Bindings in the group stay in scope: `inner` is hygienic, user names aren't
+    (@group $input:ident $delimiter:ident [$($inner:tt)*] $($rest:tt)*) => {
+        let inner = &mut $input.expect_group($crate::Delimiter::$delimiter)?;
+        $crate::__grammar!(@seq inner $($inner)*);
+        inner.expect_end()?;
+        $crate::__grammar!(@seq $input $($rest)*);
+    };
+
+    (@item $input:ident $b:tt ident $($rest:tt)*) => {
+        $crate::__grammar!(@repeat $input $b (|input| $crate::TokenScanner::expect_ident(input)) $($rest)*);
+    };
+    (@item $input:ident $b:tt label $($rest:tt)*) => {
+        $crate::__grammar!(@repeat $input $b (|input| $crate::TokenScanner::expect_label(input)) $($rest)*);
+    };
+    (@item $input:ident $b:tt directive $($rest:tt)*) => {
+        $crate::__grammar!(@repeat $input $b (|input| $crate::TokenScanner::expect_directive(input)) $($rest)*);
+    };
+    (@item $input:ident $b:tt reg $($rest:tt)*) => {
+        $crate::__grammar!(@repeat $input $b (|input| $crate::TokenScanner::expect_register(input)) $($rest)*);
+    };
+    (@item $input:ident $b:tt str $($rest:tt)*) => {
+        $crate::__grammar!(@repeat $input $b (|input| $crate::TokenScanner::expect_string(input)) $($rest)*);
+    };
+    (@item $input:ident $b:tt num $($rest:tt)*) => {
+        $crate::__grammar!(@repeat $input $b (|input| $crate::TokenScanner::expect_number(input)) $($rest)*);
+    };
+    (@item $input:ident $b:tt char $($rest:tt)*) => {
+        $crate::__grammar!(@repeat $input $b (|input| $crate::TokenScanner::expect_char(input)) $($rest)*);
+    };
+    (@item $input:ident $b:tt $l:literal $($rest:tt)*) => {
+        $crate::__grammar!(@repeat $input $b (|input| $crate::__GrammarLiteral::expect($l, input)) $($rest)*);
+    };
+    (@item $input:ident $b:tt < $t:ty > $($rest:tt)*) => {
+        $crate::__grammar!(@repeat $input $b (<$t as $crate::Parse>::parse) $($rest)*);
+    };
+    (@item $input:ident $b:tt $t:ident $($rest:tt)*) => {
+        $crate::__grammar!(@repeat $input $b (<$t as $crate::Parse>::parse) $($rest)*);
+    };
+
+    (@repeat $input:ident [$($b:tt)*] ($($p:tt)*) ? $($rest:tt)*) => {
+        let $($b)* = $crate::__grammar_optional($input, $($p)*)?;
+        $crate::__grammar!(@seq $input $($rest)*);
+    };
+    (@repeat $input:ident [$($b:tt)*] ($($p:tt)*) * $($rest:tt)*) => {
+        let $($b)* = $crate::__grammar_many($input, 0, $($p)*)?;
+        $crate::__grammar!(@seq $input $($rest)*);
+    };
+    (@repeat $input:ident [$($b:tt)*] ($($p:tt)*) + $($rest:tt)*) => {
+        let $($b)* = $crate::__grammar_many($input, 1, $($p)*)?;
+        $crate::__grammar!(@seq $input $($rest)*);
+    };
+    (@repeat $input:ident [$($b:tt)*] ($($p:tt)*) % $sep:literal $($rest:tt)*) => {
+        let $($b)* = $crate::__grammar_separated($input, $sep, $($p)*)?;
+        $crate::__grammar!(@seq $input $($rest)*);
+    };
+    (@repeat $input:ident [$($b:tt)*] ($($p:tt)*) $($rest:tt)*) => {
+        let $($b)* = $crate::__grammar_one($input, $($p)*)?;
+        $crate::__grammar!(@seq $input $($rest)*);
+    };
+}
+
+/// The literals `grammar!` accepts: chars for punctuation and strings for keywords.
+#[doc(hidden)]
+pub trait GrammarLiteral {
+    fn expect(self, input : &mut TokenScanner<'_>) -> PResult<()>;
+}
+
+impl GrammarLiteral for char {
+    fn expect(self, input : &mut TokenScanner<'_>) -> PResult<()> {
+        input.expect_punct(self)
+    }
+}
+
+impl GrammarLiteral for &str {
+    fn expect(self, input : &mut TokenScanner<'_>) -> PResult<()> {
+        input.expect_keyword(self)
+    }
+}
+
+#[doc(hidden)]
+pub fn one<'a, T>(input : &mut TokenScanner<'a>, mut p : impl FnMut(&mut TokenScanner<'a>) -> PResult<T>) -> PResult<T> {
+    p(input)
+}
+
+/// Whether `err` happened after the first token left in `input`, which makes the item that
+/// failed with it fail repetitions and options instead of just being absent.
+fn committed(input : &TokenScanner<'_>, err : &Diagnostic) -> bool {
+    if matches!(err.kind, DiagnosticKind::LimitExceeded(_)) {
+        return true;
+    }
+    let mut input = *input;
+    input.skip_trivia();
+    err.span.start > input.span().start
+}
+
+#[doc(hidden)]
+pub fn optional<'a, T>(input : &mut TokenScanner<'a>, mut p : impl FnMut(&mut TokenScanner<'a>) -> PResult<T>) -> PResult<Option<T>> {
+    let mut fork = *input;
+    match p(&mut fork) {
+        Ok(value) => {
+            *input = fork;
+            Ok(Some(value))
+        },
+        Err(err) if committed(input, &err) => Err(err),
+        Err(_) => Ok(None),
+    }
+}
+
+#[doc(hidden)]
+pub fn many<'a, T>(input : &mut TokenScanner<'a>, min : usize, mut p : impl FnMut(&mut TokenScanner<'a>) -> PResult<T>) -> PResult<Vec<T>> {
+    let mut values = Vec::new();
+    loop {
+        let mut fork = *input;
+        match p(&mut fork) {
+            Ok(value) => {
+                let progress = fork.pos > input.pos;
+                values.push(value);
+                *input = fork;
+                if !progress {
+                    break;
+                }
+            },
+            Err(err) if values.len() < min || committed(input, &err) => return Err(err),
+            Err(_) => break,
+        }
+    }
+    Ok(values)
+}
+
+#[doc(hidden)]
+pub fn separated<'a, T>(input : &mut TokenScanner<'a>, sep : char, mut p : impl FnMut(&mut TokenScanner<'a>) -> PResult<T>) -> PResult<Vec<T>> {
+    let Some(first) = optional(input, &mut p)? else { return Ok(Vec::new()) };
+    let mut values = vec![first];
+    while optional(input, |input| input.expect_punct(sep))?.is_some() {
+        values.push(p(input)?);
+    }
+    Ok(values)
+}
+
+/// The error of the alternative that got furthest, merging what was expected on a tie.
+#[doc(hidden)]
+pub fn furthest(prev : Option<Box<Diagnostic>>, err : Box<Diagnostic>) -> Box<Diagnostic> {
+    let Some(mut prev) = prev else { return err };
+    if matches!(prev.kind, DiagnosticKind::LimitExceeded(_)) {
+        return prev;
+    }
+    if err.span.start > prev.span.start || matches!(err.kind, DiagnosticKind::LimitExceeded(_)) {
+        return err;
+    }
+    if err.span == prev.span {
+        if let (DiagnosticKind::ExpectedToken { expected, .. }, DiagnosticKind::ExpectedToken { expected: other, .. }) = (&mut prev.kind, &err.kind) {
+            // Unions the expected sets, so alternatives expecting the same token don't repeat it
+            for other in other.split(" or ") {
+                if !expected.split(" or ").any(|e| e == other) {
+                    *expected = format!("{expected} or {other}");
+                }
+            }
+        }
+    }
+    prev
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Spanned, TokenStr, TokenizerConfig, tokenize_spanned};
+
+    #[derive(Debug, PartialEq)]
+    enum Operand {
+        Reg(TokenStr),
+        Imm(i64),
+        Mem(TokenStr, Option<i64>),
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Instr {
+        mnemonic : Spanned<TokenStr>,
+        operands : Vec<Operand>,
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Program(Vec<Instr>);
+
+    struct Name(TokenStr);
+
+    struct Offset(i64);
+
+    grammar! {
+        Name = name:ident => Name(name);
+        Offset = ',' n:num => Offset(n);
+        Instr = mnemonic:<Spanned<Name>> operands:Operand % ',' ';' => Instr { mnemonic: mnemonic.map(|n| n.0), operands };
+        Operand = {
+            r:reg => Operand::Reg(r),
+            '#' n:num => Operand::Imm(n),
+            bracket(base:reg offset:Offset?) => Operand::Mem(base, offset.map(|o| o.0)),
+        }
+        Program = "prog" brace(instrs:Instr*) => Program(instrs);
+    }
+
+    fn parse<T : crate::Parse>(code : &str) -> PResult<T> {
+        let config = TokenizerConfig { registers: vec!["r0..r7".to_string()], ..Default::default() };
+        let (toks, _) = tokenize_spanned(&config, code);
+        let mut input = TokenScanner::new(&toks);
+        let value = input.parse()?;
+        input.expect_end()?;
+        Ok(value)
+    }
+
+    #[test]
+    fn grammar() {
+        assert_eq!(parse::<Instr>("mov r1, #4;").unwrap(), Instr {
+            mnemonic: Spanned::new("mov".into(), crate::Span::new(0, 3)),
+            operands: vec![Operand::Reg("r1".into()), Operand::Imm(4)],
+        });
+        let program = parse::<Program>("prog { nop; ld r0, [r7] /* load */; st [r1, 8], r2; }").unwrap();
+        assert_eq!(program.0[1].operands, [Operand::Reg("r0".into()), Operand::Mem("r7".into(), None)]);
+        assert_eq!(program.0[2].operands, [Operand::Mem("r1".into(), Some(8)), Operand::Reg("r2".into())]);
+        assert!(parse::<Program>("prog {}").unwrap().0.is_empty());
+
+        let message = |code : &str| parse::<Program>(code).map(|_| ()).unwrap_err().message();
+        assert_eq!(message("prog { add r1, x; }"), "expected a register or `#` or `[`, found identifier `x`");
+        assert_eq!(message("prog { add r1 r2; }"), "expected `;`, found register `r2`");
+        assert_eq!(message("prog { nop }"), "expected `;`, found `}`");
+        assert_eq!(message("prog {} x"), "expected the end of input, found identifier `x`");
+        assert_eq!(message("{}"), "expected `prog`, found a group `{...}`");
+    }
+}
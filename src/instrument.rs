@@ -0,0 +1,88 @@
+//! Reports of what the tokenizing and parsing entry points did, with the `trace` feature, for
+//! services to forward to their own observability, e.g. as `tracing` events.
+
+#[cfg(feature = "trace")]
+use std::sync::RwLock;
+#[cfg(feature = "trace")]
+use std::time::{Duration, Instant};
+
+use crate::Diagnostic;
+
+/// What one call of an entry point did.
+#[cfg(feature = "trace")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// The entry point, e.g. "tokenize".
+    pub name : &'static str,
+    pub tokens : usize,
+    pub errors : usize,
+    pub duration : Duration,
+}
+
+#[cfg(feature = "trace")]
+static SUBSCRIBER : RwLock<Option<fn(&TraceRecord)>> = RwLock::new(None);
+
+/// Has `subscriber` receive a record of every call of `tokenize_spanned` and the functions
+/// built on it, `tokenize_into`, `TokenScanner::scan`, `Lexer::lex` and `Grammar::parse_tokens`,
+/// from any thread; `None` stops reporting.
+#[cfg(feature = "trace")]
+pub fn set_trace_subscriber(subscriber : Option<fn(&TraceRecord)>) {
+    *SUBSCRIBER.write().unwrap_or_else(|err| err.into_inner()) = subscriber;
+}
+
+pub(crate) fn count_errors(diagnostics : &[Diagnostic]) -> usize {
+    diagnostics.iter().filter(|diag| diag.is_error()).count()
+}
+
+/// A call of an entry point being timed, reported by `finish` when a subscriber is set.
+pub(crate) struct Measure {
+    #[cfg(feature = "trace")]
+    started : Option<Started>,
+}
+
+#[cfg(feature = "trace")]
+struct Started {
+    subscriber : fn(&TraceRecord),
+    name : &'static str,
+    at : Instant,
+}
+
+pub(crate) fn start(_name : &'static str) -> Measure {
+    #[cfg(feature = "trace")]
+    {
+        let subscriber = *SUBSCRIBER.read().unwrap_or_else(|err| err.into_inner());
+        Measure { started: subscriber.map(|subscriber| Started { subscriber, name: _name, at: Instant::now() }) }
+    }
+    #[cfg(not(feature = "trace"))]
+    Measure {}
+}
+
+impl Measure {
+    pub(crate) fn finish(self, _tokens : usize, _errors : usize) {
+        #[cfg(feature = "trace")]
+        if let Some(Started { subscriber, name, at }) = self.started {
+            subscriber(&TraceRecord { name, tokens: _tokens, errors: _errors, duration: at.elapsed() });
+        }
+    }
+}
+
+#[cfg(all(test, feature = "trace"))]
+mod test {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::{TokenizerConfig, tokenize_spanned};
+
+    static RECORDS : Mutex<Vec<TraceRecord>> = Mutex::new(Vec::new());
+
+    #[test]
+    fn records() {
+        set_trace_subscriber(Some(|record| RECORDS.lock().unwrap().push(record.clone())));
+        tokenize_spanned(&TokenizerConfig::default(), "a b c d e f g \"h");
+        set_trace_subscriber(None);
+
+        let records = RECORDS.lock().unwrap();
+        let record = records.iter().find(|record| record.tokens == 8).unwrap();
+        assert_eq!((record.name, record.errors), ("tokenize", 1));
+    }
+}
@@ -0,0 +1,199 @@
+//! Hash-consing of token subtrees, so that the groups a generated or heavily macro-expanded
+//! stream repeats thousands of times are stored once and compared by pointer.
+
+use alloc::collections::BTreeMap;
+use alloc::rc::Rc;
+use core::hash::{Hash, Hasher};
+
+use crate::cache::Fnv;
+use crate::prelude::*;
+use crate::{Delimiter, Group, Span, Spanned, Token};
+
+/// A token of an interned tree: a group shared with every equal one, or any other token.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SharedToken {
+    Token(Token),
+    Group(SharedGroup),
+}
+
+/// Where a token of an interned group is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeSpan {
+    /// Offsets from the start of the group, for a token in the group's file and expansion, as
+    /// most are, so that equal groups anywhere in the source are shared.
+    Relative { start : usize, end : usize },
+
+    /// The span of a token from elsewhere, e.g. one a macro expanded to, which keeps the span of
+    /// the definition.
+    Absolute(Span),
+}
+
+impl NodeSpan {
+    /// `span`, of a token in a group at `group`.
+    fn new(span : Span, group : Span) -> Self {
+        match span.file == group.file && span.expansion == group.expansion && span.start >= group.start {
+            true => NodeSpan::Relative { start: span.start - group.start, end: span.end - group.start },
+            false => NodeSpan::Absolute(span),
+        }
+    }
+
+    /// The span of the token, in a group at `group`.
+    pub fn resolve(self, group : Span) -> Span {
+        match self {
+            NodeSpan::Relative { start, end } => Span { start: group.start + start, end: group.start + end, ..group },
+            NodeSpan::Absolute(span) => span,
+        }
+    }
+}
+
+/// The contents of an interned group, which are shared by equal groups anywhere in the source.
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub struct GroupNode {
+    pub delimiter : Delimiter,
+    pub tokens : Vec<(SharedToken, NodeSpan)>,
+    pub recovered : bool,
+}
+
+/// A shared group, equal to another only if it is the same one, which interned groups with the
+/// same contents are.
+#[derive(Debug, Clone)]
+pub struct SharedGroup(Rc<GroupNode>);
+
+impl SharedGroup {
+    pub fn node(&self) -> &GroupNode {
+        &self.0
+    }
+}
+
+impl PartialEq for SharedGroup {
+    fn eq(&self, other : &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for SharedGroup {}
+
+impl Hash for SharedGroup {
+    fn hash<H : Hasher>(&self, h : &mut H) {
+        Rc::as_ptr(&self.0).hash(h)
+    }
+}
+
+/// Interns the groups of token trees, each group once however many trees it occurs in.
+///
+/// Interned groups are kept until the interner is cleared or dropped, even if no tree uses
+/// them anymore.
+#[derive(Debug, Default)]
+pub struct SubtreeInterner {
+    groups : BTreeMap<u64, Vec<SharedGroup>>,
+    hits : usize,
+}
+
+impl SubtreeInterner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `toks` with their groups, at any depth, replaced by shared ones.
+    pub fn intern(&mut self, toks : &[Spanned<Token>]) -> Vec<Spanned<SharedToken>> {
+        toks.iter().map(|tok| Spanned::new(self.intern_token(tok), tok.span)).collect()
+    }
+
+    fn intern_token(&mut self, tok : &Spanned<Token>) -> SharedToken {
+        let Token::Group(group) = &tok.value else { return SharedToken::Token(tok.value.clone()) };
+        // Inner groups are interned first, so that comparing the tokens of groups is shallow
+        let tokens = group.tokens.iter().map(|inner| (self.intern_token(inner), NodeSpan::new(inner.span, tok.span))).collect();
+        let node = GroupNode { delimiter: group.delimiter, tokens, recovered: group.recovered };
+
+        let mut h = Fnv::new();
+        node.hash(&mut h);
+        let bucket = self.groups.entry(h.finish()).or_default();
+        if let Some(shared) = bucket.iter().find(|shared| *shared.node() == node) {
+            self.hits += 1;
+            return SharedToken::Group(shared.clone());
+        }
+        let shared = SharedGroup(Rc::new(node));
+        bucket.push(shared.clone());
+        SharedToken::Group(shared)
+    }
+
+    /// The number of distinct groups interned.
+    pub fn len(&self) -> usize {
+        self.groups.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// How many groups were found already interned.
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    pub fn clear(&mut self) {
+        self.groups.clear();
+        self.hits = 0;
+    }
+}
+
+/// Rebuilds the token tree of `toks` returned by `SubtreeInterner::intern`, copying groups.
+pub fn unshare(toks : &[Spanned<SharedToken>]) -> Vec<Spanned<Token>> {
+    toks.iter().map(|tok| Spanned::new(unshare_token(&tok.value, tok.span), tok.span)).collect()
+}
+
+/// The token of `tok`, at `span`.
+fn unshare_token(tok : &SharedToken, span : Span) -> Token {
+    match tok {
+        SharedToken::Token(tok) => tok.clone(),
+        SharedToken::Group(group) => {
+            let node = group.node();
+            let tokens = node.tokens.iter().map(|(tok, inner)| {
+                let inner = inner.resolve(span);
+                Spanned::new(unshare_token(tok, inner), inner)
+            }).collect();
+            Token::from(Group { delimiter: node.delimiter, tokens, recovered: node.recovered })
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::token::tokenize_file;
+    use crate::{FileId, MacroExpander, SourceMap};
+
+    #[test]
+    fn interning() {
+        let (toks, _) = tokenize_file(&Default::default(), FileId(2), "mov [r0, (4)]\nmov  [r0, (4)]\nmov [r1, (4)]");
+        let mut interner = SubtreeInterner::new();
+        let shared = interner.intern(&toks);
+        // `[r0, (4)]` and `(4)` are shared, `[r1, (4)]` isn't
+        assert_eq!((interner.len(), interner.hits()), (3, 3));
+        assert_eq!(shared[1].value, shared[3].value);
+        assert_ne!(shared[1].value, shared[5].value);
+        let SharedToken::Group(group) = &shared[5].value else { panic!() };
+        assert_eq!(group.node().tokens[2].1, NodeSpan::Relative { start: 5, end: 8 });
+
+        assert_eq!(unshare(&shared), toks);
+        assert_eq!(interner.intern(&toks), shared);
+        interner.clear();
+        assert!(interner.is_empty());
+    }
+
+    #[test]
+    fn expanded() {
+        let mut macros = MacroExpander::new();
+        macros.define("REG", vec![], tokenize_file(&Default::default(), FileId(1), "r0").0);
+        let (toks, _) = tokenize_file(&Default::default(), FileId(2), "mov [REG, 4]\nmov [REG, 4]");
+        let (toks, _) = macros.expand(&mut SourceMap::new(), toks);
+
+        // `r0` keeps the span of the definition, each expansion its own
+        let mut interner = SubtreeInterner::new();
+        let shared = interner.intern(&toks);
+        let SharedToken::Group(group) = &shared[1].value else { panic!() };
+        assert!(matches!(group.node().tokens[0].1, NodeSpan::Absolute(span) if span.file == FileId(1) && span.range() == (0..2)));
+        assert_eq!(group.node().tokens[2].1, NodeSpan::Relative { start: 6, end: 7 });
+        assert_eq!(unshare(&shared), toks);
+    }
+}
@@ -0,0 +1,174 @@
+//! Machine-readable JSON output for diagnostics and tokens, with the `serde` feature.
+//!
+//! Each diagnostic is an object of the form
+//! `{"code":…,"severity":"error","message":…,"span":{"start":0,"end":3},"suggestions":[{"span":…,"replacement":…}],"notes":[…]}`,
+//! where `code` is `null` when the diagnostic has none and `notes` are diagnostics themselves.
+//!
+//! Each token is an object of the form `{"kind":"ident","value":"mov","span":{"start":0,"end":3}}`,
+//! where `value` is a string, or a number for numbers, and is left out for newlines and the end
+//! of input. A group has `delimiter` (its opening char), `recovered` and `tokens` instead, and
+//! an immediate has `token`, the token after its prefix without a span.
+
+use core::fmt::Write;
+
+use crate::prelude::*;
+use crate::{DefaultFormatter, Diagnostic, Edit, MessageFormatter, Span, Spanned, Token};
+
+fn write_str(out : &mut String, s : &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn write_span(out : &mut String, span : Span) {
+    write!(out, r#"{{"start":{},"end":{}}}"#, span.start, span.end).unwrap();
+}
+
+fn write_edit(out : &mut String, edit : &Edit) {
+    out.push_str(r#"{"span":"#);
+    write_span(out, edit.span);
+    out.push_str(r#","replacement":"#);
+    write_str(out, &edit.replacement);
+    out.push('}');
+}
+
+fn write_list<T>(out : &mut String, items : &[T], write_item : impl Fn(&mut String, &T)) {
+    out.push('[');
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_item(out, item);
+    }
+    out.push(']');
+}
+
+fn write_diagnostic(out : &mut String, diagnostic : &Diagnostic, formatter : &dyn MessageFormatter) {
+    out.push_str(r#"{"code":"#);
+    match diagnostic.code() {
+        Some(code) => write_str(out, code),
+        None => out.push_str("null"),
+    }
+    out.push_str(r#","severity":"#);
+    write_str(out, &diagnostic.severity.to_string());
+    out.push_str(r#","message":"#);
+    write_str(out, &diagnostic.message_with(formatter));
+    out.push_str(r#","span":"#);
+    write_span(out, diagnostic.span);
+    out.push_str(r#","suggestions":"#);
+    write_list(out, &diagnostic.suggestions, write_edit);
+    out.push_str(r#","notes":"#);
+    write_list(out, &diagnostic.notes, |out, note| write_diagnostic(out, note, formatter));
+    out.push('}');
+}
+
+impl Diagnostic {
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        write_diagnostic(&mut out, self, &DefaultFormatter);
+        out
+    }
+}
+
+/// Serializes `diagnostics` as a JSON array.
+pub fn to_json(diagnostics : &[Diagnostic]) -> String {
+    to_json_with(diagnostics, &DefaultFormatter)
+}
+
+/// Like `to_json`, with messages produced by `formatter`.
+pub fn to_json_with(diagnostics : &[Diagnostic], formatter : &dyn MessageFormatter) -> String {
+    let mut out = String::new();
+    write_list(&mut out, diagnostics, |out, diagnostic| write_diagnostic(out, diagnostic, formatter));
+    out
+}
+
+fn write_token(out : &mut String, tok : &Token, span : Option<Span>) {
+    let value = match tok {
+        Token::Ident(s) | Token::Comment(s) | Token::DocComment(s) | Token::String(s) | Token::Label(s) | Token::Directive(s) | Token::Register(s) | Token::Keyword(s) | Token::Op(s) => Some(s.to_string()),
+        Token::Punct(c) | Token::Char(c) => Some(c.to_string()),
+        _ => None,
+    };
+    out.push_str(r#"{"kind":"#);
+    write_str(out, tok.kind_name());
+    if let Some(value) = value {
+        out.push_str(r#","value":"#);
+        write_str(out, &value);
+    }
+    match tok {
+        Token::Number(n) => write!(out, r#","value":{n}"#).unwrap(),
+        // JSON has no NaN or infinities
+        Token::Float(f) if f.0.is_finite() => write!(out, r#","value":{:?}"#, f.0).unwrap(),
+        Token::Group(group) => {
+            out.push_str(r#","delimiter":"#);
+            write_str(out, &group.delimiter.open().to_string());
+            write!(out, r#","recovered":{},"tokens":"#, group.recovered).unwrap();
+            write_list(out, &group.tokens, |out, tok| write_token(out, &tok.value, Some(tok.span)));
+        },
+        Token::Immediate(tok) => {
+            out.push_str(r#","token":"#);
+            write_token(out, tok, None);
+        },
+        _ => (),
+    }
+    if let Some(span) = span {
+        out.push_str(r#","span":"#);
+        write_span(out, span);
+    }
+    out.push('}');
+}
+
+/// Serializes `toks` as a JSON array, e.g. for a web playground to highlight.
+pub fn tokens_to_json(toks : &[Spanned<Token>]) -> String {
+    let mut out = String::new();
+    write_list(&mut out, toks, |out, tok| write_token(out, &tok.value, Some(tok.span)));
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{TokenizerConfig, tokenize_spanned, tokenize_with_diagnostics};
+
+    #[test]
+    fn diagnostics() {
+        let (_, diags) = tokenize_with_diagnostics(r#"007 "\q""#);
+        assert_eq!(to_json(&diags), concat!(
+            r#"[{"code":"leading-zeros","severity":"warning","message":"number has leading zeros","#,
+            r#""span":{"start":0,"end":3},"suggestions":[{"span":{"start":0,"end":2},"replacement":""}],"notes":[]},"#,
+            r#"{"code":"unknown-escape","severity":"error","message":"unknown escape sequence `\\q`","#,
+            r#""span":{"start":5,"end":7},"suggestions":[{"span":{"start":5,"end":6},"replacement":""}],"notes":[]}]"#,
+        ));
+    }
+
+    #[test]
+    fn escaping() {
+        let diagnostic = Diagnostic::note(Span::empty(0), "\"tab\"\t\u{1}");
+        assert_eq!(diagnostic.to_json(), concat!(
+            r#"{"code":null,"severity":"note","message":"\"tab\"\t\u0001","#,
+            r#""span":{"start":0,"end":0},"suggestions":[],"notes":[]}"#,
+        ));
+    }
+
+    #[test]
+    fn tokens() {
+        let config = TokenizerConfig { immediate_prefixes: vec!['#'], ..Default::default() };
+        let (toks, _) = tokenize_spanned(&config, r#"mov [#-1, "a\"b"]"#);
+        assert_eq!(tokens_to_json(&toks), concat!(
+            r#"[{"kind":"ident","value":"mov","span":{"start":0,"end":3}},"#,
+            r#"{"kind":"group","delimiter":"[","recovered":false,"tokens":["#,
+            r#"{"kind":"immediate","token":{"kind":"number","value":-1},"span":{"start":5,"end":8}},"#,
+            r#"{"kind":"punct","value":",","span":{"start":8,"end":9}},"#,
+            r#"{"kind":"string","value":"a\"b","span":{"start":10,"end":16}}],"span":{"start":4,"end":17}}]"#,
+        ));
+    }
+}
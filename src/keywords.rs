@@ -0,0 +1,178 @@
+//! The `define_keywords!` macro, generating strongly typed keyword enums.
+
+use core::fmt;
+
+use crate::prelude::*;
+use crate::{Diagnostic, Token, TokenScanner, suggest};
+
+/// An enum of keywords, usually generated by `define_keywords!`.
+pub trait Keyword : Copy + 'static {
+    /// Every keyword, in order of declaration.
+    const ALL : &'static [Self];
+
+    fn as_str(self) -> &'static str;
+
+    /// The text of every keyword, e.g. for `Diagnostic::unknown_keyword`.
+    fn strs() -> impl Iterator<Item = &'static str> {
+        Self::ALL.iter().map(|kw| kw.as_str())
+    }
+}
+
+/// The error of parsing a string that isn't one of the keywords of `K`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseKeywordError {
+    pub found : String,
+
+    /// The keyword `found` is likely a typo of.
+    pub suggestion : Option<&'static str>,
+}
+
+impl ParseKeywordError {
+    pub fn new<K : Keyword>(found : &str) -> Self {
+        Self { found: found.to_string(), suggestion: suggest::did_you_mean(found, K::strs()) }
+    }
+}
+
+impl fmt::Display for ParseKeywordError {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown keyword `{}`", self.found)?;
+        match self.suggestion {
+            Some(keyword) => write!(f, ", did you mean `{keyword}`?"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl core::error::Error for ParseKeywordError {}
+
+impl TokenScanner<'_> {
+    /// Expects the keyword `kw`.
+    pub fn expect_kw<K : Keyword>(&mut self, kw : K) -> Result<(), Box<Diagnostic>> {
+        self.expect_keyword(kw.as_str())
+    }
+
+    /// Expects any keyword of `K`; for another identifier, the error suggests the likely one.
+    pub fn expect_any_kw<K : Keyword>(&mut self) -> Result<K, Box<Diagnostic>> {
+        self.skip_trivia();
+        let Some(tok) = self.peek() else { return Err(self.error("a keyword")) };
+        match &tok.value {
+            Token::Ident(name) => {
+                let kw = K::ALL.iter().find(|kw| kw.as_str() == &**name).copied();
+                let kw = kw.ok_or_else(|| Box::new(Diagnostic::unknown_keyword(tok.span, name, K::strs())))?;
+                self.pop();
+                Ok(kw)
+            },
+            _ => Err(self.error("a keyword")),
+        }
+    }
+}
+
+/// Parses any keyword of `K`, for the `Parse` implementations of `define_keywords!`.
+#[doc(hidden)]
+pub fn parse<K : Keyword>(input : &mut TokenScanner<'_>) -> Result<K, Box<Diagnostic>> {
+    input.expect_any_kw()
+}
+
+#[doc(hidden)]
+pub fn from_str<K : Keyword>(s : &str) -> Result<K, ParseKeywordError> {
+    K::ALL.iter().find(|kw| kw.as_str() == s).copied().ok_or_else(|| ParseKeywordError::new::<K>(s))
+}
+
+/// Defines an enum of keywords with the text of each one:
+///
+/// ```
+/// # use smpl_parser::{Keyword, TokenScanner, define_keywords, tokenize_spanned, TokenizerConfig};
+/// define_keywords! {
+///     /// The mnemonics of the instruction set.
+///     pub enum Mnemonic {
+///         Mov => "mov",
+///         Nop => "nop",
+///     }
+/// }
+///
+/// assert_eq!("mov".parse(), Ok(Mnemonic::Mov));
+/// assert_eq!(Mnemonic::Nop.to_string(), "nop");
+/// assert!(Mnemonic::strs().eq(["mov", "nop"]));
+///
+/// let (toks, _) = tokenize_spanned(&TokenizerConfig::default(), "mov nop");
+/// let mut input = TokenScanner::new(&toks);
+/// input.expect_kw(Mnemonic::Mov).unwrap();
+/// assert_eq!(input.parse(), Ok(Mnemonic::Nop));
+/// ```
+///
+/// The enum is `Copy`, `Eq`, `Ord` and `Hash`, and implements `Keyword`, `Display`, `FromStr`
+/// and `Parse`, which expects any of the keywords.
+#[macro_export]
+macro_rules! define_keywords {
+    ($(#[$attr:meta])* $vis:vis enum $name:ident { $($(#[$kw_attr:meta])* $kw:ident => $text:literal),* $(,)? }) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        $vis enum $name {
+            $($(#[$kw_attr])* $kw,)*
+        }
+
+        impl $crate::Keyword for $name {
+            const ALL : &'static [Self] = &[$($name::$kw),*];
+
+            fn as_str(self) -> &'static str {
+                match self {
+                    $($name::$kw => $text,)*
+                }
+            }
+        }
+
+        impl ::core::fmt::Display for $name {
+            fn fmt(&self, f : &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                f.write_str($crate::Keyword::as_str(*self))
+            }
+        }
+
+        impl ::core::str::FromStr for $name {
+            type Err = $crate::ParseKeywordError;
+
+            fn from_str(s : &str) -> ::core::result::Result<Self, Self::Err> {
+                $crate::__keyword_from_str(s)
+            }
+        }
+
+        impl $crate::Parse for $name {
+            fn parse(input : &mut $crate::TokenScanner<'_>) -> ::core::result::Result<Self, $crate::__Box<$crate::Diagnostic>> {
+                $crate::__keyword_parse(input)
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Span, TokenizerConfig, tokenize_spanned};
+
+    define_keywords! {
+        enum Kw {
+            Push => "push",
+            /// Pops a register
+            Pop => "pop",
+        }
+    }
+
+    #[test]
+    fn keywords() {
+        assert_eq!(Kw::ALL, [Kw::Push, Kw::Pop]);
+        assert_eq!("pop".parse::<Kw>(), Ok(Kw::Pop));
+        let err = "psuh".parse::<Kw>().unwrap_err();
+        assert_eq!(err.to_string(), "unknown keyword `psuh`, did you mean `push`?");
+        assert_eq!(format!("{} {}", Kw::Push, Kw::Pop), "push pop");
+
+        let (toks, _) = tokenize_spanned(&TokenizerConfig::default(), "push pop psuh 1");
+        let mut input = TokenScanner::new(&toks);
+        assert_eq!(input.expect_kw(Kw::Pop).unwrap_err().message(), "expected `pop`, found identifier `push`");
+        input.expect_kw(Kw::Push).unwrap();
+        assert_eq!(input.parse::<Kw>(), Ok(Kw::Pop));
+
+        let err = input.parse::<Kw>().unwrap_err();
+        assert_eq!((err.message(), err.span, err.suggestions[0].replacement.as_str()), ("unknown keyword `psuh`".to_string(), Span::new(9, 13), "push"));
+        input.pop();
+        assert_eq!(input.parse::<Kw>().unwrap_err().message(), "expected a keyword, found number 1");
+    }
+}
@@ -0,0 +1,153 @@
+//! Declarative lexers over user token types, usually implemented with `#[derive(Lexer)]`.
+
+use crate::prelude::*;
+use crate::instrument;
+use crate::regex::Regex;
+use crate::{Diagnostic, DiagnosticKind, Span, Spanned};
+
+/// A pattern a `Lexer` token is lexed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LexPattern {
+    /// Exactly this text.
+    Token(&'static str),
+
+    /// A regular expression, supporting classes, groups, `|`, `* + ?` and `\d \w \s`.
+    Regex(&'static str),
+}
+
+/// A token type that can be lexed from text by matching `patterns`.
+///
+/// At each position the longest match wins; on a tie, text patterns win over regexes and then
+/// the earlier pattern wins. Whitespace no pattern matches is skipped.
+pub trait Lexer : Sized {
+    fn patterns() -> &'static [LexPattern];
+
+    /// Builds the token for a match of `patterns()[index]`, or `None` if `text` is an invalid
+    /// value for it.
+    fn from_match(index : usize, text : &str) -> Option<Self>;
+
+    /// Lexes `code`, reporting and skipping chars that start no match and matches with an
+    /// invalid value.
+    ///
+    /// Panics if a regex pattern is invalid.
+    fn lex(code : &str) -> (Vec<Spanned<Self>>, Vec<Diagnostic>) {
+        let measure = instrument::start("lex");
+        let res = lex(code);
+        measure.finish(res.0.len(), instrument::count_errors(&res.1));
+        res
+    }
+}
+
+enum Matcher {
+    Token(&'static str),
+    Regex(Regex),
+}
+
+fn lex<T : Lexer>(code : &str) -> (Vec<Spanned<T>>, Vec<Diagnostic>) {
+    let matchers : Vec<_> = T::patterns().iter().map(|pattern| match pattern {
+        LexPattern::Token(text) => Matcher::Token(text),
+        LexPattern::Regex(regex) => Matcher::Regex(Regex::new(regex).unwrap_or_else(|err| panic!("invalid regex `{regex}`: {err}"))),
+    }).collect();
+
+    let mut toks = Vec::new();
+    let mut diagnostics = Vec::new();
+    let mut pos = 0;
+    while let Some(c) = code[pos..].chars().next() {
+        let rest = &code[pos..];
+        let best = matchers.iter().enumerate().filter_map(|(i, matcher)| match matcher {
+            Matcher::Token(text) => rest.starts_with(text).then_some((text.len(), true, i)),
+            Matcher::Regex(regex) => regex.longest_match(rest).map(|len| (len, false, i)),
+        }).filter(|(len, ..)| *len > 0).max_by_key(|(len, literal, i)| (*len, *literal, core::cmp::Reverse(*i)));
+
+        let Some((len, _, index)) = best else {
+            if !c.is_whitespace() {
+                diagnostics.push(Diagnostic::error(Span::new(pos, pos + c.len_utf8()), DiagnosticKind::UnexpectedChar(c)));
+            }
+            pos += c.len_utf8();
+            continue
+        };
+
+        let span = Span::new(pos, pos + len);
+        let text = &rest[..len];
+        match T::from_match(index, text) {
+            Some(tok) => toks.push(Spanned::new(tok, span)),
+            None => diagnostics.push(Diagnostic::error(span, DiagnosticKind::InvalidTokenValue(text.to_string()))),
+        }
+        pos += len;
+    }
+    (toks, diagnostics)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    enum Asm {
+        Mov,
+        Ident,
+        Number(u8),
+        Comma,
+    }
+
+    impl Lexer for Asm {
+        fn patterns() -> &'static [LexPattern] {
+            &[
+                LexPattern::Regex("[a-z]+"),
+                LexPattern::Token("mov"),
+                LexPattern::Regex("[0-9]+"),
+                LexPattern::Token(","),
+            ]
+        }
+
+        fn from_match(index : usize, text : &str) -> Option<Self> {
+            match index {
+                0 => Some(Asm::Ident),
+                1 => Some(Asm::Mov),
+                2 => text.parse().ok().map(Asm::Number),
+                3 => Some(Asm::Comma),
+                _ => None,
+            }
+        }
+    }
+
+    #[test]
+    fn lex() {
+        let (toks, diagnostics) = Asm::lex("mov movs, 12 ?300");
+        assert_eq!(toks, vec![
+            Spanned::new(Asm::Mov, Span::new(0, 3)),
+            Spanned::new(Asm::Ident, Span::new(4, 8)),
+            Spanned::new(Asm::Comma, Span::new(8, 9)),
+            Spanned::new(Asm::Number(12), Span::new(10, 12)),
+        ]);
+        assert_eq!(diagnostics.iter().map(|diag| (diag.code().unwrap(), diag.span)).collect::<Vec<_>>(), [
+            ("unexpected-char", Span::new(13, 14)),
+            ("invalid-token-value", Span::new(14, 17)),
+        ]);
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn derive() {
+        #[derive(Debug, PartialEq, crate::Lexer)]
+        enum Tok {
+            #[token("mov")]
+            #[token("move")]
+            Mov,
+            #[regex(r"r\d+")]
+            Register,
+            #[regex("[0-9]+|0x[0-9a-f]+")]
+            Number(
+                /// The value, if decimal
+                i64,
+            ),
+            /// Not lexed
+            Error,
+        }
+
+        let (toks, diagnostics) = Tok::lex("move r1 42 0x2a");
+        assert!(diagnostics.iter().map(Diagnostic::code).eq([Some("invalid-token-value")]));
+        assert_eq!(toks.into_iter().map(|tok| tok.value).collect::<Vec<_>>(), [Tok::Mov, Tok::Register, Tok::Number(42)]);
+        assert_ne!(Tok::Error, Tok::Mov);
+    }
+}
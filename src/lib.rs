@@ -1,5 +1,131 @@
+//! A configurable tokenizer, and utilities for parsing and rewriting its token trees.
+//!
+//! Without the default `std` feature the crate is `no_std`, needing only `alloc`. The `derive`
+//! feature provides `#[derive(Lexer)]` for declarative lexers, and the `trace` feature reports
+//! the token counts, error counts and durations of tokenizing and parsing to a subscriber set
+//! with `set_trace_subscriber`. The `unicode-security` feature warns about identifiers mixing
+//! scripts, and the `parallel` feature has `SourceMap::tokenize_files` use all cores. The `wasm`
+//! feature exports the tokenizer to JavaScript when built for WebAssembly, and the `ffi`
+//! feature to C, declared by `include/smpl_parser.h`.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+extern crate self as smpl_parser;
+
+mod prelude;
 mod token;
+mod text;
 mod scanner;
+pub mod chars;
+pub mod combinator;
+mod span;
+mod diagnostic;
+mod config;
+mod number;
+mod normalize;
+#[cfg(feature = "unicode-security")]
+mod script;
+mod source;
+pub mod lsp;
+mod cache;
+mod intern;
+#[cfg(feature = "std")]
+mod disk_cache;
+mod preprocess;
+mod tree;
+mod pattern;
+mod stream;
+mod reader;
+mod query;
+mod eval;
+mod columns;
+mod regex;
+mod lex;
+mod parse;
+mod grammar;
+mod ebnf;
+mod keywords;
+mod markers;
+mod trace;
+mod instrument;
+mod lint;
+mod stats;
+mod balance;
+mod repl;
+mod quote;
+mod matching;
+mod metadata;
+pub mod suggest;
+pub mod testing;
+#[cfg(feature = "serde")]
+pub mod json;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 
-pub use scanner::{Scanner, ScannerAction};
-pub use token::{Token, tokenize};
+pub use scanner::{Checkpoint, Matcher, Scanner, ScannerAction};
+pub use text::TokenStr;
+pub use token::{
+    Delimiter, Float, Group, LexError, Line, Token, TokenBuffers, TokenConversionError, TokenPool, structural_eq, tokenize, tokenize_into,
+    tokenize_lines, tokenize_spanned, tokenize_with, tokenize_with_diagnostics, try_tokenize,
+};
+pub use config::{ControlCharPolicy, LeadingZeroPolicy, Limits, Normalization, Recovery, TokenizerConfig};
+pub use number::{FromLexeme, NumberError, NumberType, parse_number, split_lexeme};
+pub use normalize::{is_nfc, to_nfc};
+pub use span::{ExpansionId, FileId, HasSpan, Span, Spanned, span_of};
+pub use source::{Expansion, ExpansionKind, LineCol, SourceFile, SourceMap};
+#[cfg(feature = "std")]
+pub use source::TokenizedFile;
+pub use cache::{FINGERPRINT_VERSION, ParseCache, fingerprint};
+pub use intern::{GroupNode, NodeSpan, SharedGroup, SharedToken, SubtreeInterner, unshare};
+#[cfg(feature = "std")]
+pub use disk_cache::DiskCache;
+pub use preprocess::{ConditionalResolver, ConditionalSyntax, IncludeResolver, MacroExpander};
+pub use tree::{
+    Comments, TokenOrStream, TokenVisitor, TokenVisitorMut, Tokens, TreeCursor, fold_tokens, iter_tokens,
+    iter_tokens_with_path, normalize, semantically_eq, split_comments, walk_group, walk_group_mut, walk_token,
+    walk_token_mut,
+};
+pub use pattern::{Match, Pattern};
+pub use stream::{SharedTokens, TokenStream};
+pub use reader::ChunkedTokenReader;
+pub use query::Query;
+pub use eval::{Value, eval};
+pub use columns::{ColumnFormatter, to_source};
+pub use lex::{LexPattern, Lexer};
+pub use parse::{Fuel, Marks, Parse, SCAN_ERROR_CONTEXT, ScanError, ScanErrorKind, TokenScanner};
+pub use ebnf::{Grammar, ParseChild, ParseNode};
+pub use keywords::{Keyword, ParseKeywordError};
+#[doc(hidden)]
+pub use keywords::{from_str as __keyword_from_str, parse as __keyword_parse};
+pub use markers::{Marker, punct};
+#[doc(hidden)]
+pub use markers::peek as __marker_peek;
+pub use trace::{Trace, TraceEntry, TraceEvent};
+pub use balance::{BalanceError, check_balanced};
+pub use repl::ReplSession;
+pub use lint::{HexCase, Lint, LintContext, MixedIndentation, TrailingWhitespace, run_lints};
+pub use stats::TokenStats;
+#[cfg(feature = "trace")]
+pub use instrument::{TraceRecord, set_trace_subscriber};
+#[doc(hidden)]
+pub use trace::{backtrack as __trace_backtrack, enter as __trace_enter, exit as __trace_exit};
+#[doc(hidden)]
+pub use grammar::{
+    GrammarLiteral as __GrammarLiteral, furthest as __grammar_furthest, many as __grammar_many, one as __grammar_one,
+    optional as __grammar_optional, separated as __grammar_separated,
+};
+#[cfg(feature = "derive")]
+pub use smpl_parser_derive::Lexer;
+pub use metadata::Annotations;
+pub use quote::ToTokens;
+#[doc(hidden)]
+pub use quote::{Literal as __Literal, group as __group};
+#[doc(hidden)]
+pub use alloc::{boxed::Box as __Box, vec::Vec as __Vec};
+pub use diagnostic::{
+    DefaultFormatter, Diagnostic, DiagnosticKind, Edit, MessageFormatter, Severity,
+    normalize_diagnostics, sort_diagnostics,
+};
@@ -0,0 +1,220 @@
+//! Token-level lints, checks of style over a tokenized file that report warnings, to build
+//! formatters and style tooling on.
+
+use crate::prelude::*;
+use crate::{
+    Diagnostic, DiagnosticKind, Edit, Span, Spanned, Token, TokenVisitor, TokenizerConfig, sort_diagnostics,
+    tokenize_spanned,
+};
+
+/// What a lint checks: a file, its tokens and the config it was tokenized with.
+#[derive(Debug, Clone)]
+pub struct LintContext<'a> {
+    pub config : &'a TokenizerConfig,
+    pub source : &'a str,
+    pub tokens : &'a [Spanned<Token>],
+
+    /// The spans of string and char literals, in order.
+    literals : Vec<Span>,
+}
+
+/// A check over the tokens of a file, e.g. of a style rule.
+pub trait Lint {
+    /// The name of the lint, e.g. "trailing-whitespace", for tools to enable it by.
+    fn name(&self) -> &'static str;
+
+    /// Adds a diagnostic to `out` for each problem found.
+    fn check(&self, cx : &LintContext<'_>, out : &mut Vec<Diagnostic>);
+}
+
+#[derive(Default)]
+struct Collector {
+    literals : Vec<Span>,
+    numbers : Vec<Span>,
+}
+
+impl TokenVisitor for Collector {
+    fn visit_string(&mut self, _value : &str, span : Span) {
+        self.literals.push(span);
+    }
+
+    fn visit_char(&mut self, _value : char, span : Span) {
+        self.literals.push(span);
+    }
+
+    fn visit_number(&mut self, _value : i64, span : Span) {
+        self.numbers.push(span);
+    }
+
+    fn visit_immediate(&mut self, value : &Token, span : Span) {
+        if let Token::Number(_) = value {
+            self.numbers.push(span);
+        }
+    }
+}
+
+impl<'a> LintContext<'a> {
+    pub fn new(config : &'a TokenizerConfig, source : &'a str, tokens : &'a [Spanned<Token>]) -> Self {
+        let mut collector = Collector::default();
+        collector.visit_tokens(tokens);
+        Self { config, source, tokens, literals: collector.literals }
+    }
+
+    /// The source text of `span`.
+    pub fn text(&self, span : Span) -> &'a str {
+        &self.source[span.start..span.end]
+    }
+
+    /// The lines of the source without their line breaks, each with the offset it starts at.
+    pub fn lines(&self) -> impl Iterator<Item = (usize, &'a str)> + 'a {
+        let source = self.source;
+        source.split('\n').map(move |line| (line.as_ptr() as usize - source.as_ptr() as usize, line.trim_end_matches('\r')))
+    }
+
+    /// Whether `offset` is inside a string or char literal, where whitespace is part of the value.
+    pub fn in_literal(&self, offset : usize) -> bool {
+        let i = self.literals.partition_point(|span| span.end <= offset);
+        self.literals.get(i).is_some_and(|span| span.start < offset)
+    }
+
+    /// The spans of the numbers, including immediate ones, in order.
+    pub fn numbers(&self) -> Vec<Span> {
+        let mut collector = Collector::default();
+        collector.visit_tokens(self.tokens);
+        collector.numbers
+    }
+
+    /// Runs `lints`, returning their diagnostics in source order.
+    pub fn run(&self, lints : &[&dyn Lint]) -> Vec<Diagnostic> {
+        let mut out = Vec::new();
+        for lint in lints {
+            lint.check(self, &mut out);
+        }
+        sort_diagnostics(&mut out);
+        out
+    }
+}
+
+/// Tokenizes `source` with `config` and runs `lints` over it, returning their diagnostics in
+/// source order. Those of tokenizing are left out.
+pub fn run_lints(config : &TokenizerConfig, source : &str, lints : &[&dyn Lint]) -> Vec<Diagnostic> {
+    let (tokens, _) = tokenize_spanned(config, source);
+    LintContext::new(config, source, &tokens).run(lints)
+}
+
+/// Warns about indentation with both tabs and spaces, or with the other of the two than the
+/// first indented line.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MixedIndentation;
+
+impl Lint for MixedIndentation {
+    fn name(&self) -> &'static str {
+        "mixed-indentation"
+    }
+
+    fn check(&self, cx : &LintContext<'_>, out : &mut Vec<Diagnostic>) {
+        let mut style = None;
+        for (start, line) in cx.lines() {
+            let indent = &line[..line.len() - line.trim_start_matches([' ', '\t']).len()];
+            if indent.is_empty() || indent.len() == line.len() || cx.in_literal(start) {
+                continue;
+            }
+            let style = *style.get_or_insert(indent.as_bytes()[0]);
+            if indent.bytes().any(|c| c != style) {
+                out.push(Diagnostic::warning(Span::new(start, start + indent.len()), DiagnosticKind::MixedIndentation));
+            }
+        }
+    }
+}
+
+/// Warns about whitespace at the end of a line, outside string literals.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrailingWhitespace;
+
+impl Lint for TrailingWhitespace {
+    fn name(&self) -> &'static str {
+        "trailing-whitespace"
+    }
+
+    fn check(&self, cx : &LintContext<'_>, out : &mut Vec<Diagnostic>) {
+        for (start, line) in cx.lines() {
+            let trimmed = line.trim_end_matches([' ', '\t']).len();
+            if trimmed < line.len() && !cx.in_literal(start + trimmed) {
+                let span = Span::new(start + trimmed, start + line.len());
+                out.push(Diagnostic::warning(span, DiagnosticKind::TrailingWhitespace).with_suggestion(Edit::delete(span)));
+            }
+        }
+    }
+}
+
+/// Warns about hex numbers whose letter digits aren't all of the case of those of the first
+/// hex number with any.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HexCase;
+
+impl Lint for HexCase {
+    fn name(&self) -> &'static str {
+        "hex-case"
+    }
+
+    fn check(&self, cx : &LintContext<'_>, out : &mut Vec<Diagnostic>) {
+        let mut uppercase = None;
+        for span in cx.numbers() {
+            let text = cx.text(span);
+            let Some(prefix) = text.find("0x") else { continue };
+            let digits = &text[prefix + 2..];
+            let Some(first) = digits.chars().find(char::is_ascii_alphabetic) else { continue };
+            let uppercase = *uppercase.get_or_insert(first.is_ascii_uppercase());
+            if digits.chars().any(|c| c.is_ascii_alphabetic() && c.is_ascii_uppercase() != uppercase) {
+                let fixed = if uppercase { digits.to_ascii_uppercase() } else { digits.to_ascii_lowercase() };
+                let digits = Span::new(span.start + prefix + 2, span.end);
+                out.push(Diagnostic::warning(span, DiagnosticKind::InconsistentHexCase { uppercase })
+                    .with_suggestion(Edit::new(digits, fixed)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct NoNops;
+
+    impl Lint for NoNops {
+        fn name(&self) -> &'static str {
+            "no-nops"
+        }
+
+        fn check(&self, cx : &LintContext<'_>, out : &mut Vec<Diagnostic>) {
+            for tok in cx.tokens {
+                if tok.value == Token::Ident("nop".into()) {
+                    out.push(Diagnostic::warning(tok.span, "nop"));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn builtins() {
+        let config = TokenizerConfig { immediate_prefixes: vec!['#'], ..Default::default() };
+        let code = "mov r0, 0xFF \n\tnop\n  \tmov r1, #0xaB\r\n.db \"a \" 0xfe";
+        let lints : [&dyn Lint; 4] = [&MixedIndentation, &TrailingWhitespace, &HexCase, &NoNops];
+        let diagnostics = run_lints(&config, code, &lints);
+        let found : Vec<_> = diagnostics.iter().map(|diag| (diag.code(), diag.span)).collect();
+        assert_eq!(found, [
+            (Some("trailing-whitespace"), Span::new(12, 13)),
+            (None, Span::new(15, 18)),
+            (Some("mixed-indentation"), Span::new(19, 22)),
+            (Some("inconsistent-hex-case"), Span::new(30, 35)),
+            (Some("inconsistent-hex-case"), Span::new(46, 50)),
+        ]);
+        assert_eq!(diagnostics[3].suggestions, [Edit::new(Span::new(33, 35), "AB")]);
+        assert_eq!(diagnostics[4].message(), "hex digits should be uppercase, like those of the first hex number");
+
+        let (tokens, _) = tokenize_spanned(&config, code);
+        let cx = LintContext::new(&config, code, &tokens);
+        assert!(cx.in_literal(43) && !cx.in_literal(41) && !cx.in_literal(45));
+        assert_eq!(cx.lines().nth(2), Some((19, "  \tmov r1, #0xaB")));
+    }
+}
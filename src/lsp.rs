@@ -0,0 +1,113 @@
+//! Conversions between byte offsets and the line/character positions of the Language Server Protocol.
+
+use crate::{SourceFile, Span};
+
+/// How the `character` of a `Position` is counted, as negotiated with the LSP client.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PositionEncoding {
+    /// Bytes.
+    Utf8,
+
+    /// UTF-16 code units, the LSP default.
+    #[default]
+    Utf16,
+
+    /// Chars.
+    Utf32,
+}
+
+impl PositionEncoding {
+    fn width(&self, c : char) -> usize {
+        match self {
+            PositionEncoding::Utf8 => c.len_utf8(),
+            PositionEncoding::Utf16 => c.len_utf16(),
+            PositionEncoding::Utf32 => 1,
+        }
+    }
+}
+
+/// A 0-based line and character offset within that line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Position {
+    pub line : u32,
+    pub character : u32,
+}
+
+impl Position {
+    pub fn new(line : u32, character : u32) -> Self {
+        Self { line, character }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct PositionRange {
+    pub start : Position,
+    pub end : Position,
+}
+
+impl SourceFile {
+    pub fn position(&self, offset : usize, encoding : PositionEncoding) -> Position {
+        let line = self.line_index(offset);
+        let start = self.line_starts()[line];
+        let character : usize = self.source()[start..offset].chars().map(|c| encoding.width(c)).sum();
+        Position::new(line as u32, character as u32)
+    }
+
+    /// The byte offset of `position`, or `None` if its line doesn't exist.
+    /// As the protocol requires, a character beyond the end of the line means the end of the line;
+    /// one in the middle of a char means the start of that char.
+    pub fn offset_at(&self, position : Position, encoding : PositionEncoding) -> Option<usize> {
+        let line = position.line as usize;
+        let start = *self.line_starts().get(line)?;
+
+        let mut character = 0;
+        for (i, c) in self.line(line).char_indices() {
+            character += encoding.width(c);
+            if character > position.character as usize {
+                return Some(start + i)
+            }
+        }
+        Some(start + self.line(line).len())
+    }
+
+    pub fn position_range(&self, span : Span, encoding : PositionEncoding) -> PositionRange {
+        PositionRange { start: self.position(span.start, encoding), end: self.position(span.end, encoding) }
+    }
+
+    /// The span of `range` in this file, or `None` if it refers to lines that don't exist.
+    pub fn span_at(&self, range : PositionRange, encoding : PositionEncoding) -> Option<Span> {
+        let start = self.offset_at(range.start, encoding)?;
+        let end = self.offset_at(range.end, encoding)?.max(start);
+        Some(Span::new(start, end).with_file(self.id()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn positions() {
+        let file = SourceFile::new("a.s", "mov é\r\n🦀 x\n");
+        let x = file.source().find('x').unwrap();
+        assert_eq!(file.position(x, PositionEncoding::Utf8), Position::new(1, 5));
+        assert_eq!(file.position(x, PositionEncoding::Utf16), Position::new(1, 3));
+        assert_eq!(file.position(x, PositionEncoding::Utf32), Position::new(1, 2));
+
+        for encoding in [PositionEncoding::Utf8, PositionEncoding::Utf16, PositionEncoding::Utf32] {
+            for offset in file.source().char_indices().map(|(i, _)| i).filter(|i| !file.source()[*i..].starts_with('\n')) {
+                assert_eq!(file.offset_at(file.position(offset, encoding), encoding), Some(offset));
+            }
+        }
+
+        // Past the end of the line, inside a surrogate pair, and past the last line
+        assert_eq!(file.offset_at(Position::new(0, 99), PositionEncoding::Utf16), Some(6));
+        assert_eq!(file.offset_at(Position::new(1, 1), PositionEncoding::Utf16), Some(8));
+        assert_eq!(file.offset_at(Position::new(3, 0), PositionEncoding::Utf16), None);
+
+        let span = Span::new(4, 6);
+        let range = file.position_range(span, PositionEncoding::Utf16);
+        assert_eq!(range, PositionRange { start: Position::new(0, 4), end: Position::new(0, 5) });
+        assert_eq!(file.span_at(range, PositionEncoding::Utf16), Some(span));
+    }
+}
@@ -0,0 +1,169 @@
+//! Zero-sized marker types for punctuation and keywords, named with `Token![,]`.
+
+use crate::prelude::*;
+use crate::{Diagnostic, Parse, Token, TokenScanner};
+
+/// A marker type of `Token![..]`, which parses exactly one kind of token.
+pub trait Marker : Parse {
+    /// Whether the next token other than trivia is this one.
+    fn peek(input : &TokenScanner<'_>) -> bool;
+}
+
+impl TokenScanner<'_> {
+    /// Whether `T` is next, without consuming it, e.g. `input.peek_marker::<Token![,]>()`.
+    pub fn peek_marker<T : Marker>(&self) -> bool {
+        T::peek(self)
+    }
+}
+
+#[doc(hidden)]
+pub fn peek(input : &TokenScanner<'_>, cb : impl FnOnce(&Token) -> bool) -> bool {
+    let mut input = *input;
+    input.skip_trivia();
+    input.peek().is_some_and(|tok| cb(&tok.value))
+}
+
+macro_rules! puncts {
+    ($($c:literal $name:ident,)*) => {
+        $(
+            #[doc = concat!("`", $c, "`")]
+            #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+            pub struct $name;
+
+            impl Parse for $name {
+                fn parse(input : &mut TokenScanner<'_>) -> Result<Self, Box<Diagnostic>> {
+                    input.expect_punct($c).map(|()| Self)
+                }
+            }
+
+            impl Marker for $name {
+                fn peek(input : &TokenScanner<'_>) -> bool {
+                    peek(input, |tok| *tok == Token::Punct($c))
+                }
+            }
+        )*
+    };
+}
+
+/// The punctuation types of `Token![..]`.
+pub mod punct {
+    use super::*;
+
+    puncts! {
+        ',' Comma,
+        ';' Semi,
+        ':' Colon,
+        '.' Dot,
+        '#' Pound,
+        '@' At,
+        '+' Plus,
+        '-' Minus,
+        '*' Star,
+        '/' Slash,
+        '%' Percent,
+        '^' Caret,
+        '&' And,
+        '|' Or,
+        '!' Bang,
+        '~' Tilde,
+        '?' Question,
+        '=' Eq,
+        '<' Lt,
+        '>' Gt,
+    }
+}
+
+/// The type of a punctuation, e.g. `Token![,]` for `punct::Comma`, or that of a keyword
+/// defined with `custom_keyword!`, e.g. `Token![mov]`.
+#[macro_export]
+macro_rules! Token {
+    [,] => { $crate::punct::Comma };
+    [;] => { $crate::punct::Semi };
+    [:] => { $crate::punct::Colon };
+    [.] => { $crate::punct::Dot };
+    [#] => { $crate::punct::Pound };
+    [@] => { $crate::punct::At };
+    [+] => { $crate::punct::Plus };
+    [-] => { $crate::punct::Minus };
+    [*] => { $crate::punct::Star };
+    [/] => { $crate::punct::Slash };
+    [%] => { $crate::punct::Percent };
+    [^] => { $crate::punct::Caret };
+    [&] => { $crate::punct::And };
+    [|] => { $crate::punct::Or };
+    [!] => { $crate::punct::Bang };
+    [~] => { $crate::punct::Tilde };
+    [?] => { $crate::punct::Question };
+    [=] => { $crate::punct::Eq };
+    [<] => { $crate::punct::Lt };
+    [>] => { $crate::punct::Gt };
+    [$kw:ident] => { $kw };
+}
+
+/// Defines a marker type for the keyword of the same name, e.g. `custom_keyword!(mov)`
+/// for `Token![mov]`.
+#[macro_export]
+macro_rules! custom_keyword {
+    ($(#[$attr:meta])* $vis:vis $name:ident) => {
+        $(#[$attr])*
+        #[allow(non_camel_case_types)]
+        #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+        $vis struct $name;
+
+        impl $crate::Parse for $name {
+            fn parse(input : &mut $crate::TokenScanner<'_>) -> ::core::result::Result<Self, $crate::__Box<$crate::Diagnostic>> {
+                input.expect_keyword(::core::stringify!($name)).map(|()| $name)
+            }
+        }
+
+        impl $crate::Marker for $name {
+            fn peek(input : &$crate::TokenScanner<'_>) -> bool {
+                $crate::__marker_peek(input, |tok| ::core::matches!(tok, $crate::Token::Ident(name) if name == ::core::stringify!($name)))
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{TokenStr, TokenizerConfig, tokenize_spanned};
+
+    custom_keyword!(mov);
+
+    struct Mov {
+        dst : TokenStr,
+        src : Option<i64>,
+    }
+
+    impl Parse for Mov {
+        fn parse(input : &mut TokenScanner<'_>) -> Result<Self, Box<Diagnostic>> {
+            input.parse::<Token![mov]>()?;
+            let dst = input.expect_ident()?;
+            let src = match input.peek_marker::<Token![,]>() {
+                true => {
+                    input.parse::<Token![,]>()?;
+                    Some(input.expect_number()?)
+                },
+                false => None,
+            };
+            input.parse::<Token![;]>()?;
+            Ok(Self { dst, src })
+        }
+    }
+
+    #[test]
+    fn markers() {
+        let (toks, _) = tokenize_spanned(&TokenizerConfig::default(), "mov a, 1; mov /* b */ b; nop");
+        let mut input = TokenScanner::new(&toks);
+        let first : Mov = input.parse().unwrap();
+        assert_eq!((first.dst, first.src), ("a".into(), Some(1)));
+        assert!(input.peek_marker::<Token![mov]>());
+        let second : Mov = input.parse().unwrap();
+        assert_eq!((second.dst, second.src), ("b".into(), None));
+
+        assert!(!input.peek_marker::<Token![mov]>());
+        assert_eq!(input.parse::<Mov>().err().unwrap().message(), "expected `mov`, found identifier `nop`");
+        assert_eq!(core::mem::size_of::<Token![|]>(), 0);
+    }
+}
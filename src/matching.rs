@@ -0,0 +1,222 @@
+//! The `tok_match!` macro, matching token slices against sequences of token kinds.
+
+/// Matches a `&[Token]`, e.g. the slice given to a `Scanner::scan` callback, against arms of
+/// token sequences:
+///
+/// ```
+/// # use smpl_parser::{tok_match, tokenize};
+/// let toks = tokenize("mov 4, r0");
+/// let mov = tok_match!(toks {
+///     [ident "mov", num imm, punct ',', ident reg] => Some((*imm, reg.to_string())),
+///     _ => None,
+/// });
+/// assert_eq!(mov, Some((4, "r0".to_string())));
+/// ```
+///
+/// Each element is one of `ident`, `label`, `directive`, `reg`, `str`, `num`, `punct`, `char`,
+/// `group`, `imm`, `comment`, `doc`, `kw`, `float` and `op`, optionally followed by a literal
+/// value to match or a name to bind the token's value to, or `newline`, `eof`, `_` for any token,
+/// `..` for any number of them and `name @ ..` to bind those. Arms must be separated by commas.
+#[macro_export]
+macro_rules! tok_match {
+    ($toks:tt { $($arms:tt)* }) => {
+        $crate::__tok_match!(@arms $toks [] $($arms)*)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __tok_match {
+    (@arms $toks:tt [$($done:tt)*]) => {
+        match &$toks[..] { $($done)* }
+    };
+    (@arms $toks:tt [$($done:tt)*] _ => $body:expr $(, $($rest:tt)*)?) => {
+        $crate::__tok_match!(@arms $toks [$($done)* _ => $body,] $($($rest)*)?)
+    };
+    (@arms $toks:tt [$($done:tt)*] [$($elems:tt)*] => $body:expr $(, $($rest:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($($rest)*)?] ($body) [] [] $($elems)*)
+    };
+
+    // All elements of an arm are done
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] []) => {
+        $crate::__tok_match!(@arms $toks [$($done)* [$($pats)*] => $body,] $($rest)*)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$(($($guard:tt)*))+]) => {
+        $crate::__tok_match!(@arms $toks [$($done)* [$($pats)*] if $(($($guard)*))&&+ => $body,] $($rest)*)
+    };
+
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] ident $v:literal $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Ident(__s),] [$($guards)* (__s == $v)] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] ident $b:ident $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Ident($b),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] ident $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Ident(_),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] label $v:literal $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Label(__s),] [$($guards)* (__s == $v)] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] label $b:ident $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Label($b),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] label $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Label(_),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] directive $v:literal $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Directive(__s),] [$($guards)* (__s == $v)] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] directive $b:ident $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Directive($b),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] directive $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Directive(_),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] reg $v:literal $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Register(__s),] [$($guards)* (__s == $v)] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] reg $b:ident $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Register($b),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] reg $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Register(_),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] str $v:literal $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::String(__s),] [$($guards)* (__s == $v)] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] str $b:ident $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::String($b),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] str $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::String(_),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] num $v:literal $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Number($v),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] num $b:ident $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Number($b),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] num $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Number(_),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] punct $v:literal $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Punct($v),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] punct $b:ident $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Punct($b),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] punct $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Punct(_),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] char $v:literal $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Char($v),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] char $b:ident $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Char($b),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] char $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Char(_),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] group $b:ident $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Group($b),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] group $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Group(_),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] imm $b:ident $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Immediate($b),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] imm $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Immediate(_),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] comment $b:ident $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Comment($b),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] comment $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Comment(_),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] doc $b:ident $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::DocComment($b),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] doc $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::DocComment(_),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] kw $v:literal $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Keyword(__s),] [$($guards)* (__s == $v)] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] kw $b:ident $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Keyword($b),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] kw $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Keyword(_),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] float $v:literal $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Float(__f),] [$($guards)* (__f.0 == $v)] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] float $b:ident $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Float($crate::Float($b)),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] float $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Float(_),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] op $v:literal $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Op(__s),] [$($guards)* (__s == $v)] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] op $b:ident $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Op($b),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] op $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Op(_),] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] newline $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Newline,] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] eof $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $crate::Token::Eof,] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] _ $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* _,] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] .. $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* ..,] [$($guards)*] $($($more)*)?)
+    };
+    (@elems $toks:tt [$($done:tt)*] [$($rest:tt)*] ($body:expr) [$($pats:tt)*] [$($guards:tt)*] $b:ident @ .. $(, $($more:tt)*)?) => {
+        $crate::__tok_match!(@elems $toks [$($done)*] [$($rest)*] ($body) [$($pats)* $b @ ..,] [$($guards)*] $($($more)*)?)
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{Float, Scanner, ScannerAction, Token, tokenize};
+
+    #[test]
+    fn tok_match() {
+        let mut scanner = Scanner::new(tokenize("mov r0, 3 push {r1} \"s\" 'c' nop").into());
+        let parsed = scanner.collect(|toks| tok_match!(toks {
+            [ident "mov", ident reg, punct ',', num imm] => ScannerAction::Return(format!("{reg}={imm}")),
+            [ident "mov", ..] => ScannerAction::Require,
+            [ident "push", group g] => ScannerAction::Return(format!("push {}", g.tokens.len())),
+            [ident "push"] => ScannerAction::Require,
+            [str s] => ScannerAction::Return(s.to_string()),
+            [char 'c'] => ScannerAction::Return("c".to_string()),
+            [ident, rest @ ..] => ScannerAction::Return(format!("{rest:?}")),
+            _ => ScannerAction::None,
+        }));
+        assert_eq!(parsed.unwrap(), vec!["r0=3", "push 1", "s", "c", "[]"]);
+
+        let toks = [Token::Newline, Token::Number(1)];
+        assert!(tok_match!(toks { [newline, _] => true, _ => false }));
+
+        let toks = [Token::Keyword("db".into()), Token::Float(Float(1.5)), Token::Float(Float(2.0))];
+        let floats = tok_match!(toks {
+            [kw "dw", ..] => None,
+            [kw, float 1.5, float f] => Some(*f),
+            _ => None,
+        });
+        assert_eq!(floats, Some(2.0));
+
+        let toks = [Token::Ident("a".into()), Token::Op("<<".into()), Token::Op("->".into())];
+        assert!(tok_match!(toks { [ident, op "<<", op] => true, _ => false }));
+        assert_eq!(tok_match!(toks { [.., op arrow] => Some(arrow.as_str()), _ => None }), Some("->"));
+    }
+}
@@ -0,0 +1,116 @@
+use alloc::collections::{BTreeMap, btree_map};
+
+use crate::{FileId, HasSpan, Span};
+
+/// Metadata attached to tokens by their span, e.g. the symbol an identifier resolves to or its
+/// highlight class, without changing the token tree.
+///
+/// Tokens of a file have distinct spans, and so do tokens from different expansions of a
+/// macro; use one table per kind of metadata, or a struct of them as `M`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotations<M> {
+    entries : BTreeMap<Span, M>,
+}
+
+impl<M> Default for Annotations<M> {
+    fn default() -> Self {
+        Self { entries: BTreeMap::new() }
+    }
+}
+
+impl<M> Annotations<M> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Annotates `tok`, returning its previous metadata if any.
+    pub fn insert(&mut self, tok : impl HasSpan, metadata : M) -> Option<M> {
+        self.entries.insert(tok.span(), metadata)
+    }
+
+    pub fn get(&self, tok : impl HasSpan) -> Option<&M> {
+        self.entries.get(&tok.span())
+    }
+
+    pub fn get_mut(&mut self, tok : impl HasSpan) -> Option<&mut M> {
+        self.entries.get_mut(&tok.span())
+    }
+
+    /// The metadata of `tok`, inserting `f()` if it has none.
+    pub fn get_or_insert_with(&mut self, tok : impl HasSpan, f : impl FnOnce() -> M) -> &mut M {
+        self.entries.entry(tok.span()).or_insert_with(f)
+    }
+
+    pub fn remove(&mut self, tok : impl HasSpan) -> Option<M> {
+        self.entries.remove(&tok.span())
+    }
+
+    /// The annotated spans of `file` containing byte `offset`, outermost first, e.g. for hovers.
+    pub fn at_offset(&self, file : FileId, offset : usize) -> impl Iterator<Item = (Span, &M)> {
+        self.iter().filter(move |(span, _)| span.file == file && span.contains(offset))
+    }
+
+    /// All annotations, ordered by span.
+    pub fn iter(&self) -> impl Iterator<Item = (Span, &M)> {
+        self.entries.iter().map(|(span, metadata)| (*span, metadata))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl<M> IntoIterator for Annotations<M> {
+    type Item = (Span, M);
+    type IntoIter = btree_map::IntoIter<Span, M>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Token, TokenizerConfig, tokenize_spanned};
+
+    #[derive(Debug, PartialEq)]
+    enum Highlight {
+        Keyword,
+        Register,
+        Operand,
+    }
+
+    #[test]
+    fn annotations() {
+        let (toks, _) = tokenize_spanned(&TokenizerConfig::default(), "mov r0, [r1]");
+        let mut highlights = Annotations::new();
+        for tok in &toks {
+            match &tok.value {
+                Token::Ident(name) if name == "mov" => { highlights.insert(tok, Highlight::Keyword); },
+                Token::Ident(_) => { highlights.insert(tok, Highlight::Register); },
+                Token::Group(group) => {
+                    highlights.insert(tok, Highlight::Operand);
+                    highlights.insert(&group.tokens[0], Highlight::Register);
+                },
+                _ => {},
+            }
+        }
+
+        assert_eq!(highlights.len(), 4);
+        assert_eq!(highlights.get(&toks[0]), Some(&Highlight::Keyword));
+        assert_eq!(highlights.get(&toks[2]), None);
+        assert_eq!(highlights.at_offset(FileId::default(), 10).map(|(_, h)| h).collect::<Vec<_>>(), vec![&Highlight::Operand, &Highlight::Register]);
+
+        assert_eq!(highlights.remove(&toks[1]), Some(Highlight::Register));
+        assert_eq!(highlights.at_offset(FileId::default(), 5).count(), 0);
+    }
+}
@@ -0,0 +1,125 @@
+//! Unicode NFC normalization, so that composed and decomposed spellings of an identifier,
+//! e.g. `é` and `e` followed by a combining acute accent, compare equal.
+
+mod tables;
+
+use crate::prelude::*;
+use tables::{COMBINING_CLASSES, COMPOSITIONS, DECOMPOSITIONS};
+
+// Hangul syllables are composed of leading consonants, vowels and trailing consonants
+// arithmetically rather than from the tables
+const HANGUL_S : u32 = 0xAC00;
+const HANGUL_L : u32 = 0x1100;
+const HANGUL_V : u32 = 0x1161;
+const HANGUL_T : u32 = 0x11A7;
+const HANGUL_L_COUNT : u32 = 19;
+const HANGUL_V_COUNT : u32 = 21;
+const HANGUL_T_COUNT : u32 = 28;
+const HANGUL_S_COUNT : u32 = HANGUL_L_COUNT * HANGUL_V_COUNT * HANGUL_T_COUNT;
+
+/// The canonical combining class of `c`, 0 for starters.
+pub(crate) fn combining_class(c : char) -> u8 {
+    if c.is_ascii() {
+        return 0;
+    }
+    match COMBINING_CLASSES.binary_search_by(|&(lo, hi, _)| match () {
+        _ if hi < c => core::cmp::Ordering::Less,
+        _ if lo > c => core::cmp::Ordering::Greater,
+        _ => core::cmp::Ordering::Equal,
+    }) {
+        Ok(i) => COMBINING_CLASSES[i].2,
+        Err(_) => 0,
+    }
+}
+
+fn decompose(c : char, out : &mut Vec<char>) {
+    let s = u32::from(c).wrapping_sub(HANGUL_S);
+    if s < HANGUL_S_COUNT {
+        let (l, v, t) = (s / (HANGUL_V_COUNT * HANGUL_T_COUNT), s / HANGUL_T_COUNT % HANGUL_V_COUNT, s % HANGUL_T_COUNT);
+        out.extend([HANGUL_L + l, HANGUL_V + v].into_iter().filter_map(char::from_u32));
+        if t > 0 {
+            out.extend(char::from_u32(HANGUL_T + t));
+        }
+        return;
+    }
+    match DECOMPOSITIONS.binary_search_by_key(&c, |&(c, _)| c) {
+        Ok(i) => out.extend(DECOMPOSITIONS[i].1.chars()),
+        Err(_) => out.push(c),
+    }
+}
+
+fn compose(a : char, b : char) -> Option<char> {
+    let (l, v) = (u32::from(a).wrapping_sub(HANGUL_L), u32::from(b).wrapping_sub(HANGUL_V));
+    if l < HANGUL_L_COUNT && v < HANGUL_V_COUNT {
+        return char::from_u32(HANGUL_S + (l * HANGUL_V_COUNT + v) * HANGUL_T_COUNT);
+    }
+    let (s, t) = (u32::from(a).wrapping_sub(HANGUL_S), u32::from(b).wrapping_sub(HANGUL_T));
+    if s < HANGUL_S_COUNT && s % HANGUL_T_COUNT == 0 && (1..HANGUL_T_COUNT).contains(&t) {
+        return char::from_u32(u32::from(a) + t);
+    }
+    COMPOSITIONS.binary_search_by_key(&(a, b), |&(a, b, _)| (a, b)).ok().map(|i| COMPOSITIONS[i].2)
+}
+
+/// `s` in Normalization Form C: canonically decomposed, then recomposed.
+pub fn to_nfc(s : &str) -> String {
+    if s.is_ascii() {
+        return s.to_string();
+    }
+
+    let mut chars = Vec::with_capacity(s.len());
+    for c in s.chars() {
+        decompose(c, &mut chars);
+    }
+
+    // Canonical ordering: each run of non-starters sorted by combining class, stably
+    let mut start = 0;
+    while start < chars.len() {
+        let len = chars[start..].iter().take_while(|c| combining_class(**c) != 0).count();
+        chars[start..start + len].sort_by_key(|c| combining_class(*c));
+        start += len.max(1);
+    }
+
+    // Composes each char with the last starter, unless a char in between blocks it
+    let mut out : Vec<char> = Vec::with_capacity(chars.len());
+    let mut starter = None;
+    let mut last_class = 0;
+    for c in chars {
+        let class = combining_class(c);
+        if let Some(i) = starter {
+            let blocked = out.len() > i + 1 && (last_class == 0 || last_class >= class);
+            if let Some(composed) = compose(out[i], c).filter(|_| !blocked) {
+                out[i] = composed;
+                continue;
+            }
+        }
+        if class == 0 {
+            starter = Some(out.len());
+        }
+        last_class = class;
+        out.push(c);
+    }
+    out.into_iter().collect()
+}
+
+pub fn is_nfc(s : &str) -> bool {
+    s.is_ascii() || to_nfc(s) == s
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn nfc() {
+        assert_eq!(to_nfc("cafe\u{301}"), "café");
+        assert_eq!(to_nfc("café"), "café");
+        assert_eq!(to_nfc("\u{1E0B}\u{323}"), "\u{1E0D}\u{307}");
+        assert_eq!(to_nfc("a\u{323}\u{302}\u{300}"), "\u{1EAD}\u{300}");
+        assert_eq!(to_nfc("\u{1100}\u{1161}\u{11A8}"), "\u{AC01}");
+        assert_eq!(to_nfc("\u{AC01}"), "\u{AC01}");
+        assert_eq!(to_nfc("\u{958}"), "\u{915}\u{93C}");
+        assert_eq!(to_nfc("\u{212B}"), "\u{C5}");
+        assert!(is_nfc("ascii_only") && is_nfc("é") && !is_nfc("e\u{301}"));
+        assert_eq!(combining_class('\u{301}'), 230);
+    }
+}
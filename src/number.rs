@@ -0,0 +1,145 @@
+//! Parsing the lexemes of numbers into types of the user's choosing, e.g. to reject immediates
+//! that don't fit a 16-bit target at lex time.
+
+use core::fmt;
+
+use crate::prelude::*;
+
+/// Why a number lexeme doesn't parse into a type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberError {
+    /// There are no digits.
+    Empty,
+
+    /// A char isn't a digit of the radix.
+    InvalidDigit(char),
+
+    /// The value doesn't fit the type.
+    OutOfRange,
+}
+
+impl fmt::Display for NumberError {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NumberError::Empty => write!(f, "number has no digits"),
+            NumberError::InvalidDigit(c) => write!(f, "invalid digit `{c}`"),
+            NumberError::OutOfRange => write!(f, "number out of range"),
+        }
+    }
+}
+
+impl core::error::Error for NumberError {}
+
+/// A type numbers can be parsed into, from the digits of their lexemes.
+pub trait FromLexeme : Sized {
+    /// Parses `digits`, which have no sign or radix prefix, in `radix`, negated if `negative`.
+    fn from_lexeme(digits : &str, radix : u32, negative : bool) -> Result<Self, NumberError>;
+}
+
+macro_rules! impl_from_lexeme {
+    ($($ty:ty),*) => {$(
+        impl FromLexeme for $ty {
+            fn from_lexeme(digits : &str, radix : u32, negative : bool) -> Result<Self, NumberError> {
+                if digits.is_empty() {
+                    return Err(NumberError::Empty);
+                }
+                if let Some(c) = digits.chars().find(|c| !c.is_digit(radix)) {
+                    return Err(NumberError::InvalidDigit(c));
+                }
+                // Unsigned types parse no sign, even for zero
+                if negative && digits.trim_start_matches('0').is_empty() {
+                    return Ok(0);
+                }
+                // Parsed with the sign, so that the minimum of signed types fits
+                let digits = if negative { format!("-{digits}") } else { digits.to_string() };
+                <$ty>::from_str_radix(&digits, radix).map_err(|_| NumberError::OutOfRange)
+            }
+        }
+    )*};
+}
+
+impl_from_lexeme!(i8, i16, i32, i64, i128, u8, u16, u32, u64, u128);
+
+/// The sign, radix and digits of a number lexeme, e.g. `(false, 16, "ff")` for `0xff`.
+/// Decimals with leading zeros are read as decimals.
+pub fn split_lexeme(lexeme : &str) -> (bool, u32, &str) {
+    let (negative, rest) = match lexeme.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, lexeme),
+    };
+    for (prefix, radix) in [("0x", 16), ("0o", 8), ("0b", 2)] {
+        if let Some(digits) = rest.strip_prefix(prefix) {
+            return (negative, radix, digits);
+        }
+    }
+    (negative, 10, rest)
+}
+
+/// Parses a number lexeme like those of `Token::Number`, e.g. `-12` or `0xff`, into `T`.
+pub fn parse_number<T : FromLexeme>(lexeme : &str) -> Result<T, NumberError> {
+    let (negative, radix, digits) = split_lexeme(lexeme);
+    T::from_lexeme(digits, radix, negative)
+}
+
+/// A `FromLexeme` type the tokenizer checks numbers against, reporting those that don't parse.
+#[derive(Clone, Copy)]
+pub struct NumberType {
+    name : &'static str,
+    check : fn(&str, u32, bool) -> Result<(), NumberError>,
+}
+
+impl NumberType {
+    pub fn of<T : FromLexeme>() -> Self {
+        Self { name: core::any::type_name::<T>(), check: |digits, radix, negative| T::from_lexeme(digits, radix, negative).map(drop) }
+    }
+
+    /// The name of the type, e.g. "u16".
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Whether the digits parse into the type.
+    pub fn check(&self, digits : &str, radix : u32, negative : bool) -> Result<(), NumberError> {
+        (self.check)(digits, radix, negative)
+    }
+}
+
+impl fmt::Debug for NumberType {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("NumberType").field(&self.name).finish()
+    }
+}
+
+/// Types are compared by name, as the same function may have several addresses.
+impl PartialEq for NumberType {
+    fn eq(&self, other : &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for NumberType {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn from_lexeme() {
+        assert_eq!(parse_number::<u16>("0xffff"), Ok(0xffff));
+        assert_eq!(parse_number::<u16>("0x10000"), Err(NumberError::OutOfRange));
+        assert_eq!(parse_number::<u16>("-1"), Err(NumberError::OutOfRange));
+        assert_eq!(parse_number::<u16>("-0"), Ok(0));
+        assert_eq!(parse_number::<i8>("-128"), Ok(-128));
+        assert_eq!(parse_number::<i8>("128"), Err(NumberError::OutOfRange));
+        assert_eq!(parse_number::<i64>("0b101"), Ok(5));
+        assert_eq!(parse_number::<u128>("340282366920938463463374607431768211455"), Ok(u128::MAX));
+        assert_eq!(parse_number::<i32>("0o19"), Err(NumberError::InvalidDigit('9')));
+        assert_eq!(parse_number::<i32>("0x"), Err(NumberError::Empty));
+        assert_eq!(split_lexeme("-007"), (true, 10, "007"));
+
+        assert_eq!(NumberType::of::<u16>().name(), "u16");
+        assert_eq!(NumberType::of::<u16>(), NumberType::of::<u16>());
+        assert_ne!(NumberType::of::<u16>(), NumberType::of::<i16>());
+        assert_eq!(NumberType::of::<i16>().check("8000", 16, false), Err(NumberError::OutOfRange));
+    }
+}
@@ -0,0 +1,744 @@
+//! `TokenScanner`, a cursor for parsing token trees, and the `Parse` trait.
+
+use alloc::collections::BTreeMap;
+use core::cell::Cell;
+use core::convert::Infallible;
+use core::fmt;
+use core::ops::{Bound, RangeBounds};
+
+use crate::prelude::*;
+use crate::instrument;
+use crate::{Delimiter, Diagnostic, DiagnosticKind, HasSpan, ScannerAction, Span, Spanned, Token, TokenStr, Trace, TraceEvent, span_of};
+
+/// Types that can be parsed from tokens, e.g. with the implementations `grammar!` generates.
+pub trait Parse : Sized {
+    fn parse(input : &mut TokenScanner<'_>) -> Result<Self, Box<Diagnostic>>;
+}
+
+impl<T : Parse> Parse for Box<T> {
+    fn parse(input : &mut TokenScanner<'_>) -> Result<Self, Box<Diagnostic>> {
+        T::parse(input).map(Box::new)
+    }
+}
+
+/// The value with the span from its first to its last token.
+impl<T : Parse> Parse for Spanned<T> {
+    fn parse(input : &mut TokenScanner<'_>) -> Result<Self, Box<Diagnostic>> {
+        input.skip_trivia();
+        let (first, start) = (input.pos, input.span());
+        let value = T::parse(input)?;
+        let span = match input.pos > first {
+            true => start.join(input.toks[input.pos - 1].span),
+            false => Span { end: start.start, ..start },
+        };
+        Ok(Spanned::new(value, span))
+    }
+}
+
+/// A budget of steps for parsing, shared by a `TokenScanner` given it with `with_fuel` and the
+/// scanners forked from it, so that backtracking spends it too.
+#[derive(Debug, Default)]
+pub struct Fuel(Cell<u64>);
+
+impl Fuel {
+    pub fn new(steps : u64) -> Self {
+        Self(Cell::new(steps))
+    }
+
+    pub fn remaining(&self) -> u64 {
+        self.0.get()
+    }
+}
+
+/// Why `TokenScanner::scan` failed, or `scan_fallible` with the error `E` of its callback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanErrorKind<E = Infallible> {
+    /// The tokens ended while the callback required more.
+    Eof,
+
+    /// The callback rejected a token right after requiring more.
+    UnexpectedToken,
+
+    /// The `Fuel` of the scanner ran out.
+    OutOfFuel,
+
+    /// The callback returned `ScannerAction::Fail`.
+    Fail(E),
+}
+
+impl<E : fmt::Display> fmt::Display for ScanErrorKind<E> {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ScanErrorKind::Eof => "unexpected end of input",
+            ScanErrorKind::UnexpectedToken => "unexpected token",
+            ScanErrorKind::OutOfFuel => "out of fuel",
+            ScanErrorKind::Fail(err) => return err.fmt(f),
+        })
+    }
+}
+
+/// The error of `TokenScanner::scan`, with where it failed and the tokens around, to report
+/// it meaningfully.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScanError<'a, E = Infallible> {
+    pub kind : ScanErrorKind<E>,
+
+    /// The index of the token it failed at, among those of the scanner.
+    pub index : usize,
+
+    /// The token it failed at, `None` at the end.
+    pub token : Option<&'a Spanned<Token>>,
+
+    /// Up to `SCAN_ERROR_CONTEXT` tokens before and after the one it failed at.
+    pub before : &'a [Spanned<Token>],
+    pub after : &'a [Spanned<Token>],
+
+    pub span : Span,
+}
+
+/// How many tokens around the one it failed at a `ScanError` has.
+pub const SCAN_ERROR_CONTEXT : usize = 3;
+
+impl<E> HasSpan for ScanError<'_, E> {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl<E : fmt::Display> fmt::Display for ScanError<'_, E> {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        let describe = |toks : &[Spanned<Token>]| toks.iter().map(|tok| tok.value.describe()).collect::<Vec<_>>().join(", ");
+        write!(f, "{} at token {}", self.kind, self.index)?;
+        if let Some(tok) = self.token {
+            write!(f, ", {}", tok.value.describe())?;
+        }
+        if !self.before.is_empty() {
+            write!(f, ", after {}", describe(self.before))?;
+        }
+        if !self.after.is_empty() {
+            write!(f, ", before {}", describe(self.after))?;
+        }
+        Ok(())
+    }
+}
+
+impl<E : fmt::Debug + fmt::Display> core::error::Error for ScanError<'_, E> {}
+
+/// A cursor over a slice of token trees, which descends into groups with `expect_group`.
+///
+/// Copying a scanner forks it, to try an alternative and only commit to it on success.
+#[derive(Debug, Clone, Copy)]
+pub struct TokenScanner<'a> {
+    pub(crate) toks : &'a [Spanned<Token>],
+    pub(crate) pos : usize,
+
+    /// Where the tokens end, and the delimiter they end with when inside a group.
+    end : Span,
+    close : Option<char>,
+
+    pub(crate) trace : Option<&'a Trace>,
+    fuel : Option<&'a Fuel>,
+}
+
+/// Positions of scanners saved by name, to scan again from later, e.g. the uses of labels
+/// defined further down in the first pass of an assembler, to resolve them in the second.
+/// A mark keeps the whole scanner, so it can be inside a group.
+#[derive(Debug, Clone, Default)]
+pub struct Marks<'a>(BTreeMap<String, Vec<TokenScanner<'a>>>);
+
+impl<'a> Marks<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks where `input` is under `name`, after the previous marks of that name.
+    pub fn mark(&mut self, name : &str, input : &TokenScanner<'a>) {
+        self.0.entry(name.to_string()).or_default().push(*input);
+    }
+
+    /// Scanners from the marks of `name`, in the order they were placed.
+    pub fn get(&self, name : &str) -> &[TokenScanner<'a>] {
+        self.0.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn remove(&mut self, name : &str) -> Vec<TokenScanner<'a>> {
+        self.0.remove(name).unwrap_or_default()
+    }
+
+    /// The names with marks, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+}
+
+impl<'a> TokenScanner<'a> {
+    pub fn new(toks : &'a [Spanned<Token>]) -> Self {
+        let end = toks.last().map_or(Span::default(), |tok| Span::empty(tok.span.end).with_file(tok.span.file));
+        Self { toks, pos: 0, end, close: None, trace: None, fuel: None }
+    }
+
+    /// A scanner over the tokens at `range` of this one's, sharing them, e.g. to delegate
+    /// parsing an operand field to a function that can't read past it. It keeps the trace and
+    /// fuel, and ends with an empty span right after its last token.
+    ///
+    /// Panics if `range` is out of bounds, like slicing.
+    pub fn slice(&self, range : impl RangeBounds<usize>) -> TokenScanner<'a> {
+        let (start, end) = (range.start_bound().cloned(), range.end_bound().cloned());
+        let toks = &self.toks[(start, end)];
+        let first = match start {
+            Bound::Included(start) => start,
+            Bound::Excluded(start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match toks.last() {
+            Some(tok) => Span::empty(tok.span.end).with_file(tok.span.file),
+            None => self.toks.get(first).map_or(self.end, |tok| Span::empty(tok.span.start).with_file(tok.span.file)),
+        };
+        Self { toks, pos: 0, end, close: None, ..*self }
+    }
+
+    /// Makes consuming each token, in this scanner and those forked from it, spend a step of
+    /// `fuel`. Without any left, `pop` returns `None` and `expect` errors with `LimitExceeded`.
+    pub fn with_fuel(mut self, fuel : &'a Fuel) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    /// Spends a step of fuel, if there is any left.
+    fn burn(&self) -> bool {
+        match self.fuel {
+            Some(fuel) if fuel.remaining() == 0 => false,
+            Some(fuel) => {
+                fuel.0.set(fuel.remaining() - 1);
+                true
+            },
+            None => true,
+        }
+    }
+
+    fn out_of_fuel(&self) -> Box<Diagnostic> {
+        Box::new(Diagnostic::error(self.span(), DiagnosticKind::LimitExceeded("fuel".into())))
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.pos == self.toks.len()
+    }
+
+    /// The index of the next token among those of the scanner, e.g. to report "at token 17" or
+    /// to come back to it with `set_position`.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Moves the cursor to the token at index `pos`, or to the end for the number of tokens.
+    ///
+    /// Panics if `pos` is past the end.
+    pub fn set_position(&mut self, pos : usize) {
+        assert!(pos <= self.toks.len(), "position {pos} is past the end of the {} tokens", self.toks.len());
+        self.pos = pos;
+    }
+
+    pub fn peek(&self) -> Option<&'a Spanned<Token>> {
+        self.toks.get(self.pos)
+    }
+
+    pub fn pop(&mut self) -> Option<&'a Spanned<Token>> {
+        let tok = self.peek()?;
+        if !self.burn() {
+            return None;
+        }
+        self.pos += 1;
+        Some(tok)
+    }
+
+    /// The token `n` tokens after the next one, if it could be popped, as with `Scanner::peek_n`.
+    pub fn peek_n(&self, n : usize) -> Option<&'a Spanned<Token>> {
+        if n >= self.lookahead() {
+            return None;
+        }
+        self.toks.get(self.pos.saturating_add(n))
+    }
+
+    /// Up to the next `n` tokens, as many as could be popped.
+    pub fn peek_slice(&self, n : usize) -> &'a [Spanned<Token>] {
+        &self.toks[self.pos..self.pos.saturating_add(n.min(self.lookahead())).min(self.toks.len())]
+    }
+
+    /// How many tokens the fuel left allows to pop.
+    fn lookahead(&self) -> usize {
+        self.fuel.map_or(usize::MAX, |fuel| usize::try_from(fuel.remaining()).unwrap_or(usize::MAX))
+    }
+
+    /// Runs `cb` on a fork of the scanner, moving this one to where the fork ended only if `cb`
+    /// returns `Some`. Fuel spent on a failed attempt stays spent.
+    pub fn try_scan<U>(&mut self, cb : impl FnOnce(&mut Self) -> Option<U>) -> Option<U> {
+        let mut fork = *self;
+        let res = cb(&mut fork)?;
+        *self = fork;
+        Some(res)
+    }
+
+    /// Like `try_scan`, for a `cb` that fails with an error, e.g. one that `expect`s tokens.
+    pub fn try_parse<U, E>(&mut self, cb : impl FnOnce(&mut Self) -> Result<U, E>) -> Result<U, E> {
+        let mut fork = *self;
+        let res = cb(&mut fork)?;
+        *self = fork;
+        Ok(res)
+    }
+
+    /// The span of the next token, or that of the end of the tokens.
+    pub fn span(&self) -> Span {
+        self.peek().map_or(self.end, |tok| tok.span)
+    }
+
+    pub fn skip_trivia(&mut self) {
+        while self.peek().is_some_and(|tok| tok.is_trivia()) {
+            self.pos += 1;
+        }
+    }
+
+    pub fn parse<T : Parse>(&mut self) -> Result<T, Box<Diagnostic>> {
+        T::parse(self)
+    }
+
+    /// An error at the next token, saying what was expected instead, e.g. "an identifier".
+    pub fn error(&self, expected : impl Into<String>) -> Box<Diagnostic> {
+        let found = match (self.peek(), self.close) {
+            (Some(tok), _) => tok.value.describe(),
+            (None, Some(close)) => format!("`{close}`"),
+            (None, None) => Token::Eof.describe(),
+        };
+        Box::new(Diagnostic::error(self.span(), DiagnosticKind::ExpectedToken { expected: expected.into(), found }))
+    }
+
+    /// Skips trivia and consumes the next token if `cb` accepts it, or errors with `expected`.
+    pub fn expect<T>(&mut self, expected : &str, cb : impl FnOnce(&'a Token) -> Option<T>) -> Result<T, Box<Diagnostic>> {
+        self.skip_trivia();
+        match self.peek().and_then(|tok| cb(&tok.value)) {
+            Some(_) if !self.burn() => Err(self.out_of_fuel()),
+            Some(value) => {
+                self.pos += 1;
+                Ok(value)
+            },
+            None => Err(self.error(expected)),
+        }
+    }
+
+    pub fn expect_ident(&mut self) -> Result<TokenStr, Box<Diagnostic>> {
+        self.expect("an identifier", |tok| match tok {
+            Token::Ident(name) => Some(name.clone()),
+            _ => None,
+        })
+    }
+
+    /// Expects the identifier `keyword`, or the `Token::Keyword` it was lexed as.
+    pub fn expect_keyword(&mut self, keyword : &str) -> Result<(), Box<Diagnostic>> {
+        self.expect(&format!("`{keyword}`"), |tok| matches!(tok, Token::Ident(name) | Token::Keyword(name) if name == keyword).then_some(()))
+    }
+
+    pub fn expect_punct(&mut self, c : char) -> Result<(), Box<Diagnostic>> {
+        self.expect(&format!("`{c}`"), |tok| (*tok == Token::Punct(c)).then_some(()))
+    }
+
+    /// Expects the operator `op`, as lexed with `TokenizerConfig::operators`.
+    pub fn expect_op(&mut self, op : &str) -> Result<(), Box<Diagnostic>> {
+        self.expect(&format!("`{op}`"), |tok| matches!(tok, Token::Op(found) if found == op).then_some(()))
+    }
+
+    pub fn expect_number(&mut self) -> Result<i64, Box<Diagnostic>> {
+        self.expect("a number", |tok| match tok {
+            Token::Number(n) => Some(*n),
+            _ => None,
+        })
+    }
+
+    pub fn expect_string(&mut self) -> Result<TokenStr, Box<Diagnostic>> {
+        self.expect("a string", |tok| match tok {
+            Token::String(value) => Some(value.clone()),
+            _ => None,
+        })
+    }
+
+    pub fn expect_char(&mut self) -> Result<char, Box<Diagnostic>> {
+        self.expect("a char", |tok| match tok {
+            Token::Char(c) => Some(*c),
+            _ => None,
+        })
+    }
+
+    pub fn expect_label(&mut self) -> Result<TokenStr, Box<Diagnostic>> {
+        self.expect("a label", |tok| match tok {
+            Token::Label(name) => Some(name.clone()),
+            _ => None,
+        })
+    }
+
+    pub fn expect_directive(&mut self) -> Result<TokenStr, Box<Diagnostic>> {
+        self.expect("a directive", |tok| match tok {
+            Token::Directive(name) => Some(name.clone()),
+            _ => None,
+        })
+    }
+
+    pub fn expect_register(&mut self) -> Result<TokenStr, Box<Diagnostic>> {
+        self.expect("a register", |tok| match tok {
+            Token::Register(name) => Some(name.clone()),
+            _ => None,
+        })
+    }
+
+    /// Expects a group delimited by `delimiter`, returning a scanner over its tokens.
+    pub fn expect_group(&mut self, delimiter : Delimiter) -> Result<TokenScanner<'a>, Box<Diagnostic>> {
+        self.skip_trivia();
+        let expected = format!("`{}`", delimiter.open());
+        let tok = self.peek().ok_or_else(|| self.error(&*expected))?;
+        match &tok.value {
+            Token::Group(_) if !self.burn() => Err(self.out_of_fuel()),
+            Token::Group(group) if group.delimiter == delimiter => {
+                self.pos += 1;
+                let end = Span::new(tok.span.end.saturating_sub(1).max(tok.span.start), tok.span.end).with_file(tok.span.file);
+                Ok(Self { toks: &group.tokens, pos: 0, end, close: Some(delimiter.close()), trace: self.trace, fuel: self.fuel })
+            },
+            _ => Err(self.error(expected)),
+        }
+    }
+
+    /// Expects nothing but trivia to be left.
+    pub fn expect_end(&mut self) -> Result<(), Box<Diagnostic>> {
+        self.skip_trivia();
+        match self.close {
+            _ if self.is_done() => Ok(()),
+            Some(close) => Err(self.error(format!("`{close}`"))),
+            None => Err(self.error("the end of input")),
+        }
+    }
+
+    fn scan_error<E>(&self, kind : ScanErrorKind<E>) -> ScanError<'a, E> {
+        let toks = self.toks;
+        ScanError {
+            kind,
+            index: self.pos,
+            token: self.peek(),
+            before: &toks[self.pos.saturating_sub(SCAN_ERROR_CONTEXT)..self.pos],
+            after: &toks[(self.pos + 1).min(toks.len())..(self.pos + 1 + SCAN_ERROR_CONTEXT).min(toks.len())],
+            span: self.span(),
+        }
+    }
+
+    /// Like `Scanner::scan`, over the tokens from the cursor.
+    pub fn scan<U>(&mut self, cb : impl Fn(&[Spanned<Token>]) -> ScannerAction<U>) -> Result<Option<U>, ScanError<'a>> {
+        self.scan_fallible(cb)
+    }
+
+    /// Like `scan`, with the span of the tokens the match consumed, e.g. for an error about the
+    /// whole instruction.
+    pub fn scan_spanned<U>(&mut self, cb : impl Fn(&[Spanned<Token>]) -> ScannerAction<U>) -> Result<Option<Spanned<U>>, ScanError<'a>> {
+        let start = self.pos;
+        let res = self.scan(cb)?;
+        let span = span_of(&self.toks[start..self.pos]).unwrap_or(Span::empty(self.span().start));
+        Ok(res.map(|value| Spanned::new(value, span)))
+    }
+
+    /// Applies `cb` with `scan_fallible` until the end of the tokens, yielding each value. After
+    /// an error, including for tokens `cb` matches nothing from, it yields nothing more.
+    pub fn scan_iter<'s, U, E>(&'s mut self, cb : impl Fn(&[Spanned<Token>]) -> ScannerAction<U, E> + 's) -> impl Iterator<Item = Result<U, ScanError<'a, E>>> + 's {
+        let mut failed = false;
+        core::iter::from_fn(move || {
+            if failed || self.is_done() {
+                return None;
+            }
+            let res = match self.scan_fallible(&cb) {
+                Ok(Some(value)) => Ok(value),
+                Ok(None) => Err(self.scan_error(ScanErrorKind::UnexpectedToken)),
+                Err(err) => Err(err),
+            };
+            failed = res.is_err();
+            Some(res)
+        })
+    }
+
+    /// Like `scan`, with a callback that can fail with its own error, e.g. "immediate out of
+    /// range for mov", by returning `ScannerAction::Fail`.
+    pub fn scan_fallible<U, E>(&mut self, cb : impl Fn(&[Spanned<Token>]) -> ScannerAction<U, E>) -> Result<Option<U>, ScanError<'a, E>> {
+        self.scan_with_lookahead(|toks, _| cb(toks))
+    }
+
+    /// Like `scan_fallible`, also giving the callback the token after the sequence, if any, to
+    /// decide on without consuming it.
+    pub fn scan_with_lookahead<U, E>(&mut self, cb : impl Fn(&[Spanned<Token>], Option<&Spanned<Token>>) -> ScannerAction<U, E>) -> Result<Option<U>, ScanError<'a, E>> {
+        let measure = instrument::start("scan");
+        let start = self.pos;
+        let mut request = None;
+        let mut require = false;
+
+        let res = loop {
+            if self.is_done() {
+                break if require { Err(self.scan_error(ScanErrorKind::Eof)) } else { Ok(request) }
+            }
+            if !self.burn() {
+                break Err(self.scan_error(ScanErrorKind::OutOfFuel))
+            }
+
+            self.pos += 1;
+            let action = cb(&self.toks[start..self.pos], self.toks.get(self.pos));
+            if let Some(trace) = self.trace {
+                trace.record(TraceEvent::Scan { tokens: self.toks[start..self.pos].iter().map(|tok| tok.value.describe()).collect(), action: action.name() });
+            }
+            match action {
+                ScannerAction::Return(res) => break Ok(Some(res)),
+                ScannerAction::Request(res) => {
+                    require = false;
+                    request = Some(res);
+                },
+                ScannerAction::Require => require = true,
+                ScannerAction::None => {
+                    self.pos -= 1;
+                    break if require { Err(self.scan_error(ScanErrorKind::UnexpectedToken)) } else { Ok(request) }
+                },
+                ScannerAction::Fail(err) => {
+                    self.pos = start;
+                    break Err(self.scan_error(ScanErrorKind::Fail(err)))
+                },
+            }
+        };
+        measure.finish(self.pos - start, usize::from(res.is_err()));
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{TokenizerConfig, tokenize_spanned};
+
+    #[test]
+    fn scanner() {
+        let config = TokenizerConfig { registers: vec!["r0..r7".to_string()], ..Default::default() };
+        let (toks, _) = tokenize_spanned(&config, "mov r1, [r2 /* base */] \"s\"");
+        let mut input = TokenScanner::new(&toks);
+
+        assert_eq!(input.expect_ident().unwrap(), "mov");
+        let mut fork = input;
+        assert!(fork.expect_number().is_err());
+        assert_eq!(fork.expect_register().unwrap(), "r1");
+        input = fork;
+        input.expect_punct(',').unwrap();
+
+        let mut inner = input.expect_group(Delimiter::Bracket).unwrap();
+        assert_eq!(inner.expect_register().unwrap(), "r2");
+        inner.expect_end().unwrap();
+
+        let err = input.expect_punct(',').unwrap_err();
+        assert_eq!((err.code(), err.span), (Some("expected-token"), Span::new(24, 27)));
+        assert_eq!(err.message(), "expected `,`, found string \"s\"");
+        assert_eq!(input.expect_string().unwrap(), "s");
+        input.expect_end().unwrap();
+        assert_eq!(input.error("more").span, Span::new(27, 27));
+
+        let group = &toks[3].value;
+        let Token::Group(group) = group else { panic!() };
+        let mut inner = TokenScanner { close: Some(']'), end: Span::new(22, 23), ..TokenScanner::new(&group.tokens) };
+        assert_eq!(inner.expect_number().unwrap_err().message(), "expected a number, found register `r2`");
+        inner.pop();
+        assert_eq!(inner.expect_number().unwrap_err().message(), "expected a number, found `]`");
+    }
+
+    #[test]
+    fn positions() {
+        let toks = tokenize_spanned(&TokenizerConfig::default(), "a b c").0;
+        let mut input = TokenScanner::new(&toks);
+        input.expect_ident().unwrap();
+        let mark = input.position();
+        assert_eq!(input.expect_ident().unwrap(), "b");
+        input.set_position(3);
+        assert!(input.is_done());
+        input.set_position(mark);
+        assert_eq!((input.position(), input.expect_ident().unwrap()), (1, "b".into()));
+    }
+
+    #[test]
+    #[should_panic(expected = "position 4 is past the end of the 3 tokens")]
+    fn position_out_of_bounds() {
+        let toks = tokenize_spanned(&TokenizerConfig::default(), "a b c").0;
+        TokenScanner::new(&toks).set_position(4);
+    }
+
+    #[test]
+    fn slices() {
+        let toks = tokenize_spanned(&TokenizerConfig::default(), "mov a, b; nop").0;
+        let input = TokenScanner::new(&toks);
+        let mut operands = input.slice(1..4);
+        assert_eq!(operands.expect_ident().unwrap(), "a");
+        operands.expect_punct(',').unwrap();
+        assert_eq!(operands.expect_ident().unwrap(), "b");
+        let err = operands.expect_punct(';').unwrap_err();
+        assert_eq!((err.span, err.message()), (Span::new(8, 8), "expected `;`, found the end of input".to_string()));
+
+        assert_eq!(input.slice(5..).expect_ident().unwrap(), "nop");
+        assert_eq!(input.slice(2..2).span(), Span::new(5, 5));
+        assert_eq!(input.position(), 0);
+    }
+
+    #[test]
+    fn marks() {
+        // The first pass of an assembler, marking where labels are used
+        fn mark_uses<'a>(mut input : TokenScanner<'a>, marks : &mut Marks<'a>) {
+            while let Some(tok) = input.pop() {
+                match &tok.value {
+                    Token::Ident(name) if name == "jmp" => {
+                        let mut label = input;
+                        marks.mark(&label.expect_ident().unwrap(), &input);
+                    },
+                    Token::Group(group) => mark_uses(TokenScanner::new(&group.tokens), marks),
+                    _ => (),
+                }
+            }
+        }
+
+        let toks = tokenize_spanned(&TokenizerConfig::default(), "jmp end; (jmp start); start: jmp end; end:").0;
+        let mut marks = Marks::new();
+        mark_uses(TokenScanner::new(&toks), &mut marks);
+        assert_eq!(marks.names().collect::<Vec<_>>(), ["end", "start"]);
+
+        let ends : Vec<_> = marks.get("end").iter().map(TokenScanner::span).collect();
+        assert_eq!(ends, [Span::new(4, 7), Span::new(33, 36)]);
+        let mut start = marks.get("start")[0];
+        assert_eq!(start.expect_ident().unwrap(), "start");
+        assert!(start.is_done());
+        assert_eq!(marks.remove("start").len(), 1);
+        assert!(marks.get("start").is_empty());
+    }
+
+    #[test]
+    fn scan() {
+        let toks = tokenize_spanned(&TokenizerConfig::default(), "a = 1; b").0;
+        let mut input = TokenScanner::new(&toks);
+        let assignment = |toks : &[Spanned<Token>]| match toks.len() {
+            1 | 2 => ScannerAction::Require,
+            3 => ScannerAction::Request(()),
+            4 if toks[3].value == Token::Punct(';') => ScannerAction::Return(()),
+            _ => ScannerAction::None,
+        };
+        assert_eq!(input.scan(assignment), Ok(Some(())));
+        assert_eq!(input.scan(assignment).map_err(|err| err.kind), Err(ScanErrorKind::Eof));
+        assert!(input.is_done());
+    }
+
+    #[test]
+    fn scan_spanned() {
+        let (toks, _) = tokenize_spanned(&TokenizerConfig::default(), "add r1, [r2] nop");
+        let mut input = TokenScanner::new(&toks);
+        let instruction = |toks : &[Spanned<Token>]| match toks.len() {
+            4 => ScannerAction::Return(toks[0].value.clone()),
+            _ => ScannerAction::Require,
+        };
+        assert_eq!(input.scan_spanned(instruction).unwrap(), Some(Spanned::new(Token::Ident("add".into()), Span::new(0, 12))));
+        assert_eq!(input.scan_spanned(|_| ScannerAction::<()>::None).unwrap(), None);
+        assert!(input.scan_spanned(instruction).is_err());
+    }
+
+    #[test]
+    fn scan_iter() {
+        let toks = tokenize_spanned(&TokenizerConfig::default(), "a; b; c d").0;
+        let mut input = TokenScanner::new(&toks);
+        let stmt = |toks : &[Spanned<Token>]| match toks {
+            [_] => ScannerAction::Require,
+            [Spanned { value: Token::Ident(name), .. }, Spanned { value: Token::Punct(';'), .. }] => ScannerAction::Return(name.clone()),
+            _ => ScannerAction::<_>::None,
+        };
+        let stmts : Vec<_> = input.scan_iter(stmt).map(|res| res.map_err(|err| (err.kind, err.index))).collect();
+        assert_eq!(stmts, [Ok("a".into()), Ok("b".into()), Err((ScanErrorKind::UnexpectedToken, 5))]);
+        assert_eq!(input.position(), 5);
+    }
+
+    #[test]
+    fn lookahead() {
+        let toks = tokenize_spanned(&TokenizerConfig::default(), "a b = 1").0;
+        let mut input = TokenScanner::new(&toks);
+        // An identifier is a name unless an `=` follows it
+        let name = |toks : &[Spanned<Token>], next : Option<&Spanned<Token>>| match (toks, next.map(|tok| &tok.value)) {
+            (_, Some(Token::Punct('='))) => ScannerAction::<_>::None,
+            ([Spanned { value: Token::Ident(name), .. }], _) => ScannerAction::Return(name.clone()),
+            _ => ScannerAction::None,
+        };
+        assert_eq!(input.scan_with_lookahead(name), Ok(Some("a".into())));
+        assert_eq!(input.scan_with_lookahead(name), Ok(None));
+        assert_eq!(input.position(), 1);
+    }
+
+    #[test]
+    fn backtracking() {
+        let toks = tokenize_spanned(&TokenizerConfig::default(), "a = 1").0;
+        let mut input = TokenScanner::new(&toks);
+        assert_eq!(input.peek_n(2).map(|tok| &tok.value), Some(&Token::Number(1)));
+        assert_eq!(input.peek_n(3), None);
+        assert_eq!(input.peek_slice(2), &toks[..2]);
+        assert_eq!(input.peek_slice(9), &toks[..]);
+
+        assert!(input.try_parse(|input| {
+            input.expect_ident()?;
+            input.expect_group(Delimiter::Paren)
+        }).is_err());
+        assert_eq!(input.position(), 0);
+        let name = input.try_scan(|input| input.expect_ident().ok().filter(|_| input.expect_punct('=').is_ok()));
+        assert_eq!((name, input.position()), (Some("a".into()), 2));
+        assert_eq!(input.try_scan(|input| input.pop().filter(|_| false)), None);
+        assert_eq!(input.position(), 2);
+        assert_eq!(input.peek_n(usize::MAX), None);
+
+        // Lookahead stops where the fuel does, like that of `Scanner`
+        let fuel = Fuel::new(2);
+        let input = TokenScanner::new(&toks).with_fuel(&fuel);
+        assert_eq!((input.peek_n(1), input.peek_n(2)), (Some(&toks[1]), None));
+        assert_eq!(input.peek_slice(9), &toks[..2]);
+    }
+
+    #[test]
+    fn scan_errors() {
+        let toks = tokenize_spanned(&TokenizerConfig::default(), "a b c = = d e f g").0;
+        let mut input = TokenScanner::new(&toks);
+        input.set_position(3);
+        let err = input.scan(|toks : &[Spanned<Token>]| match toks.len() {
+            1 => ScannerAction::Require,
+            _ => ScannerAction::<()>::None,
+        }).unwrap_err();
+        assert_eq!((err.kind, err.index, err.token, err.span), (ScanErrorKind::UnexpectedToken, 4, Some(&toks[4]), Span::new(8, 9)));
+        assert_eq!((err.before, err.after), (&toks[1..4], &toks[5..8]));
+        assert_eq!(err.to_string(), "unexpected token at token 4, `=`, after identifier `b`, identifier `c`, `=`, before identifier `d`, identifier `e`, identifier `f`");
+    }
+
+    #[test]
+    fn scan_fallible() {
+        let toks = tokenize_spanned(&TokenizerConfig::default(), "mov 300; mov 3;").0;
+        let mut input = TokenScanner::new(&toks);
+        let mov = |toks : &[Spanned<Token>]| match toks {
+            [_] | [_, _] => ScannerAction::Require,
+            [_, imm, _] => match imm.value {
+                Token::Number(n @ 0..=255) => ScannerAction::Return(n),
+                _ => ScannerAction::Fail("immediate out of range for mov"),
+            },
+            _ => ScannerAction::None,
+        };
+        let err = input.scan_fallible(mov).unwrap_err();
+        assert_eq!((err.kind, err.index), (ScanErrorKind::Fail("immediate out of range for mov"), 0));
+        assert_eq!(err.to_string(), "immediate out of range for mov at token 0, identifier `mov`, before number 300, `;`, identifier `mov`");
+        input.set_position(3);
+        assert_eq!(input.scan_fallible(mov), Ok(Some(3)));
+    }
+
+    #[test]
+    fn fuel() {
+        let toks = tokenize_spanned(&TokenizerConfig::default(), "a (b) c d").0;
+        let fuel = Fuel::new(3);
+        let mut input = TokenScanner::new(&toks).with_fuel(&fuel);
+        let mut fork = input;
+        assert_eq!(fork.expect_ident().unwrap(), "a");
+        input.expect_ident().unwrap();
+        assert_eq!(input.expect_group(Delimiter::Paren).unwrap().pop().map(|tok| &tok.value), None);
+        assert_eq!(fuel.remaining(), 0);
+
+        let err = input.expect_ident().unwrap_err();
+        assert_eq!((err.code(), err.span), (Some("limit-exceeded"), Span::new(6, 7)));
+        assert!(input.pop().is_none() && !input.is_done());
+    }
+}
@@ -0,0 +1,187 @@
+//! Finding and replacing token sequences.
+
+use alloc::collections::BTreeMap;
+
+use crate::prelude::*;
+use crate::{Delimiter, Group, Span, Spanned, Token, tokenize};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Elem {
+    Token(Token),
+    Capture(String),
+    Wildcard,
+    Group(Delimiter, Vec<Elem>),
+}
+
+fn elems<T : AsRef<Token>>(toks : &[T]) -> Vec<Elem> {
+    let mut res = Vec::with_capacity(toks.len());
+    let mut toks = toks.iter().map(AsRef::as_ref).peekable();
+    while let Some(tok) = toks.next() {
+        res.push(match tok {
+            Token::Punct('$') => match toks.next_if(|tok| matches!(tok, Token::Ident(_))) {
+                Some(Token::Ident(name)) if name == "_" => Elem::Wildcard,
+                Some(Token::Ident(name)) => Elem::Capture(name.to_string()),
+                _ => Elem::Token(Token::Punct('$')),
+            },
+            Token::Group(group) => Elem::Group(group.delimiter, elems(&group.tokens)),
+            tok => Elem::Token(tok.clone()),
+        });
+    }
+    res
+}
+
+/// A sequence of tokens to look for, in which `$name` matches any one token (or group) and
+/// captures it under `name`, and `$_` matches any one token without capturing it.
+///
+/// Spans are ignored when comparing tokens, and comments are compared like any other token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    elems : Vec<Elem>,
+}
+
+/// A place where a `Pattern` matched.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    /// From the first to the last matched token.
+    pub span : Span,
+    pub captures : BTreeMap<String, Spanned<Token>>,
+}
+
+impl Pattern {
+    pub fn new<T : AsRef<Token>>(toks : &[T]) -> Self {
+        Self { elems: elems(toks) }
+    }
+
+    /// The pattern written as `code`, e.g. `mov $dst, $src`.
+    pub fn parse(code : &str) -> Self {
+        Self::new(&tokenize(code))
+    }
+
+    /// The non-overlapping matches in `toks` and the groups inside it, in source order.
+    pub fn find(&self, toks : &[Spanned<Token>]) -> Vec<Match> {
+        let mut matches = Vec::new();
+        self.find_into(toks, &mut matches);
+        matches
+    }
+
+    fn find_into(&self, toks : &[Spanned<Token>], matches : &mut Vec<Match>) {
+        let mut i = 0;
+        while i < toks.len() {
+            if let Some(m) = self.match_at(&toks[i..]) {
+                matches.push(m);
+                i += self.elems.len();
+                continue;
+            }
+            if let Token::Group(group) = &toks[i].value {
+                self.find_into(&group.tokens, matches);
+            }
+            i += 1;
+        }
+    }
+
+    /// Matches the pattern against the start of `toks`.
+    fn match_at(&self, toks : &[Spanned<Token>]) -> Option<Match> {
+        if self.elems.is_empty() || toks.len() < self.elems.len() {
+            return None;
+        }
+        let toks = &toks[..self.elems.len()];
+        let mut captures = BTreeMap::new();
+        match_elems(&self.elems, toks, &mut captures).then(|| {
+            let span = toks[0].span.join(toks[toks.len() - 1].span);
+            Match { span, captures }
+        })
+    }
+
+    /// Replaces every match in `toks` with `template`, in which `$name` stands for the token
+    /// captured under `name`. Returns the new tokens and the spans of the replaced regions.
+    ///
+    /// Tokens from the template get the span of the match they replace; where `$name` isn't
+    /// captured by the pattern, it is kept as is.
+    pub fn replace(&self, toks : Vec<Spanned<Token>>, template : &Pattern) -> (Vec<Spanned<Token>>, Vec<Span>) {
+        let mut replaced = Vec::new();
+        let toks = self.replace_in(toks, template, &mut replaced);
+        (toks, replaced)
+    }
+
+    fn replace_in(&self, toks : Vec<Spanned<Token>>, template : &Pattern, replaced : &mut Vec<Span>) -> Vec<Spanned<Token>> {
+        let mut res = Vec::with_capacity(toks.len());
+        let mut i = 0;
+        while i < toks.len() {
+            if let Some(m) = self.match_at(&toks[i..]) {
+                substitute(&template.elems, &m, &mut res);
+                replaced.push(m.span);
+                i += self.elems.len();
+                continue;
+            }
+            let tok = toks[i].clone();
+            res.push(match tok.value {
+                Token::Group(group) => {
+                    let tokens = self.replace_in(group.tokens, template, replaced);
+                    Spanned::new(Token::from(Group { tokens, ..*group }), tok.span)
+                },
+                _ => tok,
+            });
+            i += 1;
+        }
+        res
+    }
+}
+
+fn match_elems(elems : &[Elem], toks : &[Spanned<Token>], captures : &mut BTreeMap<String, Spanned<Token>>) -> bool {
+    elems.len() == toks.len() && elems.iter().zip(toks).all(|(elem, tok)| match (elem, &tok.value) {
+        (Elem::Wildcard, _) => true,
+        (Elem::Capture(name), _) => {
+            captures.insert(name.clone(), tok.clone());
+            true
+        },
+        (Elem::Group(delimiter, elems), Token::Group(group)) => {
+            *delimiter == group.delimiter && match_elems(elems, &group.tokens, captures)
+        },
+        (Elem::Token(expected), tok) => expected == tok,
+        _ => false,
+    })
+}
+
+fn substitute(elems : &[Elem], m : &Match, res : &mut Vec<Spanned<Token>>) {
+    for elem in elems {
+        match elem {
+            Elem::Token(tok) => res.push(Spanned::new(tok.clone(), m.span)),
+            Elem::Capture(name) if m.captures.contains_key(name) => res.push(m.captures[name].clone()),
+            Elem::Capture(name) => {
+                res.push(Spanned::new(Token::Punct('$'), m.span));
+                res.push(Spanned::new(Token::Ident(name.as_str().into()), m.span));
+            },
+            Elem::Wildcard => {
+                res.push(Spanned::new(Token::Punct('$'), m.span));
+                res.push(Spanned::new(Token::Ident("_".into()), m.span));
+            },
+            Elem::Group(delimiter, elems) => {
+                let mut tokens = Vec::new();
+                substitute(elems, m, &mut tokens);
+                res.push(Spanned::new(Token::from(Group::new(*delimiter, tokens)), m.span));
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{TokenizerConfig, tokenize_spanned};
+
+    #[test]
+    fn find_replace() {
+        let code = "mov r0, [r1]; (mov r2, 4); mov r3";
+        let (toks, _) = tokenize_spanned(&TokenizerConfig::default(), code);
+        let pattern = Pattern::parse("mov $dst, $src");
+
+        let matches = pattern.find(&toks);
+        assert_eq!(matches.iter().map(|m| &code[m.span.range()]).collect::<Vec<_>>(), vec!["mov r0, [r1]", "mov r2, 4"]);
+        assert_eq!(matches[1].captures["src"].value, Token::Number(4));
+        assert_eq!(Pattern::parse("mov $_, [$_]").find(&toks).len(), 1);
+
+        let (toks, replaced) = pattern.replace(toks, &Pattern::parse("movl $src, $dst $other"));
+        assert_eq!(replaced, vec![Span::new(0, 12), Span::new(15, 24)]);
+        assert!(crate::structural_eq(&toks, &tokenize("movl [r1], r0 $other; (movl 4, r2 $other); mov r3")));
+    }
+}
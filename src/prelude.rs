@@ -0,0 +1,12 @@
+//! The parts of the `std` prelude that `alloc` provides, for `no_std` builds.
+
+#![allow(unused_imports)]
+
+pub(crate) use alloc::{
+    borrow::ToOwned,
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
@@ -0,0 +1,549 @@
+//! Token-level preprocessing passes run between lexing and parsing.
+
+use alloc::collections::{BTreeMap, VecDeque};
+
+use crate::prelude::*;
+use crate::{Delimiter, Diagnostic, DiagnosticKind, ExpansionId, ExpansionKind, FileId, Group, SourceFile, SourceMap, Span, Spanned, Token, TokenizerConfig, tokenize};
+
+/// Replaces include directives with the tokens of the files they name.
+///
+/// A directive is the configured sequence of tokens followed by a string literal holding the
+/// file name, e.g. `.include "file.s"`. Only directives at the top level of a file are
+/// recognized, not inside groups. Included files are added to the `SourceMap` (or reused if
+/// it already has a file of that name), so the spliced tokens point into the right file, and
+/// their spans refer to an `ExpansionKind::Include` expansion whose call site is the directive.
+pub struct IncludeResolver {
+    directive : Vec<Token>,
+    config : TokenizerConfig,
+}
+
+impl IncludeResolver {
+    /// A resolver for directives starting with `directive`, e.g. `tokenize(".include")`.
+    pub fn new(directive : Vec<Token>) -> Self {
+        assert!(!directive.is_empty(), "empty include directive");
+        Self { directive, config: TokenizerConfig::default() }
+    }
+
+    /// Sets the configuration used to tokenize every file.
+    pub fn with_config(mut self, config : TokenizerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Tokenizes `file` with its includes spliced in. `load` is given the name of a file to include
+    /// and the file including it, and returns its contents, or `None` if it doesn't exist.
+    pub fn resolve(
+        &self,
+        map : &mut SourceMap,
+        file : FileId,
+        mut load : impl FnMut(&str, &SourceFile) -> Option<String>,
+    ) -> (Vec<Spanned<Token>>, Vec<Diagnostic>) {
+        let mut diags = Vec::new();
+        let toks = self.resolve_file(map, file, None, &mut load, &mut vec![(file, None)], &mut diags);
+        crate::sort_diagnostics(&mut diags);
+        (toks, diags)
+    }
+
+    fn resolve_file(
+        &self,
+        map : &mut SourceMap,
+        file : FileId,
+        expansion : Option<ExpansionId>,
+        load : &mut impl FnMut(&str, &SourceFile) -> Option<String>,
+        stack : &mut Vec<(FileId, Option<Span>)>,
+        diags : &mut Vec<Diagnostic>,
+    ) -> Vec<Spanned<Token>> {
+        let (mut toks, file_diags) = map.tokenize_with(&self.config, file);
+        diags.extend(file_diags);
+        if let Some(expansion) = expansion {
+            set_expansion(&mut toks, expansion);
+        }
+
+        let mut res = Vec::with_capacity(toks.len());
+        let mut toks = toks.into_iter().peekable();
+        let mut pending = Vec::new();
+
+        while let Some(tok) = toks.next() {
+            pending.push(tok);
+            while !self.directive.iter().zip(&pending).all(|(a, b)| *a == b.value) {
+                res.push(pending.remove(0));
+            }
+            if pending.len() < self.directive.len() {
+                continue;
+            }
+
+            let start = pending[0].span;
+            pending.clear();
+            let Some(Spanned { value: Token::String(name), span }) = toks.next_if(|tok| matches!(tok.value, Token::String(_))) else {
+                let end = toks.peek().map_or(start, |tok| tok.span);
+                diags.push(Diagnostic::error(start.join(end), DiagnosticKind::ExpectedIncludePath));
+                continue;
+            };
+
+            let span = start.join(span);
+            let included = match map.find(&name) {
+                Some(id) => id,
+                None => match load(&name, map.get(file)) {
+                    Some(code) => map.add(name.clone(), code),
+                    None => {
+                        diags.push(Diagnostic::error(span, DiagnosticKind::IncludeNotFound(name.into_string())));
+                        continue;
+                    },
+                },
+            };
+
+            if stack.iter().any(|(id, _)| *id == included) {
+                diags.push(self.cycle(map, span, included, stack));
+                continue;
+            }
+
+            stack.push((included, Some(span)));
+            let expansion = map.add_expansion(ExpansionKind::Include(name.into_string()), span);
+            res.extend(self.resolve_file(map, included, Some(expansion), load, stack, diags));
+            stack.pop();
+        }
+
+        res.append(&mut pending);
+        res
+    }
+
+    /// The error for the directive at `span` including `file`, which is already being included
+    /// by `stack`, with notes pointing at the directives that form the cycle.
+    fn cycle(&self, map : &SourceMap, span : Span, file : FileId, stack : &[(FileId, Option<Span>)]) -> Diagnostic {
+        let mut diagnostic = Diagnostic::error(span, DiagnosticKind::IncludeCycle(map.name(file).to_string()));
+        let cycle = &stack[stack.iter().position(|(id, _)| *id == file).unwrap()..];
+        for (included, directive) in cycle {
+            if let Some(directive) = directive {
+                let name = map.name(*included).to_string();
+                diagnostic = diagnostic.with_note(Diagnostic::note(*directive, DiagnosticKind::IncludedFrom(name)));
+            }
+        }
+        diagnostic
+    }
+}
+
+struct Macro {
+    params : Vec<String>,
+    body : Vec<Spanned<Token>>,
+}
+
+/// Expands invocations of token-tree macros.
+///
+/// A macro without parameters is invoked by its name alone; one with parameters by its name
+/// followed by a parenthesized, comma-separated argument list, e.g. `push2(r0, r1)`. Without
+/// the argument list its name is left as is. Occurrences of the parameters in the body are
+/// replaced by the arguments, and the result is expanded again, up to the recursion limit.
+///
+/// Tokens coming from a body keep the spans of the definition, and arguments those of the
+/// invocation, so diagnostics about expanded code point at the text it came from. The spans of
+/// body tokens also refer to an `ExpansionKind::Macro` expansion recorded in the `SourceMap`,
+/// whose call site is the macro name in the invocation.
+pub struct MacroExpander {
+    macros : BTreeMap<String, Macro>,
+    limit : usize,
+}
+
+impl Default for MacroExpander {
+    fn default() -> Self {
+        Self { macros: BTreeMap::new(), limit: 64 }
+    }
+}
+
+impl MacroExpander {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets how many expansions may be nested, 64 by default.
+    pub fn with_limit(mut self, limit : usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Defines, or redefines, the macro `name`.
+    pub fn define(&mut self, name : impl Into<String>, params : Vec<String>, body : Vec<Spanned<Token>>) {
+        self.macros.insert(name.into(), Macro { params, body });
+    }
+
+    pub fn is_defined(&self, name : &str) -> bool {
+        self.macros.contains_key(name)
+    }
+
+    pub fn expand(&self, map : &mut SourceMap, toks : Vec<Spanned<Token>>) -> (Vec<Spanned<Token>>, Vec<Diagnostic>) {
+        let mut res = Vec::with_capacity(toks.len());
+        let mut diags = Vec::new();
+        self.expand_into(map, toks, 0, &mut res, &mut diags);
+        crate::sort_diagnostics(&mut diags);
+        (res, diags)
+    }
+
+    fn expand_into(
+        &self,
+        map : &mut SourceMap,
+        toks : Vec<Spanned<Token>>,
+        depth : usize,
+        res : &mut Vec<Spanned<Token>>,
+        diags : &mut Vec<Diagnostic>,
+    ) {
+        let mut toks = toks.into_iter().peekable();
+        while let Some(tok) = toks.next() {
+            let (name, mac) = match tok.value {
+                Token::Ident(ref name) if self.macros.contains_key(name.as_str()) => (name.to_string(), &self.macros[name.as_str()]),
+                Token::Group(group) => {
+                    let mut inner = Vec::with_capacity(group.tokens.len());
+                    self.expand_into(map, group.tokens, depth, &mut inner, diags);
+                    res.push(Spanned::new(Token::from(Group { tokens: inner, ..*group }), tok.span));
+                    continue;
+                },
+                _ => {
+                    res.push(tok);
+                    continue;
+                },
+            };
+
+            let mut invocation = vec![tok];
+            let args = if mac.params.is_empty() {
+                Vec::new()
+            } else if let Some(args) = toks.next_if(|tok| matches!(&tok.value, Token::Group(g) if g.delimiter == Delimiter::Paren)) {
+                invocation.push(args.clone());
+                let Token::Group(group) = args.value else { unreachable!() };
+                split_args(group.tokens)
+            } else {
+                res.extend(invocation);
+                continue;
+            };
+
+            let span = invocation[0].span;
+            if args.len() != mac.params.len() {
+                diags.push(Diagnostic::error(span, DiagnosticKind::MacroArity { name, expected: mac.params.len(), found: args.len() }));
+                res.extend(invocation);
+            } else if depth == self.limit {
+                diags.push(Diagnostic::error(span, DiagnosticKind::MacroRecursionLimit(name)));
+                res.extend(invocation);
+            } else {
+                let expansion = map.add_expansion(ExpansionKind::Macro(name), span);
+                let expanded = substitute(&mac.body, &mac.params, &args, expansion);
+                self.expand_into(map, expanded, depth + 1, res, diags);
+            }
+        }
+    }
+}
+
+/// Splits the tokens of an argument list at its top-level commas.
+fn split_args(toks : Vec<Spanned<Token>>) -> Vec<Vec<Spanned<Token>>> {
+    if toks.is_empty() {
+        return Vec::new();
+    }
+    let mut args = vec![Vec::new()];
+    for tok in toks {
+        match tok.value {
+            Token::Punct(',') => args.push(Vec::new()),
+            _ => args.last_mut().unwrap().push(tok),
+        }
+    }
+    args
+}
+
+fn substitute(body : &[Spanned<Token>], params : &[String], args : &[Vec<Spanned<Token>>], expansion : ExpansionId) -> Vec<Spanned<Token>> {
+    let mut res = Vec::with_capacity(body.len());
+    for tok in body {
+        let span = tok.span.with_expansion(expansion);
+        match &tok.value {
+            Token::Ident(name) => match params.iter().position(|param| param == name) {
+                Some(i) => res.extend(args[i].iter().cloned()),
+                None => res.push(Spanned::new(tok.value.clone(), span)),
+            },
+            Token::Group(group) => {
+                let tokens = substitute(&group.tokens, params, args, expansion);
+                res.push(Spanned::new(Token::from(Group { tokens, ..(**group).clone() }), span));
+            },
+            _ => res.push(Spanned::new(tok.value.clone(), span)),
+        }
+    }
+    res
+}
+
+fn set_expansion(toks : &mut [Spanned<Token>], expansion : ExpansionId) {
+    for tok in toks {
+        tok.span = tok.span.with_expansion(expansion);
+        if let Token::Group(group) = &mut tok.value {
+            set_expansion(&mut group.tokens, expansion);
+        }
+    }
+}
+
+/// The directives delimiting conditional sections, C-style (`#if`, `#ifdef`, `#else`, `#endif`) by default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConditionalSyntax {
+    /// Followed by a number or symbol, the section is kept if it is non-zero.
+    pub if_directive : Vec<Token>,
+
+    /// Followed by a symbol, the section is kept if it is defined.
+    pub ifdef_directive : Vec<Token>,
+    pub else_directive : Vec<Token>,
+    pub endif_directive : Vec<Token>,
+}
+
+impl Default for ConditionalSyntax {
+    fn default() -> Self {
+        Self {
+            if_directive: tokenize("#if"),
+            ifdef_directive: tokenize("#ifdef"),
+            else_directive: tokenize("#else"),
+            endif_directive: tokenize("#endif"),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Directive {
+    If,
+    Ifdef,
+    Else,
+    Endif,
+}
+
+struct Section {
+    start : Span,
+    parent_active : bool,
+    active : bool,
+    taken : bool,
+    has_else : bool,
+}
+
+/// Removes the sections of conditional directives whose condition doesn't hold, along with
+/// the directives themselves.
+///
+/// Conditions are evaluated against a symbol table given to `resolve`. Kept tokens are not
+/// modified, so their spans still point at the original text. A section must open and close
+/// within the same group.
+#[derive(Default)]
+pub struct ConditionalResolver {
+    syntax : ConditionalSyntax,
+}
+
+impl ConditionalResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_syntax(mut self, syntax : ConditionalSyntax) -> Self {
+        self.syntax = syntax;
+        self
+    }
+
+    pub fn resolve(&self, toks : Vec<Spanned<Token>>, symbols : &BTreeMap<String, i64>) -> (Vec<Spanned<Token>>, Vec<Diagnostic>) {
+        let mut diags = Vec::new();
+        let toks = self.resolve_list(toks, symbols, &mut diags);
+        crate::sort_diagnostics(&mut diags);
+        (toks, diags)
+    }
+
+    /// The longest directive at the front of `toks`, and its length.
+    fn directive(&self, toks : &VecDeque<Spanned<Token>>) -> Option<(Directive, usize)> {
+        let syntax = &self.syntax;
+        [
+            (Directive::If, &syntax.if_directive),
+            (Directive::Ifdef, &syntax.ifdef_directive),
+            (Directive::Else, &syntax.else_directive),
+            (Directive::Endif, &syntax.endif_directive),
+        ]
+        .into_iter()
+        .filter(|(_, directive)| !directive.is_empty() && directive.len() <= toks.len())
+        .filter(|(_, directive)| directive.iter().zip(toks).all(|(a, b)| *a == b.value))
+        .max_by_key(|(_, directive)| directive.len())
+        .map(|(kind, directive)| (kind, directive.len()))
+    }
+
+    fn resolve_list(&self, toks : Vec<Spanned<Token>>, symbols : &BTreeMap<String, i64>, diags : &mut Vec<Diagnostic>) -> Vec<Spanned<Token>> {
+        let mut toks = VecDeque::from(toks);
+        let mut res = Vec::with_capacity(toks.len());
+        let mut sections : Vec<Section> = Vec::new();
+
+        while !toks.is_empty() {
+            let active = sections.last().is_none_or(|section| section.active);
+            let Some((directive, len)) = self.directive(&toks) else {
+                let tok = toks.pop_front().unwrap();
+                match tok.value {
+                    Token::Group(group) if active => {
+                        let tokens = self.resolve_list(group.tokens, symbols, diags);
+                        res.push(Spanned::new(Token::from(Group { tokens, ..*group }), tok.span));
+                    },
+                    _ if active => res.push(tok),
+                    _ => {},
+                }
+                continue;
+            };
+
+            let span = toks.drain(..len).map(|tok| tok.span).reduce(Span::join).unwrap();
+            match directive {
+                Directive::If | Directive::Ifdef => {
+                    let is_cond = toks.front().is_some_and(|tok| matches!(tok.value, Token::Ident(_) | Token::Number(_)));
+                    let cond = if is_cond { toks.pop_front() } else { None };
+                    let taken = match cond.map(Spanned::into_inner) {
+                        Some(Token::Ident(name)) if matches!(directive, Directive::Ifdef) => symbols.contains_key(name.as_str()),
+                        Some(Token::Ident(name)) => symbols.get(name.as_str()).is_some_and(|value| *value != 0),
+                        Some(Token::Number(value)) => value != 0,
+                        _ => {
+                            diags.push(Diagnostic::error(span, DiagnosticKind::ExpectedCondition));
+                            false
+                        },
+                    };
+                    sections.push(Section { start: span, parent_active: active, active: active && taken, taken, has_else: false });
+                },
+                Directive::Else => match sections.last_mut() {
+                    Some(section) if section.has_else => diags.push(Diagnostic::error(span, DiagnosticKind::DuplicateElse)),
+                    Some(section) => {
+                        section.active = section.parent_active && !section.taken;
+                        section.has_else = true;
+                    },
+                    None => diags.push(Diagnostic::error(span, DiagnosticKind::UnmatchedConditional)),
+                },
+                Directive::Endif => if sections.pop().is_none() {
+                    diags.push(Diagnostic::error(span, DiagnosticKind::UnmatchedConditional));
+                },
+            }
+        }
+
+        for section in sections {
+            diags.push(Diagnostic::error(section.start, DiagnosticKind::UnterminatedConditional));
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tokenize_spanned;
+
+    fn files(name : &str, _ : &SourceFile) -> Option<String> {
+        match name {
+            "regs.s" => Some("r0 r1".to_string()),
+            "a.s" => Some(".include \"b.s\" a".to_string()),
+            "b.s" => Some(".include \"a.s\" b".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn include() {
+        let mut map = SourceMap::new();
+        let main = map.add("main.s", "mov . .include \"regs.s\"\n.include \"missing.s\" . include 1");
+        let resolver = IncludeResolver::new(tokenize(".include"));
+        let (toks, diags) = resolver.resolve(&mut map, main, files);
+
+        let regs = map.find("regs.s").unwrap();
+        assert_eq!(toks.iter().map(|tok| map.snippet(tok.span)).collect::<Vec<_>>(), vec!["mov", ".", "r0", "r1", "1"]);
+        let expansion = toks[2].span.expansion.unwrap();
+        assert_eq!(toks[2].span, Span::new(0, 2).with_file(regs).with_expansion(expansion));
+        assert_eq!(map.expansion(expansion).kind, ExpansionKind::Include("regs.s".to_string()));
+        assert_eq!(map.snippet(map.expansion(expansion).call_site), ".include \"regs.s\"");
+        assert!(!toks[0].span.from_expansion() && !map.is_generated(toks[2].span));
+        assert_eq!(diags.iter().map(|d| (d.code().unwrap(), map.snippet(d.span))).collect::<Vec<_>>(), vec![
+            ("include-not-found", ".include \"missing.s\""),
+            ("expected-include-path", ". include 1"),
+        ]);
+    }
+
+    #[test]
+    fn cycle() {
+        let mut map = SourceMap::new();
+        let main = map.add("main.s", ".include \"a.s\"");
+        let (toks, diags) = IncludeResolver::new(tokenize(".include")).resolve(&mut map, main, files);
+
+        assert_eq!(toks.iter().map(|tok| map.snippet(tok.span)).collect::<Vec<_>>(), vec!["b", "a"]);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(map.render(&diags[0]), concat!(
+            "b.s:1:1: error: `a.s` includes itself\n",
+            "  main.s:1:1: note: `a.s` included from here\n",
+            "  a.s:1:1: note: `b.s` included from here",
+        ));
+    }
+
+    fn spanned(code : &str) -> Vec<Spanned<Token>> {
+        tokenize_spanned(&TokenizerConfig::default(), code).0
+    }
+
+    fn names(toks : &[Spanned<Token>]) -> String {
+        toks.iter().map(|tok| match &tok.value {
+            Token::Ident(name) => name.to_string(),
+            Token::Punct(c) => c.to_string(),
+            Token::Number(n) => n.to_string(),
+            Token::Group(group) => format!("{}{}{}", group.delimiter.open(), names(&group.tokens), group.delimiter.close()),
+            tok => format!("{tok:?}"),
+        }).collect::<Vec<_>>().join(" ")
+    }
+
+    #[test]
+    fn macros() {
+        let mut macros = MacroExpander::new();
+        macros.define("SP", vec![], spanned("r13"));
+        macros.define("push", vec!["r".to_string()], spanned("sub SP, 4\nstr r, [SP]"));
+        macros.define("push2", vec!["a".to_string(), "b".to_string()], spanned("push(a) push(b)"));
+
+        let code = "push2(r0, (1 2)) push [push(r3)] push2(r0)";
+        let (toks, diags) = macros.expand(&mut SourceMap::new(), spanned(code));
+        assert_eq!(names(&toks), concat!(
+            "sub r13 , 4 str r0 , [r13] sub r13 , 4 str (1 2) , [r13] ",
+            "push [sub r13 , 4 str r3 , [r13]] push2 (r0)",
+        ));
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].message(), "macro `push2` takes 2 argument(s) but 1 were given");
+        assert_eq!(diags[0].span, Span::new(33, 38));
+
+        // Arguments keep their spans, body tokens those of the definition.
+        assert_eq!(toks[0].span.range(), 0..3);
+        assert_eq!(toks[5].span, Span::new(6, 8));
+    }
+
+    #[test]
+    fn backtrace() {
+        let mut map = SourceMap::new();
+        let main = map.add("main.s", "nop\nouter");
+        let defs = map.add("defs.s", "mov r0\ninner");
+        let (mut body, _) = map.tokenize(defs);
+        let mut macros = MacroExpander::new();
+        macros.define("outer", vec![], body.split_off(2));
+        macros.define("inner", vec![], body);
+
+        let (toks, _) = map.tokenize(main);
+        let (toks, _) = macros.expand(&mut map, toks);
+        assert_eq!(names(&toks), "nop mov r0");
+        assert!(!map.is_generated(toks[0].span) && map.is_generated(toks[1].span));
+        assert_eq!(map.render(&map.with_backtrace(Diagnostic::error(toks[1].span, "bad"))), concat!(
+            "defs.s:1:1: error: bad\n",
+            "  defs.s:2:1: note: expanded from macro `inner`\n",
+            "  main.s:2:1: note: expanded from macro `outer`",
+        ));
+    }
+
+    #[test]
+    fn recursion_limit() {
+        let mut macros = MacroExpander::new().with_limit(3);
+        macros.define("loop", vec![], spanned("x loop"));
+        let (toks, diags) = macros.expand(&mut SourceMap::new(), spanned("loop"));
+        assert_eq!(names(&toks), "x x x loop");
+        assert_eq!(diags[0].code(), Some("macro-recursion-limit"));
+    }
+
+    #[test]
+    fn conditionals() {
+        let symbols = BTreeMap::from([("DEBUG".to_string(), 0), ("ARM".to_string(), 1)]);
+        let code = "a #ifdef DEBUG b #if DEBUG c #else d #endif #else e #endif [#if ARM f #endif] #if 0 #if 1 g #endif #endif h";
+        let (toks, diags) = ConditionalResolver::new().resolve(spanned(code), &symbols);
+        assert_eq!(names(&toks), "a b d [f] h");
+        assert_eq!(toks[1].span, Span::new(15, 16));
+        assert!(diags.is_empty());
+
+        let (toks, diags) = ConditionalResolver::new().resolve(spanned("#endif #if x #else #else #if"), &symbols);
+        assert!(toks.is_empty());
+        assert_eq!(diags.iter().map(|d| d.code().unwrap()).collect::<Vec<_>>(), vec![
+            "unmatched-conditional",
+            "unterminated-conditional",
+            "duplicate-else",
+            "expected-condition",
+            "unterminated-conditional",
+        ]);
+    }
+}
@@ -0,0 +1,271 @@
+//! CSS-like selectors over token trees.
+
+use crate::prelude::*;
+use crate::{Delimiter, Diagnostic, DiagnosticKind, Float, Span, Spanned, Token, TokenizerConfig, tokenize_spanned};
+
+/// Matches a single token, e.g. `Ident("mov")`, `Number` or `Group(Brace)`.
+#[derive(Debug, Clone, PartialEq)]
+enum Selector {
+    Any,
+    Ident(Option<String>),
+    Punct(Option<char>),
+    Comment,
+    Group(Option<Delimiter>),
+    Newline,
+    String(Option<String>),
+    Char(Option<char>),
+    Number(Option<i64>),
+    Label(Option<String>),
+    Directive(Option<String>),
+
+    /// The number or identifier an immediate holds.
+    Immediate(Option<Token>),
+    Register(Option<String>),
+    Eof,
+    DocComment,
+    Keyword(Option<String>),
+    Float(Option<Float>),
+    Op(Option<String>),
+}
+
+impl Selector {
+    fn matches(&self, tok : &Token) -> bool {
+        match (self, tok) {
+            (Selector::Any, _) | (Selector::Comment, Token::Comment(_)) | (Selector::Newline, Token::Newline)
+            | (Selector::Eof, Token::Eof) | (Selector::DocComment, Token::DocComment(_)) => true,
+            (Selector::Ident(name), Token::Ident(found)) => name.as_ref().is_none_or(|name| name == found),
+            (Selector::Punct(c), Token::Punct(found)) => c.is_none_or(|c| c == *found),
+            (Selector::Group(delimiter), Token::Group(group)) => delimiter.is_none_or(|d| d == group.delimiter),
+            (Selector::String(value), Token::String(found)) => value.as_ref().is_none_or(|value| value == found),
+            (Selector::Char(value), Token::Char(found)) => value.is_none_or(|value| value == *found),
+            (Selector::Number(value), Token::Number(found)) => value.is_none_or(|value| value == *found),
+            (Selector::Label(name), Token::Label(found)) => name.as_ref().is_none_or(|name| name == found),
+            (Selector::Directive(name), Token::Directive(found)) => name.as_ref().is_none_or(|name| name == found),
+            (Selector::Immediate(value), Token::Immediate(found)) => value.as_ref().is_none_or(|value| value == &**found),
+            (Selector::Register(name), Token::Register(found)) => name.as_ref().is_none_or(|name| name == found),
+            (Selector::Keyword(name), Token::Keyword(found)) => name.as_ref().is_none_or(|name| name == found),
+            (Selector::Float(value), Token::Float(found)) => value.is_none_or(|value| value == *found),
+            (Selector::Op(op), Token::Op(found)) => op.as_ref().is_none_or(|op| op == found),
+            _ => false,
+        }
+    }
+}
+
+/// How a selector relates to the previous one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Combinator {
+    /// `a b`: inside a group matched by `a`, at any depth.
+    Descendant,
+
+    /// `a > b`: directly inside a group matched by `a`.
+    Child,
+
+    /// `a ~ b`: after `a` in the same group.
+    Sibling,
+
+    /// `a + b`: right after `a` in the same group.
+    Adjacent,
+}
+
+impl Combinator {
+    /// Whether the token at path `b` stands in this relation to the one at path `a`.
+    fn relates(&self, a : &[usize], b : &[usize]) -> bool {
+        let same_parent = || a.len() == b.len() && a[..a.len() - 1] == b[..b.len() - 1];
+        match self {
+            Combinator::Descendant => b.len() > a.len() && b.starts_with(a),
+            Combinator::Child => b.len() == a.len() + 1 && b.starts_with(a),
+            Combinator::Sibling => same_parent() && b[b.len() - 1] > a[a.len() - 1],
+            Combinator::Adjacent => same_parent() && b[b.len() - 1] == a[a.len() - 1] + 1,
+        }
+    }
+}
+
+/// A query selecting tokens in a tree, written like a CSS selector over token kinds, e.g.
+/// `Group(Brace) > Ident("mov") ~ Number` for the numbers following a `mov` directly inside braces.
+///
+/// A selector is a token kind (`Ident`, `Punct`, `Comment`, `Group`, `Newline`, `String`, `Char`,
+/// `Number`, `Label`, `Directive`, `Immediate`, `Register`, `Eof`, `DocComment`, `Keyword`, `Float`
+/// or `Op`), optionally followed by the value to match in parentheses, e.g. the name of a directive
+/// without its sigil, the number or name of an immediate or the text of an operator such as
+/// `Op("<<")`, or `*` for any token. Selectors are combined with ` `, `>`, `~` and `+` as in CSS.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    first : Selector,
+    steps : Vec<(Combinator, Selector)>,
+}
+
+impl Query {
+    pub fn parse(query : &str) -> Result<Self, Box<Diagnostic>> {
+        // Floats lex, for `Float(1.5)`
+        let (toks, diags) = tokenize_spanned(&TokenizerConfig { floats: true, ..Default::default() }, query);
+        if let Some(diag) = diags.into_iter().find(Diagnostic::is_error) {
+            return Err(Box::new(diag));
+        }
+
+        let mut toks = toks.into_iter().peekable();
+        let first = parse_selector(&mut toks, query.len())?;
+        let mut steps = Vec::new();
+        while let Some(tok) = toks.peek() {
+            let combinator = match tok.value {
+                Token::Punct('>') => Combinator::Child,
+                Token::Punct('~') => Combinator::Sibling,
+                Token::Punct('+') => Combinator::Adjacent,
+                _ => Combinator::Descendant,
+            };
+            if combinator != Combinator::Descendant {
+                toks.next();
+            }
+            steps.push((combinator, parse_selector(&mut toks, query.len())?));
+        }
+        Ok(Self { first, steps })
+    }
+
+    /// The tokens of `toks` matching the query, in source order.
+    pub fn select<'a>(&self, toks : &'a [Spanned<Token>]) -> Vec<&'a Spanned<Token>> {
+        let mut nodes = Vec::new();
+        collect(toks, &mut Vec::new(), &mut nodes);
+
+        let mut selected : Vec<_> = nodes.iter().filter(|(_, tok)| self.first.matches(tok)).collect();
+        for (combinator, selector) in &self.steps {
+            selected = nodes.iter()
+                .filter(|(path, tok)| selector.matches(tok) && selected.iter().any(|(prev, _)| combinator.relates(prev, path)))
+                .collect();
+        }
+        selected.into_iter().map(|(_, tok)| *tok).collect()
+    }
+}
+
+/// Every token of the tree along with its path, see `iter_tokens_with_path`.
+fn collect<'a>(toks : &'a [Spanned<Token>], path : &mut Vec<usize>, nodes : &mut Vec<(Vec<usize>, &'a Spanned<Token>)>) {
+    for (i, tok) in toks.iter().enumerate() {
+        path.push(i);
+        nodes.push((path.clone(), tok));
+        if let Token::Group(group) = &tok.value {
+            collect(&group.tokens, path, nodes);
+        }
+        path.pop();
+    }
+}
+
+fn parse_selector(toks : &mut core::iter::Peekable<impl Iterator<Item = Spanned<Token>>>, end : usize) -> Result<Selector, Box<Diagnostic>> {
+    let expected = |span| Box::new(Diagnostic::error(span, DiagnosticKind::ExpectedSelector));
+    let Some(tok) = toks.next() else {
+        return Err(expected(Span::empty(end)));
+    };
+
+    let kind = match tok.value {
+        Token::Punct('*') => return Ok(Selector::Any),
+        Token::Ident(kind) => kind,
+        _ => return Err(expected(tok.span)),
+    };
+    let arg = toks.next_if(|tok| matches!(&tok.value, Token::Group(group) if group.delimiter == Delimiter::Paren));
+    let span = arg.as_ref().map_or(tok.span, |arg| tok.span.join(arg.span));
+    let arg = match arg.map(Spanned::into_inner) {
+        Some(Token::Group(group)) if group.tokens.len() == 1 => Some(group.tokens.into_iter().next().unwrap().value),
+        Some(_) => return Err(expected(span)),
+        None => None,
+    };
+
+    Ok(match (kind.as_str(), arg) {
+        ("Ident", None) => Selector::Ident(None),
+        ("Ident", Some(Token::String(name))) => Selector::Ident(Some(name.into_string())),
+        ("Punct", None) => Selector::Punct(None),
+        ("Punct", Some(Token::Char(c))) => Selector::Punct(Some(c)),
+        ("Comment", None) => Selector::Comment,
+        ("Newline", None) => Selector::Newline,
+        ("Group", None) => Selector::Group(None),
+        ("Group", Some(Token::Ident(delimiter))) => Selector::Group(Some(match delimiter.as_str() {
+            "Paren" => Delimiter::Paren,
+            "Bracket" => Delimiter::Bracket,
+            "Brace" => Delimiter::Brace,
+            _ => return Err(expected(span)),
+        })),
+        ("String", None) => Selector::String(None),
+        ("String", Some(Token::String(value))) => Selector::String(Some(value.into_string())),
+        ("Char", None) => Selector::Char(None),
+        ("Char", Some(Token::Char(value))) => Selector::Char(Some(value)),
+        ("Number", None) => Selector::Number(None),
+        ("Number", Some(Token::Number(value))) => Selector::Number(Some(value)),
+        ("Label", None) => Selector::Label(None),
+        ("Label", Some(Token::String(name))) => Selector::Label(Some(name.into_string())),
+        ("Directive", None) => Selector::Directive(None),
+        ("Directive", Some(Token::String(name))) => Selector::Directive(Some(name.into_string())),
+        ("Immediate", None) => Selector::Immediate(None),
+        ("Immediate", Some(Token::Number(value))) => Selector::Immediate(Some(Token::Number(value))),
+        ("Immediate", Some(Token::String(name))) => Selector::Immediate(Some(Token::Ident(name))),
+        ("Register", None) => Selector::Register(None),
+        ("Register", Some(Token::String(name))) => Selector::Register(Some(name.into_string())),
+        ("Eof", None) => Selector::Eof,
+        ("DocComment", None) => Selector::DocComment,
+        ("Keyword", None) => Selector::Keyword(None),
+        ("Keyword", Some(Token::String(name))) => Selector::Keyword(Some(name.into_string())),
+        ("Float", None) => Selector::Float(None),
+        ("Float", Some(Token::Float(value))) => Selector::Float(Some(value)),
+        ("Op", None) => Selector::Op(None),
+        ("Op", Some(Token::String(op))) => Selector::Op(Some(op.into_string())),
+        _ => return Err(expected(span)),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn select() {
+        let code = "mov 1 2 {mov r0, 3 (mov 4)} {jmp 5 [mov 6]}";
+        let (toks, _) = tokenize_spanned(&TokenizerConfig::default(), code);
+        let select = |query| -> Vec<&str> {
+            Query::parse(query).unwrap().select(&toks).into_iter().map(|tok| &code[tok.span.range()]).collect()
+        };
+
+        assert_eq!(select("Group(Brace) > Ident(\"mov\") ~ Number"), vec!["3"]);
+        assert_eq!(select("Group(Brace) Ident(\"mov\") + Number"), vec!["4", "6"]);
+        assert_eq!(select("Group(Brace) > Group"), vec!["(mov 4)", "[mov 6]"]);
+        assert_eq!(select("Ident(\"mov\") ~ *").len(), 10);
+        assert_eq!(select("Punct(',')"), vec![","]);
+
+        assert_eq!(Query::parse("Group(Angle)").unwrap_err().span, Span::new(0, 12));
+        assert_eq!(Query::parse("Ident >").unwrap_err().code(), Some("expected-selector"));
+    }
+
+    #[test]
+    fn token_kinds() {
+        let config = TokenizerConfig {
+            labels: true,
+            directive_sigil: Some('.'),
+            immediate_prefixes: vec!['#'],
+            registers: vec!["r0..r7".to_string()],
+            eof: true,
+            doc_comments: vec!["///".to_string()],
+            keywords: vec!["mov".to_string()],
+            floats: true,
+            operators: vec!["<<".to_string(), "->".to_string()],
+            ..Default::default()
+        };
+        let code = "/// entry\nstart: nop // idle\nend: b start\n.text\n.org 4\nmov r0, #4, #data\ndd 1.5, 2.0\nlsl x, y << 2 -> z\ndd 3.0";
+        let (toks, _) = tokenize_spanned(&config, code);
+        let select = |query| -> Vec<&str> {
+            Query::parse(query).unwrap().select(&toks).into_iter().map(|tok| &code[tok.span.range()]).collect()
+        };
+
+        assert_eq!(select("Label"), vec!["start:", "end:"]);
+        assert_eq!(select("Label(\"end\") + Ident"), vec!["b"]);
+        assert_eq!(select("Directive"), vec![".text", ".org"]);
+        assert_eq!(select("Directive(\"org\") + Number"), vec!["4"]);
+        assert_eq!(select("Immediate"), vec!["#4", "#data"]);
+        assert_eq!(select("Immediate(4)"), vec!["#4"]);
+        assert_eq!(select("Immediate(\"data\")"), vec!["#data"]);
+        assert_eq!(select("Register"), vec!["r0"]);
+        assert_eq!(select("Register(\"r0\") ~ Immediate"), vec!["#4", "#data"]);
+        assert_eq!(select("Float + Eof"), vec![""]);
+        assert_eq!(select("DocComment + Label"), vec!["start:"]);
+        assert_eq!(select("Comment").len(), 1);
+        assert_eq!(select("Keyword(\"mov\") + Register"), vec!["r0"]);
+        assert_eq!(select("Keyword").len(), 1);
+        assert_eq!(select("Float"), vec!["1.5", "2.0", "3.0"]);
+        assert_eq!(select("Float(1.5)"), vec!["1.5"]);
+        assert_eq!(select("Op"), vec!["<<", "->"]);
+        assert_eq!(select("Op(\"->\") + Ident"), vec!["z"]);
+    }
+}
@@ -0,0 +1,175 @@
+//! The `toks!` macro, building token trees from source-like syntax.
+
+use crate::prelude::*;
+use crate::{Delimiter, Group, Span, Spanned, Token};
+
+/// Values that can be interpolated into `toks!` with `#name`.
+pub trait ToTokens {
+    fn to_tokens(&self, toks : &mut Vec<Token>);
+}
+
+impl ToTokens for Token {
+    fn to_tokens(&self, toks : &mut Vec<Token>) {
+        toks.push(self.clone());
+    }
+}
+
+impl<T : ToTokens> ToTokens for Spanned<T> {
+    fn to_tokens(&self, toks : &mut Vec<Token>) {
+        self.value.to_tokens(toks);
+    }
+}
+
+impl<T : ToTokens> ToTokens for [T] {
+    fn to_tokens(&self, toks : &mut Vec<Token>) {
+        self.iter().for_each(|tok| tok.to_tokens(toks));
+    }
+}
+
+impl<T : ToTokens> ToTokens for Vec<T> {
+    fn to_tokens(&self, toks : &mut Vec<Token>) {
+        self.as_slice().to_tokens(toks);
+    }
+}
+
+impl<T : ToTokens + ?Sized> ToTokens for &T {
+    fn to_tokens(&self, toks : &mut Vec<Token>) {
+        (**self).to_tokens(toks);
+    }
+}
+
+/// A string literal, as in `quote`; use a `Token::Ident` to interpolate an identifier.
+impl ToTokens for str {
+    fn to_tokens(&self, toks : &mut Vec<Token>) {
+        toks.push(Token::String(self.into()));
+    }
+}
+
+impl ToTokens for String {
+    fn to_tokens(&self, toks : &mut Vec<Token>) {
+        self.as_str().to_tokens(toks);
+    }
+}
+
+impl ToTokens for char {
+    fn to_tokens(&self, toks : &mut Vec<Token>) {
+        toks.push(Token::Char(*self));
+    }
+}
+
+impl ToTokens for i64 {
+    fn to_tokens(&self, toks : &mut Vec<Token>) {
+        toks.push(Token::Number(*self));
+    }
+}
+
+/// The literals `toks!` accepts.
+#[doc(hidden)]
+pub trait Literal {
+    fn into_token(self) -> Token;
+}
+
+impl Literal for &str {
+    fn into_token(self) -> Token {
+        Token::String(self.into())
+    }
+}
+
+impl Literal for char {
+    fn into_token(self) -> Token {
+        Token::Char(self)
+    }
+}
+
+impl Literal for i32 {
+    fn into_token(self) -> Token {
+        Token::Number(self.into())
+    }
+}
+
+impl Literal for i64 {
+    fn into_token(self) -> Token {
+        Token::Number(self)
+    }
+}
+
+#[doc(hidden)]
+pub fn group(delimiter : Delimiter, toks : Vec<Token>) -> Token {
+    Token::from(Group::new(delimiter, toks.into_iter().map(|tok| Spanned::new(tok, Span::default())).collect()))
+}
+
+/// Builds a `Vec<Token>` from source-like syntax, e.g. `toks!(mov r0, [sp] #imm)`.
+///
+/// Identifiers, string, char and integer literals, punctuation and groups are written as they
+/// would be in source, except that comments and line breaks can't be. `#name` interpolates the
+/// value of the variable `name`, of any type implementing `ToTokens`; `##` is a literal `#`.
+/// All spans are `Span::default()`.
+#[macro_export]
+macro_rules! toks {
+    ($($tt:tt)*) => {{
+        #[allow(unused_mut)]
+        let mut toks = $crate::__Vec::<$crate::Token>::new();
+        $crate::__toks!(toks; $($tt)*);
+        toks
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __toks {
+    ($v:ident;) => {};
+    ($v:ident; # # $($rest:tt)*) => {
+        $v.push($crate::Token::Punct('#'));
+        $crate::__toks!($v; $($rest)*);
+    };
+    ($v:ident; # $var:ident $($rest:tt)*) => {
+        $crate::ToTokens::to_tokens(&$var, &mut $v);
+        $crate::__toks!($v; $($rest)*);
+    };
+    ($v:ident; ( $($inner:tt)* ) $($rest:tt)*) => {
+        $v.push($crate::__group($crate::Delimiter::Paren, $crate::toks!($($inner)*)));
+        $crate::__toks!($v; $($rest)*);
+    };
+    ($v:ident; [ $($inner:tt)* ] $($rest:tt)*) => {
+        $v.push($crate::__group($crate::Delimiter::Bracket, $crate::toks!($($inner)*)));
+        $crate::__toks!($v; $($rest)*);
+    };
+    ($v:ident; { $($inner:tt)* } $($rest:tt)*) => {
+        $v.push($crate::__group($crate::Delimiter::Brace, $crate::toks!($($inner)*)));
+        $crate::__toks!($v; $($rest)*);
+    };
+    ($v:ident; $i:ident $($rest:tt)*) => {
+        $v.push($crate::Token::Ident(::core::stringify!($i).into()));
+        $crate::__toks!($v; $($rest)*);
+    };
+    ($v:ident; $l:literal $($rest:tt)*) => {
+        $v.push($crate::__Literal::into_token($l));
+        $crate::__toks!($v; $($rest)*);
+    };
+    ($v:ident; $p:tt $($rest:tt)*) => {
+        // Rust lexes some operators, e.g. `->`, as one token
+        $v.extend(::core::stringify!($p).chars().map($crate::Token::Punct));
+        $crate::__toks!($v; $($rest)*);
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{structural_eq, tokenize};
+
+    fn same(a : Vec<Token>, code : &str) -> bool {
+        structural_eq(&a, &tokenize(code))
+    }
+
+    #[test]
+    fn quote() {
+        let reg = Token::Ident("r1".into());
+        let imm = 42i64;
+        let regs = vec![reg.clone(), Token::Punct(','), reg.clone()];
+        assert!(same(toks!(mov r0, [sp, -4] #imm), "mov r0, [sp, -4] 42"));
+        assert!(same(toks!(push {#regs} -> #reg), "push {r1, r1} -> r1"));
+        assert!(same(toks!(##if true "s" 'c' (a::b)), "#if true \"s\" 'c' (a::b)"));
+        assert!(toks!().is_empty());
+    }
+}
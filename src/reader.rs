@@ -0,0 +1,226 @@
+//! Tokenizing input as it is read, for files too large to hold along with all of their tokens.
+
+use alloc::collections::VecDeque;
+
+use crate::prelude::*;
+use crate::stream::rebase;
+use crate::token::tokenize_file;
+use crate::{Diagnostic, DiagnosticKind, FileId, ReplSession, Span, Spanned, Token, TokenizerConfig};
+
+/// Tokenizes chars as they are pulled from an iterator or reader, yielding the tokens of a chunk
+/// of lines as soon as it is complete.
+///
+/// This isn't lexing char by char: the chars of a chunk are collected into a string, which is
+/// tokenized once the chunk is complete, and its tokens are queued until they are pulled. Chunks
+/// end at the line breaks outside of groups and block comments, so memory is bounded by the
+/// largest top-level group (or block comment) rather than the input, but a group spanning the
+/// whole input is held whole along with its tokens.
+///
+/// Each chunk is tokenized on its own: `Limits` apply to a chunk rather than the whole input, and
+/// `TokenizerConfig::concat_strings` doesn't join strings across chunks. Otherwise the tokens and
+/// spans are those `tokenize_spanned` would give.
+///
+/// The diagnostics of a chunk are yielded as errors before its tokens, and lexing goes on after
+/// them, except after failing to read the input, which ends it.
+pub struct ChunkedTokenReader<'a> {
+    config : TokenizerConfig,
+    file : FileId,
+    chars : Box<dyn Iterator<Item = Result<char, String>> + 'a>,
+
+    /// Tracks whether the lines of `chunk` leave a group or block comment open.
+    session : ReplSession,
+    chunk : String,
+
+    /// The offset of `chunk` in the input.
+    offset : usize,
+    pending : VecDeque<Result<Spanned<Token>, Box<Diagnostic>>>,
+    eof : bool,
+    done : bool,
+}
+
+impl<'a> ChunkedTokenReader<'a> {
+    pub fn new(config : &TokenizerConfig, chars : impl Iterator<Item = char> + 'a) -> Self {
+        Self::with_chars(config, Box::new(chars.map(Ok)))
+    }
+
+    /// Reads UTF-8 text from `reader`, reporting an error that fails it, or invalid UTF-8, as
+    /// `DiagnosticKind::ReadError`.
+    #[cfg(feature = "std")]
+    pub fn from_reader(config : &TokenizerConfig, reader : impl std::io::Read + 'a) -> Self {
+        Self::with_chars(config, Box::new(ReadChars { reader: std::io::BufReader::new(reader), line: String::new(), pos: 0, failed: false }))
+    }
+
+    fn with_chars(config : &TokenizerConfig, chars : Box<dyn Iterator<Item = Result<char, String>> + 'a>) -> Self {
+        Self {
+            // The end of a chunk isn't that of the input
+            config: TokenizerConfig { eof: false, ..config.clone() },
+            file: FileId::default(),
+            chars,
+            session: ReplSession::new().with_config(config.clone()),
+            chunk: String::new(),
+            offset: 0,
+            pending: VecDeque::new(),
+            eof: config.eof,
+            done: false,
+        }
+    }
+
+    /// Sets the file of the spans.
+    pub fn with_file(mut self, file : FileId) -> Self {
+        self.file = file;
+        self
+    }
+
+    /// Reads the next line, tokenizing the chunk if it completes it or the input ends.
+    fn read_line(&mut self) {
+        let start = self.chunk.len();
+        let mut failed = None;
+        let ended = loop {
+            match self.chars.next() {
+                Some(Ok(c)) => {
+                    self.chunk.push(c);
+                    if c == '\n' {
+                        break false;
+                    }
+                },
+                Some(Err(err)) => {
+                    failed = Some(err);
+                    break true;
+                },
+                None => break true,
+            }
+        };
+
+        let line = &self.chunk[start..];
+        if self.session.push_line(line.strip_suffix('\n').unwrap_or(line)) || ended {
+            self.flush();
+        }
+        if let Some(err) = failed {
+            let span = Span::empty(self.offset).with_file(self.file);
+            self.pending.push_back(Err(Box::new(Diagnostic::error(span, DiagnosticKind::ReadError(err)))));
+        }
+        if ended {
+            if self.eof {
+                self.pending.push_back(Ok(Spanned::new(Token::Eof, Span::empty(self.offset).with_file(self.file))));
+            }
+            self.done = true;
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.chunk.is_empty() {
+            return;
+        }
+        let (toks, diagnostics) = tokenize_file(&self.config, self.file, &self.chunk);
+        let delta = self.offset as isize;
+        self.pending.extend(diagnostics.into_iter().map(|mut diagnostic| {
+            rebase_diagnostic(&mut diagnostic, delta);
+            Err(Box::new(diagnostic))
+        }));
+        self.pending.extend(toks.into_iter().map(|mut tok| {
+            rebase(&mut tok, delta, self.file);
+            Ok(tok)
+        }));
+        self.offset += self.chunk.len();
+        self.chunk.clear();
+        self.session.clear();
+    }
+}
+
+/// Moves `diagnostic`, with its suggestions and notes, by `delta` bytes.
+fn rebase_diagnostic(diagnostic : &mut Diagnostic, delta : isize) {
+    diagnostic.span = diagnostic.span.offset_by(delta);
+    for edit in &mut diagnostic.suggestions {
+        edit.span = edit.span.offset_by(delta);
+    }
+    for note in &mut diagnostic.notes {
+        rebase_diagnostic(note, delta);
+    }
+}
+
+impl Iterator for ChunkedTokenReader<'_> {
+    type Item = Result<Spanned<Token>, Box<Diagnostic>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.pending.pop_front() {
+                return Some(item);
+            }
+            if self.done {
+                return None;
+            }
+            self.read_line();
+        }
+    }
+}
+
+/// The chars of a reader, a line at a time, until it fails.
+#[cfg(feature = "std")]
+struct ReadChars<R> {
+    reader : std::io::BufReader<R>,
+    line : String,
+    pos : usize,
+    failed : bool,
+}
+
+#[cfg(feature = "std")]
+impl<R : std::io::Read> Iterator for ReadChars<R> {
+    type Item = Result<char, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        use std::io::BufRead;
+
+        if self.pos == self.line.len() {
+            if self.failed {
+                return None;
+            }
+            self.line.clear();
+            self.pos = 0;
+            if let Err(err) = self.reader.read_line(&mut self.line) {
+                self.failed = true;
+                return Some(Err(err.to_string()));
+            }
+        }
+        let c = self.line[self.pos..].chars().next()?;
+        self.pos += c.len_utf8();
+        Some(Ok(c))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tokenize_spanned;
+
+    #[test]
+    fn token_reader() {
+        let code = "mov r0, [r1\n  + 4] // a\n/* b\n*/ \"c\n.db 1 )\n";
+        let config = TokenizerConfig { newlines: true, eof: true, ..Default::default() };
+        let (toks, diagnostics) = tokenize_spanned(&config, code);
+        let (read, errors) : (Vec<_>, Vec<_>) = ChunkedTokenReader::new(&config, code.chars()).partition(Result::is_ok);
+        assert_eq!(read.into_iter().map(Result::unwrap).collect::<Vec<_>>(), toks);
+        assert_eq!(errors.into_iter().map(|err| *err.unwrap_err()).collect::<Vec<_>>(), diagnostics);
+    }
+
+    #[test]
+    fn line_comments() {
+        let config = TokenizerConfig { line_comments: vec![";".into()], ..Default::default() };
+        // The first line is complete, so the rest isn't read for its tokens
+        let chars = "a ; (\n".chars().chain(core::iter::from_fn(|| panic!("read past the first line")));
+        let toks : Vec<_> = ChunkedTokenReader::new(&config, chars).take(2).map(|tok| tok.unwrap().value).collect();
+        assert_eq!(toks, [Token::Ident("a".into()), Token::Comment(" (".into())]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn from_reader() {
+        let config = TokenizerConfig { newlines: true, eof: true, ..Default::default() };
+        let mut reader = ChunkedTokenReader::from_reader(&config, &b"nop\nmov \xff"[..]).with_file(FileId(1));
+        assert_eq!(reader.next(), Some(Ok(Spanned::new(Token::Ident("nop".into()), Span::new(0, 3).with_file(FileId(1))))));
+        assert_eq!(reader.next().unwrap().unwrap().value, Token::Newline);
+        let Some(Err(diagnostic)) = reader.next() else { panic!() };
+        assert_eq!((diagnostic.kind.code(), diagnostic.span), (Some("read-error"), Span::new(4, 4).with_file(FileId(1))));
+        assert_eq!(reader.next().unwrap().unwrap().value, Token::Eof);
+        assert_eq!(reader.next(), None);
+    }
+}
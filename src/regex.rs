@@ -0,0 +1,282 @@
+//! A small regular expression engine, for the patterns of declarative lexers.
+//!
+//! Supports literals, `.`, classes like `[a-z_]` and `[^"]`, the escapes `\d \w \s` (and their
+//! negations), `\n \t \r` and escaped metacharacters, groups, `|` and the quantifiers `* + ?`.
+//! Matching simulates the NFA, so it is linear in the input and finds the longest match.
+
+use crate::prelude::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Class {
+    negated : bool,
+    ranges : Vec<(char, char)>,
+}
+
+impl Class {
+    fn char(c : char) -> Self {
+        Self { negated: false, ranges: vec![(c, c)] }
+    }
+
+    fn matches(&self, c : char) -> bool {
+        self.ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&c)) != self.negated
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Class(Class),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Optional(Box<Node>),
+    Star(Box<Node>),
+    Plus(Box<Node>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Inst {
+    Class(Class),
+    Split(usize, usize),
+    Jmp(usize),
+    Match,
+}
+
+struct Parser<'a> {
+    chars : core::iter::Peekable<core::str::Chars<'a>>,
+}
+
+impl Parser<'_> {
+    fn alt(&mut self) -> Result<Node, &'static str> {
+        let mut alts = vec![self.concat()?];
+        while self.chars.next_if_eq(&'|').is_some() {
+            alts.push(self.concat()?);
+        }
+        Ok(if alts.len() == 1 { alts.pop().unwrap() } else { Node::Alt(alts) })
+    }
+
+    fn concat(&mut self) -> Result<Node, &'static str> {
+        let mut nodes = Vec::new();
+        while self.chars.peek().is_some_and(|c| !matches!(c, '|' | ')')) {
+            nodes.push(self.repeat()?);
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    fn repeat(&mut self) -> Result<Node, &'static str> {
+        let mut node = self.atom()?;
+        while let Some(c) = self.chars.next_if(|c| matches!(c, '*' | '+' | '?')) {
+            node = match c {
+                '*' => Node::Star(Box::new(node)),
+                '+' => Node::Plus(Box::new(node)),
+                _ => Node::Optional(Box::new(node)),
+            };
+        }
+        Ok(node)
+    }
+
+    fn atom(&mut self) -> Result<Node, &'static str> {
+        Ok(Node::Class(match self.chars.next().ok_or("unexpected end")? {
+            '(' => {
+                let node = self.alt()?;
+                self.chars.next_if_eq(&')').ok_or("unclosed group")?;
+                return Ok(node)
+            },
+            '[' => self.class()?,
+            '.' => Class { negated: true, ranges: vec![('\n', '\n')] },
+            '\\' => self.escape()?,
+            '*' | '+' | '?' => return Err("nothing to repeat"),
+            c => Class::char(c),
+        }))
+    }
+
+    fn escape(&mut self) -> Result<Class, &'static str> {
+        let c = self.chars.next().ok_or("unfinished escape")?;
+        let ranges = match c.to_ascii_lowercase() {
+            'd' => vec![('0', '9')],
+            'w' => vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')],
+            's' => vec![(' ', ' '), ('\t', '\r')],
+            _ => return Ok(Class::char(match c {
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                c => c,
+            })),
+        };
+        Ok(Class { negated: c.is_ascii_uppercase(), ranges })
+    }
+
+    fn class(&mut self) -> Result<Class, &'static str> {
+        let negated = self.chars.next_if_eq(&'^').is_some();
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let lo = match self.chars.next().ok_or("unclosed class")? {
+                ']' if !first => break,
+                '\\' => {
+                    let class = self.escape()?;
+                    match class.ranges[..] {
+                        [(c, d)] if c == d && !class.negated => c,
+                        _ => {
+                            ranges.extend(class.ranges);
+                            continue
+                        },
+                    }
+                },
+                c => c,
+            };
+            first = false;
+
+            let hi = match self.chars.peek() {
+                Some('-') => {
+                    self.chars.next();
+                    match self.chars.next().ok_or("unclosed class")? {
+                        ']' => {
+                            ranges.extend([(lo, lo), ('-', '-')]);
+                            break
+                        },
+                        '\\' => self.chars.next().ok_or("unfinished escape")?,
+                        hi => hi,
+                    }
+                },
+                _ => lo,
+            };
+            if hi < lo {
+                return Err("invalid range");
+            }
+            ranges.push((lo, hi));
+        }
+        Ok(Class { negated, ranges })
+    }
+}
+
+fn compile(node : &Node, prog : &mut Vec<Inst>) {
+    match node {
+        Node::Class(class) => prog.push(Inst::Class(class.clone())),
+        Node::Concat(nodes) => nodes.iter().for_each(|node| compile(node, prog)),
+        Node::Alt(alts) => {
+            let mut jumps = Vec::new();
+            for (i, alt) in alts.iter().enumerate() {
+                let split = prog.len();
+                if i + 1 < alts.len() {
+                    prog.push(Inst::Split(split + 1, 0));
+                }
+                compile(alt, prog);
+                if i + 1 < alts.len() {
+                    jumps.push(prog.len());
+                    prog.push(Inst::Jmp(0));
+                    prog[split] = Inst::Split(split + 1, prog.len());
+                }
+            }
+            for jump in jumps {
+                prog[jump] = Inst::Jmp(prog.len());
+            }
+        },
+        Node::Optional(node) => {
+            let split = prog.len();
+            prog.push(Inst::Split(split + 1, 0));
+            compile(node, prog);
+            prog[split] = Inst::Split(split + 1, prog.len());
+        },
+        Node::Star(node) => {
+            let split = prog.len();
+            prog.push(Inst::Split(split + 1, 0));
+            compile(node, prog);
+            prog.push(Inst::Jmp(split));
+            prog[split] = Inst::Split(split + 1, prog.len());
+        },
+        Node::Plus(node) => {
+            let start = prog.len();
+            compile(node, prog);
+            prog.push(Inst::Split(start, prog.len() + 1));
+        },
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Regex {
+    prog : Vec<Inst>,
+}
+
+impl Regex {
+    pub(crate) fn new(pattern : &str) -> Result<Self, &'static str> {
+        let mut parser = Parser { chars: pattern.chars().peekable() };
+        let node = parser.alt()?;
+        if parser.chars.next().is_some() {
+            return Err("unmatched `)`");
+        }
+
+        let mut prog = Vec::new();
+        compile(&node, &mut prog);
+        prog.push(Inst::Match);
+        Ok(Self { prog })
+    }
+
+    /// Adds `pc` and the instructions reachable from it without consuming a char to `threads`.
+    fn add(&self, threads : &mut Vec<usize>, seen : &mut [bool], pc : usize) {
+        if core::mem::replace(&mut seen[pc], true) {
+            return;
+        }
+        match self.prog[pc] {
+            Inst::Split(a, b) => {
+                self.add(threads, seen, a);
+                self.add(threads, seen, b);
+            },
+            Inst::Jmp(a) => self.add(threads, seen, a),
+            _ => threads.push(pc),
+        }
+    }
+
+    /// The length in bytes of the longest match at the start of `s`, if any.
+    pub(crate) fn longest_match(&self, s : &str) -> Option<usize> {
+        let mut seen = vec![false; self.prog.len()];
+        let mut threads = Vec::new();
+        self.add(&mut threads, &mut seen, 0);
+
+        let mut longest = None;
+        let mut next = Vec::new();
+        for next_char in s.char_indices().map(Some).chain([None]) {
+            if threads.iter().any(|pc| self.prog[*pc] == Inst::Match) {
+                longest = Some(next_char.map_or(s.len(), |(i, _)| i));
+            }
+            let Some((_, c)) = next_char else { break };
+
+            seen.iter_mut().for_each(|seen| *seen = false);
+            for pc in &threads {
+                if matches!(&self.prog[*pc], Inst::Class(class) if class.matches(c)) {
+                    self.add(&mut next, &mut seen, pc + 1);
+                }
+            }
+            core::mem::swap(&mut threads, &mut next);
+            next.clear();
+            if threads.is_empty() {
+                break;
+            }
+        }
+        longest
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn longest(pattern : &str, s : &str) -> Option<usize> {
+        Regex::new(pattern).unwrap().longest_match(s)
+    }
+
+    #[test]
+    fn matches() {
+        assert_eq!(longest("[0-9]+", "123abc"), Some(3));
+        assert_eq!(longest("[0-9]+", "abc"), None);
+        assert_eq!(longest("0x[0-9a-fA-F]+|[0-9]+", "0x1fz"), Some(4));
+        assert_eq!(longest("[a-z_]\\w*", "mov_2 r0"), Some(5));
+        assert_eq!(longest("\"([^\"\\\\]|\\\\.)*\"", "\"a\\\"b\" c"), Some(6));
+        assert_eq!(longest("a?b*", "xyz"), Some(0));
+        assert_eq!(longest("(ab)*a", "ababac"), Some(5));
+        assert_eq!(longest("\\d+(\\.\\d+)?", "3.14."), Some(4));
+        assert_eq!(longest("[-+]?é", "-é!"), Some(3));
+
+        for invalid in ["(a", "a)", "*", "[a", "[z-a]", "\\"] {
+            assert!(Regex::new(invalid).is_err(), "{invalid}");
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! Collecting the lines of an interactive prompt until they form a complete input.
+
+use crate::prelude::*;
+use crate::balance::literal_end;
+use crate::{Delimiter, Diagnostic, Spanned, Token, TokenizerConfig, tokenize_spanned};
+
+/// The lines submitted to a REPL so far, which are complete once every group and block comment
+/// they open is closed.
+///
+/// Strings and chars end at line breaks like in the tokenizer, so an unterminated one is an
+/// error of the input rather than a reason to wait for more. So is a delimiter closing no group
+/// or the wrong one, which makes the input complete so that the error is reported.
+#[derive(Debug, Clone, Default)]
+pub struct ReplSession {
+    config : TokenizerConfig,
+    source : String,
+
+    /// The opening delimiters of the groups still open, from the outermost.
+    open : Vec<char>,
+    in_comment : bool,
+    broken : bool,
+}
+
+impl ReplSession {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the configuration the input is tokenized with.
+    pub fn with_config(mut self, config : TokenizerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Adds a submitted line, returning whether the input is now complete.
+    pub fn push_line(&mut self, line : &str) -> bool {
+        if !self.source.is_empty() {
+            self.source.push('\n');
+        }
+        self.source.push_str(line);
+        // Line breaks in `line` end literals and comments as in the source, so lines are scanned one by one
+        for line in line.split('\n') {
+            self.scan(line);
+        }
+        self.is_complete()
+    }
+
+    fn scan(&mut self, line : &str) {
+        let bytes = line.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() {
+            if self.in_comment {
+                let Some(end) = line[i..].find("*/") else { return };
+                self.in_comment = false;
+                i += end + 2;
+                continue;
+            }
+            let mut prefixes = self.config.doc_comments.iter().chain(&self.config.line_comments);
+            if prefixes.any(|prefix| !prefix.is_empty() && bytes[i..].starts_with(prefix.as_bytes())) {
+                return;
+            }
+            match bytes[i] {
+                b'(' | b'[' | b'{' => self.open.push(char::from(bytes[i])),
+                b')' | b']' | b'}' => match self.open.pop() {
+                    Some(open) if Delimiter::from_open(open) == Delimiter::from_close(char::from(bytes[i])) => (),
+                    _ => self.broken = true,
+                },
+                b'"' | b'\'' => {
+                    i = literal_end(bytes, i);
+                    continue;
+                },
+                b'/' if bytes.get(i + 1) == Some(&b'/') => return,
+                b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                    self.in_comment = true;
+                    i += 2;
+                    continue;
+                },
+                _ => (),
+            }
+            i += 1;
+        }
+    }
+
+    /// Whether the input submitted so far needs no more lines.
+    pub fn is_complete(&self) -> bool {
+        self.broken || (self.open.is_empty() && !self.in_comment)
+    }
+
+    /// The opening delimiters of the groups still open, from the outermost, e.g. for a prompt.
+    pub fn open_delimiters(&self) -> &[char] {
+        &self.open
+    }
+
+    /// Whether the input ends inside a block comment.
+    pub fn in_comment(&self) -> bool {
+        self.in_comment
+    }
+
+    /// The input submitted so far, its lines joined by `\n`.
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Discards the input submitted so far.
+    pub fn clear(&mut self) {
+        self.source.clear();
+        self.open.clear();
+        self.in_comment = false;
+        self.broken = false;
+    }
+
+    /// The tokens and diagnostics of the input if it is complete, clearing it for the next one.
+    pub fn finish(&mut self) -> Option<(Vec<Spanned<Token>>, Vec<Diagnostic>)> {
+        if !self.is_complete() {
+            return None;
+        }
+        let result = tokenize_spanned(&self.config, &self.source);
+        self.clear();
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::tokenize;
+
+    #[test]
+    fn repl_session() {
+        let mut session = ReplSession::new();
+        assert!(!session.push_line("macro m(a, {"));
+        assert_eq!(session.open_delimiters(), ['(', '{']);
+        assert!(session.finish().is_none());
+        assert!(!session.push_line("  \"}\" ')' // }"));
+        assert!(!session.push_line("}) /* ("));
+        assert!(session.in_comment());
+        assert!(session.push_line("*/ nop"));
+        let code = "macro m(a, {\n  \"}\" ')' // }\n}) /* (\n*/ nop";
+        assert_eq!(session.source(), code);
+
+        let (toks, diagnostics) = session.finish().unwrap();
+        assert!(diagnostics.is_empty());
+        assert_eq!(toks.into_iter().map(|tok| tok.value).collect::<Vec<_>>(), tokenize(code));
+        assert_eq!(session.source(), "");
+
+        assert!(session.push_line("(a]"));
+        assert!(!session.finish().unwrap().1.is_empty());
+        assert!(session.push_line("\"(\""));
+
+        let config = TokenizerConfig { line_comments: vec![";".into()], doc_comments: vec!["@@".into()], ..Default::default() };
+        let mut session = ReplSession::new().with_config(config);
+        assert!(session.push_line("mov r0 ; (oops"));
+        assert!(session.push_line("@@ [doc"));
+    }
+}
@@ -1,7 +1,11 @@
-use std::collections::VecDeque;
+use alloc::collections::VecDeque;
+use core::convert::Infallible;
+
+use crate::prelude::*;
+use crate::{LineCol, ScanErrorKind, SourceFile, Trace, TraceEvent};
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum ScannerAction<T> {
+pub enum ScannerAction<T, E = Infallible> {
     /// Immediately advance the cursor and return T.
     Return(T),
 
@@ -12,15 +16,84 @@ pub enum ScannerAction<T> {
     Require,
 
     None,
+
+    /// Fail with `E`, e.g. for an operand out of range, leaving the cursor where the scan started.
+    /// Only `TokenScanner::scan_fallible` accepts it.
+    Fail(E),
+}
+
+/// A matcher for `Scanner::scan_any`, returning a value if it matches.
+pub type Matcher<'a, T, U> = &'a dyn Fn(&mut Scanner<T>) -> Option<U>;
+
+/// Where a scanner was, to `Scanner::rewind` to or `Scanner::commit` past.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    journal : usize,
+
+    /// Whether an enclosing checkpoint was recording already.
+    nested : bool,
 }
 
 pub struct Scanner<T> {
     toks : VecDeque<T>,
+    scratch : Vec<T>,
+    offset : usize,
+    width : fn(&T) -> usize,
+    trace : Option<Trace>,
+    describe : fn(&T) -> String,
+    fuel : Option<u64>,
+
+    /// Copies of the tokens popped since the outermost checkpoint, to put back on rewinding.
+    journal : Vec<T>,
+    record : Option<fn(&T) -> T>,
 }
 
 impl<T> Scanner<T> {
     pub fn new(toks : VecDeque<T>) -> Self {
-        Self { toks }
+        Self::with_width(toks, |_| 1)
+    }
+
+    /// Like `new`, but `offset` advances by `width(tok)` for each popped token instead of 1
+    /// (e.g. `char::len_utf8` to track byte offsets).
+    pub fn with_width(toks : VecDeque<T>, width : fn(&T) -> usize) -> Self {
+        Self { toks, scratch: Vec::new(), offset: 0, width, trace: None, describe: |_| String::new(), fuel: None, journal: Vec::new(), record: None }
+    }
+
+    /// Records, in `trace`, the tokens each `scan` considers and the action its callback takes.
+    pub fn with_trace(mut self, trace : &Trace) -> Self where T : core::fmt::Debug {
+        self.trace = Some(trace.clone());
+        self.describe = |tok| format!("{tok:?}");
+        self
+    }
+
+    /// Uses `scratch`, cleared, as the buffer `scan` collects sequences in, so that scanning
+    /// doesn't allocate once it has grown large enough.
+    pub fn with_scratch(mut self, mut scratch : Vec<T>) -> Self {
+        scratch.clear();
+        self.scratch = scratch;
+        self
+    }
+
+    /// The queue of remaining tokens and the, cleared, scratch buffer, to reuse their memory.
+    pub fn into_buffers(mut self) -> (VecDeque<T>, Vec<T>) {
+        self.scratch.clear();
+        (self.toks, self.scratch)
+    }
+
+    /// Makes the scanner act as if it were done once it has popped `fuel` tokens.
+    pub fn with_fuel(mut self, fuel : u64) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    /// Whether there are tokens left, but no fuel to pop them.
+    pub fn is_out_of_fuel(&self) -> bool {
+        self.fuel == Some(0) && !self.toks.is_empty()
+    }
+
+    /// Total width of the tokens consumed so far.
+    pub fn offset(&self) -> usize {
+        self.offset
     }
 
     pub fn is_done(&self) -> bool {
@@ -28,11 +101,54 @@ impl<T> Scanner<T> {
     }
 
     pub fn peek(&self) -> Option<&T> {
-        self.toks.front()
+        self.peek_n(0)
+    }
+
+    /// The token `n` tokens after the next one, if it could be popped.
+    pub fn peek_n(&self, n : usize) -> Option<&T> {
+        if n >= self.lookahead() {
+            return None;
+        }
+        self.toks.get(n)
+    }
+
+    /// Up to the next `n` tokens, as many as could be popped.
+    pub fn peek_slice(&mut self, n : usize) -> &[T] {
+        let len = n.min(self.lookahead()).min(self.toks.len());
+        &self.toks.make_contiguous()[..len]
+    }
+
+    /// How many tokens the fuel left allows to pop.
+    fn lookahead(&self) -> usize {
+        self.fuel.map_or(usize::MAX, |fuel| usize::try_from(fuel).unwrap_or(usize::MAX))
     }
 
     pub fn pop(&mut self) -> Option<T> {
-        self.toks.pop_front()
+        if self.fuel == Some(0) {
+            return None;
+        }
+        let tok = self.toks.pop_front()?;
+        self.offset += (self.width)(&tok);
+        if let Some(record) = self.record {
+            self.journal.push(record(&tok));
+        }
+        if let Some(fuel) = &mut self.fuel {
+            *fuel -= 1;
+        }
+        Some(tok)
+    }
+
+    /// Puts back the token `pop` just returned, which a checkpoint mustn't put back again.
+    fn unpop(&mut self, tok : T) {
+        if self.record.is_some() {
+            self.journal.pop();
+        }
+        self.put_back(tok);
+    }
+
+    fn put_back(&mut self, tok : T) {
+        self.offset -= (self.width)(&tok);
+        self.toks.push_front(tok);
     }
 
     pub fn transform<U>(&mut self, cb : impl FnOnce(&T) -> Option<U>) -> Option<U> {
@@ -58,18 +174,24 @@ impl<T> Scanner<T> {
         res
     }
 
-    pub fn scan<U>(&mut self, cb : impl Fn(&[T]) -> ScannerAction<U>) -> Result<Option<U>, &'static str> {
-        let mut sequence = Vec::new();
+    pub fn scan<U>(&mut self, cb : impl Fn(&[T]) -> ScannerAction<U>) -> Result<Option<U>, ScanErrorKind> {
+        let mut sequence = core::mem::take(&mut self.scratch);
         let mut request = None;
         let mut require = false;
 
-        loop {
+        let res = loop {
             let Some(tok) = self.pop() else {
-                break if require { Err("EOF") } else { Ok(request) }
+                break if self.is_out_of_fuel() {
+                    Err(ScanErrorKind::OutOfFuel)
+                } else if require { Err(ScanErrorKind::Eof) } else { Ok(request) }
             };
 
             sequence.push(tok);
-            match cb(&sequence[..]) {
+            let action = cb(&sequence[..]);
+            if let Some(trace) = &self.trace {
+                trace.record(TraceEvent::Scan { tokens: sequence.iter().map(self.describe).collect(), action: action.name() });
+            }
+            match action {
                 ScannerAction::Return(res) => break Ok(Some(res)),
                 ScannerAction::Request(res) => {
                     require = false;
@@ -77,14 +199,64 @@ impl<T> Scanner<T> {
                 },
                 ScannerAction::Require => require = true,
                 ScannerAction::None => {
-                    self.toks.push_front(sequence.pop().unwrap()); // Put it back
-                    break if require { Err("TODO: Error") } else { Ok(request) }
-                }
+                    self.unpop(sequence.pop().unwrap()); // Put it back
+                    break if require { Err(ScanErrorKind::UnexpectedToken) } else { Ok(request) }
+                },
+                ScannerAction::Fail(never) => match never {},
             }
+        };
+
+        sequence.clear();
+        self.scratch = sequence;
+        res
+    }
+
+    /// Saves where the scanner is, recording what it pops from then on so that `rewind` can put
+    /// it back. Checkpoints nest, and each must be rewound to or committed, innermost first, to
+    /// stop recording.
+    pub fn checkpoint(&mut self) -> Checkpoint where T : Clone {
+        let nested = self.record.replace(T::clone).is_some();
+        Checkpoint { journal: self.journal.len(), nested }
+    }
+
+    /// Puts back the tokens popped since `checkpoint`. The fuel they took stays spent, as
+    /// backtracking is work too.
+    pub fn rewind(&mut self, checkpoint : Checkpoint) {
+        while self.journal.len() > checkpoint.journal {
+            let tok = self.journal.pop().unwrap();
+            self.put_back(tok);
+        }
+        self.commit(checkpoint);
+    }
+
+    /// Keeps the tokens popped since `checkpoint`, which can't be rewound to anymore. They are
+    /// still put back when rewinding to an enclosing checkpoint.
+    pub fn commit(&mut self, checkpoint : Checkpoint) {
+        if !checkpoint.nested {
+            self.journal.clear();
+            self.record = None;
+        }
+    }
+
+    /// Runs `cb`, putting back what it popped if it returns `None`.
+    pub fn try_scan<U>(&mut self, cb : impl FnOnce(&mut Self) -> Option<U>) -> Option<U> where T : Clone {
+        let checkpoint = self.checkpoint();
+        let res = cb(self);
+        match res {
+            Some(_) => self.commit(checkpoint),
+            None => self.rewind(checkpoint),
         }
+        res
     }
 
-    pub fn collect<U>(&mut self, cb : impl Fn(&[T]) -> ScannerAction<U>) -> Result<Vec<U>, &'static str> {
+    /// Tries `matchers` in order until one returns a value, putting back what those returning
+    /// `None` consumed, so each starts where the scanner was. Returns the index of the matcher
+    /// that matched with its value.
+    pub fn scan_any<U>(&mut self, matchers : &[Matcher<'_, T, U>]) -> Option<(usize, U)> where T : Clone {
+        matchers.iter().enumerate().find_map(|(i, matcher)| Some((i, self.try_scan(matcher)?)))
+    }
+
+    pub fn collect<U>(&mut self, cb : impl Fn(&[T]) -> ScannerAction<U>) -> Result<Vec<U>, ScanErrorKind> {
         let mut res = Vec::new();
 
         while !self.is_done() {
@@ -95,3 +267,119 @@ impl<T> Scanner<T> {
         Ok(res)
     }
 }
+
+impl Scanner<char> {
+    /// Scans `chars` with byte offsets, as `with_width` with `char::len_utf8` would. The chars
+    /// are collected up front; to tokenize input too large for that, see `ChunkedTokenReader`.
+    pub fn from_chars(chars : impl IntoIterator<Item = char>) -> Self {
+        Self::with_width(chars.into_iter().collect(), |c| c.len_utf8())
+    }
+
+    /// The line and column in `file` of the next char, for a scanner over its source since the
+    /// start with byte offsets, as from `from_chars`.
+    pub fn position(&self, file : &SourceFile) -> LineCol {
+        file.line_col(self.offset)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn digits(scanner : &mut Scanner<char>) -> Option<String> {
+        let digits : String = scanner.take_while(char::is_ascii_digit).into_iter().collect();
+        (!digits.is_empty()).then_some(digits)
+    }
+
+    fn hex(scanner : &mut Scanner<char>) -> Option<String> {
+        scanner.take(|c| *c == '0')?;
+        scanner.take(|c| *c == 'x')?;
+        Some(scanner.take_while(char::is_ascii_hexdigit).into_iter().collect())
+    }
+
+    #[test]
+    fn from_chars() {
+        let mut scanner = Scanner::from_chars("é1".chars());
+        assert_eq!(scanner.pop(), Some('é'));
+        assert_eq!((scanner.offset(), scanner.peek()), (2, Some(&'1')));
+    }
+
+    #[test]
+    fn position() {
+        let file = SourceFile::new("a.s", "ab\nçd\n");
+        let mut scanner = Scanner::from_chars(file.source().chars());
+        assert_eq!(scanner.position(&file), LineCol { line: 1, column: 1 });
+        scanner.take_while(|c| *c != 'd');
+        assert_eq!(scanner.position(&file), LineCol { line: 2, column: 2 });
+        let checkpoint = scanner.checkpoint();
+        scanner.take_while(|_| true);
+        assert_eq!(scanner.position(&file), LineCol { line: 3, column: 1 });
+        scanner.rewind(checkpoint);
+        assert_eq!(scanner.position(&file), LineCol { line: 2, column: 2 });
+    }
+
+    #[test]
+    fn scan_any() {
+        let mut scanner = Scanner::new("0xff 0y1".chars().collect());
+        assert_eq!(scanner.scan_any(&[&hex, &digits]), Some((0, "ff".to_string())));
+        scanner.pop();
+        assert_eq!(scanner.scan_any(&[&hex, &digits]), Some((1, "0".to_string())));
+        assert_eq!(scanner.offset(), 6);
+        assert_eq!(scanner.scan_any(&[&hex, &digits]), None);
+        assert_eq!(scanner.peek(), Some(&'y'));
+
+        // An inner match is undone with the outer attempt
+        let mut scanner = Scanner::new("12x".chars().collect()).with_fuel(10);
+        let nested = |scanner : &mut Scanner<char>| {
+            scanner.scan_any(&[&hex, &digits])?;
+            scanner.take(|c| *c == 'y')
+        };
+        assert_eq!(scanner.scan_any(&[&nested]), None);
+        assert_eq!((scanner.offset(), scanner.pop()), (0, Some('1')));
+    }
+
+    #[test]
+    fn checkpoints() {
+        let mut scanner = Scanner::new("abcdef".chars().collect()).with_fuel(5);
+        assert_eq!((scanner.peek_n(1), scanner.peek_n(5)), (Some(&'b'), None));
+        assert_eq!(scanner.peek_slice(3), ['a', 'b', 'c']);
+        assert_eq!(scanner.peek_slice(9).len(), 5);
+
+        let outer = scanner.checkpoint();
+        scanner.pop();
+        let inner = scanner.checkpoint();
+        scanner.pop();
+        scanner.pop();
+        scanner.rewind(inner);
+        // The fuel spent on the tokens put back stays spent
+        assert_eq!((scanner.offset(), scanner.peek_slice(9)), (1, &['b', 'c'][..]));
+        scanner.rewind(outer);
+        assert_eq!((scanner.offset(), scanner.pop()), (0, Some('a')));
+
+        assert_eq!(scanner.try_scan(|scanner| scanner.pop().filter(|c| *c == 'x')), None);
+        assert!(scanner.is_out_of_fuel());
+        assert_eq!(scanner.try_scan(|scanner| scanner.pop()), None);
+        assert_eq!(scanner.offset(), 1);
+
+        // A token `scan` puts back isn't put back again
+        let mut scanner = Scanner::new("abc".chars().collect());
+        let checkpoint = scanner.checkpoint();
+        scanner.pop();
+        let b = |toks : &[char]| if toks == ['b'] { ScannerAction::Request(()) } else { ScannerAction::None };
+        assert_eq!(scanner.scan(b), Ok(Some(())));
+        scanner.rewind(checkpoint);
+        assert_eq!((scanner.offset(), scanner.take_while(|_| true)), (0, vec!['a', 'b', 'c']));
+    }
+
+    #[test]
+    fn scan_errors() {
+        let pair = |toks : &[char]| match toks {
+            ['('] => ScannerAction::Require,
+            ['(', ')'] => ScannerAction::Return(()),
+            _ => ScannerAction::None,
+        };
+        assert_eq!(Scanner::new("()(".chars().collect()).collect(pair), Err(ScanErrorKind::Eof));
+        assert_eq!(Scanner::new("(]".chars().collect()).scan(pair), Err(ScanErrorKind::UnexpectedToken));
+        assert_eq!(Scanner::new("()()".chars().collect()).with_fuel(3).collect(pair), Err(ScanErrorKind::OutOfFuel));
+    }
+}
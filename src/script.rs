@@ -0,0 +1,55 @@
+//! Detection of identifiers mixing scripts, e.g. a Cyrillic `о` among Latin letters to spoof
+//! another name, with the `unicode-security` feature.
+
+mod tables;
+
+use crate::prelude::*;
+use tables::{SCRIPT_NAMES, SCRIPTS};
+
+/// The script of `c`, if it is a letter of one.
+pub(crate) fn script(c : char) -> Option<&'static str> {
+    if c.is_ascii() {
+        return c.is_ascii_alphabetic().then_some("Latin");
+    }
+    let i = SCRIPTS.binary_search_by(|&(lo, hi, _)| match () {
+        _ if hi < c => core::cmp::Ordering::Less,
+        _ if lo > c => core::cmp::Ordering::Greater,
+        _ => core::cmp::Ordering::Equal,
+    }).ok()?;
+    Some(SCRIPT_NAMES[SCRIPTS[i].2 as usize])
+}
+
+/// The scripts that are written together, with Latin: Japanese, Korean and Chinese with Bopomofo.
+const MIXES : [&[&str]; 3] = [&["Latin", "Han", "Hiragana", "Katakana"], &["Latin", "Han", "Hangul"], &["Latin", "Han", "Bopomofo"]];
+
+/// Two scripts of `ident` that aren't written together, if any.
+pub(crate) fn mixed_scripts(ident : &str) -> Option<(&'static str, &'static str)> {
+    let mut scripts = Vec::new();
+    for script in ident.chars().filter_map(script) {
+        if !scripts.contains(&script) {
+            scripts.push(script);
+        }
+    }
+    if scripts.len() < 2 || MIXES.iter().any(|mix| scripts.iter().all(|script| mix.contains(script))) {
+        return None;
+    }
+    let second = scripts[1..].iter().find(|script| !MIXES.iter().any(|mix| mix.contains(&scripts[0]) && mix.contains(script)));
+    Some((scripts[0], *second.unwrap_or(&scripts[1])))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scripts() {
+        assert_eq!((script('a'), script('о'), script('λ'), script('1'), script('_')), (Some("Latin"), Some("Cyrillic"), Some("Greek"), None, None));
+        assert_eq!((script('漢'), script('가'), script('é')), (Some("Han"), Some("Hangul"), Some("Latin")));
+
+        assert_eq!(mixed_scripts("paypal_2"), None);
+        assert_eq!(mixed_scripts("pаypal"), Some(("Latin", "Cyrillic")));
+        assert_eq!(mixed_scripts("名前なまえ_id"), None);
+        assert_eq!(mixed_scripts("漢字가λ"), Some(("Han", "Greek")));
+        assert_eq!(mixed_scripts("가かな"), Some(("Hangul", "Hiragana")));
+    }
+}
@@ -0,0 +1,431 @@
+//! The script of letters, approximated from the character names of the Unicode 14.0.0 database.
+
+/// The names of the scripts, indexed by `SCRIPTS`.
+pub(super) const SCRIPT_NAMES : &[&str] = &[
+    "Latin",
+    "Greek",
+    "Cyrillic",
+    "Armenian",
+    "Hebrew",
+    "Arabic",
+    "Syriac",
+    "Thaana",
+    "Nko",
+    "Devanagari",
+    "Bengali",
+    "Gurmukhi",
+    "Gujarati",
+    "Oriya",
+    "Tamil",
+    "Telugu",
+    "Kannada",
+    "Malayalam",
+    "Sinhala",
+    "Thai",
+    "Lao",
+    "Tibetan",
+    "Myanmar",
+    "Georgian",
+    "Hangul",
+    "Ethiopic",
+    "Cherokee",
+    "Canadian Aboriginal",
+    "Ogham",
+    "Runic",
+    "Khmer",
+    "Mongolian",
+    "Hiragana",
+    "Katakana",
+    "Bopomofo",
+    "Han",
+    "Yi",
+    "Coptic",
+    "Tifinagh",
+    "Javanese",
+    "Balinese",
+    "Vai",
+    "Bamum",
+];
+
+/// Ranges of letters of the same script, by index into `SCRIPT_NAMES`. Other chars are in no
+/// particular script, like digits and punctuation.
+pub(super) const SCRIPTS : &[(char, char, u8)] = &[
+    ('\u{41}', '\u{5A}', 0),
+    ('\u{61}', '\u{7A}', 0),
+    ('\u{C0}', '\u{D6}', 0),
+    ('\u{D8}', '\u{F6}', 0),
+    ('\u{F8}', '\u{2AF}', 0),
+    ('\u{370}', '\u{374}', 1),
+    ('\u{376}', '\u{377}', 1),
+    ('\u{37A}', '\u{37D}', 1),
+    ('\u{37F}', '\u{37F}', 1),
+    ('\u{386}', '\u{386}', 1),
+    ('\u{388}', '\u{38A}', 1),
+    ('\u{38C}', '\u{38C}', 1),
+    ('\u{38E}', '\u{3A1}', 1),
+    ('\u{3A3}', '\u{3E1}', 1),
+    ('\u{3E2}', '\u{3EF}', 37),
+    ('\u{3F0}', '\u{3F5}', 1),
+    ('\u{3F7}', '\u{3FF}', 1),
+    ('\u{400}', '\u{481}', 2),
+    ('\u{48A}', '\u{52F}', 2),
+    ('\u{531}', '\u{556}', 3),
+    ('\u{559}', '\u{559}', 3),
+    ('\u{560}', '\u{588}', 3),
+    ('\u{5D0}', '\u{5EA}', 4),
+    ('\u{5EF}', '\u{5F2}', 4),
+    ('\u{620}', '\u{64A}', 5),
+    ('\u{66E}', '\u{66F}', 5),
+    ('\u{671}', '\u{6D3}', 5),
+    ('\u{6D5}', '\u{6D5}', 5),
+    ('\u{6E5}', '\u{6E6}', 5),
+    ('\u{6EE}', '\u{6EF}', 5),
+    ('\u{6FA}', '\u{6FC}', 5),
+    ('\u{6FF}', '\u{6FF}', 5),
+    ('\u{710}', '\u{710}', 6),
+    ('\u{712}', '\u{72F}', 6),
+    ('\u{74D}', '\u{74F}', 6),
+    ('\u{750}', '\u{77F}', 5),
+    ('\u{780}', '\u{7A5}', 7),
+    ('\u{7B1}', '\u{7B1}', 7),
+    ('\u{7CA}', '\u{7EA}', 8),
+    ('\u{7F4}', '\u{7F5}', 8),
+    ('\u{7FA}', '\u{7FA}', 8),
+    ('\u{860}', '\u{86A}', 6),
+    ('\u{870}', '\u{887}', 5),
+    ('\u{889}', '\u{88E}', 5),
+    ('\u{8A0}', '\u{8C9}', 5),
+    ('\u{904}', '\u{939}', 9),
+    ('\u{93D}', '\u{93D}', 9),
+    ('\u{950}', '\u{950}', 9),
+    ('\u{958}', '\u{961}', 9),
+    ('\u{971}', '\u{97F}', 9),
+    ('\u{980}', '\u{980}', 10),
+    ('\u{985}', '\u{98C}', 10),
+    ('\u{98F}', '\u{990}', 10),
+    ('\u{993}', '\u{9A8}', 10),
+    ('\u{9AA}', '\u{9B0}', 10),
+    ('\u{9B2}', '\u{9B2}', 10),
+    ('\u{9B6}', '\u{9B9}', 10),
+    ('\u{9BD}', '\u{9BD}', 10),
+    ('\u{9CE}', '\u{9CE}', 10),
+    ('\u{9DC}', '\u{9DD}', 10),
+    ('\u{9DF}', '\u{9E1}', 10),
+    ('\u{9F0}', '\u{9F1}', 10),
+    ('\u{9FC}', '\u{9FC}', 10),
+    ('\u{A05}', '\u{A0A}', 11),
+    ('\u{A0F}', '\u{A10}', 11),
+    ('\u{A13}', '\u{A28}', 11),
+    ('\u{A2A}', '\u{A30}', 11),
+    ('\u{A32}', '\u{A33}', 11),
+    ('\u{A35}', '\u{A36}', 11),
+    ('\u{A38}', '\u{A39}', 11),
+    ('\u{A59}', '\u{A5C}', 11),
+    ('\u{A5E}', '\u{A5E}', 11),
+    ('\u{A72}', '\u{A74}', 11),
+    ('\u{A85}', '\u{A8D}', 12),
+    ('\u{A8F}', '\u{A91}', 12),
+    ('\u{A93}', '\u{AA8}', 12),
+    ('\u{AAA}', '\u{AB0}', 12),
+    ('\u{AB2}', '\u{AB3}', 12),
+    ('\u{AB5}', '\u{AB9}', 12),
+    ('\u{ABD}', '\u{ABD}', 12),
+    ('\u{AD0}', '\u{AD0}', 12),
+    ('\u{AE0}', '\u{AE1}', 12),
+    ('\u{AF9}', '\u{AF9}', 12),
+    ('\u{B05}', '\u{B0C}', 13),
+    ('\u{B0F}', '\u{B10}', 13),
+    ('\u{B13}', '\u{B28}', 13),
+    ('\u{B2A}', '\u{B30}', 13),
+    ('\u{B32}', '\u{B33}', 13),
+    ('\u{B35}', '\u{B39}', 13),
+    ('\u{B3D}', '\u{B3D}', 13),
+    ('\u{B5C}', '\u{B5D}', 13),
+    ('\u{B5F}', '\u{B61}', 13),
+    ('\u{B71}', '\u{B71}', 13),
+    ('\u{B83}', '\u{B83}', 14),
+    ('\u{B85}', '\u{B8A}', 14),
+    ('\u{B8E}', '\u{B90}', 14),
+    ('\u{B92}', '\u{B95}', 14),
+    ('\u{B99}', '\u{B9A}', 14),
+    ('\u{B9C}', '\u{B9C}', 14),
+    ('\u{B9E}', '\u{B9F}', 14),
+    ('\u{BA3}', '\u{BA4}', 14),
+    ('\u{BA8}', '\u{BAA}', 14),
+    ('\u{BAE}', '\u{BB9}', 14),
+    ('\u{BD0}', '\u{BD0}', 14),
+    ('\u{C05}', '\u{C0C}', 15),
+    ('\u{C0E}', '\u{C10}', 15),
+    ('\u{C12}', '\u{C28}', 15),
+    ('\u{C2A}', '\u{C39}', 15),
+    ('\u{C3D}', '\u{C3D}', 15),
+    ('\u{C58}', '\u{C5A}', 15),
+    ('\u{C5D}', '\u{C5D}', 15),
+    ('\u{C60}', '\u{C61}', 15),
+    ('\u{C80}', '\u{C80}', 16),
+    ('\u{C85}', '\u{C8C}', 16),
+    ('\u{C8E}', '\u{C90}', 16),
+    ('\u{C92}', '\u{CA8}', 16),
+    ('\u{CAA}', '\u{CB3}', 16),
+    ('\u{CB5}', '\u{CB9}', 16),
+    ('\u{CBD}', '\u{CBD}', 16),
+    ('\u{CDD}', '\u{CDE}', 16),
+    ('\u{CE0}', '\u{CE1}', 16),
+    ('\u{CF1}', '\u{CF2}', 16),
+    ('\u{D04}', '\u{D0C}', 17),
+    ('\u{D0E}', '\u{D10}', 17),
+    ('\u{D12}', '\u{D3A}', 17),
+    ('\u{D3D}', '\u{D3D}', 17),
+    ('\u{D4E}', '\u{D4E}', 17),
+    ('\u{D54}', '\u{D56}', 17),
+    ('\u{D5F}', '\u{D61}', 17),
+    ('\u{D7A}', '\u{D7F}', 17),
+    ('\u{D85}', '\u{D96}', 18),
+    ('\u{D9A}', '\u{DB1}', 18),
+    ('\u{DB3}', '\u{DBB}', 18),
+    ('\u{DBD}', '\u{DBD}', 18),
+    ('\u{DC0}', '\u{DC6}', 18),
+    ('\u{E01}', '\u{E30}', 19),
+    ('\u{E32}', '\u{E33}', 19),
+    ('\u{E40}', '\u{E46}', 19),
+    ('\u{E81}', '\u{E82}', 20),
+    ('\u{E84}', '\u{E84}', 20),
+    ('\u{E86}', '\u{E8A}', 20),
+    ('\u{E8C}', '\u{EA3}', 20),
+    ('\u{EA5}', '\u{EA5}', 20),
+    ('\u{EA7}', '\u{EB0}', 20),
+    ('\u{EB2}', '\u{EB3}', 20),
+    ('\u{EBD}', '\u{EBD}', 20),
+    ('\u{EC0}', '\u{EC4}', 20),
+    ('\u{EC6}', '\u{EC6}', 20),
+    ('\u{EDC}', '\u{EDF}', 20),
+    ('\u{F00}', '\u{F00}', 21),
+    ('\u{F40}', '\u{F47}', 21),
+    ('\u{F49}', '\u{F6C}', 21),
+    ('\u{F88}', '\u{F8C}', 21),
+    ('\u{1000}', '\u{102A}', 22),
+    ('\u{103F}', '\u{103F}', 22),
+    ('\u{1050}', '\u{1055}', 22),
+    ('\u{105A}', '\u{105D}', 22),
+    ('\u{1061}', '\u{1061}', 22),
+    ('\u{1065}', '\u{1066}', 22),
+    ('\u{106E}', '\u{1070}', 22),
+    ('\u{1075}', '\u{1081}', 22),
+    ('\u{108E}', '\u{108E}', 22),
+    ('\u{10A0}', '\u{10C5}', 23),
+    ('\u{10C7}', '\u{10C7}', 23),
+    ('\u{10CD}', '\u{10CD}', 23),
+    ('\u{10D0}', '\u{10FA}', 23),
+    ('\u{10FD}', '\u{10FF}', 23),
+    ('\u{1100}', '\u{11FF}', 24),
+    ('\u{1200}', '\u{1248}', 25),
+    ('\u{124A}', '\u{124D}', 25),
+    ('\u{1250}', '\u{1256}', 25),
+    ('\u{1258}', '\u{1258}', 25),
+    ('\u{125A}', '\u{125D}', 25),
+    ('\u{1260}', '\u{1288}', 25),
+    ('\u{128A}', '\u{128D}', 25),
+    ('\u{1290}', '\u{12B0}', 25),
+    ('\u{12B2}', '\u{12B5}', 25),
+    ('\u{12B8}', '\u{12BE}', 25),
+    ('\u{12C0}', '\u{12C0}', 25),
+    ('\u{12C2}', '\u{12C5}', 25),
+    ('\u{12C8}', '\u{12D6}', 25),
+    ('\u{12D8}', '\u{1310}', 25),
+    ('\u{1312}', '\u{1315}', 25),
+    ('\u{1318}', '\u{135A}', 25),
+    ('\u{1380}', '\u{138F}', 25),
+    ('\u{13A0}', '\u{13F5}', 26),
+    ('\u{13F8}', '\u{13FD}', 26),
+    ('\u{1401}', '\u{166C}', 27),
+    ('\u{166F}', '\u{167F}', 27),
+    ('\u{1681}', '\u{169A}', 28),
+    ('\u{16A0}', '\u{16EA}', 29),
+    ('\u{16F1}', '\u{16F8}', 29),
+    ('\u{1780}', '\u{17B3}', 30),
+    ('\u{17D7}', '\u{17D7}', 30),
+    ('\u{17DC}', '\u{17DC}', 30),
+    ('\u{1820}', '\u{1878}', 31),
+    ('\u{1880}', '\u{1884}', 31),
+    ('\u{1887}', '\u{18A8}', 31),
+    ('\u{18AA}', '\u{18AA}', 31),
+    ('\u{18B0}', '\u{18F5}', 27),
+    ('\u{1B05}', '\u{1B33}', 40),
+    ('\u{1B45}', '\u{1B4C}', 40),
+    ('\u{1C80}', '\u{1C88}', 2),
+    ('\u{1C90}', '\u{1CBA}', 23),
+    ('\u{1CBD}', '\u{1CBF}', 23),
+    ('\u{1D00}', '\u{1D25}', 0),
+    ('\u{1D26}', '\u{1D2A}', 1),
+    ('\u{1D2B}', '\u{1D2B}', 2),
+    ('\u{1D62}', '\u{1D65}', 0),
+    ('\u{1D66}', '\u{1D6A}', 1),
+    ('\u{1D6B}', '\u{1D77}', 0),
+    ('\u{1D79}', '\u{1D9A}', 0),
+    ('\u{1E00}', '\u{1EFF}', 0),
+    ('\u{1F00}', '\u{1F15}', 1),
+    ('\u{1F18}', '\u{1F1D}', 1),
+    ('\u{1F20}', '\u{1F45}', 1),
+    ('\u{1F48}', '\u{1F4D}', 1),
+    ('\u{1F50}', '\u{1F57}', 1),
+    ('\u{1F59}', '\u{1F59}', 1),
+    ('\u{1F5B}', '\u{1F5B}', 1),
+    ('\u{1F5D}', '\u{1F5D}', 1),
+    ('\u{1F5F}', '\u{1F7D}', 1),
+    ('\u{1F80}', '\u{1FB4}', 1),
+    ('\u{1FB6}', '\u{1FBC}', 1),
+    ('\u{1FBE}', '\u{1FBE}', 1),
+    ('\u{1FC2}', '\u{1FC4}', 1),
+    ('\u{1FC6}', '\u{1FCC}', 1),
+    ('\u{1FD0}', '\u{1FD3}', 1),
+    ('\u{1FD6}', '\u{1FDB}', 1),
+    ('\u{1FE0}', '\u{1FEC}', 1),
+    ('\u{1FF2}', '\u{1FF4}', 1),
+    ('\u{1FF6}', '\u{1FFC}', 1),
+    ('\u{2090}', '\u{209C}', 0),
+    ('\u{2184}', '\u{2184}', 0),
+    ('\u{2C60}', '\u{2C7C}', 0),
+    ('\u{2C7E}', '\u{2C7F}', 0),
+    ('\u{2C80}', '\u{2CE4}', 37),
+    ('\u{2CEB}', '\u{2CEE}', 37),
+    ('\u{2CF2}', '\u{2CF3}', 37),
+    ('\u{2D00}', '\u{2D25}', 23),
+    ('\u{2D27}', '\u{2D27}', 23),
+    ('\u{2D2D}', '\u{2D2D}', 23),
+    ('\u{2D30}', '\u{2D67}', 38),
+    ('\u{2D6F}', '\u{2D6F}', 38),
+    ('\u{2D80}', '\u{2D96}', 25),
+    ('\u{2DA0}', '\u{2DA6}', 25),
+    ('\u{2DA8}', '\u{2DAE}', 25),
+    ('\u{2DB0}', '\u{2DB6}', 25),
+    ('\u{2DB8}', '\u{2DBE}', 25),
+    ('\u{2DC0}', '\u{2DC6}', 25),
+    ('\u{2DC8}', '\u{2DCE}', 25),
+    ('\u{2DD0}', '\u{2DD6}', 25),
+    ('\u{2DD8}', '\u{2DDE}', 25),
+    ('\u{3041}', '\u{3096}', 32),
+    ('\u{309D}', '\u{309F}', 32),
+    ('\u{30A1}', '\u{30FA}', 33),
+    ('\u{30FD}', '\u{30FF}', 33),
+    ('\u{3105}', '\u{312F}', 34),
+    ('\u{3131}', '\u{318E}', 24),
+    ('\u{31A0}', '\u{31BF}', 34),
+    ('\u{31F0}', '\u{31FF}', 33),
+    ('\u{3400}', '\u{4DBF}', 35),
+    ('\u{4E00}', '\u{9FFF}', 35),
+    ('\u{A000}', '\u{A48C}', 36),
+    ('\u{A500}', '\u{A60C}', 41),
+    ('\u{A610}', '\u{A61F}', 41),
+    ('\u{A62A}', '\u{A62B}', 41),
+    ('\u{A640}', '\u{A66E}', 2),
+    ('\u{A67F}', '\u{A69B}', 2),
+    ('\u{A6A0}', '\u{A6E5}', 42),
+    ('\u{A722}', '\u{A76F}', 0),
+    ('\u{A771}', '\u{A787}', 0),
+    ('\u{A78B}', '\u{A7CA}', 0),
+    ('\u{A7D0}', '\u{A7D1}', 0),
+    ('\u{A7D3}', '\u{A7D3}', 0),
+    ('\u{A7D5}', '\u{A7D9}', 0),
+    ('\u{A7F5}', '\u{A7F7}', 0),
+    ('\u{A7FA}', '\u{A7FF}', 0),
+    ('\u{A8F2}', '\u{A8F7}', 9),
+    ('\u{A8FB}', '\u{A8FB}', 9),
+    ('\u{A8FD}', '\u{A8FE}', 9),
+    ('\u{A960}', '\u{A97C}', 24),
+    ('\u{A984}', '\u{A9B2}', 39),
+    ('\u{A9CF}', '\u{A9CF}', 39),
+    ('\u{A9E0}', '\u{A9E4}', 22),
+    ('\u{A9E6}', '\u{A9EF}', 22),
+    ('\u{A9FA}', '\u{A9FE}', 22),
+    ('\u{AA60}', '\u{AA76}', 22),
+    ('\u{AA7A}', '\u{AA7A}', 22),
+    ('\u{AA7E}', '\u{AA7F}', 22),
+    ('\u{AB01}', '\u{AB06}', 25),
+    ('\u{AB09}', '\u{AB0E}', 25),
+    ('\u{AB11}', '\u{AB16}', 25),
+    ('\u{AB20}', '\u{AB26}', 25),
+    ('\u{AB28}', '\u{AB2E}', 25),
+    ('\u{AB30}', '\u{AB5A}', 0),
+    ('\u{AB60}', '\u{AB64}', 0),
+    ('\u{AB65}', '\u{AB65}', 1),
+    ('\u{AB66}', '\u{AB68}', 0),
+    ('\u{AB70}', '\u{ABBF}', 26),
+    ('\u{AC00}', '\u{D7A3}', 24),
+    ('\u{D7B0}', '\u{D7C6}', 24),
+    ('\u{D7CB}', '\u{D7FB}', 24),
+    ('\u{F900}', '\u{FA6D}', 35),
+    ('\u{FA70}', '\u{FAD9}', 35),
+    ('\u{FB00}', '\u{FB06}', 0),
+    ('\u{FB13}', '\u{FB17}', 3),
+    ('\u{FB1D}', '\u{FB1D}', 4),
+    ('\u{FB1F}', '\u{FB28}', 4),
+    ('\u{FB2A}', '\u{FB36}', 4),
+    ('\u{FB38}', '\u{FB3C}', 4),
+    ('\u{FB3E}', '\u{FB3E}', 4),
+    ('\u{FB40}', '\u{FB41}', 4),
+    ('\u{FB43}', '\u{FB44}', 4),
+    ('\u{FB46}', '\u{FB4F}', 4),
+    ('\u{FB50}', '\u{FBB1}', 5),
+    ('\u{FBD3}', '\u{FD3D}', 5),
+    ('\u{FD50}', '\u{FD8F}', 5),
+    ('\u{FD92}', '\u{FDC7}', 5),
+    ('\u{FDF0}', '\u{FDFB}', 5),
+    ('\u{FE70}', '\u{FE74}', 5),
+    ('\u{FE76}', '\u{FEFC}', 5),
+    ('\u{11AB0}', '\u{11ABF}', 27),
+    ('\u{16800}', '\u{16A38}', 42),
+    ('\u{1AFF0}', '\u{1AFF3}', 33),
+    ('\u{1AFF5}', '\u{1AFFB}', 33),
+    ('\u{1AFFD}', '\u{1AFFE}', 33),
+    ('\u{1B000}', '\u{1B000}', 33),
+    ('\u{1B001}', '\u{1B001}', 32),
+    ('\u{1B11F}', '\u{1B11F}', 32),
+    ('\u{1B120}', '\u{1B122}', 33),
+    ('\u{1B150}', '\u{1B152}', 32),
+    ('\u{1B164}', '\u{1B167}', 33),
+    ('\u{1DF00}', '\u{1DF1E}', 0),
+    ('\u{1E7E0}', '\u{1E7E6}', 25),
+    ('\u{1E7E8}', '\u{1E7EB}', 25),
+    ('\u{1E7ED}', '\u{1E7EE}', 25),
+    ('\u{1E7F0}', '\u{1E7FE}', 25),
+    ('\u{1EE00}', '\u{1EE03}', 5),
+    ('\u{1EE05}', '\u{1EE1F}', 5),
+    ('\u{1EE21}', '\u{1EE22}', 5),
+    ('\u{1EE24}', '\u{1EE24}', 5),
+    ('\u{1EE27}', '\u{1EE27}', 5),
+    ('\u{1EE29}', '\u{1EE32}', 5),
+    ('\u{1EE34}', '\u{1EE37}', 5),
+    ('\u{1EE39}', '\u{1EE39}', 5),
+    ('\u{1EE3B}', '\u{1EE3B}', 5),
+    ('\u{1EE42}', '\u{1EE42}', 5),
+    ('\u{1EE47}', '\u{1EE47}', 5),
+    ('\u{1EE49}', '\u{1EE49}', 5),
+    ('\u{1EE4B}', '\u{1EE4B}', 5),
+    ('\u{1EE4D}', '\u{1EE4F}', 5),
+    ('\u{1EE51}', '\u{1EE52}', 5),
+    ('\u{1EE54}', '\u{1EE54}', 5),
+    ('\u{1EE57}', '\u{1EE57}', 5),
+    ('\u{1EE59}', '\u{1EE59}', 5),
+    ('\u{1EE5B}', '\u{1EE5B}', 5),
+    ('\u{1EE5D}', '\u{1EE5D}', 5),
+    ('\u{1EE5F}', '\u{1EE5F}', 5),
+    ('\u{1EE61}', '\u{1EE62}', 5),
+    ('\u{1EE64}', '\u{1EE64}', 5),
+    ('\u{1EE67}', '\u{1EE6A}', 5),
+    ('\u{1EE6C}', '\u{1EE72}', 5),
+    ('\u{1EE74}', '\u{1EE77}', 5),
+    ('\u{1EE79}', '\u{1EE7C}', 5),
+    ('\u{1EE7E}', '\u{1EE7E}', 5),
+    ('\u{1EE80}', '\u{1EE89}', 5),
+    ('\u{1EE8B}', '\u{1EE9B}', 5),
+    ('\u{1EEA1}', '\u{1EEA3}', 5),
+    ('\u{1EEA5}', '\u{1EEA9}', 5),
+    ('\u{1EEAB}', '\u{1EEBB}', 5),
+    ('\u{20000}', '\u{2A6DF}', 35),
+    ('\u{2A700}', '\u{2B738}', 35),
+    ('\u{2B740}', '\u{2B81D}', 35),
+    ('\u{2B820}', '\u{2CEA1}', 35),
+    ('\u{2CEB0}', '\u{2EBE0}', 35),
+    ('\u{2F800}', '\u{2FA1D}', 35),
+    ('\u{30000}', '\u{3134A}', 35),
+];
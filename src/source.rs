@@ -0,0 +1,331 @@
+use core::cell::OnceCell;
+#[cfg(feature = "std")]
+use std::{io, path::Path};
+
+use crate::prelude::*;
+use crate::{Diagnostic, DiagnosticKind, ExpansionId, FileId, Span, Spanned, Token, TokenizerConfig, token::tokenize_file};
+
+/// A 1-based line and column, the column counted in chars.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LineCol {
+    pub line : usize,
+    pub column : usize,
+}
+
+/// A named source text, with an index of its line starts built on first use.
+pub struct SourceFile {
+    id : FileId,
+    name : String,
+    code : String,
+    line_starts : OnceCell<Vec<usize>>,
+}
+
+impl SourceFile {
+    /// A file outside of any `SourceMap`, with the default `FileId`.
+    pub fn new(name : impl Into<String>, code : impl Into<String>) -> Self {
+        Self::with_id(FileId::default(), name, code)
+    }
+
+    fn with_id(id : FileId, name : impl Into<String>, code : impl Into<String>) -> Self {
+        Self { id, name: name.into(), code: code.into(), line_starts: OnceCell::new() }
+    }
+
+    pub fn id(&self) -> FileId {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn source(&self) -> &str {
+        &self.code
+    }
+
+    /// Byte offsets at which each line starts; the first is always 0.
+    pub fn line_starts(&self) -> &[usize] {
+        self.line_starts.get_or_init(|| {
+            core::iter::once(0).chain(self.code.match_indices('\n').map(|(i, _)| i + 1)).collect()
+        })
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts().len()
+    }
+
+    /// The 0-based index of the line containing byte `offset`.
+    pub fn line_index(&self, offset : usize) -> usize {
+        self.line_starts().partition_point(|start| *start <= offset) - 1
+    }
+
+    /// The text of the 0-based line `index`, without its line break.
+    pub fn line(&self, index : usize) -> &str {
+        let starts = self.line_starts();
+        let end = starts.get(index + 1).map_or(self.code.len(), |next| next - 1);
+        self.code[starts[index]..end].trim_end_matches('\r')
+    }
+
+    pub fn line_col(&self, offset : usize) -> LineCol {
+        let line = self.line_index(offset);
+        let column = self.code[self.line_starts()[line]..offset].chars().count();
+        LineCol { line: line + 1, column: column + 1 }
+    }
+
+    /// The text covered by `span`.
+    pub fn snippet(&self, span : Span) -> &str {
+        &self.code[span.start..span.end]
+    }
+
+    pub fn tokenize(&self) -> (Vec<Spanned<Token>>, Vec<Diagnostic>) {
+        self.tokenize_with(&TokenizerConfig::default())
+    }
+
+    pub fn tokenize_with(&self, config : &TokenizerConfig) -> (Vec<Spanned<Token>>, Vec<Diagnostic>) {
+        tokenize_file(config, self.id, &self.code)
+    }
+
+    /// Formats `diagnostic` and its notes, which must point into this file, as
+    /// `file:line:column: severity: message` lines.
+    pub fn render(&self, diagnostic : &Diagnostic) -> String {
+        render(diagnostic, |_| self)
+    }
+}
+
+fn render<'a>(diagnostic : &Diagnostic, file : impl Fn(FileId) -> &'a SourceFile) -> String {
+    let line = |diagnostic : &Diagnostic| {
+        let file = file(diagnostic.span.file);
+        let LineCol { line, column } = file.line_col(diagnostic.span.start);
+        format!("{}:{line}:{column}: {}: {}", file.name(), diagnostic.severity, diagnostic.message())
+    };
+
+    let mut out = line(diagnostic);
+    for note in &diagnostic.notes {
+        out += "\n  ";
+        out += &line(note);
+    }
+    out
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpansionKind {
+    /// An invocation of the named macro.
+    Macro(String),
+
+    /// An include of the named file.
+    Include(String),
+}
+
+/// How some tokens were brought to where they are, e.g. by a macro invocation at `call_site`.
+/// `call_site.expansion` is in turn the expansion the invocation came from, if any.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expansion {
+    pub kind : ExpansionKind,
+    pub call_site : Span,
+}
+
+/// A file read and tokenized by `SourceMap::tokenize_files`.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct TokenizedFile {
+    pub file : FileId,
+    pub tokens : Vec<Spanned<Token>>,
+    pub diagnostics : Vec<Diagnostic>,
+
+    /// Why the file couldn't be read, leaving it empty.
+    pub error : Option<io::Error>,
+}
+
+/// The set of source files of a project. Files are identified by the `FileId` returned
+/// when adding them, which the spans of their tokens and diagnostics carry.
+///
+/// It also records the expansions done by preprocessing passes, which spans refer to by `ExpansionId`.
+#[derive(Default)]
+pub struct SourceMap {
+    files : Vec<SourceFile>,
+    expansions : Vec<Expansion>,
+}
+
+impl SourceMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name : impl Into<String>, code : impl Into<String>) -> FileId {
+        let id = FileId(self.files.len().try_into().expect("too many files"));
+        self.files.push(SourceFile::with_id(id, name, code));
+        id
+    }
+
+    /// Finds a file by the name it was added with.
+    pub fn find(&self, name : &str) -> Option<FileId> {
+        self.files.iter().find(|file| file.name == name).map(SourceFile::id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Panics if `file` doesn't belong to this map.
+    pub fn get(&self, file : FileId) -> &SourceFile {
+        &self.files[file.index()]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SourceFile> {
+        self.files.iter()
+    }
+
+    pub fn name(&self, file : FileId) -> &str {
+        self.get(file).name()
+    }
+
+    pub fn source(&self, file : FileId) -> &str {
+        self.get(file).source()
+    }
+
+    /// The text covered by `span`.
+    pub fn snippet(&self, span : Span) -> &str {
+        self.get(span.file).snippet(span)
+    }
+
+    pub fn tokenize(&self, file : FileId) -> (Vec<Spanned<Token>>, Vec<Diagnostic>) {
+        self.get(file).tokenize()
+    }
+
+    pub fn tokenize_with(&self, config : &TokenizerConfig, file : FileId) -> (Vec<Spanned<Token>>, Vec<Diagnostic>) {
+        self.get(file).tokenize_with(config)
+    }
+
+    /// Reads the files at `paths`, adds them and tokenizes them with `config`, on all cores with
+    /// the `parallel` feature. A file that can't be read is added empty, with the error.
+    #[cfg(feature = "std")]
+    pub fn tokenize_files<P : AsRef<Path>>(&mut self, config : &TokenizerConfig, paths : impl IntoIterator<Item = P>) -> Vec<TokenizedFile> {
+        let read : Vec<_> = paths.into_iter().map(|path| {
+            let path = path.as_ref();
+            let (code, res) = match std::fs::read_to_string(path) {
+                Ok(code) => (code, Ok(())),
+                Err(err) => (String::new(), Err(err)),
+            };
+            (self.add(path.display().to_string(), code), res)
+        }).collect();
+
+        let files : Vec<_> = read.iter().map(|(file, _)| (*file, self.source(*file))).collect();
+        #[cfg(feature = "parallel")]
+        let toks : Vec<_> = {
+            let threads = std::thread::available_parallelism().map_or(1, usize::from);
+            std::thread::scope(|scope| {
+                let chunks : Vec<_> = files.chunks(files.len().div_ceil(threads).max(1)).map(|chunk| {
+                    scope.spawn(move || chunk.iter().map(|(file, code)| tokenize_file(config, *file, code)).collect::<Vec<_>>())
+                }).collect();
+                chunks.into_iter().flat_map(|chunk| chunk.join().unwrap()).collect()
+            })
+        };
+        #[cfg(not(feature = "parallel"))]
+        let toks : Vec<_> = files.iter().map(|(file, code)| tokenize_file(config, *file, code)).collect();
+
+        read.into_iter().zip(toks).map(|((file, res), (tokens, diagnostics))| TokenizedFile { file, tokens, diagnostics, error: res.err() }).collect()
+    }
+
+    pub fn add_expansion(&mut self, kind : ExpansionKind, call_site : Span) -> ExpansionId {
+        let id = ExpansionId(self.expansions.len().try_into().expect("too many expansions"));
+        self.expansions.push(Expansion { kind, call_site });
+        id
+    }
+
+    /// Panics if `expansion` doesn't belong to this map.
+    pub fn expansion(&self, expansion : ExpansionId) -> &Expansion {
+        &self.expansions[expansion.index()]
+    }
+
+    /// The expansions that led to `span`, innermost first.
+    pub fn backtrace(&self, span : Span) -> impl Iterator<Item = &Expansion> {
+        core::iter::successors(span.expansion.map(|id| self.expansion(id)), |expansion| {
+            expansion.call_site.expansion.map(|id| self.expansion(id))
+        })
+    }
+
+    /// Whether the text at `span` was generated by a macro rather than written by the user.
+    pub fn is_generated(&self, span : Span) -> bool {
+        self.backtrace(span).next().is_some_and(|expansion| matches!(expansion.kind, ExpansionKind::Macro(_)))
+    }
+
+    /// Adds to `diagnostic` a note for each expansion its span went through, e.g.
+    /// "expanded from macro `LOOP`" pointing at the invocation.
+    pub fn with_backtrace(&self, mut diagnostic : Diagnostic) -> Diagnostic {
+        for expansion in self.backtrace(diagnostic.span) {
+            let kind = match &expansion.kind {
+                ExpansionKind::Macro(name) => DiagnosticKind::ExpandedFrom(name.clone()),
+                ExpansionKind::Include(name) => DiagnosticKind::IncludedFrom(name.clone()),
+            };
+            diagnostic.notes.push(Diagnostic::note(expansion.call_site, kind));
+        }
+        diagnostic
+    }
+
+    /// Formats `diagnostic` and its notes as `file:line:column: severity: message` lines.
+    pub fn render(&self, diagnostic : &Diagnostic) -> String {
+        render(diagnostic, |file| self.get(file))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn files() {
+        let mut map = SourceMap::new();
+        let main = map.add("main.s", "nop");
+        let lib = map.add("lib.s", "mov\n\"abc");
+        assert_ne!(main, lib);
+        assert_eq!(map.find("lib.s"), Some(lib));
+        assert_eq!(map.name(lib), "lib.s");
+
+        let (toks, diags) = map.tokenize(lib);
+        assert_eq!(toks[0], Spanned::new(Token::Ident("mov".into()), Span::new(0, 3).with_file(lib)));
+        assert_eq!(diags[0].span, Span::new(4, 5).with_file(lib));
+        assert_eq!(map.snippet(diags[0].span), "\"");
+        assert_eq!(map.render(&diags[0]), "lib.s:2:1: error: unterminated string literal\n  lib.s:2:5: note: literal ends here");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn tokenize_files() {
+        let dir = std::env::temp_dir().join(format!("smpl_parser_files_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let paths : Vec<_> = (0..20).map(|i| dir.join(format!("{i}.s"))).collect();
+        for (i, path) in paths.iter().enumerate() {
+            std::fs::write(path, format!("mov r{i}")).unwrap();
+        }
+
+        let mut map = SourceMap::new();
+        map.add("main.s", "nop");
+        let config = TokenizerConfig::default();
+        let files = map.tokenize_files(&config, paths.iter().chain([&dir.join("missing.s")]));
+        assert_eq!((files.len(), map.len()), (21, 22));
+        let seventh = &files[7];
+        assert_eq!((map.name(seventh.file), seventh.diagnostics.len()), (&*paths[7].display().to_string(), 0));
+        assert_eq!(seventh.tokens[1], Spanned::new(Token::Ident("r7".into()), Span::new(4, 6).with_file(seventh.file)));
+        assert!(seventh.error.is_none());
+        assert_eq!(files[20].error.as_ref().map(io::Error::kind), Some(io::ErrorKind::NotFound));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn lines() {
+        let file = SourceFile::new("a.s", "mov\r\n\nnоp \"x");
+        assert_eq!(file.line_starts(), &[0, 5, 6]);
+        assert_eq!(file.line_count(), 3);
+        assert_eq!((file.line(0), file.line(1), file.line(2)), ("mov", "", "nоp \"x"));
+        assert_eq!(file.line_col(0), LineCol { line: 1, column: 1 });
+        assert_eq!(file.line_col(3), LineCol { line: 1, column: 4 });
+        assert_eq!(file.line_col(5), LineCol { line: 2, column: 1 });
+        assert_eq!(file.line_col(11), LineCol { line: 3, column: 5 });
+
+        let (_, diags) = file.tokenize();
+        assert_eq!(file.render(&diags[0]), "a.s:3:1: warning: identifier `nоp` uses confusable character `о` (U+043E), which looks like `o`");
+    }
+}
@@ -0,0 +1,251 @@
+use core::{fmt, ops::{Deref, DerefMut, Range}};
+
+use crate::prelude::*;
+
+/// Identifies a file registered in a `SourceMap`.
+///
+/// Spans not produced through a `SourceMap` use `FileId::default()`, which is also the id
+/// of the first file added to a map.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileId(pub(crate) u32);
+
+impl FileId {
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Identifies a macro expansion or include registered in a `SourceMap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ExpansionId(pub(crate) u32);
+
+impl ExpansionId {
+    pub fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// A half-open byte range `start..end` into the source text of `file`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Span {
+    pub file : FileId,
+    pub start : usize,
+    pub end : usize,
+
+    /// The expansion that produced the text at this span, if it didn't get there by itself.
+    pub expansion : Option<ExpansionId>,
+}
+
+impl Span {
+    pub fn new(start : usize, end : usize) -> Self {
+        debug_assert!(start <= end);
+        Self { file: FileId::default(), start, end, expansion: None }
+    }
+
+    pub fn with_file(self, file : FileId) -> Self {
+        Self { file, ..self }
+    }
+
+    pub fn with_expansion(self, expansion : ExpansionId) -> Self {
+        Self { expansion: Some(expansion), ..self }
+    }
+
+    /// Whether the text at this span was brought there by a macro expansion or an include.
+    pub fn from_expansion(&self) -> bool {
+        self.expansion.is_some()
+    }
+
+    /// A zero-width span at `offset`.
+    pub fn empty(offset : usize) -> Self {
+        Self::new(offset, offset)
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// The smallest span covering both `self` and `other`, e.g. the first and last token of a production.
+    /// The expansion is that of `self`.
+    pub fn join(self, other : Span) -> Span {
+        debug_assert_eq!(self.file, other.file);
+        Span { start: self.start.min(other.start), end: self.end.max(other.end), ..self }
+    }
+
+    /// Whether byte `offset` lies within the span. The end is included, so that a cursor
+    /// placed right after a token is still considered to be on it.
+    pub fn contains(&self, offset : usize) -> bool {
+        self.start <= offset && offset <= self.end
+    }
+
+    pub fn contains_span(&self, other : Span) -> bool {
+        self.file == other.file && self.start <= other.start && other.end <= self.end
+    }
+
+    /// The overlap of both spans, if any.
+    pub fn intersect(self, other : Span) -> Option<Span> {
+        let start = self.start.max(other.start);
+        let end = self.end.min(other.end);
+        (self.file == other.file && start <= end).then_some(Span { start, end, ..self })
+    }
+
+    /// Removes `left` bytes from the start and `right` bytes from the end, e.g. to drop the
+    /// quotes around a string. Never shrinks past a zero-width span.
+    pub fn shrink(self, left : usize, right : usize) -> Span {
+        let start = (self.start + left).min(self.end);
+        let end = self.end.saturating_sub(right).max(start);
+        Span { start, end, ..self }
+    }
+
+    /// Moves the span by `delta` bytes, e.g. after splicing text in front of it.
+    pub fn offset_by(self, delta : isize) -> Span {
+        let shift = |n : usize| n.checked_add_signed(delta).expect("span offset out of bounds");
+        Span { start: shift(self.start), end: shift(self.end), ..self }
+    }
+
+    /// The span relative to `base`, i.e. with offsets counted from `base`.
+    pub fn relative_to(self, base : usize) -> Span {
+        debug_assert!(base <= self.start);
+        Span { start: self.start - base, end: self.end - base, ..self }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+/// Anything that covers a known source range.
+///
+/// Implement it for AST nodes to have spans propagate uniformly through parser layers.
+pub trait HasSpan {
+    fn span(&self) -> Span;
+}
+
+impl HasSpan for Span {
+    fn span(&self) -> Span {
+        *self
+    }
+}
+
+impl<T : HasSpan + ?Sized> HasSpan for &T {
+    fn span(&self) -> Span {
+        (**self).span()
+    }
+}
+
+impl<T : HasSpan + ?Sized> HasSpan for Box<T> {
+    fn span(&self) -> Span {
+        (**self).span()
+    }
+}
+
+/// The span from the first to the last of `items`, or `None` if there are none.
+pub fn span_of<T : HasSpan>(items : &[T]) -> Option<Span> {
+    Some(items.first()?.span().join(items.last()?.span()))
+}
+
+/// A `T` along with the span it was parsed from.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Spanned<T> {
+    pub value : T,
+    pub span : Span,
+}
+
+impl<T> Spanned<T> {
+    pub fn new(value : T, span : Span) -> Self {
+        Self { value, span }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
+
+    /// A `Spanned` borrowing the value, unlike `AsRef::as_ref`, which gives the bare value.
+    pub fn by_ref(&self) -> Spanned<&T> {
+        Spanned::new(&self.value, self.span)
+    }
+
+    pub fn map<U>(self, f : impl FnOnce(T) -> U) -> Spanned<U> {
+        Spanned::new(f(self.value), self.span)
+    }
+}
+
+impl<T> HasSpan for Spanned<T> {
+    fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl<T> AsRef<T> for Spanned<T> {
+    fn as_ref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> Deref for Spanned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for Spanned<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T : fmt::Debug> fmt::Debug for Spanned<T> {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} @ {}", self.value, self.span)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn arithmetic() {
+        let a = Span::new(2, 5);
+        let b = Span::new(8, 10);
+        assert_eq!(a.join(b), Span::new(2, 10));
+        assert_eq!(b.join(a), Span::new(2, 10));
+
+        assert!(a.contains(2) && a.contains(5) && !a.contains(6));
+        assert!(a.join(b).contains_span(b) && !a.contains_span(b));
+        assert!(!a.contains_span(a.with_file(FileId(1))));
+
+        assert_eq!(a.intersect(Span::new(4, 9)), Some(Span::new(4, 5)));
+        assert_eq!(a.intersect(b), None);
+
+        assert_eq!(a.shrink(1, 1), Span::new(3, 4));
+        assert_eq!(a.shrink(2, 2), Span::new(4, 4));
+        assert_eq!(a.shrink(5, 0), Span::new(5, 5));
+
+        assert_eq!(a.offset_by(3), Span::new(5, 8));
+        assert_eq!(a.offset_by(-2), Span::new(0, 3));
+        assert_eq!(b.relative_to(8), Span::new(0, 2));
+    }
+
+    #[test]
+    fn spanned() {
+        let toks = [Spanned::new("mov", Span::new(0, 3)), Spanned::new("r0", Span::new(4, 6))];
+        assert_eq!(toks[1].len(), 2);
+        assert_eq!(span_of(&toks), Some(Span::new(0, 6)));
+        assert_eq!(span_of::<Span>(&[]), None);
+        assert_eq!(format!("{:?}", toks[0].map(str::len)), "3 @ 0..3");
+        assert_eq!(toks[1].by_ref(), Spanned::new(&"r0", Span::new(4, 6)));
+        assert_eq!(AsRef::<&str>::as_ref(&toks[1]), &"r0");
+    }
+}
@@ -0,0 +1,109 @@
+//! Statistics of token trees, for corpus analysis and sanity checks of generated code.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+
+use crate::{Spanned, Token};
+
+/// Counts over a token tree, including the tokens in groups and immediates.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TokenStats {
+    /// The number of tokens of each kind, by `Token::kind_name`.
+    pub kinds : BTreeMap<&'static str, usize>,
+
+    /// How deeply groups nest, 0 without any.
+    pub max_depth : usize,
+
+    /// The number of identifiers, including immediate ones.
+    pub idents : usize,
+    pub unique_idents : usize,
+
+    /// How many bytes of the source are in tokens other than groups, or are the delimiters of
+    /// groups; so not whitespace. Only known for spanned tokens.
+    pub covered_bytes : Option<usize>,
+}
+
+#[derive(Default)]
+struct Counter<'a> {
+    stats : TokenStats,
+    idents : BTreeSet<&'a str>,
+    covered : usize,
+}
+
+impl<'a> Counter<'a> {
+    fn count(&mut self, tok : &'a Token, depth : usize) {
+        *self.stats.kinds.entry(tok.kind_name()).or_default() += 1;
+        match tok {
+            Token::Ident(name) => {
+                self.stats.idents += 1;
+                self.idents.insert(name);
+            },
+            Token::Group(group) => {
+                self.stats.max_depth = self.stats.max_depth.max(depth + 1);
+                self.covered += if group.recovered { 1 } else { 2 };
+                for tok in &group.tokens {
+                    self.count_spanned(tok, depth + 1);
+                }
+            },
+            Token::Immediate(tok) => self.count(tok, depth),
+            _ => (),
+        }
+    }
+
+    fn count_spanned(&mut self, tok : &'a Spanned<Token>, depth : usize) {
+        if !matches!(tok.value, Token::Group(_)) {
+            self.covered += tok.span.len();
+        }
+        self.count(&tok.value, depth);
+    }
+
+    fn finish(mut self) -> TokenStats {
+        self.stats.unique_idents = self.idents.len();
+        self.stats
+    }
+}
+
+impl TokenStats {
+    /// The statistics of `toks`, whose bytes covered are unknown as they have no spans.
+    pub fn compute(toks : &[Token]) -> Self {
+        let mut counter = Counter::default();
+        for tok in toks {
+            counter.count(tok, 0);
+        }
+        counter.finish()
+    }
+
+    /// The statistics of `toks`, with the bytes they cover.
+    pub fn compute_spanned(toks : &[Spanned<Token>]) -> Self {
+        let mut counter = Counter::default();
+        for tok in toks {
+            counter.count_spanned(tok, 0);
+        }
+        let covered = counter.covered;
+        TokenStats { covered_bytes: Some(covered), ..counter.finish() }
+    }
+
+    /// The number of tokens of all kinds.
+    pub fn total(&self) -> usize {
+        self.kinds.values().sum()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{TokenizerConfig, tokenize, tokenize_spanned};
+
+    #[test]
+    fn stats() {
+        let stats = TokenStats::compute(&tokenize("mov a, [b + (a)] // a\nnop"));
+        assert_eq!(stats.kinds, BTreeMap::from([("comment", 1), ("group", 2), ("ident", 5), ("punct", 2)]));
+        assert_eq!((stats.total(), stats.max_depth, stats.idents, stats.unique_idents), (10, 2, 5, 4));
+        assert_eq!(stats.covered_bytes, None);
+
+        let config = TokenizerConfig { immediate_prefixes: vec!['$'], ..Default::default() };
+        let (toks, _) = tokenize_spanned(&config, "jmp $end  (x");
+        let stats = TokenStats::compute_spanned(&toks);
+        assert_eq!(stats.kinds, BTreeMap::from([("group", 1), ("ident", 3), ("immediate", 1)]));
+        assert_eq!((stats.idents, stats.covered_bytes), (3, Some(9)));
+    }
+}
@@ -0,0 +1,201 @@
+use alloc::sync::Arc;
+use core::ops::{Deref, Range};
+
+use crate::prelude::*;
+use crate::{FileId, Spanned, Token, TokenizerConfig, tokenize_spanned};
+
+/// Moves `tok`, and the tokens inside it if it is a group, by `delta` bytes into `file`.
+pub(crate) fn rebase(tok : &mut Spanned<Token>, delta : isize, file : FileId) {
+    tok.span = tok.span.offset_by(delta).with_file(file);
+    if let Token::Group(group) = &mut tok.value {
+        group.tokens.iter_mut().for_each(|tok| rebase(tok, delta, file));
+    }
+}
+
+/// The tokens of a text, editable like the text itself: splicing tokens in or out shifts the
+/// spans of the following tokens, so they stay those of the patched text.
+///
+/// Edits take ranges of top-level tokens, so groups are always kept or replaced whole. The
+/// text is assumed to start at offset 0; `len` is its length in bytes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TokenStream {
+    toks : Vec<Spanned<Token>>,
+    len : usize,
+}
+
+impl TokenStream {
+    /// `toks` along with the length of their text, e.g. to keep trailing whitespace.
+    pub fn new(toks : Vec<Spanned<Token>>, len : usize) -> Self {
+        debug_assert!(toks.last().is_none_or(|tok| tok.span.end <= len));
+        Self { toks, len }
+    }
+
+    pub fn parse(code : &str) -> Self {
+        Self::new(tokenize_spanned(&TokenizerConfig::default(), code).0, code.len())
+    }
+
+    pub fn tokens(&self) -> &[Spanned<Token>] {
+        &self.toks
+    }
+
+    pub fn into_tokens(self) -> Vec<Spanned<Token>> {
+        self.toks
+    }
+
+    /// Number of top-level tokens.
+    pub fn len(&self) -> usize {
+        self.toks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.toks.is_empty()
+    }
+
+    /// Length of the text, in bytes.
+    pub fn text_len(&self) -> usize {
+        self.len
+    }
+
+    /// The byte range of the text covered by the tokens in `range`: from the first token's
+    /// start to the last one's end, or where the tokens would be inserted for an empty range.
+    fn text_range(&self, range : &Range<usize>) -> Range<usize> {
+        let start = match self.toks.get(range.start) {
+            Some(tok) => tok.span.start,
+            None => self.len,
+        };
+        let end = match range.end.checked_sub(1).and_then(|i| self.toks.get(i)) {
+            Some(tok) if !range.is_empty() => tok.span.end,
+            _ => start,
+        };
+        start..end
+    }
+
+    /// Replaces the tokens in `range` with `replacement` and returns them, as a stream of
+    /// their own text starting at 0.
+    pub fn splice(&mut self, range : Range<usize>, replacement : TokenStream) -> TokenStream {
+        assert!(range.start <= range.end && range.end <= self.toks.len(), "splice range out of bounds");
+        let file = self.toks.first().map_or(FileId::default(), |tok| tok.span.file);
+        let text = self.text_range(&range);
+        let delta = replacement.len as isize - text.len() as isize;
+
+        let mut inserted = replacement.toks;
+        inserted.iter_mut().for_each(|tok| rebase(tok, text.start as isize, file));
+        let mut removed : Vec<_> = self.toks.splice(range.clone(), inserted.iter().cloned()).collect();
+        self.toks[range.start + inserted.len()..].iter_mut().for_each(|tok| rebase(tok, delta, file));
+        self.len = (self.len as isize + delta) as usize;
+
+        removed.iter_mut().for_each(|tok| rebase(tok, -(text.start as isize), file));
+        TokenStream::new(removed, text.len())
+    }
+
+    /// Inserts `toks` before the token at `index`.
+    pub fn insert(&mut self, index : usize, toks : TokenStream) {
+        self.splice(index..index, toks);
+    }
+
+    /// Removes the tokens in `range` along with their text, and returns them.
+    pub fn extract(&mut self, range : Range<usize>) -> TokenStream {
+        self.splice(range, TokenStream::default())
+    }
+}
+
+impl From<TokenStream> for Vec<Spanned<Token>> {
+    fn from(stream : TokenStream) -> Self {
+        stream.toks
+    }
+}
+
+/// A cheaply cloneable, thread-shareable view of lexed tokens, e.g. to run several passes
+/// over the same tokens on different threads. Slicing gives a view of part of the same tokens.
+#[derive(Debug, Clone)]
+pub struct SharedTokens {
+    toks : Arc<[Spanned<Token>]>,
+    range : Range<usize>,
+}
+
+const _ : () = {
+    const fn assert_send_sync<T : Send + Sync>() {}
+    assert_send_sync::<Token>();
+    assert_send_sync::<SharedTokens>();
+};
+
+impl SharedTokens {
+    pub fn new(toks : impl Into<Arc<[Spanned<Token>]>>) -> Self {
+        let toks = toks.into();
+        Self { range: 0..toks.len(), toks }
+    }
+
+    /// The view of the tokens at `range` within this one.
+    pub fn slice(&self, range : Range<usize>) -> SharedTokens {
+        assert!(range.start <= range.end && range.end <= self.range.len(), "slice out of bounds");
+        let start = self.range.start;
+        Self { toks: self.toks.clone(), range: start + range.start..start + range.end }
+    }
+}
+
+impl Deref for SharedTokens {
+    type Target = [Spanned<Token>];
+
+    fn deref(&self) -> &[Spanned<Token>] {
+        &self.toks[self.range.clone()]
+    }
+}
+
+impl From<Vec<Spanned<Token>>> for SharedTokens {
+    fn from(toks : Vec<Spanned<Token>>) -> Self {
+        Self::new(toks)
+    }
+}
+
+impl From<TokenStream> for SharedTokens {
+    fn from(stream : TokenStream) -> Self {
+        Self::new(stream.toks)
+    }
+}
+
+impl PartialEq for SharedTokens {
+    fn eq(&self, other : &Self) -> bool {
+        **self == **other
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Span, structural_eq, tokenize};
+
+    #[test]
+    fn splice() {
+        let mut stream = TokenStream::parse("mov r0, (r1 r2)\nnop ");
+        let removed = stream.splice(1..4, TokenStream::parse("[sp] "));
+        assert_eq!(removed.text_len(), 11);
+        assert_eq!(removed.tokens()[2].span, Span::new(4, 11));
+        assert!(structural_eq(removed.tokens(), &tokenize("r0, (r1 r2)")));
+
+        // "mov [sp] \nnop "
+        assert_eq!(stream.text_len(), 14);
+        assert_eq!(stream.tokens()[1].span, Span::new(4, 8));
+        assert_eq!(stream.tokens()[2].span, Span::new(10, 13));
+
+        stream.insert(2, TokenStream::parse("; "));
+        let nop = stream.extract(3..4);
+        assert_eq!(nop, TokenStream::parse("nop"));
+        stream.insert(3, removed);
+        // "mov [sp] \n;  r0, (r1 r2)"
+        assert_eq!(stream.tokens()[5].span, Span::new(17, 24));
+        assert_eq!(stream.text_len(), 24);
+        assert!(structural_eq(stream.tokens(), &tokenize("mov [sp]; r0, (r1 r2)")));
+    }
+
+    #[test]
+    fn shared() {
+        let toks = SharedTokens::from(TokenStream::parse("mov r0, 1\nnop"));
+        let line = toks.slice(0..4);
+        let handles : Vec<_> = [toks.clone(), line.slice(1..3)].into_iter()
+            .map(|toks| std::thread::spawn(move || toks.len()))
+            .collect();
+        assert_eq!(handles.into_iter().map(|h| h.join().unwrap()).collect::<Vec<_>>(), vec![5, 2]);
+        assert_eq!(line.slice(1..3)[0].value, Token::Ident("r0".into()));
+        assert_eq!(toks.slice(4..5), SharedTokens::from(TokenStream::parse("mov r0, 1\nnop")).slice(4..5));
+    }
+}
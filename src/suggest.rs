@@ -0,0 +1,64 @@
+use crate::prelude::*;
+
+/// Edit distance between `a` and `b` counted in chars, where an insertion, deletion, substitution
+/// or transposition of two adjacent chars each cost one edit (optimal string alignment distance).
+pub fn edit_distance(a : &str, b : &str) -> usize {
+    let a : Vec<char> = a.chars().collect();
+    let b : Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1).min(d[i][j - 1] + 1).min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
+}
+
+/// Picks the candidate closest to `word`, if any is close enough to plausibly be a typo of it
+/// (at most a third of its length away, and at least one edit is always tolerated).
+/// Ties are resolved in favour of the earliest candidate.
+pub fn did_you_mean<'a>(word : &str, candidates : impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let max = (word.chars().count() / 3).max(1);
+    candidates.into_iter()
+        .map(|candidate| (edit_distance(word, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn distance() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("mov", "mov"), 0);
+        assert_eq!(edit_distance("mvo", "mov"), 1);
+        assert_eq!(edit_distance("mo", "mov"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn closest() {
+        let keywords = ["mov", "nop", "jmp", "push", "pop"];
+        assert_eq!(did_you_mean("mvo", keywords), Some("mov"));
+        assert_eq!(did_you_mean("mob", keywords), Some("mov"));
+        assert_eq!(did_you_mean("psuh", keywords), Some("push"));
+        assert_eq!(did_you_mean("pus", keywords), Some("push"));
+        assert_eq!(did_you_mean("xyz", keywords), None);
+    }
+}
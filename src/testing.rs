@@ -0,0 +1,413 @@
+//! Shorthands for writing tests of tokenizers and parsers.
+//!
+//! Tokens built here have default spans wherever a span is needed, and `assert_tokens!`
+//! ignores spans when comparing. With `std`, `Corpus` checks a directory of inputs against
+//! expected dumps of their tokens, parse trees or diagnostics.
+
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+use core::fmt;
+
+use crate::prelude::*;
+use crate::{Delimiter, Float, Group, Span, Spanned, Token, TokenizerConfig, to_source, tokenize_spanned};
+#[cfg(feature = "std")]
+use crate::{Diagnostic, Parse, TokenScanner};
+
+pub fn ident(name : &str) -> Token {
+    Token::Ident(name.into())
+}
+
+pub fn punct(c : char) -> Token {
+    Token::Punct(c)
+}
+
+pub fn comment(text : &str) -> Token {
+    Token::Comment(text.into())
+}
+
+pub fn doc_comment(text : &str) -> Token {
+    Token::DocComment(text.into())
+}
+
+pub fn string(value : &str) -> Token {
+    Token::String(value.into())
+}
+
+pub fn chr(value : char) -> Token {
+    Token::Char(value)
+}
+
+pub fn num(value : i64) -> Token {
+    Token::Number(value)
+}
+
+pub fn label(name : &str) -> Token {
+    Token::Label(name.into())
+}
+
+pub fn directive(name : &str) -> Token {
+    Token::Directive(name.into())
+}
+
+pub fn immediate(tok : Token) -> Token {
+    Token::Immediate(Box::new(tok))
+}
+
+pub fn register(name : &str) -> Token {
+    Token::Register(name.into())
+}
+
+pub fn keyword(name : &str) -> Token {
+    Token::Keyword(name.into())
+}
+
+pub fn float(value : f64) -> Token {
+    Token::Float(Float(value))
+}
+
+pub fn op(op : &str) -> Token {
+    Token::Op(op.into())
+}
+
+pub fn newline() -> Token {
+    Token::Newline
+}
+
+pub fn eof() -> Token {
+    Token::Eof
+}
+
+pub fn group(delimiter : Delimiter, toks : impl IntoIterator<Item = Token>) -> Token {
+    Token::from(Group::new(delimiter, toks.into_iter().map(|tok| Spanned::new(tok, Span::default())).collect()))
+}
+
+pub fn group_paren(toks : impl IntoIterator<Item = Token>) -> Token {
+    group(Delimiter::Paren, toks)
+}
+
+pub fn group_bracket(toks : impl IntoIterator<Item = Token>) -> Token {
+    group(Delimiter::Bracket, toks)
+}
+
+pub fn group_brace(toks : impl IntoIterator<Item = Token>) -> Token {
+    group(Delimiter::Brace, toks)
+}
+
+/// One line per token, the tokens of groups indented below them.
+fn lines<T : AsRef<Token>>(toks : &[T], depth : usize, out : &mut Vec<String>) {
+    for tok in toks {
+        match tok.as_ref() {
+            Token::Group(group) => {
+                let recovered = if group.recovered { ", recovered" } else { "" };
+                out.push(format!("{:indent$}Group({:?}{recovered})", "", group.delimiter, indent = depth * 2));
+                lines(&group.tokens, depth + 1, out);
+            },
+            tok => out.push(format!("{:indent$}{tok:?}", "", indent = depth * 2)),
+        }
+    }
+}
+
+/// A line diff of `expected` and `found`, from their longest common subsequence.
+fn diff(expected : &[String], found : &[String]) -> String {
+    let mut lcs = vec![vec![0; found.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..found.len()).rev() {
+            lcs[i][j] = if expected[i] == found[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut out = String::new();
+    while i < expected.len() || j < found.len() {
+        if i < expected.len() && j < found.len() && expected[i] == found[j] {
+            out += &format!("  {}\n", expected[i]);
+            (i, j) = (i + 1, j + 1);
+        } else if j == found.len() || (i < expected.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out += &format!("- {}\n", expected[i]);
+            i += 1;
+        } else {
+            out += &format!("+ {}\n", found[j]);
+            j += 1;
+        }
+    }
+    out
+}
+
+/// Panics with a diff if the tokens differ, ignoring spans. Used by `assert_tokens!`.
+#[track_caller]
+pub fn check_tokens<T : AsRef<Token>, U : AsRef<Token>>(code : &str, found : &[T], expected : &[U]) {
+    let (mut found_lines, mut expected_lines) = (Vec::new(), Vec::new());
+    lines(found, 0, &mut found_lines);
+    lines(expected, 0, &mut expected_lines);
+    if found_lines != expected_lines {
+        panic!("tokens of {code:?} differ (- expected, + found):\n{}", diff(&expected_lines, &found_lines));
+    }
+}
+
+/// Asserts that tokenizing `code` gives the `expected` tokens, ignoring spans, and panics
+/// with a diff otherwise:
+///
+/// ```
+/// # use smpl_parser::{assert_tokens, testing::*};
+/// assert_tokens!("mov r0, [sp]", [ident("mov"), ident("r0"), punct(','), group_bracket([ident("sp")])]);
+/// ```
+#[macro_export]
+macro_rules! assert_tokens {
+    ($code:expr, $expected:expr $(,)?) => {
+        $crate::testing::check_tokens($code, &$crate::tokenize($code), &$expected)
+    };
+}
+
+/// The source re-emitted from the tokens of some code didn't tokenize to the same tokens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoundtripMismatch {
+    /// The source `to_source` re-emitted.
+    pub emitted : String,
+
+    /// The indices of the first token that differs, and of the groups it is in.
+    pub path : Vec<usize>,
+
+    /// A line diff of the tokens, as `assert_tokens!` shows.
+    pub diff : String,
+}
+
+impl fmt::Display for RoundtripMismatch {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "re-emitted source {:?} tokenizes differently at token {:?} (- original, + re-tokenized):\n{}", self.emitted, self.path, self.diff)
+    }
+}
+
+impl core::error::Error for RoundtripMismatch {}
+
+/// The path to the first token that differs between `a` and `b`, ignoring spans.
+fn first_difference(a : &[Spanned<Token>], b : &[Spanned<Token>], path : &mut Vec<usize>) -> bool {
+    for i in 0..a.len().max(b.len()) {
+        path.push(i);
+        match (a.get(i).map(|tok| &tok.value), b.get(i).map(|tok| &tok.value)) {
+            (Some(Token::Group(a)), Some(Token::Group(b))) if a.delimiter == b.delimiter && a.recovered == b.recovered => {
+                if first_difference(&a.tokens, &b.tokens, path) {
+                    return true;
+                }
+            },
+            (Some(a), Some(b)) if a.eq_ignoring_spans(b) => {},
+            _ => return true,
+        }
+        path.pop();
+    }
+    false
+}
+
+/// Tokenizes `src`, re-emits the tokens with `to_source` and checks that tokenizing that gives
+/// the same tokens, ignoring spans, e.g. from a fuzzer or over a corpus in CI.
+pub fn verify_roundtrip(config : &TokenizerConfig, src : &str) -> Result<(), RoundtripMismatch> {
+    let (original, _) = tokenize_spanned(config, src);
+    let emitted = to_source(config, &original);
+    let (retokenized, _) = tokenize_spanned(config, &emitted);
+
+    let mut path = Vec::new();
+    if !first_difference(&original, &retokenized, &mut path) {
+        return Ok(());
+    }
+    let (mut expected, mut found) = (Vec::new(), Vec::new());
+    lines(&original, 0, &mut expected);
+    lines(&retokenized, 0, &mut found);
+    Err(RoundtripMismatch { emitted, path, diff: diff(&expected, &found) })
+}
+
+/// The tokens of `code`, one per line as in the diffs of `assert_tokens!`, then its diagnostics.
+#[cfg(feature = "std")]
+pub fn dump_tokens(config : &TokenizerConfig, code : &str) -> String {
+    let (toks, diagnostics) = tokenize_spanned(config, code);
+    let mut out = Vec::new();
+    lines(&toks, 0, &mut out);
+    out.extend(diagnostics.iter().map(Diagnostic::to_string));
+    out.iter().map(|line| format!("{line}\n")).collect()
+}
+
+/// The value `T` parses all of `code` to, pretty-printed with `Debug`, or the error.
+#[cfg(feature = "std")]
+pub fn dump_parse<T : Parse + core::fmt::Debug>(config : &TokenizerConfig, code : &str) -> String {
+    let (toks, diagnostics) = tokenize_spanned(config, code);
+    let mut input = TokenScanner::new(&toks);
+    let mut out : String = diagnostics.iter().map(|diag| format!("{diag}\n")).collect();
+    match input.parse::<T>().and_then(|value| input.expect_end().map(|()| value)) {
+        Ok(value) => out += &format!("{value:#?}\n"),
+        Err(err) => out += &format!("{err}\n"),
+    }
+    out
+}
+
+/// A directory of input files, each checked against the file next to it with `.expected`
+/// appended to its name, e.g. `add.asm` against `add.asm.expected`:
+///
+/// ```no_run
+/// # use smpl_parser::{TokenizerConfig, testing::{Corpus, dump_tokens}};
+/// Corpus::new("tests/corpus").extension("asm").check(|code| dump_tokens(&TokenizerConfig::default(), code));
+/// ```
+///
+/// In update mode, set with `update` or by the environment variable `UPDATE_CORPUS=1`, the
+/// expected files are written instead.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+pub struct Corpus {
+    dir : PathBuf,
+    extension : Option<String>,
+    update : bool,
+}
+
+#[cfg(feature = "std")]
+impl Corpus {
+    pub fn new(dir : impl Into<PathBuf>) -> Self {
+        let update = std::env::var_os("UPDATE_CORPUS").is_some_and(|value| value != "0");
+        Self { dir: dir.into(), extension: None, update }
+    }
+
+    /// Only checks files with `extension`, rather than every file other than expected ones.
+    pub fn extension(mut self, extension : &str) -> Self {
+        self.extension = Some(extension.to_string());
+        self
+    }
+
+    pub fn update(mut self, update : bool) -> Self {
+        self.update = update;
+        self
+    }
+
+    /// The inputs under the directory, recursively, in order.
+    pub fn inputs(&self) -> Vec<PathBuf> {
+        fn walk(dir : &Path, corpus : &Corpus, out : &mut Vec<PathBuf>) {
+            let entries = std::fs::read_dir(dir).unwrap_or_else(|err| panic!("can't read {}: {err}", dir.display()));
+            for entry in entries {
+                let path = entry.unwrap_or_else(|err| panic!("can't read {}: {err}", dir.display())).path();
+                if path.is_dir() {
+                    walk(&path, corpus, out);
+                } else if corpus.is_input(&path) {
+                    out.push(path);
+                }
+            }
+        }
+
+        let mut out = Vec::new();
+        walk(&self.dir, self, &mut out);
+        out.sort();
+        out
+    }
+
+    fn is_input(&self, path : &Path) -> bool {
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        match &self.extension {
+            Some(expected) => extension == Some(expected.as_str()),
+            None => extension != Some("expected"),
+        }
+    }
+
+    /// Compares `render` of each input with its expected file, or writes it in update mode,
+    /// returning how many inputs there were. Panics with a diff of every mismatch.
+    #[track_caller]
+    pub fn check(&self, render : impl Fn(&str) -> String) -> usize {
+        let inputs = self.inputs();
+        let mut failures = Vec::new();
+        for input in &inputs {
+            let code = std::fs::read_to_string(input).unwrap_or_else(|err| panic!("can't read {}: {err}", input.display()));
+            let found = render(&code);
+            let mut expected_path = input.clone().into_os_string();
+            expected_path.push(".expected");
+            let expected_path = PathBuf::from(expected_path);
+
+            if self.update {
+                std::fs::write(&expected_path, &found).unwrap_or_else(|err| panic!("can't write {}: {err}", expected_path.display()));
+                continue;
+            }
+            match std::fs::read_to_string(&expected_path) {
+                Ok(expected) if expected == found => {},
+                Ok(expected) => {
+                    let (expected, found) : (Vec<_>, Vec<_>) = (expected.lines().map(str::to_string).collect(), found.lines().map(str::to_string).collect());
+                    failures.push(format!("{} differs (- expected, + found):\n{}", input.display(), diff(&expected, &found)));
+                },
+                Err(_) => failures.push(format!("{} has no {}", input.display(), expected_path.display())),
+            }
+        }
+
+        if !failures.is_empty() {
+            panic!("{} of {} corpus files failed, rerun with UPDATE_CORPUS=1 to accept:\n{}", failures.len(), inputs.len(), failures.join("\n"));
+        }
+        inputs.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn assert_tokens() {
+        assert_tokens!("f(1, 'c') \"s\" /* c */", [
+            ident("f"),
+            group_paren([num(1), punct(','), chr('c')]),
+            string("s"),
+            comment(" c "),
+        ]);
+
+        let config = TokenizerConfig { keywords: vec!["db".to_string()], floats: true, ..Default::default() };
+        check_tokens("db 1.5", &tokenize_spanned(&config, "db 1.5").0, &[keyword("db"), float(1.5)]);
+        let config = TokenizerConfig { operators: vec!["<<".to_string()], ..Default::default() };
+        check_tokens("a << 2", &tokenize_spanned(&config, "a << 2").0, &[ident("a"), op("<<"), num(2)]);
+
+        let expected = [ident("a"), group_paren([num(1), num(2)]), ident("c")];
+        let found = [ident("a"), group_paren([num(1), num(3)]), ident("b"), ident("c")];
+        let err = std::panic::catch_unwind(|| check_tokens("...", &found, &expected)).unwrap_err();
+        assert_eq!(err.downcast_ref::<String>().unwrap(), concat!(
+            "tokens of \"...\" differ (- expected, + found):\n",
+            "  Ident(\"a\")\n",
+            "  Group(Paren)\n",
+            "    Number(1)\n",
+            "-   Number(2)\n",
+            "+   Number(3)\n",
+            "+ Ident(\"b\")\n",
+            "  Ident(\"c\")\n",
+        ));
+    }
+
+    #[test]
+    fn roundtrip() {
+        let config = TokenizerConfig { labels: true, immediate_prefixes: vec!['#'], ..Default::default() };
+        verify_roundtrip(&config, "start: ld r0, [sp, #-4] // load\n  b \"s\\\"\" 'c' <<").unwrap();
+
+        verify_roundtrip(&config, "f(a // a */ b\n) /// d\nb").unwrap();
+
+        // The missing `)` that the group was recovered from is written
+        let err = verify_roundtrip(&config, "f(a").unwrap_err();
+        assert_eq!((err.emitted.as_str(), err.path.as_slice()), ("f (a)", &[1][..]));
+        assert_eq!(err.to_string(), concat!(
+            "re-emitted source \"f (a)\" tokenizes differently at token [1] (- original, + re-tokenized):\n",
+            "  Ident(\"f\")\n",
+            "- Group(Paren, recovered)\n",
+            "+ Group(Paren)\n",
+            "    Ident(\"a\")\n",
+        ));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn corpus() {
+        let dir = std::env::temp_dir().join(format!("smpl_parser_corpus_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::write(dir.join("a.asm"), "mov r0").unwrap();
+        std::fs::write(dir.join("nested/b.asm"), "'x").unwrap();
+        std::fs::write(dir.join("notes.txt"), "skipped").unwrap();
+
+        let config = TokenizerConfig::default();
+        let corpus = Corpus::new(&dir).extension("asm");
+        assert_eq!(corpus.clone().update(true).check(|code| dump_tokens(&config, code)), 2);
+        assert_eq!(std::fs::read_to_string(dir.join("a.asm.expected")).unwrap(), "Ident(\"mov\")\nIdent(\"r0\")\n");
+        assert_eq!(corpus.clone().update(false).check(|code| dump_tokens(&config, code)), 2);
+
+        std::fs::write(dir.join("a.asm"), "mov r1").unwrap();
+        let err = std::panic::catch_unwind(|| corpus.clone().update(false).check(|code| dump_tokens(&config, code))).unwrap_err();
+        let message = err.downcast_ref::<String>().unwrap();
+        assert!(message.starts_with("1 of 2 corpus files failed"));
+        assert!(message.ends_with("  Ident(\"mov\")\n- Ident(\"r0\")\n+ Ident(\"r1\")\n"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
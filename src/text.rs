@@ -0,0 +1,119 @@
+use core::{borrow::Borrow, fmt, ops::{Deref, DerefMut}};
+
+use crate::prelude::*;
+
+/// The text of an identifier, comment or string literal.
+///
+/// A boxed `str`, which is smaller than a `String` and doesn't keep spare capacity. It derefs
+/// to `str` and compares with strings, so it can mostly be used like one.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TokenStr(Box<str>);
+
+impl TokenStr {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_string(self) -> String {
+        self.0.into()
+    }
+}
+
+impl Deref for TokenStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl DerefMut for TokenStr {
+    fn deref_mut(&mut self) -> &mut str {
+        &mut self.0
+    }
+}
+
+impl AsRef<str> for TokenStr {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for TokenStr {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for TokenStr {
+    fn from(s : &str) -> Self {
+        Self(s.into())
+    }
+}
+
+impl From<String> for TokenStr {
+    fn from(s : String) -> Self {
+        Self(s.into_boxed_str())
+    }
+}
+
+impl From<TokenStr> for String {
+    fn from(s : TokenStr) -> Self {
+        s.into_string()
+    }
+}
+
+impl FromIterator<char> for TokenStr {
+    fn from_iter<I : IntoIterator<Item = char>>(iter : I) -> Self {
+        String::from_iter(iter).into()
+    }
+}
+
+impl PartialEq<str> for TokenStr {
+    fn eq(&self, other : &str) -> bool {
+        *self.0 == *other
+    }
+}
+
+impl PartialEq<&str> for TokenStr {
+    fn eq(&self, other : &&str) -> bool {
+        *self.0 == **other
+    }
+}
+
+impl PartialEq<String> for TokenStr {
+    fn eq(&self, other : &String) -> bool {
+        *self.0 == **other
+    }
+}
+
+impl PartialEq<TokenStr> for str {
+    fn eq(&self, other : &TokenStr) -> bool {
+        *self == *other.0
+    }
+}
+
+impl PartialEq<TokenStr> for &str {
+    fn eq(&self, other : &TokenStr) -> bool {
+        **self == *other.0
+    }
+}
+
+impl PartialEq<TokenStr> for String {
+    fn eq(&self, other : &TokenStr) -> bool {
+        **self == *other.0
+    }
+}
+
+/// Like a `str`, so that tokens debug-print as before.
+impl fmt::Debug for TokenStr {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl fmt::Display for TokenStr {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&*self.0, f)
+    }
+}
@@ -1,163 +1,1329 @@
-use crate::{Scanner, ScannerAction};
+use alloc::collections::VecDeque;
+use core::fmt;
 
-#[derive(Debug, Clone, PartialEq)]
+use crate::prelude::*;
+use crate::{chars, instrument};
+use crate::normalize::combining_class;
+use crate::{
+    ControlCharPolicy, Diagnostic, DiagnosticKind, Edit, FileId, LeadingZeroPolicy, Normalization, NumberError, Recovery, Scanner, Severity,
+    Span, Spanned, TokenStr, TokenizerConfig, is_nfc, sort_diagnostics, split_lexeme, to_nfc,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Delimiter {
+    /// `( ... )`
+    Paren,
+
+    /// `[ ... ]`
+    Bracket,
+
+    /// `{ ... }`
+    Brace,
+}
+
+impl Delimiter {
+    pub const ALL : [Delimiter; 3] = [Delimiter::Paren, Delimiter::Bracket, Delimiter::Brace];
+
+    pub fn iter() -> impl Iterator<Item = Delimiter> {
+        Self::ALL.into_iter()
+    }
+
+    /// The delimiter opened by `c`, if any.
+    pub fn from_open(c : char) -> Option<Self> {
+        match c {
+            '(' => Some(Delimiter::Paren),
+            '[' => Some(Delimiter::Bracket),
+            '{' => Some(Delimiter::Brace),
+            _ => None,
+        }
+    }
+
+    /// The delimiter closed by `c`, if any.
+    pub fn from_close(c : char) -> Option<Self> {
+        match c {
+            ')' => Some(Delimiter::Paren),
+            ']' => Some(Delimiter::Bracket),
+            '}' => Some(Delimiter::Brace),
+            _ => None,
+        }
+    }
+
+    pub fn open(&self) -> char {
+        match self {
+            Delimiter::Paren => '(',
+            Delimiter::Bracket => '[',
+            Delimiter::Brace => '{',
+        }
+    }
+
+    pub fn close(&self) -> char {
+        match self {
+            Delimiter::Paren => ')',
+            Delimiter::Bracket => ']',
+            Delimiter::Brace => '}',
+        }
+    }
+
+    pub fn is_open(c : char) -> bool {
+        Self::from_open(c).is_some()
+    }
+
+    pub fn is_close(c : char) -> bool {
+        Self::from_close(c).is_some()
+    }
+}
+
+/// Both characters, e.g. `()`.
+impl fmt::Display for Delimiter {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.open(), self.close())
+    }
+}
+
+/// Tokens enclosed in a pair of delimiters.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Group {
+    pub delimiter : Delimiter,
+    pub tokens : Vec<Spanned<Token>>,
+
+    /// The closing delimiter was missing from the source and has been synthesized.
+    pub recovered : bool,
+}
+
+impl Group {
+    pub fn new(delimiter : Delimiter, tokens : Vec<Spanned<Token>>) -> Self {
+        Self { delimiter, tokens, recovered: false }
+    }
+}
+
+/// The value of a `Token::Float`, compared and hashed by its bits so that tokens stay `Eq` and
+/// `Hash`: a NaN equals itself, and `0.0` differs from `-0.0`.
+#[derive(Debug, Clone, Copy)]
+pub struct Float(pub f64);
+
+impl PartialEq for Float {
+    fn eq(&self, other : &Self) -> bool {
+        self.0.to_bits() == other.0.to_bits()
+    }
+}
+
+impl Eq for Float {}
+
+impl core::hash::Hash for Float {
+    fn hash<H : core::hash::Hasher>(&self, h : &mut H) {
+        self.0.to_bits().hash(h)
+    }
+}
+
+impl fmt::Display for Float {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Tokens are `Eq` and `Hash`, comparing the spans inside groups too; use `eq_ignoring_spans`
+/// or `structural_eq` to compare them by content only.
+///
+/// Text payloads are `TokenStr`s and groups are boxed, to keep tokens small.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Token {
-    Ident(String),
+    Ident(TokenStr),
     Punct(char),
-    Comment(String),
+    Comment(TokenStr),
+    Group(Box<Group>),
+
+    /// A line break, only emitted with `TokenizerConfig::newlines`.
+    Newline,
 
-    String(String),
+    String(TokenStr),
     Char(char),
     Number(i64),
+
+    /// An identifier directly followed by `:` at the start of a line, e.g. `loop:`, only
+    /// emitted with `TokenizerConfig::labels`. The span includes the colon.
+    Label(TokenStr),
+
+    /// An identifier prefixed with `TokenizerConfig::directive_sigil`, e.g. `.org`, holding
+    /// the name without the sigil.
+    Directive(TokenStr),
+
+    /// A number or identifier prefixed with one of `TokenizerConfig::immediate_prefixes`,
+    /// e.g. `#4` or `$label`, holding the token without the prefix.
+    Immediate(Box<Token>),
+
+    /// An identifier that `TokenizerConfig::registers` declares a register name.
+    Register(TokenStr),
+
+    /// The end of input, with an empty span at the end of the code, only emitted last with
+    /// `TokenizerConfig::eof`.
+    Eof,
+
+    /// A line comment starting with one of `TokenizerConfig::doc_comments`, holding the text
+    /// after the prefix. Unlike other comments, doc comments are not trivia.
+    DocComment(TokenStr),
+
+    /// A decimal number with a fraction or an exponent, e.g. `1.5` or `-2e3`, only emitted
+    /// with `TokenizerConfig::floats`.
+    Float(Float),
+
+    /// An identifier that `TokenizerConfig::keywords` lists.
+    Keyword(TokenStr),
+
+    /// One of `TokenizerConfig::operators`, e.g. `==` or `->`.
+    Op(TokenStr),
+}
+
+impl Token {
+    /// Whether the token has no bearing on the meaning of the code, i.e. is a comment.
+    /// Newlines are not trivia: they are only emitted when asked for, because lines matter.
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, Token::Comment(_))
+    }
+
+    /// The name of the kind of token, e.g. "ident" or "doc-comment".
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            Token::Ident(_) => "ident",
+            Token::Punct(_) => "punct",
+            Token::Comment(_) => "comment",
+            Token::Group(_) => "group",
+            Token::Newline => "newline",
+            Token::String(_) => "string",
+            Token::Char(_) => "char",
+            Token::Number(_) => "number",
+            Token::Label(_) => "label",
+            Token::Directive(_) => "directive",
+            Token::Immediate(_) => "immediate",
+            Token::Register(_) => "register",
+            Token::Eof => "eof",
+            Token::DocComment(_) => "doc-comment",
+            Token::Float(_) => "float",
+            Token::Keyword(_) => "keyword",
+            Token::Op(_) => "op",
+        }
+    }
+
+    /// Describes the token for messages, e.g. "identifier `x`" or "a comment".
+    pub(crate) fn describe(&self) -> String {
+        match self {
+            Token::Ident(name) => format!("identifier `{name}`"),
+            Token::Punct(c) => format!("`{c}`"),
+            Token::Comment(_) => "a comment".to_string(),
+            Token::Group(group) => format!("a group `{}...{}`", group.delimiter.open(), group.delimiter.close()),
+            Token::Newline => "a line break".to_string(),
+            Token::String(value) => format!("string {value:?}"),
+            Token::Char(value) => format!("char {value:?}"),
+            Token::Number(value) => format!("number {value}"),
+            Token::Label(name) => format!("label `{name}:`"),
+            Token::Directive(name) => format!("directive `{name}`"),
+            Token::Immediate(_) => "an immediate".to_string(),
+            Token::Register(name) => format!("register `{name}`"),
+            Token::Eof => "the end of input".to_string(),
+            Token::DocComment(_) => "a doc comment".to_string(),
+            Token::Float(value) => format!("number {value}"),
+            Token::Keyword(name) => format!("keyword `{name}`"),
+            Token::Op(op) => format!("`{op}`"),
+        }
+    }
+
+    /// Whether both tokens are equal except, inside groups, for their spans.
+    pub fn eq_ignoring_spans(&self, other : &Token) -> bool {
+        match (self, other) {
+            (Token::Group(a), Token::Group(b)) => {
+                a.delimiter == b.delimiter && a.recovered == b.recovered && structural_eq(&a.tokens, &b.tokens)
+            },
+            (a, b) => a == b,
+        }
+    }
+}
+
+/// Whether both token trees are equal, ignoring spans.
+pub fn structural_eq<T : AsRef<Token>, U : AsRef<Token>>(a : &[T], b : &[U]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.as_ref().eq_ignoring_spans(b.as_ref()))
+}
+
+impl From<Group> for Token {
+    fn from(group : Group) -> Self {
+        Token::Group(Box::new(group))
+    }
+}
+
+impl AsRef<Token> for Token {
+    fn as_ref(&self) -> &Token {
+        self
+    }
+}
+
+/// The error of converting a `Token` into the payload of another kind of token.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenConversionError {
+    /// What the conversion accepts, e.g. "a number".
+    pub expected : &'static str,
+
+    /// The token that was given, to get it back.
+    pub found : Token,
+}
+
+impl fmt::Display for TokenConversionError {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected {}, found {}", self.expected, self.found.describe())
+    }
+}
+
+impl core::error::Error for TokenConversionError {}
+
+/// The errors `try_tokenize` found, e.g. `DiagnosticKind::UnterminatedString`, along with the
+/// tokens lexing recovered despite them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexError {
+    /// In source order, at least one.
+    pub errors : Vec<Diagnostic>,
+    pub tokens : Vec<Spanned<Token>>,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.errors[0])?;
+        match self.errors.len() {
+            1 => Ok(()),
+            n => write!(f, "\n(and {} more)", n - 1),
+        }
+    }
+}
+
+impl core::error::Error for LexError {}
+
+/// The name of an identifier or the value of a string literal.
+impl TryFrom<Token> for String {
+    type Error = TokenConversionError;
+
+    fn try_from(tok : Token) -> Result<Self, Self::Error> {
+        match tok {
+            Token::Ident(s) | Token::String(s) => Ok(s.into_string()),
+            found => Err(TokenConversionError { expected: "an identifier or a string", found }),
+        }
+    }
+}
+
+impl TryFrom<Token> for i64 {
+    type Error = TokenConversionError;
+
+    fn try_from(tok : Token) -> Result<Self, Self::Error> {
+        match tok {
+            Token::Number(n) => Ok(n),
+            found => Err(TokenConversionError { expected: "a number", found }),
+        }
+    }
+}
+
+/// The value of a char literal or a punctuation character.
+impl TryFrom<Token> for char {
+    type Error = TokenConversionError;
+
+    fn try_from(tok : Token) -> Result<Self, Self::Error> {
+        match tok {
+            Token::Char(c) | Token::Punct(c) => Ok(c),
+            found => Err(TokenConversionError { expected: "a char or a punctuation", found }),
+        }
+    }
+}
+
+/// The delimiter and the tokens of a group, without their spans.
+impl TryFrom<Token> for (Delimiter, Vec<Token>) {
+    type Error = TokenConversionError;
+
+    fn try_from(tok : Token) -> Result<Self, Self::Error> {
+        match tok {
+            Token::Group(group) => Ok((group.delimiter, group.tokens.into_iter().map(Spanned::into_inner).collect())),
+            found => Err(TokenConversionError { expected: "a group", found }),
+        }
+    }
+}
+
+impl Scanner<Token> {
+    /// Discards tokens up to and including the next `Token::Newline`, to resume parsing
+    /// on the next line after an error.
+    pub fn skip_line(&mut self) {
+        while self.pop().is_some_and(|tok| tok != Token::Newline) {}
+    }
+}
+
+struct Lexer<'a> {
+    config : &'a TokenizerConfig,
+    file : FileId,
+    code : &'a str,
+    scanner : Scanner<char>,
+    diagnostics : Vec<Diagnostic>,
+
+    /// How many tokens were lexed, and whether lexing stopped at `Limits::max_tokens`.
+    tokens : usize,
+    stopped : bool,
+
+    /// Where the groups come from, if not the global allocator.
+    pool : Option<&'a mut dyn TokenPool>,
+}
+
+impl<'a> Lexer<'a> {
+    fn new(config : &'a TokenizerConfig, file : FileId, code : &'a str) -> Self {
+        Self::with_pool(config, file, code, None)
+    }
+
+    fn with_pool(config : &'a TokenizerConfig, file : FileId, code : &'a str, mut pool : Option<&'a mut dyn TokenPool>) -> Self {
+        let (mut chars, scratch) = match &mut pool {
+            Some(pool) => (pool.chars(), pool.scratch()),
+            None => (VecDeque::new(), Vec::new()),
+        };
+        chars.clear();
+        let too_large = config.limits.max_input_bytes.filter(|limit| code.len() > *limit);
+        if too_large.is_none() {
+            chars.extend(code.chars());
+        }
+
+        let mut scanner = Scanner::with_width(chars, |c| c.len_utf8()).with_scratch(scratch);
+        if let Some(fuel) = config.limits.fuel {
+            scanner = scanner.with_fuel(fuel);
+        }
+        let mut lexer = Self {
+            config,
+            file,
+            code,
+            scanner,
+            diagnostics: Vec::new(),
+            tokens: 0,
+            stopped: false,
+            pool,
+        };
+        if let Some(limit) = too_large {
+            lexer.emit(Diagnostic::error(lexer.span(limit, code.len()), DiagnosticKind::InputTooLarge { limit, found: code.len() }));
+        }
+        lexer
+    }
+
+    fn offset(&self) -> usize {
+        self.scanner.offset()
+    }
+
+    /// Span from `start` up to the current position.
+    fn span_from(&self, start : usize) -> Span {
+        self.span(start, self.offset())
+    }
+
+    fn span(&self, start : usize, end : usize) -> Span {
+        Span::new(start, end).with_file(self.file)
+    }
+
+    fn emit(&mut self, diagnostic : Diagnostic) {
+        self.diagnostics.push(diagnostic)
+    }
+
+    /// Reports an unknown escape `\c` whose backslash starts at `start`, suggesting to drop the backslash.
+    fn unknown_escape(&mut self, start : usize, c : char) {
+        self.emit(Diagnostic::error(self.span_from(start), DiagnosticKind::UnknownEscape(c))
+            .with_suggestion(Edit::delete(self.span(start, start + 1))));
+    }
+
+    /// Reports a literal opened by the quote at `open` that was `abandoned` before its closing quote,
+    /// suggesting to replace `abandoned` (usually empty) with the closing quote.
+    fn unterminated(&mut self, open : usize, kind : DiagnosticKind, abandoned : Span) {
+        let quote = if kind == DiagnosticKind::UnterminatedChar { "'" } else { "\"" };
+        self.emit(Diagnostic::error(self.span(open, open + 1), kind)
+            .with_note(Diagnostic::note(self.span(abandoned.start, abandoned.start), DiagnosticKind::LiteralAbandoned))
+            .with_suggestion(Edit::new(abandoned, quote)));
+    }
+
+    /// Reports the group opened at `open` as missing its closing delimiter at `close`.
+    fn unclosed(&mut self, open : usize, delimiter : Delimiter, close : usize) {
+        self.emit(Diagnostic::error(self.span(open, open + 1), DiagnosticKind::UnclosedDelimiter(delimiter.open()))
+            .with_suggestion(Edit::new(self.span(close, close), delimiter.close().to_string())));
+    }
+}
+
+/// Whether `c` is a bidirectional embedding, override or isolate, which can reorder how the
+/// code around it is displayed.
+fn is_bidi_control(c : char) -> bool {
+    matches!(c, '\u{202A}'..='\u{202E}' | '\u{2066}'..='\u{2069}')
+}
+
+/// Returns the ASCII character `c` is commonly mistaken for, if any.
+fn confusable(c : char) -> Option<char> {
+    Some(match c {
+        'а' => 'a', 'е' => 'e', 'о' => 'o', 'р' => 'p', 'с' => 'c', 'у' => 'y', 'х' => 'x',
+        'і' => 'i', 'ј' => 'j', 'ѕ' => 's',
+        'А' => 'A', 'В' => 'B', 'Е' => 'E', 'К' => 'K', 'М' => 'M', 'Н' => 'H', 'О' => 'O',
+        'Р' => 'P', 'С' => 'C', 'Т' => 'T', 'Х' => 'X',
+        'α' => 'a', 'ο' => 'o', 'ν' => 'v',
+        'Α' => 'A', 'Β' => 'B', 'Ε' => 'E', 'Κ' => 'K', 'Ο' => 'O', 'Τ' => 'T', 'Χ' => 'X',
+        _ => return None,
+    })
+}
+
+fn skip_whitespace(lexer : &mut Lexer) {
+    let newlines = lexer.config.newlines;
+    let start = lexer.offset();
+    lexer.scanner.take_while(|c| c.is_whitespace() && !(newlines && *c == '\n'));
+    check_ascii(lexer, start);
+}
+
+/// Reports the non-ASCII chars lexed since `start`, with `TokenizerConfig::ascii_only`.
+fn check_ascii(lexer : &mut Lexer, start : usize) {
+    if !lexer.config.ascii_only {
+        return;
+    }
+    let code = lexer.code;
+    for (i, c) in code[start..lexer.offset()].char_indices().filter(|(_, c)| !c.is_ascii()) {
+        lexer.emit(Diagnostic::error(lexer.span(start + i, start + i + c.len_utf8()), DiagnosticKind::NonAsciiChar(c)));
+    }
+}
+
+fn match_newline(lexer : &mut Lexer) -> Option<Token> {
+    lexer.config.newlines.then(|| lexer.scanner.take(|c| *c == '\n'))?.map(|_| Token::Newline)
+}
+
+/// Takes the rest of the line if it starts with one of `prefixes`, without the prefix.
+fn take_prefixed_line(lexer : &mut Lexer, prefixes : &[String]) -> Option<String> {
+    let rest = &lexer.code[lexer.offset()..];
+    let prefix = prefixes.iter().find(|prefix| !prefix.is_empty() && rest.starts_with(prefix.as_str()))?;
+    for _ in prefix.chars() {
+        lexer.scanner.pop();
+    }
+    Some(chars::take_line(&mut lexer.scanner))
+}
+
+fn match_doc_comment(lexer : &mut Lexer) -> Option<Token> {
+    let config = lexer.config;
+    Some(Token::DocComment(take_prefixed_line(lexer, &config.doc_comments)?.into()))
+}
+
+fn match_line_comment(lexer : &mut Lexer) -> Option<Token> {
+    let config = lexer.config;
+    Some(Token::Comment(take_prefixed_line(lexer, &config.line_comments)?.into()))
+}
+
+fn match_comment(lexer : &mut Lexer) -> Option<Token> {
+    let open = lexer.offset();
+    lexer.scanner.take(|c| *c == '/')?;
+
+    if lexer.scanner.take(|c| *c == '/').is_some() {
+        Some(Token::Comment(chars::take_line(&mut lexer.scanner).into()))
+    } else if lexer.scanner.take(|c| *c == '*').is_some() {
+        let mut comment = String::new();
+        loop {
+            match lexer.scanner.pop() {
+                Some('*') if lexer.scanner.take(|c| *c == '/').is_some() => break,
+                Some(c) => comment.push(c),
+                None => {
+                    // Running out of fuel is reported by `get_tok`
+                    if !lexer.scanner.is_out_of_fuel() {
+                        let end = lexer.span_from(lexer.offset());
+                        lexer.emit(Diagnostic::error(lexer.span(open, open + 2), DiagnosticKind::UnterminatedComment)
+                            .with_suggestion(Edit::new(end, "*/")));
+                    }
+                    break;
+                },
+            }
+        }
+        Some(Token::Comment(comment.into()))
+    } else {
+        Some(Token::Punct('/'))
+    }
+}
+
+/// Whether only whitespace precedes `offset` on its line.
+fn at_line_start(lexer : &Lexer, offset : usize) -> bool {
+    let before = lexer.code[..offset].trim_end_matches(|c : char| c.is_whitespace() && c != '\n');
+    before.is_empty() || before.ends_with('\n')
+}
+
+/// Takes an identifier, warning about confusable chars in it.
+fn take_identifier(lexer : &mut Lexer) -> Option<String> {
+    let start = lexer.offset();
+    let normalize = lexer.config.normalization != Normalization::None;
+    let ident : String = lexer.scanner.test(|c| c.is_alphabetic() || *c == '_')
+        .then(|| lexer.scanner.take_while(|c| c.is_alphanumeric() || *c == '_' || (normalize && combining_class(*c) != 0)).iter().collect())?;
+
+    let ident = match normalize && !is_nfc(&ident) {
+        true => {
+            let normalized = to_nfc(&ident);
+            if lexer.config.normalization == Normalization::NfcWarn {
+                let span = lexer.span_from(start);
+                lexer.emit(Diagnostic::warning(span, DiagnosticKind::UnnormalizedIdentifier(ident.clone()))
+                    .with_suggestion(Edit::new(span, normalized.clone())));
+            }
+            normalized
+        },
+        false => ident,
+    };
+
+    if let Some(c) = ident.chars().find(|c| confusable(*c).is_some()) {
+        let lookalike = confusable(c).unwrap();
+        let span = lexer.span_from(start);
+        let ascii : String = ident.chars().map(|c| confusable(c).unwrap_or(c)).collect();
+        let kind = DiagnosticKind::ConfusableIdentifier { ident: ident.clone(), found: c, lookalike };
+        lexer.emit(Diagnostic::warning(span, kind).with_suggestion(Edit::new(span, ascii)));
+    }
+
+    // The confusable warning, with its fix, is enough
+    #[cfg(feature = "unicode-security")]
+    if let Some((first, second)) = crate::script::mixed_scripts(&ident).filter(|_| !ident.chars().any(|c| confusable(c).is_some())) {
+        let kind = DiagnosticKind::MixedScriptIdentifier { ident: ident.clone(), first: first.to_string(), second: second.to_string() };
+        lexer.emit(Diagnostic::warning(lexer.span_from(start), kind));
+    }
+
+    let mut ident = ident;
+    if let Some(limit) = lexer.config.limits.max_ident_len.filter(|limit| ident.len() > *limit) {
+        lexer.emit(Diagnostic::error(lexer.span_from(start), DiagnosticKind::IdentifierTooLong(limit)));
+        ident.truncate(floor_char_boundary(&ident, limit));
+    }
+
+    Some(ident)
+}
+
+/// The largest char boundary of `s` that is at most `index`.
+fn floor_char_boundary(s : &str, index : usize) -> usize {
+    (0..=index).rev().find(|i| s.is_char_boundary(*i)).unwrap_or(0)
+}
+
+fn match_identifier(lexer : &mut Lexer) -> Option<Token> {
+    let start = lexer.offset();
+    let ident = take_identifier(lexer)?;
+    if lexer.config.labels && at_line_start(lexer, start) && lexer.scanner.take(|c| *c == ':').is_some() {
+        return Some(Token::Label(ident.into()))
+    }
+
+    if lexer.config.keywords.contains(&ident) {
+        return Some(Token::Keyword(ident.into()))
+    }
+
+    if lexer.config.is_register(&ident) {
+        return Some(Token::Register(ident.into()))
+    }
+
+    Some(Token::Ident(ident.into()))
+}
+
+fn match_directive(lexer : &mut Lexer) -> Option<Token> {
+    let sigil = lexer.config.directive_sigil?;
+    let mut chars = lexer.code[lexer.offset()..].chars();
+    if chars.next() != Some(sigil) || !chars.next().is_some_and(|c| c.is_alphabetic() || c == '_') {
+        return None
+    }
+
+    lexer.scanner.pop();
+    Some(Token::Directive(take_identifier(lexer)?.into()))
+}
+
+fn match_immediate(lexer : &mut Lexer) -> Option<Token> {
+    let mut chars = lexer.code[lexer.offset()..].chars();
+    if !chars.next().is_some_and(|c| lexer.config.immediate_prefixes.contains(&c)) {
+        return None
+    }
+    match (chars.next(), chars.next()) {
+        (Some(c), _) if c.is_ascii_digit() || c.is_alphabetic() || c == '_' => (),
+        (Some('-'), Some(c)) if c.is_ascii_digit() => (),
+        _ => return None,
+    }
+
+    lexer.scanner.pop();
+    let tok = match_number(lexer).or_else(|| take_identifier(lexer).map(|ident| Token::Ident(ident.into())))?;
+    Some(Token::Immediate(Box::new(tok)))
+}
+
+/// The states of the number DFA, which lexes numbers in a single pass. Each state past the
+/// start accepts a `-` or a number with the digits of its `NUMBER_RADIX`, except for the
+/// prefixes like `0x`, of radix 0, which need a digit. The remaining states are the prefixes
+/// and digits of hexadecimal, octal and binary numbers.
+const NUMBER_START : u8 = 0;
+const NUMBER_MINUS : u8 = 1;
+const NUMBER_NEGATIVE : u8 = 2;
+const NUMBER_ZERO : u8 = 3;
+const NUMBER_DECIMAL : u8 = 4;
+const NUMBER_DEAD : u8 = u8::MAX;
+const NUMBER_RADIX : [u32; 11] = [0, 10, 10, 10, 10, 0, 16, 0, 8, 0, 2];
+
+/// Char classes: `-`, `0`, `1`, `2..=7`, `8 9`, other hex digits, `b`, `x`, `o`, the rest and
+/// `_` with `TokenizerConfig::digit_separators`.
+const NUMBER_CLASSES : usize = 11;
+const NUMBER_SEPARATOR : usize = 10;
+
+const fn number_class(c : char) -> usize {
+    match c {
+        '-' => 0,
+        '0' => 1,
+        '1' => 2,
+        '2'..='7' => 3,
+        '8' | '9' => 4,
+        'b' => 6,
+        'a'..='f' | 'A'..='F' => 5,
+        'x' => 7,
+        'o' => 8,
+        _ => 9,
+    }
+}
+
+/// The transitions of the number DFA, built at compile time.
+const NUMBER_TRANSITIONS : [[u8; NUMBER_CLASSES]; 11] = {
+    let mut table = [[NUMBER_DEAD; NUMBER_CLASSES]; 11];
+    let mut class = 1;
+    while class <= 4 {
+        table[NUMBER_START as usize][class] = if class == 1 { NUMBER_ZERO } else { NUMBER_DECIMAL };
+        table[NUMBER_MINUS as usize][class] = NUMBER_NEGATIVE;
+        table[NUMBER_NEGATIVE as usize][class] = NUMBER_NEGATIVE;
+        table[NUMBER_ZERO as usize][class] = NUMBER_DECIMAL;
+        table[NUMBER_DECIMAL as usize][class] = NUMBER_DECIMAL;
+        class += 1;
+    }
+    table[NUMBER_START as usize][0] = NUMBER_MINUS;
+    table[NUMBER_ZERO as usize][NUMBER_SEPARATOR] = NUMBER_DECIMAL;
+    table[NUMBER_DECIMAL as usize][NUMBER_SEPARATOR] = NUMBER_DECIMAL;
+    table[NUMBER_NEGATIVE as usize][NUMBER_SEPARATOR] = NUMBER_NEGATIVE;
+
+    // Prefixes are followed by digits of their radix
+    let prefixes = [(7, 5, 16), (8, 7, 8), (6, 9, 2)];
+    let mut i = 0;
+    while i < prefixes.len() {
+        let (class, prefix, radix) = prefixes[i];
+        table[NUMBER_ZERO as usize][class] = prefix;
+        // A prefix followed by separators still needs a digit
+        table[prefix as usize][NUMBER_SEPARATOR] = prefix;
+        table[prefix as usize + 1][NUMBER_SEPARATOR] = prefix + 1;
+        let mut digit = 1;
+        while digit < NUMBER_SEPARATOR {
+            let max = match digit {
+                1 | 2 => 2,
+                3 => 8,
+                4 => 10,
+                5 | 6 => 16,
+                _ => u32::MAX,
+            };
+            if max <= radix {
+                table[prefix as usize][digit] = prefix + 1;
+                table[prefix as usize + 1][digit] = prefix + 1;
+            }
+            digit += 1;
+        }
+        i += 1;
+    }
+    table
+};
+
+/// Whether the char before `offset` ends an operand, which a `-` right after subtracts from with
+/// `TokenizerConfig::binary_minus`.
+fn follows_operand(lexer : &Lexer, offset : usize) -> bool {
+    lexer.code[..offset].chars().next_back().is_some_and(|c| c.is_alphanumeric() || c == '_' || Delimiter::is_close(c))
+}
+
+fn match_number(lexer : &mut Lexer) -> Option<Token> {
+    let start = lexer.offset();
+    if lexer.config.binary_minus && lexer.scanner.test(|c| *c == '-') && follows_operand(lexer, start) {
+        return None
+    }
+    if lexer.scanner.test(|c| c.is_ascii_digit() || *c == '-') { // TODO: is_numeric?
+        let number_type = lexer.config.number_type;
+        let separators = lexer.config.digit_separators;
+        let (mut tok, mut overflow) = match lex_number(&mut lexer.scanner, separators) {
+            Ok(lexed) => lexed,
+            Err(_) if lexer.scanner.is_out_of_fuel() => (Token::Number(0), false),
+            Err(radix) => {
+                lexer.emit(Diagnostic::error(lexer.span_from(start), DiagnosticKind::MissingDigits { radix }));
+                return Some(Token::Number(0));
+            },
+        };
+
+        let code = lexer.code;
+        let decimal = code[start..lexer.offset()].bytes().all(|b| b.is_ascii_digit() || b == b'-' || b == b'_');
+        if lexer.config.floats && matches!(tok, Token::Number(_)) && decimal {
+            if let Some(value) = match_float_rest(lexer, start) {
+                return Some(Token::Float(Float(value)));
+            }
+        }
+
+        let span = lexer.span_from(start);
+        let digits = code[span.start..span.end].trim_start_matches('-');
+        if digits.len() > 1 && digits.starts_with('0') && digits.chars().all(|c| c.is_ascii_digit()) {
+            let zeros = digits.len() - digits.trim_start_matches('0').len().max(1);
+            let zeros = lexer.span(span.end - digits.len(), span.end - digits.len() + zeros);
+            let diag = |severity| Diagnostic::new(severity, span, DiagnosticKind::LeadingZeros).with_suggestion(Edit::delete(zeros));
+            match lexer.config.leading_zeros {
+                LeadingZeroPolicy::Decimal => lexer.emit(diag(Severity::Warning)),
+                LeadingZeroPolicy::Error => lexer.emit(diag(Severity::Error)),
+                LeadingZeroPolicy::Octal => match digits.char_indices().find(|(_, c)| !c.is_digit(8)) {
+                    Some((i, digit)) => {
+                        let at = span.end - digits.len() + i;
+                        lexer.emit(Diagnostic::error(lexer.span(at, at + 1), DiagnosticKind::InvalidDigit { digit, radix: 8 }));
+                    },
+                    // Smaller than the decimal value, so it may fit when that doesn't
+                    None => if let Ok(value) = i64::from_str_radix(&code[span.start..span.end], 8) {
+                        tok = Token::Number(value);
+                        overflow = false;
+                    },
+                },
+            }
+        }
+
+        // Values that don't fit the `i64` of the token are reported even if they fit `number_type`
+        let mut out_of_range = overflow.then_some("i64");
+        if let (Some(number_type), Token::Number(_)) = (number_type, &tok) {
+            let lexeme = code[span.start..span.end].replace('_', "");
+            let (negative, mut radix, digits) = split_lexeme(&lexeme);
+            if radix == 10 && digits.len() > 1 && digits.starts_with('0') && lexer.config.leading_zeros == LeadingZeroPolicy::Octal {
+                radix = 8;
+            }
+            // Invalid digits were reported above
+            if number_type.check(digits, radix, negative) == Err(NumberError::OutOfRange) {
+                out_of_range = Some(number_type.name());
+            }
+        }
+        if let Some(name) = out_of_range {
+            lexer.emit(Diagnostic::error(span, DiagnosticKind::NumberOutOfRange(name.into())));
+        }
+
+        Some(tok)
+    } else { None }
+}
+
+
+/// Runs the number DFA, one transition per char, accumulating the value as it goes. Returns the
+/// token and whether its value overflowed, in which case it is saturated; or the radix of the
+/// digits missing after a prefix like `0x`.
+fn lex_number(scanner : &mut Scanner<char>, separators : bool) -> Result<(Token, bool), u32> {
+    let mut state = NUMBER_START;
+    let mut value : i64 = 0;
+    let mut overflow = false;
+    loop {
+        let next = scanner.peek().map_or(NUMBER_DEAD, |c| {
+            let class = if separators && *c == '_' { NUMBER_SEPARATOR } else { number_class(*c) };
+            NUMBER_TRANSITIONS[state as usize][class]
+        });
+        if next == NUMBER_DEAD {
+            break;
+        }
+        let c = scanner.pop().unwrap();
+        let radix = NUMBER_RADIX[next as usize];
+        if let Some(digit) = c.to_digit(radix.max(2)).filter(|_| radix != 0) {
+            // Negative numbers are accumulated downwards, so that `i64::MIN` fits
+            let digit = if next == NUMBER_NEGATIVE { -i64::from(digit) } else { digit.into() };
+            let checked = value.checked_mul(radix.into()).and_then(|value| value.checked_add(digit));
+            overflow |= checked.is_none();
+            value = match checked {
+                Some(value) => value,
+                None if digit < 0 => i64::MIN,
+                None => i64::MAX,
+            };
+        }
+        state = next;
+    }
+
+    match state {
+        NUMBER_MINUS => Ok((Token::Punct('-'), false)),
+        // The digits of a prefix are in the state after it
+        _ if NUMBER_RADIX[state as usize] == 0 => Err(NUMBER_RADIX[state as usize + 1]),
+        _ => Ok((Token::Number(value), overflow)),
+    }
+}
+
+/// Lexes the fraction and exponent of a float whose integer part starts at `start`, returning
+/// its value if it has either.
+fn match_float_rest(lexer : &mut Lexer, start : usize) -> Option<f64> {
+    let separators = lexer.config.digit_separators;
+    let digits = |lexer : &mut Lexer| lexer.scanner.take_while(|c| c.is_ascii_digit() || (separators && *c == '_'));
+
+    let rest = &lexer.code[lexer.offset()..];
+    let fraction = rest.strip_prefix('.').is_some_and(|rest| rest.starts_with(|c : char| c.is_ascii_digit()));
+    if fraction {
+        lexer.scanner.pop();
+        digits(lexer);
+    }
+    let rest = &lexer.code[lexer.offset()..];
+    let exponent = rest.strip_prefix(['e', 'E'])
+        .map(|rest| rest.strip_prefix(['+', '-']).unwrap_or(rest))
+        .is_some_and(|rest| rest.starts_with(|c : char| c.is_ascii_digit()));
+    if exponent {
+        lexer.scanner.pop();
+        lexer.scanner.take(|c| *c == '+' || *c == '-');
+        digits(lexer);
+    }
+
+    // A lexeme cut short by running out of fuel may not parse
+    let lexeme = lexer.code[start..lexer.offset()].replace('_', "");
+    (fraction || exponent).then(|| lexeme.parse().ok())?
+}
+
+/// Takes the next char of a literal, unless the literal ends there because of
+/// a line break or the end of input.
+fn take_literal_char(lexer : &mut Lexer) -> Option<char> {
+    let start = lexer.offset();
+    let c = lexer.scanner.take(|c| !(*c == '\n' || *c == '\r'))?;
+    if c.is_control() {
+        let span = lexer.span_from(start);
+        match lexer.config.control_chars {
+            ControlCharPolicy::Allow => (),
+            ControlCharPolicy::Warn => lexer.emit(Diagnostic::warning(span, DiagnosticKind::ControlCharInLiteral(c))),
+            ControlCharPolicy::Error => lexer.emit(Diagnostic::error(span, DiagnosticKind::ControlCharInLiteral(c))),
+        }
+    }
+    Some(c)
+}
+
+/// The char the escape `\c`, whose backslash is at `start`, stands for in a literal quoted by
+/// `quote`, reporting unknown escapes.
+fn unescape(lexer : &mut Lexer, start : usize, c : char, quote : char) -> char {
+    if c == quote {
+        return c
+    }
+    if lexer.config.escapes {
+        match c {
+            'n' => return '\n',
+            'r' => return '\r',
+            't' => return '\t',
+            '0' => return '\0',
+            '\\' | '\'' | '"' => return c,
+            'u' => return unicode_escape(lexer, start),
+            _ => (),
+        }
+    }
+    lexer.unknown_escape(start, c);
+    c
+}
+
+/// Lexes the `{...}` of a `\u{...}` escape whose backslash is at `start`.
+fn unicode_escape(lexer : &mut Lexer, start : usize) -> char {
+    let c = lexer.scanner.take(|c| *c == '{').and_then(|_| {
+        let digits : String = lexer.scanner.take_while(char::is_ascii_hexdigit).into_iter().collect();
+        lexer.scanner.take(|c| *c == '}')?;
+        (1..=6).contains(&digits.len()).then(|| char::from_u32(u32::from_str_radix(&digits, 16).ok()?))?
+    });
+    c.unwrap_or_else(|| {
+        lexer.emit(Diagnostic::error(lexer.span_from(start), DiagnosticKind::InvalidUnicodeEscape));
+        char::REPLACEMENT_CHARACTER
+    })
+}
+
+/// Whether a string literal follows after whitespace, to concatenate with `TokenizerConfig::concat_strings`.
+fn next_is_string(lexer : &Lexer) -> bool {
+    let newlines = lexer.config.newlines;
+    lexer.code[lexer.offset()..].trim_start_matches(|c : char| c.is_whitespace() && !(newlines && c == '\n')).starts_with('"')
+}
+
+fn match_string(lexer : &mut Lexer) -> Option<Token> {
+    let open = lexer.offset();
+    if lexer.scanner.take(|c| *c == '"').is_some() {
+        let mut s = String::new();
+        let mut too_long = None;
+        loop {
+            let start = lexer.offset();
+            let Some(c) = take_literal_char(lexer) else {
+                lexer.unterminated(open, DiagnosticKind::UnterminatedString, lexer.span(start, start));
+                break
+            };
+
+            let c = if c == '"' {
+                if !(lexer.config.concat_strings && next_is_string(lexer)) {
+                    break;
+                }
+                skip_whitespace(lexer);
+                lexer.scanner.pop();
+                continue;
+            } else if c == '\\' {
+                let Some(c) = take_literal_char(lexer) else {
+                    lexer.unterminated(open, DiagnosticKind::UnterminatedString, lexer.span_from(start));
+                    break
+                };
+                unescape(lexer, start, c, '"')
+            } else {
+                c
+            };
+
+            match lexer.config.limits.max_string_len {
+                Some(limit) if s.len() + c.len_utf8() > limit => too_long = Some(limit),
+                _ => s.push(c),
+            }
+        }
+
+        if let Some(limit) = too_long {
+            lexer.emit(Diagnostic::error(lexer.span_from(open), DiagnosticKind::StringTooLong(limit)));
+        }
+        Some(Token::String(s.into()))
+    } else { None }
+}
+
+fn match_char(lexer : &mut Lexer) -> Option<Token> {
+    let open = lexer.offset();
+    if lexer.scanner.take(|c| *c == '\'').is_some() {
+        let start = lexer.offset();
+        let Some(mut c) = take_literal_char(lexer) else {
+            lexer.unterminated(open, DiagnosticKind::UnterminatedChar, lexer.span(start, start));
+            return Some(Token::Char(char::REPLACEMENT_CHARACTER))
+        };
+
+        if c == '\'' && !lexer.scanner.test(|c| *c == '\'') {
+            lexer.emit(Diagnostic::error(lexer.span_from(open), DiagnosticKind::EmptyChar));
+            return Some(Token::Char(char::REPLACEMENT_CHARACTER))
+        } else if c == '\\' {
+            let Some(escaped) = take_literal_char(lexer) else {
+                lexer.unterminated(open, DiagnosticKind::UnterminatedChar, lexer.span_from(start));
+                return Some(Token::Char(char::REPLACEMENT_CHARACTER))
+            };
+            c = unescape(lexer, start, escaped, '\'');
+        }
+
+        if lexer.scanner.take(|c| *c == '\'').is_none() {
+            lexer.unterminated(open, DiagnosticKind::UnterminatedChar, lexer.span_from(lexer.offset()));
+        }
+
+        Some(Token::Char(c))
+    } else { None }
+}
+
+fn match_group(lexer : &mut Lexer) -> Option<Token> {
+    let open = lexer.offset();
+    let delimiter = lexer.scanner.transform(|c| Delimiter::from_open(*c))?;
+    let mut group = match &mut lexer.pool {
+        Some(pool) => pool.group(),
+        None => Box::new(Group::new(delimiter, Vec::new())),
+    };
+
+    let recovered = loop {
+        if let Some(tok) = get_tok(lexer) {
+            group.tokens.push(tok);
+            continue;
+        }
+        if lexer.stopped {
+            break true
+        }
+
+        let close = lexer.offset();
+        match lexer.scanner.peek().and_then(|c| Delimiter::from_close(*c)) {
+            Some(found) if found == delimiter => {
+                lexer.scanner.pop();
+                break false
+            },
+            Some(_) if lexer.config.lenient => {
+                lexer.unclosed(open, delimiter, close); // The delimiter is left for an enclosing group
+                break true
+            },
+            Some(found) => {
+                lexer.scanner.pop();
+                lexer.emit(Diagnostic::error(lexer.span_from(close), DiagnosticKind::MismatchedDelimiter {
+                    expected: delimiter.close(),
+                    found: found.close(),
+                })
+                    .with_note(Diagnostic::note(lexer.span(open, open + 1), DiagnosticKind::UnclosedDelimiter(delimiter.open())))
+                    .with_suggestion(Edit::new(lexer.span_from(close), delimiter.close().to_string())));
+            },
+            None => {
+                lexer.unclosed(open, delimiter, close);
+                break true
+            },
+        }
+    };
+
+    group.delimiter = delimiter;
+    group.recovered = recovered;
+    Some(Token::Group(group))
+}
+
+fn match_operator(lexer : &mut Lexer) -> Option<Token> {
+    let rest = &lexer.code[lexer.offset()..];
+    let op = lexer.config.operators.iter().filter(|op| !op.is_empty() && rest.starts_with(op.as_str())).max_by_key(|op| op.len())?;
+    for _ in op.chars() {
+        lexer.scanner.pop();
+    }
+    Some(Token::Op(op.as_str().into()))
+}
+
+fn match_punct(scanner : &mut Scanner<char>) -> Option<Token> {
+    Some(Token::Punct(scanner.pop()?))
 }
 
-fn skip_whitespace(scanner : &mut Scanner<char>) {
-    scanner.take_while(|c| c.is_whitespace());
+/// Returns the next token, or `None` at the end of input or of the current group.
+fn get_tok(lexer : &mut Lexer) -> Option<Spanned<Token>> {
+    loop {
+        let tok = get_any_tok(lexer)?;
+        if !(lexer.config.skip_comments && tok.value.is_trivia()) {
+            return Some(tok)
+        }
+    }
 }
 
-fn match_comment(scanner : &mut Scanner<char>) -> Option<Token> {
-    scanner.take(|c| *c == '/')?;
+/// Like `get_tok`, including comments `TokenizerConfig::skip_comments` drops.
+fn get_any_tok(lexer : &mut Lexer) -> Option<Spanned<Token>> {
+    skip_whitespace(lexer);
+    if lexer.stopped || lexer.scanner.is_done() || lexer.scanner.test(|c| Delimiter::is_close(*c)) {
+        return None
+    }
 
-    if scanner.take(|c| *c == '/').is_some() {
-        let comment = scanner.take_while(|c| !(*c == '\n' || *c == '\r'));
-        Some(Token::Comment(comment.into_iter().collect()))
-    } else if scanner.take(|c| *c == '*').is_some() {
-        scanner.scan(|chars| match chars {
-            ['*', '/'] => ScannerAction::Return(Token::Comment("".to_string())),
-            [comment @ .., '*', '/'] => ScannerAction::Return(Token::Comment(comment.iter().collect())),
-            _ => ScannerAction::Require,
-        }).unwrap() // TODO: Handle
-    } else {
-        Some(Token::Punct('/'))
+    let start = lexer.offset();
+    if lexer.scanner.is_out_of_fuel() {
+        lexer.emit(Diagnostic::error(lexer.span(start, start), DiagnosticKind::LimitExceeded("fuel".into())));
+        lexer.stopped = true;
+        return None
+    }
+    if let Some(limit) = lexer.config.limits.max_tokens.filter(|limit| lexer.tokens >= *limit) {
+        lexer.emit(Diagnostic::error(lexer.span(start, start), DiagnosticKind::TooManyTokens(limit)));
+        lexer.stopped = true;
+        return None
+    }
+    lexer.tokens += 1;
+
+    let tok = match_newline(lexer)
+    .or_else(|| match_directive(lexer))
+    .or_else(|| match_immediate(lexer))
+    .or_else(|| match_identifier(lexer))
+    .or_else(|| match_doc_comment(lexer))
+    .or_else(|| match_line_comment(lexer))
+    .or_else(|| match_comment(lexer))
+    .or_else(|| match_string(lexer))
+    .or_else(|| match_operator(lexer))
+    .or_else(|| match_number(lexer))
+    .or_else(|| match_char(lexer))
+    .or_else(|| match_group(lexer))
+    .or_else(|| match_punct(&mut lexer.scanner))?;
+    // The tokens of groups were checked as they were lexed
+    if !matches!(tok, Token::String(_) | Token::Char(_) | Token::Group(_)) {
+        check_ascii(lexer, start);
     }
+    Some(Spanned::new(tok, lexer.span_from(start)))
 }
 
-fn match_identifier(scanner : &mut Scanner<char>) -> Option<Token> {
-    scanner.test(|c| c.is_alphabetic() || *c == '_')
-        .then(|| scanner.take_while(|c| c.is_alphanumeric() || *c == '_').iter().collect())
-        .map(Token::Ident)
+/// Implements `Recovery::SkipLine`: if an error was reported since the current line started,
+/// drops the line's tokens and all of its diagnostics but that error, and skips the rest of the line,
+/// through its `Token::Newline` if any. Returns where the next line starts then.
+fn skip_bad_line(lexer : &mut Lexer, toks : &mut Vec<Spanned<Token>>, line : (usize, usize)) -> Option<(usize, usize)> {
+    let (line_toks, line_diags) = line;
+    let error = lexer.diagnostics[line_diags..].iter().position(Diagnostic::is_error)?;
+
+    let error = lexer.diagnostics.remove(line_diags + error);
+    lexer.diagnostics.truncate(line_diags);
+    lexer.diagnostics.push(error);
+    toks.truncate(line_toks);
+    lexer.scanner.take_while(|c| *c != '\n');
+    let start = lexer.offset();
+    if let Some(newline) = match_newline(lexer) {
+        toks.push(Spanned::new(newline, lexer.span_from(start)));
+    }
+    Some((toks.len(), lexer.diagnostics.len()))
 }
 
-fn match_number(scanner : &mut Scanner<char>) -> Option<Token> {
-    if scanner.test(|c| c.is_ascii_digit() || *c == '-') { // TODO: is_numeric?
-        scanner.scan(|chars| match chars {
-            ['-'] => ScannerAction::Request(Token::Punct('-')),
-            ['-', ..] if chars.iter().skip(1).all(|c| c.is_ascii_digit())
-                => ScannerAction::Request(Token::Number(
-                    -chars.iter().skip(1).collect::<String>().parse::<i64>().unwrap()
-                )),
-            ['0'] => ScannerAction::Request(Token::Number(0)),
+fn tokenize_lexer(lexer : &mut Lexer, toks : &mut Vec<Spanned<Token>>) {
+    let mut line = (toks.len(), 0); // Number of tokens and diagnostics before the current line
 
-            ['0', 'x'] => ScannerAction::Require,
-            ['0', 'x', ..] if chars.iter().skip(2).all(|c| c.is_ascii_hexdigit())
-                => ScannerAction::Request(Token::Number(
-                    i64::from_str_radix(&chars.iter().skip(2).collect::<String>(), 16).unwrap()
-                )),
+    loop {
+        if lexer.config.recovery == Recovery::SkipLine {
+            let start = lexer.offset();
+            skip_whitespace(lexer);
+            if toks.last().is_some_and(|tok| tok.value == Token::Newline) || lexer.code[start..lexer.offset()].contains('\n') {
+                line = (toks.len(), lexer.diagnostics.len());
+            }
+        }
 
-            ['0', 'o'] => ScannerAction::Require,
-            ['0', 'o', ..] if chars.iter().skip(2).all(|c| c.is_digit(8))
-                => ScannerAction::Request(Token::Number(
-                    i64::from_str_radix(&chars.iter().skip(2).collect::<String>(), 8).unwrap()
-                )),
+        if let Some(tok) = get_tok(lexer) {
+            toks.push(tok);
+        } else if lexer.stopped {
+            break
+        } else if let Some(c) = lexer.scanner.peek().copied() {
+            let start = lexer.offset();
+            lexer.scanner.pop();
+            let span = lexer.span_from(start);
+            lexer.emit(Diagnostic::error(span, DiagnosticKind::UnexpectedCloseDelimiter(c))
+                .with_suggestion(Edit::delete(span)));
+        } else {
+            break
+        }
 
-            ['0', 'b'] => ScannerAction::Require,
-            ['0', 'b', ..] if chars.iter().skip(2).all(|c| c.is_digit(2))
-                => ScannerAction::Request(Token::Number(
-                    i64::from_str_radix(&chars.iter().skip(2).collect::<String>(), 2).unwrap()
-                )),
+        if lexer.config.recovery == Recovery::SkipLine {
+            if let Some(next) = skip_bad_line(lexer, toks, line) {
+                line = next;
+            }
+        }
+    }
 
-            _ if chars.iter().all(|c| c.is_ascii_digit())
-                => ScannerAction::Request(Token::Number(chars.iter().collect::<String>().parse().unwrap())),
+    if lexer.config.eof {
+        toks.push(Spanned::new(Token::Eof, lexer.span(lexer.code.len(), lexer.code.len())));
+    }
 
-            _ => ScannerAction::None,
-        }).unwrap() // TODO: Handle
-    } else { None }
+    // Wherever they are, even in literals and comments, and on lines `Recovery::SkipLine` drops
+    let too_large = lexer.config.limits.max_input_bytes.is_some_and(|limit| lexer.code.len() > limit);
+    if !lexer.code.is_ascii() && !too_large {
+        let severity = if lexer.config.lenient { Severity::Warning } else { Severity::Error };
+        for (i, c) in lexer.code.char_indices().filter(|(_, c)| is_bidi_control(*c)) {
+            lexer.emit(Diagnostic::new(severity, lexer.span(i, i + c.len_utf8()), DiagnosticKind::BidiControl(c)));
+        }
+    }
 }
 
+pub fn tokenize(code : &str) -> Vec<Token> {
+    tokenize_with_diagnostics(code).0
+}
 
-fn match_string(scanner : &mut Scanner<char>) -> Option<Token> {
-    if scanner.take(|c| *c == '"').is_some() {
-        let mut s = String::new();
-        while let Some(c) = scanner.pop() {
-            if c == '"' {
-                break;
-            } else if c == '\\' {
-                let Some(c) = scanner.pop() else { panic!() }; // TODO: Handle
-                match c {
-                    '"' => s.push(c),
-                    _ => panic!("Unknown control sequence: '{c}'"),
-                }
-            } else {
-                s.push(c)
-            }
-        }
+/// Like `tokenize`, but also returns the errors and warnings emitted while lexing, in source order.
+pub fn tokenize_with_diagnostics(code : &str) -> (Vec<Token>, Vec<Diagnostic>) {
+    tokenize_with(&TokenizerConfig::default(), code)
+}
 
-        Some(Token::String(s))
-    } else { None }
+/// Like `tokenize_with_diagnostics`, with the behaviour adjusted by `config`.
+pub fn tokenize_with(config : &TokenizerConfig, code : &str) -> (Vec<Token>, Vec<Diagnostic>) {
+    let (toks, diags) = tokenize_spanned(config, code);
+    (toks.into_iter().map(Spanned::into_inner).collect(), diags)
 }
 
-fn match_char(scanner : &mut Scanner<char>) -> Option<Token> {
-    if scanner.take(|c| *c == '\'').is_some() {
-        let mut c = scanner.pop().unwrap(); // TODO: Handle
-        if c == '\\' {
-            c = scanner.pop().unwrap();
-            match c {
-                '\'' => (),
-                _ => panic!("Unknown control sequence: '{c}'"),
-            }
-        }
+/// Like `tokenize_with`, keeping the span of every token.
+pub fn tokenize_spanned(config : &TokenizerConfig, code : &str) -> (Vec<Spanned<Token>>, Vec<Diagnostic>) {
+    tokenize_file(config, FileId::default(), code)
+}
+
+/// Like `tokenize_spanned`, but fails if lexing reported errors. Warnings are dropped.
+pub fn try_tokenize(config : &TokenizerConfig, code : &str) -> Result<Vec<Spanned<Token>>, LexError> {
+    let (tokens, diagnostics) = tokenize_spanned(config, code);
+    let errors : Vec<_> = diagnostics.into_iter().filter(Diagnostic::is_error).collect();
+    match errors.is_empty() {
+        true => Ok(tokens),
+        false => Err(LexError { errors, tokens }),
+    }
+}
 
-        if scanner.take(|c| *c == '\'').is_none() {
-            panic!("Unclosed character")
+/// The tokens of a line, with the span of the whole line.
+pub type Line = Spanned<Vec<Spanned<Token>>>;
+
+/// Like `tokenize_spanned`, but splits the tokens into lines, each with the span of the whole line,
+/// for line-oriented languages. `Token::Newline`s are not included and lines without tokens are
+/// skipped; a group spanning several lines belongs to the line it starts on. Combine with
+/// `Recovery::SkipLine` to recover from errors line by line.
+pub fn tokenize_lines(config : &TokenizerConfig, code : &str) -> (Vec<Line>, Vec<Diagnostic>) {
+    let (toks, diags) = tokenize_spanned(&TokenizerConfig { newlines: true, ..config.clone() }, code);
+
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for line in toks.split_inclusive(|tok| tok.value == Token::Newline) {
+        let (end, toks) = match line.split_last() {
+            Some((newline, toks)) if newline.value == Token::Newline => {
+                (newline.span.start - usize::from(code[..newline.span.start].ends_with('\r')), toks)
+            },
+            _ => (code.len(), line),
+        };
+        if !toks.is_empty() {
+            lines.push(Spanned::new(toks.to_vec(), Span::new(start, end)));
         }
-        
-        Some(Token::Char(c))
-    } else { None }
+        start = line.last().map_or(end, |tok| tok.span.end);
+    }
+    (lines, diags)
 }
 
-fn match_punct(scanner : &mut Scanner<char>) -> Option<Token> {
-    Some(Token::Punct(scanner.pop()?))
+/// Like `tokenize_spanned`, with spans pointing into `file`.
+pub(crate) fn tokenize_file(config : &TokenizerConfig, file : FileId, code : &str) -> (Vec<Spanned<Token>>, Vec<Diagnostic>) {
+    let measure = instrument::start("tokenize");
+    let mut lexer = Lexer::new(config, file, code);
+    let mut toks = Vec::new();
+    tokenize_lexer(&mut lexer, &mut toks);
+    sort_diagnostics(&mut lexer.diagnostics);
+    measure.finish(toks.len(), instrument::count_errors(&lexer.diagnostics));
+    (toks, lexer.diagnostics)
 }
 
-fn get_tok(scanner : &mut Scanner<char>) -> Option<Token> {
-    skip_whitespace(scanner);
+/// Where `tokenize_into` takes the memory it lexes in and the groups it lexes from, e.g. an
+/// arena preallocated by an embedded program, so that lexing doesn't need the global allocator
+/// but for the text of tokens and diagnostics.
+pub trait TokenPool {
+    /// An empty queue for the chars of the input.
+    fn chars(&mut self) -> VecDeque<char>;
 
-    match_identifier(scanner)
-    .or_else(|| match_comment(scanner))
-    .or_else(|| match_string(scanner))
-    .or_else(|| match_number(scanner))
-    .or_else(|| match_char(scanner))
-    .or_else(|| match_punct(scanner))
+    /// An empty buffer for the sequences the scanner collects.
+    fn scratch(&mut self) -> Vec<char>;
+
+    /// A group without tokens, which `tokenize_into` sets the delimiter of and fills.
+    fn group(&mut self) -> Box<Group>;
+
+    /// Takes back the buffers of `chars` and `scratch` once lexing is done.
+    fn give_back(&mut self, chars : VecDeque<char>, scratch : Vec<char>);
 }
 
-fn tokenize_scanner(scanner : &mut Scanner<char>) -> Vec<Token> {
-    let mut toks = Vec::new();
-    while let Some(tok) = get_tok(scanner) {
-        toks.push(tok);
+/// Memory kept between calls to `tokenize_into`, so that lexing many files, or the same one
+/// repeatedly, doesn't allocate its working buffers again once they are large enough, nor the
+/// groups given back with `recycle`.
+#[derive(Debug, Default)]
+pub struct TokenBuffers {
+    chars : VecDeque<char>,
+    scratch : Vec<char>,
+    #[allow(clippy::vec_box)] // The boxes are what `Token::Group` reuses
+    groups : Vec<Box<Group>>,
+}
+
+impl TokenBuffers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Buffers that can lex sources of up to `chars` chars without allocating.
+    pub fn with_capacity(chars : usize) -> Self {
+        Self { chars: VecDeque::with_capacity(chars), scratch: Vec::with_capacity(chars), groups: Vec::new() }
+    }
+
+    /// Keeps the groups of `toks`, and those nested in them, for `tokenize_into` to lex groups
+    /// in, leaving `toks` empty.
+    pub fn recycle(&mut self, toks : &mut Vec<Spanned<Token>>) {
+        for tok in toks.drain(..) {
+            if let Token::Group(mut group) = tok.value {
+                self.recycle(&mut group.tokens);
+                self.groups.push(group);
+            }
+        }
     }
-    toks
 }
 
-pub fn tokenize(code : &str) -> Vec<Token> {
-    let mut scanner = Scanner::new(code.chars().collect());
-    tokenize_scanner(&mut scanner)
+impl TokenPool for TokenBuffers {
+    fn chars(&mut self) -> VecDeque<char> {
+        core::mem::take(&mut self.chars)
+    }
+
+    fn scratch(&mut self) -> Vec<char> {
+        core::mem::take(&mut self.scratch)
+    }
+
+    fn group(&mut self) -> Box<Group> {
+        self.groups.pop().unwrap_or_else(|| Box::new(Group::new(Delimiter::Paren, Vec::new())))
+    }
+
+    fn give_back(&mut self, chars : VecDeque<char>, scratch : Vec<char>) {
+        (self.chars, self.scratch) = (chars, scratch);
+    }
+}
+
+/// Like `tokenize_spanned`, but appends the tokens to `toks` and lexes in the memory of `pool`,
+/// e.g. `TokenBuffers`, taking the groups from it too.
+pub fn tokenize_into(
+    config : &TokenizerConfig,
+    code : &str,
+    pool : &mut dyn TokenPool,
+    toks : &mut Vec<Spanned<Token>>,
+) -> Vec<Diagnostic> {
+    let measure = instrument::start("tokenize_into");
+    let len = toks.len();
+    let mut lexer = Lexer::with_pool(config, FileId::default(), code, Some(pool));
+    tokenize_lexer(&mut lexer, toks);
+    sort_diagnostics(&mut lexer.diagnostics);
+    measure.finish(toks.len() - len, instrument::count_errors(&lexer.diagnostics));
+    let (chars, scratch) = lexer.scanner.into_buffers();
+    if let Some(pool) = lexer.pool {
+        pool.give_back(chars, scratch);
+    }
+    lexer.diagnostics
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::{Limits, NumberType, Severity};
 
     #[test]
     fn ident() {
         let code = "nop _nop no_p nop_ no1p nop1";
         let toks = tokenize(code);
         assert_eq!(toks, vec![
-            Token::Ident("nop".to_string()),
-            Token::Ident("_nop".to_string()),
-            Token::Ident("no_p".to_string()),
-            Token::Ident("nop_".to_string()),
-            Token::Ident("no1p".to_string()),
-            Token::Ident("nop1".to_string()),
+            Token::Ident("nop".into()),
+            Token::Ident("_nop".into()),
+            Token::Ident("no_p".into()),
+            Token::Ident("nop_".into()),
+            Token::Ident("no1p".into()),
+            Token::Ident("nop1".into()),
         ]);
     }
 
@@ -166,8 +1332,8 @@ mod test {
         let code = r#""A string" "\"A quoted string\"""#;
         let toks = tokenize(code);
         assert_eq!(toks, vec![
-            Token::String("A string".to_string()),
-            Token::String("\"A quoted string\"".to_string()),
+            Token::String("A string".into()),
+            Token::String("\"A quoted string\"".into()),
         ]);
     }
 
@@ -203,8 +1369,623 @@ mod test {
         let code = "// 0 1 asd\n/* 0 1 *\n * / asd */";
         let toks = tokenize(code);
         assert_eq!(toks, vec![
-            Token::Comment(" 0 1 asd".to_string()),
-            Token::Comment(" 0 1 *\n * / asd ".to_string()),
+            Token::Comment(" 0 1 asd".into()),
+            Token::Comment(" 0 1 *\n * / asd ".into()),
+        ]);
+    }
+
+    #[test]
+    fn warnings() {
+        let code = "010 -007 0 nоp";
+        let (toks, diags) = tokenize_with_diagnostics(code);
+        assert_eq!(toks, vec![
+            Token::Number(10),
+            Token::Number(-7),
+            Token::Number(0),
+            Token::Ident("nоp".into()),
+        ]);
+        assert_eq!(diags.iter().map(|d| (d.severity, d.span)).collect::<Vec<_>>(), vec![
+            (Severity::Warning, Span::new(0, 3)),
+            (Severity::Warning, Span::new(4, 8)),
+            (Severity::Warning, Span::new(11, 15)),
+        ]);
+    }
+
+    #[test]
+    fn suggestions() {
+        let code = r#"007 nоp "a\qb" '\q'"#;
+        let (toks, diags) = tokenize_with_diagnostics(code);
+        assert_eq!(toks, vec![
+            Token::Number(7),
+            Token::Ident("nоp".into()),
+            Token::String("aqb".into()),
+            Token::Char('q'),
+        ]);
+        assert_eq!(diags.iter().map(|d| (d.severity, &d.suggestions[..])).collect::<Vec<_>>(), vec![
+            (Severity::Warning, &[Edit::delete(Span::new(0, 2))][..]),
+            (Severity::Warning, &[Edit::new(Span::new(4, 8), "nop")][..]),
+            (Severity::Error, &[Edit::delete(Span::new(11, 12))][..]),
+            (Severity::Error, &[Edit::delete(Span::new(17, 18))][..]),
+        ]);
+    }
+
+    #[test]
+    fn unterminated() {
+        let code = "\"abc\n'x\n\"\\";
+        let (toks, diags) = tokenize_with_diagnostics(code);
+        assert_eq!(toks, vec![
+            Token::String("abc".into()),
+            Token::Char('x'),
+            Token::String("".into()),
+        ]);
+        assert_eq!(diags, vec![
+            Diagnostic::error(Span::new(0, 1), DiagnosticKind::UnterminatedString)
+                .with_note(Diagnostic::note(Span::empty(4), DiagnosticKind::LiteralAbandoned))
+                .with_suggestion(Edit::insert(4, "\"")),
+            Diagnostic::error(Span::new(5, 6), DiagnosticKind::UnterminatedChar)
+                .with_note(Diagnostic::note(Span::empty(7), DiagnosticKind::LiteralAbandoned))
+                .with_suggestion(Edit::insert(7, "'")),
+            Diagnostic::error(Span::new(8, 9), DiagnosticKind::UnterminatedString)
+                .with_note(Diagnostic::note(Span::empty(9), DiagnosticKind::LiteralAbandoned))
+                .with_suggestion(Edit::new(Span::new(9, 10), "\"")),
+        ]);
+
+        let (_, diags) = tokenize_with_diagnostics(r#""\q"#);
+        assert_eq!(diags.iter().map(|d| d.code().unwrap()).collect::<Vec<_>>(), vec![
+            "unterminated-string",
+            "unknown-escape",
+        ]);
+
+        let (toks, diags) = tokenize_with_diagnostics("''");
+        assert_eq!(toks, vec![Token::Char(char::REPLACEMENT_CHARACTER)]);
+        assert_eq!(diags, vec![Diagnostic::error(Span::new(0, 2), DiagnosticKind::EmptyChar)]);
+    }
+
+    fn at(tok : Token, start : usize, end : usize) -> Spanned<Token> {
+        Spanned::new(tok, Span::new(start, end))
+    }
+
+    fn id(name : &str) -> Token {
+        Token::Ident(name.into())
+    }
+
+    #[test]
+    fn group() {
+        let code = "(a [b] {})";
+        let (toks, _) = tokenize_spanned(&TokenizerConfig::default(), code);
+        assert_eq!(toks, vec![
+            at(Token::from(Group::new(Delimiter::Paren, vec![
+                at(id("a"), 1, 2),
+                at(Token::from(Group::new(Delimiter::Bracket, vec![at(id("b"), 4, 5)])), 3, 6),
+                at(Token::from(Group::new(Delimiter::Brace, vec![])), 7, 9),
+            ])), 0, 10),
+        ]);
+    }
+
+    #[test]
+    fn unbalanced_group() {
+        let code = "[(a] b) c)";
+        let (toks, diags) = tokenize_with_diagnostics(code);
+        assert_eq!(toks, vec![
+            Token::from(Group {
+                delimiter: Delimiter::Bracket,
+                tokens: vec![
+                    at(Token::from(Group::new(Delimiter::Paren, vec![at(id("a"), 2, 3), at(id("b"), 5, 6)])), 1, 7),
+                    at(id("c"), 8, 9),
+                ],
+                recovered: true,
+            }),
+        ]);
+        assert_eq!(diags.iter().map(|d| (d.code().unwrap(), d.span)).collect::<Vec<_>>(), vec![
+            ("unclosed-delimiter", Span::new(0, 1)),
+            ("mismatched-delimiter", Span::new(3, 4)),
+            ("mismatched-delimiter", Span::new(9, 10)),
+        ]);
+        assert_eq!(diags[1].suggestions, vec![Edit::new(Span::new(3, 4), ")")]);
+
+        let (toks, diags) = tokenize_with(&TokenizerConfig { lenient: true, ..Default::default() }, code);
+        assert_eq!(toks, vec![
+            Token::from(Group::new(Delimiter::Bracket, vec![
+                at(Token::from(Group { delimiter: Delimiter::Paren, tokens: vec![at(id("a"), 2, 3)], recovered: true }), 1, 3),
+            ])),
+            id("b"),
+            id("c"),
+        ]);
+        assert_eq!(diags.iter().map(|d| (d.code().unwrap(), d.span)).collect::<Vec<_>>(), vec![
+            ("unclosed-delimiter", Span::new(1, 2)),
+            ("unexpected-close-delimiter", Span::new(6, 7)),
+            ("unexpected-close-delimiter", Span::new(9, 10)),
+        ]);
+        assert_eq!(diags[0].suggestions, vec![Edit::insert(3, ")")]);
+
+        let (toks, _) = tokenize_with(&TokenizerConfig { lenient: true, ..Default::default() }, "{a");
+        assert_eq!(toks, vec![
+            Token::from(Group { delimiter: Delimiter::Brace, tokens: vec![at(id("a"), 1, 2)], recovered: true }),
+        ]);
+    }
+
+    #[test]
+    fn skip_line() {
+        let config = TokenizerConfig { newlines: true, recovery: Recovery::SkipLine, ..Default::default() };
+        let code = "mov 1\n  nop 'x '' \"\\q\nnop)\r\njmp 2";
+        let (toks, diags) = tokenize_with(&config, code);
+        assert_eq!(toks, vec![
+            Token::Ident("mov".into()),
+            Token::Number(1),
+            Token::Newline,
+            Token::Newline,
+            Token::Newline,
+            Token::Ident("jmp".into()),
+            Token::Number(2),
+        ]);
+        assert_eq!(diags.iter().map(|d| (d.code().unwrap(), d.span)).collect::<Vec<_>>(), vec![
+            ("unterminated-char", Span::new(12, 13)),
+            ("unexpected-close-delimiter", Span::new(25, 26)),
+        ]);
+
+        let (toks, diags) = tokenize_with(&config, "'ab\nnop\nnop");
+        assert_eq!(toks, [Token::Newline, id("nop"), Token::Newline, id("nop")]);
+        assert_eq!(diags.iter().map(|d| d.code().unwrap()).collect::<Vec<_>>(), ["unterminated-char"]);
+
+        let mut scanner = Scanner::new(tokenize_with(&config, code).0.into());
+        scanner.skip_line();
+        assert_eq!(scanner.peek(), Some(&Token::Newline));
+        scanner.skip_line();
+        scanner.skip_line();
+        assert_eq!(scanner.pop(), Some(Token::Ident("jmp".into())));
+    }
+
+    #[test]
+    fn eq_ignoring_spans() {
+        let a = tokenize("f (x [y])");
+        let b = tokenize("f(x[y])");
+        assert_ne!(a, b);
+        assert!(structural_eq(&a, &b) && a[1].eq_ignoring_spans(&b[1]));
+        assert!(!structural_eq(&a, &tokenize("f (x (y))")) && !structural_eq(&a, &b[..1]));
+
+        let counts : std::collections::HashMap<Token, usize> = [(id("f"), 1)].into();
+        assert_eq!(counts[&a[0]], 1);
+    }
+
+    #[test]
+    fn try_from() {
+        let mut toks = tokenize("mov \"s\" 3 'c' (a b)").into_iter();
+        assert_eq!(String::try_from(toks.next().unwrap()), Ok("mov".to_string()));
+        assert_eq!(String::try_from(toks.next().unwrap()), Ok("s".to_string()));
+        assert_eq!(i64::try_from(toks.next().unwrap()), Ok(3));
+        assert_eq!(char::try_from(toks.next().unwrap()), Ok('c'));
+        assert_eq!(<(Delimiter, Vec<Token>)>::try_from(toks.next().unwrap()), Ok((Delimiter::Paren, vec![id("a"), id("b")])));
+
+        let err = i64::try_from(id("r0")).unwrap_err();
+        assert_eq!(err.to_string(), "expected a number, found identifier `r0`");
+        assert_eq!(err.found, id("r0"));
+    }
+
+    #[test]
+    fn delimiters() {
+        for delimiter in Delimiter::iter() {
+            assert_eq!(Delimiter::from_open(delimiter.open()), Some(delimiter));
+            assert_eq!(Delimiter::from_close(delimiter.close()), Some(delimiter));
+        }
+        assert!(Delimiter::is_open('[') && !Delimiter::is_open(']') && Delimiter::is_close(']'));
+        assert_eq!(Delimiter::from_open('<'), None);
+        assert_eq!(Delimiter::Brace.to_string(), "{}");
+    }
+
+    #[test]
+    fn size() {
+        assert_eq!(std::mem::size_of::<Token>(), 24);
+
+        let toks = tokenize("mov \"s\" /* c */");
+        assert_eq!(toks, vec![id("mov"), Token::String("s".into()), Token::Comment(" c ".into())]);
+        assert!(matches!(&toks[0], Token::Ident(name) if name == "mov" && name.len() == 3));
+    }
+
+    #[test]
+    fn buffers() {
+        let config = TokenizerConfig::default();
+        let mut buffers = TokenBuffers::with_capacity(64);
+        let mut toks = Vec::with_capacity(16);
+
+        let diags = tokenize_into(&config, "mov r0, (1 2", &mut buffers, &mut toks);
+        assert_eq!((toks.clone(), diags), tokenize_spanned(&config, "mov r0, (1 2"));
+
+        toks.clear();
+        let (capacity, chars) = (toks.capacity(), buffers.chars.capacity());
+        assert!(tokenize_into(&config, "nop /* x*/", &mut buffers, &mut toks).is_empty());
+        assert!(tokenize_into(&config, "jmp", &mut buffers, &mut toks).is_empty());
+        assert_eq!(toks.iter().map(|tok| tok.value.clone()).collect::<Vec<_>>(), tokenize("nop /* x*/ jmp"));
+        assert_eq!((toks.capacity(), buffers.chars.capacity()), (capacity, chars));
+
+        toks.clear();
+        tokenize_into(&config, "(a [b])", &mut buffers, &mut toks);
+        let Token::Group(group) = &toks[0].value else { panic!() };
+        let outer : *const Group = &**group;
+        buffers.recycle(&mut toks);
+        assert_eq!((toks.len(), buffers.groups.len()), (0, 2));
+        tokenize_into(&config, "{c}", &mut buffers, &mut toks);
+        let Token::Group(group) = &toks[0].value else { panic!() };
+        assert_eq!(**group, Group::new(Delimiter::Brace, vec![at(id("c"), 1, 2)]));
+        assert!(core::ptr::eq(&**group, outer));
+        assert_eq!(buffers.groups.len(), 1);
+    }
+
+    #[test]
+    fn labels() {
+        let config = TokenizerConfig { labels: true, ..Default::default() };
+        let (toks, _) = tokenize_spanned(&config, "start:\n  loop: mov r0, a:b\nx :");
+        assert_eq!(toks[..2], [at(Token::Label("start".into()), 0, 6), at(Token::Label("loop".into()), 9, 14)]);
+        assert_eq!(toks[2..].iter().map(|tok| tok.value.clone()).collect::<Vec<_>>(), tokenize("mov r0, a:b x :"));
+
+        assert_eq!(tokenize("loop: nop")[..2], [id("loop"), Token::Punct(':')]);
+    }
+
+    #[test]
+    fn directives() {
+        let config = TokenizerConfig { directive_sigil: Some('.'), ..Default::default() };
+        let (toks, _) = tokenize_spanned(&TokenizerConfig { labels: true, ..config }, ".org 0x10\n.word a.b, . x");
+        assert_eq!(toks[0], at(Token::Directive("org".into()), 0, 4));
+        assert_eq!(toks[2].value, Token::Directive("word".into()));
+        assert_eq!(toks[3..].iter().map(|tok| tok.value.clone()).collect::<Vec<_>>(), vec![
+            id("a"), Token::Directive("b".into()), Token::Punct(','), Token::Punct('.'), id("x"),
+        ]);
+
+        let config = TokenizerConfig { directive_sigil: Some('%'), ..Default::default() };
+        assert_eq!(tokenize_with(&config, "%include .x").0, vec![Token::Directive("include".into()), Token::Punct('.'), id("x")]);
+    }
+
+    #[test]
+    fn immediates() {
+        let config = TokenizerConfig { immediate_prefixes: vec!['#', '$'], ..Default::default() };
+        let (toks, _) = tokenize_spanned(&config, "mov r0, #-4, $end # x");
+        assert_eq!(toks[3], at(Token::Immediate(Box::new(Token::Number(-4))), 8, 11));
+        assert_eq!(toks[5].value, Token::Immediate(Box::new(id("end"))));
+        assert_eq!(toks[6..].iter().map(|tok| tok.value.clone()).collect::<Vec<_>>(), vec![Token::Punct('#'), id("x")]);
+    }
+
+    #[test]
+    fn registers() {
+        let registers = ["r0..r15", "sp", "pc"].map(String::from).to_vec();
+        let config = TokenizerConfig { registers, ..Default::default() };
+        let (toks, _) = tokenize_with(&config, "mov r15, sp r16 r01 pcx r3:");
+        assert_eq!(toks, vec![
+            id("mov"), Token::Register("r15".into()), Token::Punct(','), Token::Register("sp".into()),
+            id("r16"), id("r01"), id("pcx"), Token::Register("r3".into()), Token::Punct(':'),
+        ]);
+
+        let operand = crate::tok_match!(toks {
+            [ident "mov", reg dst, punct ',', reg "sp", ..] => Some(dst.to_string()),
+            _ => None,
+        });
+        assert_eq!(operand.as_deref(), Some("r15"));
+    }
+
+    #[test]
+    fn lines() {
+        let code = "mov r0, 1\r\n\n  nop // c\n(a\nb) x";
+        let (lines, _) = tokenize_lines(&TokenizerConfig::default(), code);
+        assert_eq!(lines.iter().map(|line| &code[line.span.start..line.span.end]).collect::<Vec<_>>(), vec![
+            "mov r0, 1", "  nop // c", "(a\nb) x",
+        ]);
+        assert_eq!(lines[1].iter().map(|tok| tok.value.clone()).collect::<Vec<_>>(), tokenize("nop // c"));
+        assert_eq!(lines[2].len(), 2);
+
+        let config = TokenizerConfig { recovery: Recovery::SkipLine, ..Default::default() };
+        let (lines, diags) = tokenize_lines(&config, "nop\nmov 'ab\njmp x");
+        assert_eq!(lines.iter().map(|line| line.len()).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(diags.len(), 1);
+        let (lines, diags) = tokenize_lines(&config, "'ab\nnop\nnop");
+        assert_eq!(lines.iter().map(|line| line.len()).collect::<Vec<_>>(), vec![1, 1]);
+        assert_eq!(diags.len(), 1);
+    }
+
+    #[test]
+    fn eof() {
+        let config = TokenizerConfig { eof: true, ..Default::default() };
+        let (toks, _) = tokenize_spanned(&config, "nop (x) ");
+        assert_eq!(toks.len(), 3);
+        assert_eq!(toks[2], at(Token::Eof, 8, 8));
+        assert_eq!(tokenize_with(&config, "").0, vec![Token::Eof]);
+        assert!(!tokenize("nop").contains(&Token::Eof));
+    }
+
+    #[test]
+    fn doc_comments() {
+        let config = TokenizerConfig { doc_comments: vec!["///".into(), ";;".into()], ..Default::default() };
+        let (toks, _) = tokenize_with(&config, "/// Adds\n;; twice\nadd // plain\n/* block */");
+        assert_eq!(toks, vec![
+            Token::DocComment(" Adds".into()),
+            Token::DocComment(" twice".into()),
+            id("add"),
+            Token::Comment(" plain".into()),
+            Token::Comment(" block ".into()),
+        ]);
+        assert!(!toks[0].is_trivia() && toks[3].is_trivia());
+        assert_eq!(tokenize("/// x"), vec![Token::Comment("/ x".into())]);
+    }
+
+    #[test]
+    fn number_dfa() {
+        assert_eq!(tokenize("-x 12abc 0b102 -0x1 0xFf 0o78"), vec![
+            Token::Punct('-'), id("x"),
+            Token::Number(12), id("abc"),
+            Token::Number(0b10), Token::Number(2),
+            Token::Number(0), id("x1"),
+            Token::Number(0xff),
+            Token::Number(0o7), Token::Number(8),
+        ]);
+
+        // Linear in the length of the literal
+        let code = format!("{}1", "0".repeat(100_000));
+        let (toks, diagnostics) = tokenize_with_diagnostics(&code);
+        assert_eq!((toks, diagnostics[0].kind.clone()), (vec![Token::Number(1)], DiagnosticKind::LeadingZeros));
+    }
+
+    #[test]
+    fn limits() {
+        let limits = |limits : Limits| TokenizerConfig { limits, ..Default::default() };
+        let kinds = |diagnostics : Vec<Diagnostic>| diagnostics.into_iter().map(|diag| (diag.kind, diag.span)).collect::<Vec<_>>();
+
+        let (toks, diagnostics) = tokenize_with(&limits(Limits { max_input_bytes: Some(4), ..Default::default() }), "a b c");
+        assert_eq!((toks, kinds(diagnostics)), (vec![], vec![(DiagnosticKind::InputTooLarge { limit: 4, found: 5 }, Span::new(4, 5))]));
+
+        let (toks, diagnostics) = tokenize_with(&limits(Limits { max_tokens: Some(3), ..Default::default() }), "a (b c [d]) e");
+        assert_eq!(toks.len(), 2);
+        assert_eq!(kinds(diagnostics), vec![(DiagnosticKind::TooManyTokens(3), Span::new(5, 5))]);
+
+        let config = limits(Limits { max_string_len: Some(3), max_ident_len: Some(2), ..Default::default() });
+        let (toks, diagnostics) = tokenize_with(&config, "\"abcd\" \"abc\" abc.é");
+        assert_eq!(toks, vec![Token::String("abc".into()), Token::String("abc".into()), id("ab"), Token::Punct('.'), id("é")]);
+        assert_eq!(kinds(diagnostics), vec![
+            (DiagnosticKind::StringTooLong(3), Span::new(0, 6)),
+            (DiagnosticKind::IdentifierTooLong(2), Span::new(13, 16)),
+        ]);
+        assert_eq!(tokenize_with(&config, "ééé").0, vec![id("é")]);
+    }
+
+    #[test]
+    fn normalization() {
+        let code = "caf\u{E9} cafe\u{301}";
+        assert_eq!(tokenize(code), vec![id("caf\u{E9}"), id("cafe"), Token::Punct('\u{301}')]);
+
+        let config = TokenizerConfig { normalization: Normalization::Nfc, ..Default::default() };
+        let (toks, diagnostics) = tokenize_with(&config, code);
+        assert_eq!((toks, diagnostics), (vec![id("caf\u{E9}"), id("caf\u{E9}")], vec![]));
+
+        let config = TokenizerConfig { normalization: Normalization::NfcWarn, ..Default::default() };
+        let (toks, diagnostics) = tokenize_with(&config, code);
+        assert_eq!(toks[0], toks[1]);
+        assert_eq!((diagnostics.len(), diagnostics[0].span, diagnostics[0].code()), (1, Span::new(6, 12), Some("unnormalized-identifier")));
+        assert_eq!(diagnostics[0].suggestions[0].replacement, "caf\u{E9}");
+    }
+    #[cfg(feature = "unicode-security")]
+    #[test]
+    fn mixed_scripts() {
+        let (toks, diagnostics) = tokenize_with_diagnostics("λx xλ2 名前なまえ nоp");
+        assert_eq!((toks.len(), diagnostics.len()), (4, 3));
+        let kinds : Vec<_> = diagnostics[..2].iter().map(|diag| (diag.kind.clone(), diag.span)).collect();
+        assert_eq!(kinds, vec![
+            (DiagnosticKind::MixedScriptIdentifier { ident: "λx".into(), first: "Greek".into(), second: "Latin".into() }, Span::new(0, 3)),
+            (DiagnosticKind::MixedScriptIdentifier { ident: "xλ2".into(), first: "Latin".into(), second: "Greek".into() }, Span::new(4, 8)),
+        ]);
+        assert_eq!(diagnostics[0].message(), "identifier `λx` mixes Greek and Latin letters");
+    }
+
+    #[test]
+    fn bidi_controls() {
+        let code = "s = \"user\u{202E} \u{2066}// admin\u{2069}\" /* \u{202D} */";
+        let (toks, diagnostics) = tokenize_with_diagnostics(code);
+        assert_eq!(toks.len(), 4);
+        let found : Vec<_> = diagnostics.iter().map(|diag| (diag.severity, diag.kind.clone(), diag.span)).collect();
+        assert_eq!(found, vec![
+            (Severity::Error, DiagnosticKind::BidiControl('\u{202E}'), Span::new(9, 12)),
+            (Severity::Error, DiagnosticKind::BidiControl('\u{2066}'), Span::new(13, 16)),
+            (Severity::Error, DiagnosticKind::BidiControl('\u{2069}'), Span::new(24, 27)),
+            (Severity::Error, DiagnosticKind::BidiControl('\u{202D}'), Span::new(32, 35)),
+        ]);
+        assert_eq!(diagnostics[0].message(), "bidirectional control character U+202E can make code display differently from how it is lexed");
+
+        let config = TokenizerConfig { lenient: true, ..Default::default() };
+        assert!(tokenize_with(&config, code).1.iter().all(|diag| diag.severity == Severity::Warning));
+    }
+
+    #[test]
+    fn control_chars() {
+        let code = "\"a\tb\0\" '\u{B}' \"\\\t\"\t";
+        let (toks, diagnostics) = tokenize_with_diagnostics(code);
+        assert_eq!(toks, vec![Token::String("a\tb\0".into()), Token::Char('\u{B}'), Token::String("\t".into())]);
+        assert_eq!(diagnostics.iter().filter(|diag| diag.kind == DiagnosticKind::ControlCharInLiteral('\t')).count(), 0);
+
+        let config = TokenizerConfig { control_chars: ControlCharPolicy::Warn, ..Default::default() };
+        let found : Vec<_> = tokenize_with(&config, code).1.into_iter().map(|diag| (diag.severity, diag.kind, diag.span)).collect();
+        assert_eq!(found, vec![
+            (Severity::Warning, DiagnosticKind::ControlCharInLiteral('\t'), Span::new(2, 3)),
+            (Severity::Warning, DiagnosticKind::ControlCharInLiteral('\0'), Span::new(4, 5)),
+            (Severity::Warning, DiagnosticKind::ControlCharInLiteral('\u{B}'), Span::new(8, 9)),
+            (Severity::Error, DiagnosticKind::UnknownEscape('\t'), Span::new(12, 14)),
+            (Severity::Warning, DiagnosticKind::ControlCharInLiteral('\t'), Span::new(13, 14)),
+        ]);
+
+        let config = TokenizerConfig { control_chars: ControlCharPolicy::Error, ..Default::default() };
+        let (toks, diagnostics) = tokenize_with(&config, code);
+        assert_eq!(toks.len(), 3);
+        assert_eq!(diagnostics[0].message(), "control character U+0009 in a literal");
+        assert!(diagnostics.iter().all(Diagnostic::is_error));
+    }
+
+    #[test]
+    fn leading_zero_policy() {
+        let config = |leading_zeros| TokenizerConfig { leading_zeros, ..Default::default() };
+        let found = |(toks, diagnostics) : (Vec<Token>, Vec<Diagnostic>)| (toks, diagnostics.into_iter().map(|diag| (diag.severity, diag.kind, diag.span)).collect::<Vec<_>>());
+
+        assert_eq!(found(tokenize_with(&config(LeadingZeroPolicy::Octal), "010 -017 0 0x10 019")), (
+            vec![Token::Number(8), Token::Number(-15), Token::Number(0), Token::Number(16), Token::Number(19)],
+            vec![(Severity::Error, DiagnosticKind::InvalidDigit { digit: '9', radix: 8 }, Span::new(18, 19))],
+        ));
+        assert_eq!(found(tokenize_with(&config(LeadingZeroPolicy::Error), "007")), (
+            vec![Token::Number(7)],
+            vec![(Severity::Error, DiagnosticKind::LeadingZeros, Span::new(0, 3))],
+        ));
+        assert_eq!(tokenize_with(&config(LeadingZeroPolicy::Decimal), "010").1[0].severity, Severity::Warning);
+    }
+
+    #[test]
+    fn ascii_only() {
+        let config = TokenizerConfig { ascii_only: true, ..Default::default() };
+        let (toks, diagnostics) = tokenize_with(&config, "café \u{A0}('é' \"ü\") // ß");
+        assert_eq!(toks.len(), 3);
+        let found : Vec<_> = diagnostics.iter().map(|diag| (diag.kind.clone(), diag.span)).collect();
+        assert_eq!(found, vec![
+            (DiagnosticKind::NonAsciiChar('é'), Span::new(3, 5)),
+            (DiagnosticKind::NonAsciiChar('\u{A0}'), Span::new(6, 8)),
+            (DiagnosticKind::NonAsciiChar('ß'), Span::new(23, 25)),
+        ]);
+        assert_eq!(diagnostics[0].message(), "non-ASCII character `é` (U+00E9)");
+        assert!(tokenize_with_diagnostics("café").1.is_empty());
+    }
+
+    #[test]
+    fn number_type() {
+        let config = TokenizerConfig { number_type: Some(NumberType::of::<u16>()), ..Default::default() };
+        let (toks, diagnostics) = tokenize_with(&config, "0xffff 65536 -1 99999999999999999999");
+        assert_eq!(toks[..2], [Token::Number(0xffff), Token::Number(65536)]);
+        let found : Vec<_> = diagnostics.iter().map(|diag| (diag.kind.clone(), diag.span)).collect();
+        assert_eq!(found, vec![
+            (DiagnosticKind::NumberOutOfRange("u16".into()), Span::new(7, 12)),
+            (DiagnosticKind::NumberOutOfRange("u16".into()), Span::new(13, 15)),
+            (DiagnosticKind::NumberOutOfRange("u16".into()), Span::new(16, 36)),
+        ]);
+        assert_eq!(diagnostics[0].message(), "number literal out of range for `u16`");
+
+        // Fitting the type doesn't make a number fit the token
+        let config = TokenizerConfig { number_type: Some(NumberType::of::<u64>()), leading_zeros: LeadingZeroPolicy::Octal, ..Default::default() };
+        let (toks, diagnostics) = tokenize_with(&config, "0xffffffffffffffff 0777");
+        assert_eq!(toks, [Token::Number(i64::MAX), Token::Number(0o777)]);
+        let found : Vec<_> = diagnostics.iter().map(|diag| (diag.kind.clone(), diag.span)).collect();
+        assert_eq!(found, vec![(DiagnosticKind::NumberOutOfRange("i64".into()), Span::new(0, 18))]);
+    }
+
+    #[test]
+    fn comments() {
+        let config = TokenizerConfig { line_comments: vec![";".into(), "#".into()], doc_comments: vec![";;".into()], ..Default::default() };
+        let (toks, _) = tokenize_with(&config, "nop ; a\n;; b\n# c\n// d");
+        assert_eq!(toks, [id("nop"), Token::Comment(" a".into()), Token::DocComment(" b".into()), Token::Comment(" c".into()), Token::Comment(" d".into())]);
+
+        let config = TokenizerConfig { skip_comments: true, ..config };
+        assert_eq!(tokenize_with(&config, "(/* a */ ; b\n) ;; c").0, [
+            Token::from(Group::new(Delimiter::Paren, vec![])), Token::DocComment(" c".into()),
+        ]);
+    }
+
+    #[test]
+    fn floats() {
+        let config = TokenizerConfig { floats: true, digit_separators: true, ..Default::default() };
+        let (toks, diagnostics) = tokenize_with(&config, "1.5 -2e3 1_000.000_1 6.02E+23 1..2 0x1_f 1e 0x_");
+        let float = |value| Token::Float(Float(value));
+        assert_eq!(toks, [
+            float(1.5), float(-2e3), float(1000.0001), float(6.02e23), Token::Number(1), Token::Punct('.'), Token::Punct('.'),
+            Token::Number(2), Token::Number(0x1f), Token::Number(1), id("e"), Token::Number(0),
         ]);
+        assert_eq!(diagnostics.iter().map(|diag| diag.kind.clone()).collect::<Vec<_>>(), [DiagnosticKind::MissingDigits { radix: 16 }]);
+        assert_eq!(tokenize("1.5 1_0"), [Token::Number(1), Token::Punct('.'), Token::Number(5), Token::Number(1), id("_0")]);
+    }
+
+    #[test]
+    fn escapes() {
+        let config = TokenizerConfig { escapes: true, ..Default::default() };
+        let (toks, diagnostics) = tokenize_with(&config, r#""a\n\t\\\0\u{e9}\'" '\n' '\u{1F600}' "\u{110000} \q""#);
+        assert_eq!(toks, [
+            Token::String("a\n\t\\\0\u{e9}'".into()), Token::Char('\n'), Token::Char('\u{1F600}'), Token::String("\u{FFFD} q".into()),
+        ]);
+        let found : Vec<_> = diagnostics.iter().map(|diag| (diag.kind.clone(), diag.span)).collect();
+        assert_eq!(found, [(DiagnosticKind::InvalidUnicodeEscape, Span::new(38, 48)), (DiagnosticKind::UnknownEscape('q'), Span::new(49, 51))]);
+        assert_eq!(tokenize_with_diagnostics(r#""\n""#).1[0].kind, DiagnosticKind::UnknownEscape('n'));
+    }
+
+    #[test]
+    fn keywords() {
+        let config = TokenizerConfig { keywords: vec!["if".into(), "macro".into()], labels: true, ..Default::default() };
+        assert_eq!(tokenize_with(&config, "if iff macro\nif: nop").0, [
+            Token::Keyword("if".into()), id("iff"), Token::Keyword("macro".into()), Token::Label("if".into()), id("nop"),
+        ]);
+    }
+
+    #[test]
+    fn operators() {
+        let ops = ["==", "!=", "<=", ">=", "->", "::", "&&", "||", "<<="].map(String::from).to_vec();
+        let config = TokenizerConfig { operators: ops, ..Default::default() };
+        let op = |op : &str| Token::Op(op.into());
+        assert_eq!(tokenize_with(&config, "x == 1 -> a::b <<= !c <").0, [
+            id("x"), op("=="), Token::Number(1), op("->"), id("a"), op("::"), id("b"), op("<<="), Token::Punct('!'), id("c"), Token::Punct('<'),
+        ]);
+
+        let minus = |config : &TokenizerConfig, code| tokenize_with(config, code).0;
+        assert_eq!(minus(&config, "a - 1 a -1 a-1"), [
+            id("a"), Token::Punct('-'), Token::Number(1), id("a"), Token::Number(-1), id("a"), Token::Number(-1),
+        ]);
+        let config = TokenizerConfig { binary_minus: true, ..config };
+        assert_eq!(minus(&config, "a -1 a-1 (1)-(-1) a->b"), [
+            id("a"), Token::Number(-1), id("a"), Token::Punct('-'), Token::Number(1),
+            Token::from(Group::new(Delimiter::Paren, vec![Spanned::new(Token::Number(1), Span::new(10, 11))])), Token::Punct('-'),
+            Token::from(Group::new(Delimiter::Paren, vec![Spanned::new(Token::Number(-1), Span::new(14, 16))])), id("a"), op("->"), id("b"),
+        ]);
+    }
+
+    #[test]
+    fn malformed() {
+        let (toks, diagnostics) = tokenize_with(&Default::default(), "0x -9223372036854775808 99999999999999999999 /* x");
+        assert_eq!(toks, [Token::Number(0), Token::Number(i64::MIN), Token::Number(i64::MAX), Token::Comment(" x".into())]);
+        let found : Vec<_> = diagnostics.iter().map(|diag| (diag.kind.clone(), diag.span)).collect();
+        assert_eq!(found, vec![
+            (DiagnosticKind::MissingDigits { radix: 16 }, Span::new(0, 2)),
+            (DiagnosticKind::NumberOutOfRange("i64".into()), Span::new(24, 44)),
+            (DiagnosticKind::UnterminatedComment, Span::new(45, 47)),
+        ]);
+        assert_eq!(diagnostics[2].suggestions, vec![Edit::insert(49, "*/")]);
+    }
+
+    #[test]
+    fn try_tokenize_errors() {
+        let config = TokenizerConfig::default();
+        assert_eq!(try_tokenize(&config, "a 1").map(|toks| toks.len()), Ok(2));
+        let err = try_tokenize(&config, "0x \"a").unwrap_err();
+        let found : Vec<_> = err.errors.iter().map(|diag| diag.kind.clone()).collect();
+        assert_eq!(found, [DiagnosticKind::MissingDigits { radix: 16 }, DiagnosticKind::UnterminatedString]);
+        assert_eq!(err.tokens.len(), 2);
+        assert!(err.to_string().ends_with("\n(and 1 more)"));
+    }
+
+    #[test]
+    fn concat_strings() {
+        let config = TokenizerConfig { concat_strings: true, ..Default::default() };
+        let (toks, diagnostics) = tokenize_spanned(&config, ".db \"ab\" \"c\\\"\"\n\t\"d\" , \"e\"");
+        assert!(diagnostics.is_empty());
+        let found : Vec<_> = toks.into_iter().map(|tok| (tok.value, tok.span)).collect();
+        assert_eq!(found, [
+            (Token::Punct('.'), Span::new(0, 1)),
+            (id("db"), Span::new(1, 3)),
+            (Token::String("abc\"d".into()), Span::new(4, 19)),
+            (Token::Punct(','), Span::new(20, 21)),
+            (Token::String("e".into()), Span::new(22, 25)),
+        ]);
+
+        let config = TokenizerConfig { newlines: true, ..config };
+        assert_eq!(tokenize_with(&config, "\"a\" \"b\"\n\"c\"").0, [Token::String("ab".into()), Token::Newline, Token::String("c".into())]);
+        assert_eq!(tokenize("\"a\" \"b\"").len(), 2);
+    }
+
+    #[test]
+    fn fuel() {
+        let config = |fuel| TokenizerConfig { limits: Limits { fuel: Some(fuel), ..Default::default() }, ..Default::default() };
+        let (toks, diagnostics) = tokenize_with(&config(5), "ab cd ef");
+        assert_eq!(toks, vec![id("ab"), id("cd")]);
+        assert_eq!((diagnostics[0].kind.clone(), diagnostics[0].span), (DiagnosticKind::LimitExceeded("fuel".into()), Span::new(5, 5)));
+        assert_eq!(diagnostics[0].message(), "exceeded the fuel limit");
+
+        assert_eq!(tokenize_with(&config(8), "ab cd ef"), (vec![id("ab"), id("cd"), id("ef")], vec![]));
+        for code in ["/* long comment */", "0x1f", "(((a)))", "\"abc\""] {
+            let (_, diagnostics) = tokenize_with(&config(3), code);
+            assert_eq!(diagnostics.last().unwrap().kind, DiagnosticKind::LimitExceeded("fuel".into()), "{code}");
+        }
     }
 }
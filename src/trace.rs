@@ -0,0 +1,198 @@
+//! Opt-in tracing of parsing decisions, to see why a grammar rejects an input.
+
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use core::fmt;
+
+use crate::prelude::*;
+use crate::{Diagnostic, ScannerAction, Span, TokenScanner};
+
+/// A decision made while parsing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceEvent {
+    /// A rule was attempted from `span`.
+    Enter { rule : String, span : Span },
+
+    /// The rule attempted last matched or failed.
+    Exit { rule : String, ok : bool },
+
+    /// `scan` considered `tokens`, and its callback returned `action`, e.g. "Require".
+    Scan { tokens : Vec<String>, action : &'static str },
+
+    /// An alternative failed with `error`, so the parser went back to where it started.
+    Backtrack { error : String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// How many rules were being attempted.
+    pub depth : usize,
+    pub event : TraceEvent,
+}
+
+/// A log of parsing decisions, shared by the scanners given it with `with_trace`.
+///
+/// It displays as one line per entry, indented by rule depth.
+#[derive(Debug, Clone, Default)]
+pub struct Trace(Rc<RefCell<TraceLog>>);
+
+#[derive(Debug, Default)]
+struct TraceLog {
+    entries : Vec<TraceEntry>,
+    depth : usize,
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> Vec<TraceEntry> {
+        self.0.borrow().entries.clone()
+    }
+
+    pub fn clear(&self) {
+        *self.0.borrow_mut() = TraceLog::default();
+    }
+
+    pub(crate) fn record(&self, event : TraceEvent) {
+        let mut log = self.0.borrow_mut();
+        let depth = log.depth;
+        log.entries.push(TraceEntry { depth, event });
+    }
+
+    pub(crate) fn enter(&self, rule : &str, span : Span) {
+        self.record(TraceEvent::Enter { rule: rule.to_string(), span });
+        self.0.borrow_mut().depth += 1;
+    }
+
+    pub(crate) fn exit(&self, rule : &str, ok : bool) {
+        let mut log = self.0.borrow_mut();
+        log.depth = log.depth.saturating_sub(1);
+        drop(log);
+        self.record(TraceEvent::Exit { rule: rule.to_string(), ok });
+    }
+}
+
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceEvent::Enter { rule, span } => write!(f, "{rule} at {span}"),
+            TraceEvent::Exit { rule, ok: true } => write!(f, "{rule} matched"),
+            TraceEvent::Exit { rule, ok: false } => write!(f, "{rule} failed"),
+            TraceEvent::Scan { tokens, action } => write!(f, "scan [{}] -> {action}", tokens.join(", ")),
+            TraceEvent::Backtrack { error } => write!(f, "backtrack: {error}"),
+        }
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.0.borrow().entries {
+            writeln!(f, "{:indent$}{}", "", entry.event, indent = entry.depth * 2)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T, E> ScannerAction<T, E> {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ScannerAction::Return(_) => "Return",
+            ScannerAction::Request(_) => "Request",
+            ScannerAction::Require => "Require",
+            ScannerAction::None => "None",
+            ScannerAction::Fail(_) => "Fail",
+        }
+    }
+}
+
+impl<'a> TokenScanner<'a> {
+    /// Records the decisions of parsing with this scanner, and those forked from it, in `trace`.
+    pub fn with_trace(mut self, trace : &'a Trace) -> Self {
+        self.trace = Some(trace);
+        self
+    }
+}
+
+/// Records that `rule` is attempted from the next token of `input`.
+#[doc(hidden)]
+pub fn enter(input : &TokenScanner<'_>, rule : &str) {
+    if let Some(trace) = input.trace {
+        let mut start = *input;
+        start.skip_trivia();
+        trace.enter(rule, start.span());
+    }
+}
+
+#[doc(hidden)]
+pub fn exit<T>(input : &TokenScanner<'_>, rule : &str, res : Result<T, Box<Diagnostic>>) -> Result<T, Box<Diagnostic>> {
+    if let Some(trace) = input.trace {
+        trace.exit(rule, res.is_ok());
+    }
+    res
+}
+
+#[doc(hidden)]
+pub fn backtrack(input : &TokenScanner<'_>, err : &Diagnostic) {
+    if let Some(trace) = input.trace {
+        trace.record(TraceEvent::Backtrack { error: err.message() });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Scanner, TokenizerConfig, grammar, tokenize_spanned};
+
+    struct Operand;
+
+    struct Instr;
+
+    grammar! {
+        Operand = {
+            reg => Operand,
+            '#' num => Operand,
+        }
+        Instr = ident Operand % ',' => Instr;
+    }
+
+    #[test]
+    fn rules() {
+        let config = TokenizerConfig { registers: vec!["r0..r7".to_string()], ..Default::default() };
+        let (toks, _) = tokenize_spanned(&config, "add #1, x");
+        let trace = Trace::new();
+        assert!(TokenScanner::new(&toks).with_trace(&trace).parse::<Instr>().is_err());
+        assert_eq!(trace.to_string(), [
+            "Instr at 0..3",
+            "  Operand at 4..5",
+            "    backtrack: expected a register, found `#`",
+            "  Operand matched",
+            "  Operand at 8..9",
+            "    backtrack: expected a register, found identifier `x`",
+            "    backtrack: expected `#`, found identifier `x`",
+            "  Operand failed",
+            "  backtrack: expected a register or `#`, found identifier `x`",
+            "Instr failed",
+            "",
+        ].join("\n"));
+        assert_eq!(trace.entries()[1], TraceEntry { depth: 1, event: TraceEvent::Enter { rule: "Operand".into(), span: Span::new(4, 5) } });
+
+        trace.clear();
+        assert!(TokenScanner::new(&toks).parse::<Instr>().is_err());
+        assert!(trace.entries().is_empty());
+    }
+
+    #[test]
+    fn scan() {
+        let trace = Trace::new();
+        let mut scanner = Scanner::new("ab".chars().collect()).with_trace(&trace);
+        let pair = |toks : &[char]| match toks {
+            ['a'] => ScannerAction::Require,
+            ['a', 'b'] => ScannerAction::Request(()),
+            _ => ScannerAction::None,
+        };
+        assert_eq!(scanner.scan(pair), Ok(Some(())));
+        assert_eq!(trace.to_string(), "scan ['a'] -> Require\nscan ['a', 'b'] -> Request\n");
+    }
+}
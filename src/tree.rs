@@ -0,0 +1,593 @@
+//! Utilities for walking and rewriting token trees.
+
+use core::slice;
+
+use crate::prelude::*;
+use crate::{Group, Span, Spanned, Token, TokenStr};
+
+/// Depth-first iterator over a token tree, returned by `iter_tokens_with_path`.
+pub struct Tokens<'a, T> {
+    top : slice::Iter<'a, T>,
+    groups : Vec<slice::Iter<'a, Spanned<Token>>>,
+
+    /// For each level, how many of its tokens have been yielded.
+    counts : Vec<usize>,
+}
+
+impl<'a, T : AsRef<Token>> Iterator for Tokens<'a, T> {
+    /// The token along with its path, the index of each of its enclosing groups followed by its own.
+    type Item = (Vec<usize>, &'a Token);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let tok = loop {
+            let tok = match self.groups.last_mut() {
+                Some(group) => group.next().map(|tok| &tok.value),
+                None => self.top.next().map(AsRef::as_ref),
+            };
+            match tok {
+                Some(tok) => break tok,
+                None if self.groups.pop().is_some() => self.counts.pop(),
+                None => return None,
+            };
+        };
+
+        *self.counts.last_mut().unwrap() += 1;
+        let path = self.counts.iter().map(|count| count - 1).collect();
+        if let Token::Group(group) = tok {
+            self.groups.push(group.tokens.iter());
+            self.counts.push(0);
+        }
+        Some((path, tok))
+    }
+}
+
+/// Iterates over `toks` and, right after each group, over the tokens inside it.
+pub fn iter_tokens<T : AsRef<Token>>(toks : &[T]) -> impl Iterator<Item = &Token> {
+    iter_tokens_with_path(toks).map(|(_, tok)| tok)
+}
+
+/// Like `iter_tokens`, but also yields the path of each token in the tree, e.g. `[2, 0]`
+/// for the first token inside the group at index 2. Its depth is the path's length minus one.
+pub fn iter_tokens_with_path<T : AsRef<Token>>(toks : &[T]) -> Tokens<'_, T> {
+    Tokens { top: toks.iter(), groups: Vec::new(), counts: vec![0] }
+}
+
+/// Walks a token tree, calling a hook for each token.
+///
+/// Every hook does nothing by default, except `visit_token` and `visit_group`, which dispatch
+/// to the hooks of the variant and of the group's tokens. Override them to stop descending or to
+/// act before and after a group; `walk_token` and `walk_group` keep the default behavior available.
+pub trait TokenVisitor {
+    fn visit_tokens(&mut self, toks : &[Spanned<Token>]) {
+        for tok in toks {
+            self.visit_token(tok);
+        }
+    }
+
+    fn visit_token(&mut self, tok : &Spanned<Token>) {
+        walk_token(self, tok)
+    }
+
+    fn visit_group(&mut self, group : &Group, span : Span) {
+        walk_group(self, group, span)
+    }
+
+    fn visit_ident(&mut self, _name : &str, _span : Span) {}
+    fn visit_punct(&mut self, _c : char, _span : Span) {}
+    fn visit_comment(&mut self, _text : &str, _span : Span) {}
+    fn visit_newline(&mut self, _span : Span) {}
+    fn visit_string(&mut self, _value : &str, _span : Span) {}
+    fn visit_char(&mut self, _value : char, _span : Span) {}
+    fn visit_number(&mut self, _value : i64, _span : Span) {}
+    fn visit_label(&mut self, _name : &str, _span : Span) {}
+    fn visit_directive(&mut self, _name : &str, _span : Span) {}
+    fn visit_immediate(&mut self, _value : &Token, _span : Span) {}
+    fn visit_register(&mut self, _name : &str, _span : Span) {}
+    fn visit_eof(&mut self, _span : Span) {}
+    fn visit_doc_comment(&mut self, _text : &str, _span : Span) {}
+    fn visit_float(&mut self, _value : f64, _span : Span) {}
+    fn visit_keyword(&mut self, _name : &str, _span : Span) {}
+    fn visit_op(&mut self, _op : &str, _span : Span) {}
+}
+
+pub fn walk_token<V : TokenVisitor + ?Sized>(visitor : &mut V, tok : &Spanned<Token>) {
+    let span = tok.span;
+    match &tok.value {
+        Token::Ident(name) => visitor.visit_ident(name, span),
+        Token::Punct(c) => visitor.visit_punct(*c, span),
+        Token::Comment(text) => visitor.visit_comment(text, span),
+        Token::Group(group) => visitor.visit_group(group, span),
+        Token::Newline => visitor.visit_newline(span),
+        Token::String(value) => visitor.visit_string(value, span),
+        Token::Char(value) => visitor.visit_char(*value, span),
+        Token::Number(value) => visitor.visit_number(*value, span),
+        Token::Label(name) => visitor.visit_label(name, span),
+        Token::Directive(name) => visitor.visit_directive(name, span),
+        Token::Immediate(value) => visitor.visit_immediate(value, span),
+        Token::Register(name) => visitor.visit_register(name, span),
+        Token::Eof => visitor.visit_eof(span),
+        Token::DocComment(text) => visitor.visit_doc_comment(text, span),
+        Token::Float(value) => visitor.visit_float(value.0, span),
+        Token::Keyword(name) => visitor.visit_keyword(name, span),
+        Token::Op(op) => visitor.visit_op(op, span),
+    }
+}
+
+pub fn walk_group<V : TokenVisitor + ?Sized>(visitor : &mut V, group : &Group, _span : Span) {
+    visitor.visit_tokens(&group.tokens)
+}
+
+/// Like `TokenVisitor`, but with mutable access to the tokens to rewrite them in place.
+pub trait TokenVisitorMut {
+    fn visit_tokens_mut(&mut self, toks : &mut [Spanned<Token>]) {
+        for tok in toks {
+            self.visit_token_mut(tok);
+        }
+    }
+
+    fn visit_token_mut(&mut self, tok : &mut Spanned<Token>) {
+        walk_token_mut(self, tok)
+    }
+
+    fn visit_group_mut(&mut self, group : &mut Group, span : Span) {
+        walk_group_mut(self, group, span)
+    }
+
+    fn visit_ident_mut(&mut self, _name : &mut TokenStr, _span : Span) {}
+    fn visit_punct_mut(&mut self, _c : &mut char, _span : Span) {}
+    fn visit_comment_mut(&mut self, _text : &mut TokenStr, _span : Span) {}
+    fn visit_newline_mut(&mut self, _span : Span) {}
+    fn visit_string_mut(&mut self, _value : &mut TokenStr, _span : Span) {}
+    fn visit_char_mut(&mut self, _value : &mut char, _span : Span) {}
+    fn visit_number_mut(&mut self, _value : &mut i64, _span : Span) {}
+    fn visit_label_mut(&mut self, _name : &mut TokenStr, _span : Span) {}
+    fn visit_directive_mut(&mut self, _name : &mut TokenStr, _span : Span) {}
+    fn visit_immediate_mut(&mut self, _value : &mut Token, _span : Span) {}
+    fn visit_register_mut(&mut self, _name : &mut TokenStr, _span : Span) {}
+    fn visit_eof_mut(&mut self, _span : Span) {}
+    fn visit_doc_comment_mut(&mut self, _text : &mut TokenStr, _span : Span) {}
+    fn visit_float_mut(&mut self, _value : &mut f64, _span : Span) {}
+    fn visit_keyword_mut(&mut self, _name : &mut TokenStr, _span : Span) {}
+    fn visit_op_mut(&mut self, _op : &mut TokenStr, _span : Span) {}
+}
+
+pub fn walk_token_mut<V : TokenVisitorMut + ?Sized>(visitor : &mut V, tok : &mut Spanned<Token>) {
+    let span = tok.span;
+    match &mut tok.value {
+        Token::Ident(name) => visitor.visit_ident_mut(name, span),
+        Token::Punct(c) => visitor.visit_punct_mut(c, span),
+        Token::Comment(text) => visitor.visit_comment_mut(text, span),
+        Token::Group(group) => visitor.visit_group_mut(group, span),
+        Token::Newline => visitor.visit_newline_mut(span),
+        Token::String(value) => visitor.visit_string_mut(value, span),
+        Token::Char(value) => visitor.visit_char_mut(value, span),
+        Token::Number(value) => visitor.visit_number_mut(value, span),
+        Token::Label(name) => visitor.visit_label_mut(name, span),
+        Token::Directive(name) => visitor.visit_directive_mut(name, span),
+        Token::Immediate(value) => visitor.visit_immediate_mut(value, span),
+        Token::Register(name) => visitor.visit_register_mut(name, span),
+        Token::Eof => visitor.visit_eof_mut(span),
+        Token::DocComment(text) => visitor.visit_doc_comment_mut(text, span),
+        Token::Float(value) => visitor.visit_float_mut(&mut value.0, span),
+        Token::Keyword(name) => visitor.visit_keyword_mut(name, span),
+        Token::Op(op) => visitor.visit_op_mut(op, span),
+    }
+}
+
+pub fn walk_group_mut<V : TokenVisitorMut + ?Sized>(visitor : &mut V, group : &mut Group, _span : Span) {
+    visitor.visit_tokens_mut(&mut group.tokens)
+}
+
+/// What `fold_tokens` replaces a token with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenOrStream {
+    Token(Spanned<Token>),
+
+    /// Any number of tokens, none to remove it.
+    Stream(Vec<Spanned<Token>>),
+}
+
+impl From<Spanned<Token>> for TokenOrStream {
+    fn from(tok : Spanned<Token>) -> Self {
+        TokenOrStream::Token(tok)
+    }
+}
+
+impl From<Vec<Spanned<Token>>> for TokenOrStream {
+    fn from(toks : Vec<Spanned<Token>>) -> Self {
+        TokenOrStream::Stream(toks)
+    }
+}
+
+/// Rebuilds a token tree, replacing each token with what `f` returns for it.
+///
+/// Groups are folded bottom-up: their tokens first, then the rebuilt group itself is given to `f`.
+/// Tokens returned by `f` are not folded again.
+pub fn fold_tokens(toks : Vec<Spanned<Token>>, mut f : impl FnMut(Spanned<Token>) -> TokenOrStream) -> Vec<Spanned<Token>> {
+    fold_into(toks, &mut f)
+}
+
+fn fold_into(toks : Vec<Spanned<Token>>, f : &mut impl FnMut(Spanned<Token>) -> TokenOrStream) -> Vec<Spanned<Token>> {
+    let mut res = Vec::with_capacity(toks.len());
+    for tok in toks {
+        let tok = match tok.value {
+            Token::Group(group) => {
+                let tokens = fold_into(group.tokens, f);
+                Spanned::new(Token::from(Group { tokens, ..*group }), tok.span)
+            },
+            _ => tok,
+        };
+        match f(tok) {
+            TokenOrStream::Token(tok) => res.push(tok),
+            TokenOrStream::Stream(toks) => res.extend(toks),
+        }
+    }
+    res
+}
+
+fn group_tokens(tok : &Spanned<Token>) -> &Vec<Spanned<Token>> {
+    match &tok.value {
+        Token::Group(group) => &group.tokens,
+        _ => unreachable!("path goes through a token that isn't a group"),
+    }
+}
+
+fn group_tokens_mut(tok : &mut Spanned<Token>) -> &mut Vec<Spanned<Token>> {
+    match &mut tok.value {
+        Token::Group(group) => &mut group.tokens,
+        _ => unreachable!("path goes through a token that isn't a group"),
+    }
+}
+
+/// A position in a token tree that can move to the parent, siblings and children of its
+/// token, and edit the tree in place.
+///
+/// The position is kept as a path of indices from the root, see `iter_tokens_with_path`.
+pub struct TreeCursor<'a> {
+    root : &'a mut Vec<Spanned<Token>>,
+    path : Vec<usize>,
+}
+
+impl<'a> TreeCursor<'a> {
+    /// A cursor on the first token of `root`, or `None` if it is empty.
+    pub fn new(root : &'a mut Vec<Spanned<Token>>) -> Option<Self> {
+        (!root.is_empty()).then(|| Self { root, path: vec![0] })
+    }
+
+    /// A cursor on the innermost token whose span contains `offset`, e.g. the one under the mouse.
+    pub fn at_offset(root : &'a mut Vec<Spanned<Token>>, offset : usize) -> Option<Self> {
+        let mut path = Vec::new();
+        let mut toks : &[Spanned<Token>] = root;
+        while let Some(i) = toks.iter().position(|tok| tok.span.contains(offset)) {
+            path.push(i);
+            match &toks[i].value {
+                Token::Group(group) => toks = &group.tokens,
+                _ => break,
+            }
+        }
+        (!path.is_empty()).then_some(Self { root, path })
+    }
+
+    pub fn path(&self) -> &[usize] {
+        &self.path
+    }
+
+    /// 0 for the tokens at the root.
+    pub fn depth(&self) -> usize {
+        self.path.len() - 1
+    }
+
+    /// The tokens the current one is part of.
+    fn siblings(&self) -> &Vec<Spanned<Token>> {
+        let (_, parents) = self.path.split_last().unwrap();
+        parents.iter().fold(&*self.root, |toks, i| group_tokens(&toks[*i]))
+    }
+
+    fn siblings_mut(&mut self) -> &mut Vec<Spanned<Token>> {
+        let (_, parents) = self.path.split_last().unwrap();
+        parents.iter().fold(&mut *self.root, |toks, i| group_tokens_mut(&mut toks[*i]))
+    }
+
+    fn index(&self) -> usize {
+        *self.path.last().unwrap()
+    }
+
+    pub fn token(&self) -> &Spanned<Token> {
+        &self.siblings()[self.index()]
+    }
+
+    pub fn token_mut(&mut self) -> &mut Spanned<Token> {
+        let i = self.index();
+        &mut self.siblings_mut()[i]
+    }
+
+    /// The tokens of the current group, none if the token isn't a group.
+    pub fn children(&self) -> &[Spanned<Token>] {
+        match &self.token().value {
+            Token::Group(group) => &group.tokens,
+            _ => &[],
+        }
+    }
+
+    /// The groups enclosing the current token, innermost first.
+    pub fn ancestors(&self) -> Vec<&Spanned<Token>> {
+        let mut res = Vec::with_capacity(self.depth());
+        let mut toks : &[Spanned<Token>] = self.root;
+        for i in &self.path[..self.depth()] {
+            res.push(&toks[*i]);
+            toks = group_tokens(&toks[*i]);
+        }
+        res.reverse();
+        res
+    }
+
+    /// Moves to the enclosing group. Returns false, without moving, at the root.
+    pub fn parent(&mut self) -> bool {
+        self.path.len() > 1 && self.path.pop().is_some()
+    }
+
+    /// Moves to the first token of the current group. Returns false, without moving, if there is none.
+    pub fn first_child(&mut self) -> bool {
+        let has_child = !self.children().is_empty();
+        if has_child {
+            self.path.push(0);
+        }
+        has_child
+    }
+
+    /// Returns false, without moving, at the last token of its group.
+    pub fn next_sibling(&mut self) -> bool {
+        let has_next = self.index() + 1 < self.siblings().len();
+        if has_next {
+            *self.path.last_mut().unwrap() += 1;
+        }
+        has_next
+    }
+
+    /// Returns false, without moving, at the first token of its group.
+    pub fn prev_sibling(&mut self) -> bool {
+        let has_prev = self.index() > 0;
+        if has_prev {
+            *self.path.last_mut().unwrap() -= 1;
+        }
+        has_prev
+    }
+
+    /// Replaces the current token, returning the old one.
+    pub fn replace(&mut self, tok : Spanned<Token>) -> Spanned<Token> {
+        core::mem::replace(self.token_mut(), tok)
+    }
+
+    /// Inserts `tok` before the current token, which the cursor stays on.
+    pub fn insert_before(&mut self, tok : Spanned<Token>) {
+        let i = self.index();
+        self.siblings_mut().insert(i, tok);
+        *self.path.last_mut().unwrap() += 1;
+    }
+
+    pub fn insert_after(&mut self, tok : Spanned<Token>) {
+        let i = self.index();
+        self.siblings_mut().insert(i + 1, tok);
+    }
+
+    /// Removes the current token and moves to its next sibling, or else its previous one, or
+    /// else its parent. Returns `None`, without removing it, if it is the only token of the root.
+    pub fn remove(&mut self) -> Option<Spanned<Token>> {
+        if self.path.len() == 1 && self.root.len() == 1 {
+            return None;
+        }
+        let i = self.index();
+        let tok = self.siblings_mut().remove(i);
+        if i == self.siblings().len() && !self.prev_sibling() {
+            self.path.pop();
+        }
+        Some(tok)
+    }
+}
+
+/// Removes trivia from a token tree, keeping the spans of the other tokens, and marks all
+/// groups as not recovered.
+///
+/// Numbers need no canonicalization: they are stored by value, so `0x10` and `16` are already
+/// the same token.
+pub fn normalize(toks : Vec<Spanned<Token>>) -> Vec<Spanned<Token>> {
+    toks.into_iter()
+        .filter(|tok| !tok.is_trivia())
+        .map(|tok| match tok.value {
+            Token::Group(group) => {
+                let group = Group { tokens: normalize(group.tokens), recovered: false, ..*group };
+                Spanned::new(group.into(), tok.span)
+            },
+            _ => tok,
+        })
+        .collect()
+}
+
+/// The comments of a token tree, removed from it by `split_comments`, in source order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Comments {
+    comments : Vec<Spanned<TokenStr>>,
+}
+
+impl Comments {
+    /// The comments inside `span`, e.g. those of the line of an instruction, for a listing.
+    pub fn within(&self, span : Span) -> impl Iterator<Item = &Spanned<TokenStr>> {
+        let start = self.comments.partition_point(|comment| (comment.span.file, comment.span.start) < (span.file, span.start));
+        self.comments[start..].iter()
+            .take_while(move |comment| comment.span.file == span.file && comment.span.start < span.end)
+            .filter(move |comment| comment.span.end <= span.end)
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, Spanned<TokenStr>> {
+        self.comments.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.comments.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.comments.is_empty()
+    }
+}
+
+/// Removes the comments from a token tree like `normalize`, but returns them, so that they
+/// remain available to tools, such as listing generators, after parsing the rest.
+pub fn split_comments(toks : Vec<Spanned<Token>>) -> (Vec<Spanned<Token>>, Comments) {
+    fn split(toks : Vec<Spanned<Token>>, comments : &mut Vec<Spanned<TokenStr>>) -> Vec<Spanned<Token>> {
+        toks.into_iter()
+            .filter_map(|tok| match tok.value {
+                Token::Comment(text) => {
+                    comments.push(Spanned::new(text, tok.span));
+                    None
+                },
+                Token::Group(group) => {
+                    let group = Group { tokens: split(group.tokens, comments), ..*group };
+                    Some(Spanned::new(group.into(), tok.span))
+                },
+                _ => Some(tok),
+            })
+            .collect()
+    }
+
+    let mut comments = Vec::new();
+    let toks = split(toks, &mut comments);
+    comments.sort_by_key(|comment| comment.span);
+    (toks, Comments { comments })
+}
+
+/// Whether both token trees mean the same, i.e. are equal once normalized, ignoring spans.
+pub fn semantically_eq<T : AsRef<Token>, U : AsRef<Token>>(a : &[T], b : &[U]) -> bool {
+    let mut a = a.iter().map(AsRef::as_ref).filter(|tok| !tok.is_trivia());
+    let mut b = b.iter().map(AsRef::as_ref).filter(|tok| !tok.is_trivia());
+    loop {
+        let same = match (a.next(), b.next()) {
+            (None, None) => return true,
+            (Some(Token::Group(a)), Some(Token::Group(b))) => a.delimiter == b.delimiter && semantically_eq(&a.tokens, &b.tokens),
+            (a, b) => a == b,
+        };
+        if !same {
+            return false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Delimiter, TokenizerConfig, tokenize, tokenize_spanned};
+
+    #[test]
+    fn iter() {
+        let toks = tokenize("a (b [c] ()) d");
+        let idents : Vec<_> = iter_tokens(&toks).filter_map(|tok| match tok {
+            Token::Ident(name) => Some(name.as_str()),
+            _ => None,
+        }).collect();
+        assert_eq!(idents, vec!["a", "b", "c", "d"]);
+
+        let (toks, _) = tokenize_spanned(&TokenizerConfig::default(), "a (b [c] ()) d");
+        let paths : Vec<_> = iter_tokens_with_path(&toks).map(|(path, _)| path).collect();
+        assert_eq!(paths, vec![vec![0], vec![1], vec![1, 0], vec![1, 1], vec![1, 1, 0], vec![1, 2], vec![2]]);
+    }
+
+    #[test]
+    fn visitors() {
+        struct Numbers(Vec<(i64, Span)>);
+        impl TokenVisitor for Numbers {
+            fn visit_number(&mut self, value : i64, span : Span) {
+                self.0.push((value, span));
+            }
+
+            // Don't look into brackets
+            fn visit_group(&mut self, group : &Group, span : Span) {
+                if group.delimiter != Delimiter::Bracket {
+                    walk_group(self, group, span)
+                }
+            }
+        }
+
+        struct Upper;
+        impl TokenVisitorMut for Upper {
+            fn visit_ident_mut(&mut self, name : &mut TokenStr, _ : Span) {
+                name.make_ascii_uppercase();
+            }
+        }
+
+        let (mut toks, _) = tokenize_spanned(&TokenizerConfig::default(), "mov r0, (1 [2] {x 3})");
+        let mut numbers = Numbers(Vec::new());
+        numbers.visit_tokens(&toks);
+        assert_eq!(numbers.0, vec![(1, Span::new(9, 10)), (3, Span::new(18, 19))]);
+
+        Upper.visit_tokens_mut(&mut toks);
+        assert_eq!(toks.into_iter().map(Spanned::into_inner).collect::<Vec<_>>(), tokenize("MOV R0, (1 [2] {X 3})"));
+    }
+
+    #[test]
+    fn fold() {
+        let (toks, _) = tokenize_spanned(&TokenizerConfig::default(), "push (rax rbx); nop");
+        let toks = fold_tokens(toks, |tok| match &tok.value {
+            Token::Ident(name) if name == "push" => Vec::new().into(),
+            Token::Ident(name) if name.starts_with('r') => {
+                vec![Spanned::new(Token::Punct('%'), tok.span), Spanned::new(Token::Ident(name[1..].into()), tok.span)].into()
+            },
+            Token::Group(group) => TokenOrStream::Stream(group.tokens.clone()),
+            _ => tok.into(),
+        });
+        assert_eq!(toks.into_iter().map(Spanned::into_inner).collect::<Vec<_>>(), tokenize("%ax %bx; nop"));
+    }
+
+    #[test]
+    fn cursor() {
+        let code = "mov r0, (a [b c])";
+        let (mut toks, _) = tokenize_spanned(&TokenizerConfig::default(), code);
+        let b = code.find('b').unwrap();
+
+        let mut cursor = TreeCursor::at_offset(&mut toks, b).unwrap();
+        assert_eq!(cursor.path(), &[3, 1, 0]);
+        assert_eq!(cursor.ancestors().iter().map(|tok| &code[tok.span.range()]).collect::<Vec<_>>(), vec!["[b c]", "(a [b c])"]);
+        assert!(cursor.next_sibling() && !cursor.next_sibling());
+        assert_eq!(cursor.token().value, Token::Ident("c".into()));
+
+        cursor.insert_before(Spanned::new(Token::Punct(','), Span::empty(b + 1)));
+        cursor.replace(Spanned::new(Token::Number(1), cursor.token().span));
+        assert!(cursor.parent() && cursor.prev_sibling());
+        cursor.remove();
+        assert_eq!(cursor.children().len(), 3);
+        assert!(cursor.parent() && !cursor.parent());
+        assert_eq!(cursor.depth(), 0);
+
+        let toks : Vec<_> = toks.into_iter().map(Spanned::into_inner).collect();
+        assert!(crate::structural_eq(&toks, &tokenize("mov r0, ([b, 1])")));
+        assert!(TreeCursor::at_offset(&mut Vec::new(), 0).is_none());
+    }
+
+    #[test]
+    fn semantics() {
+        let a = tokenize("mov /* zero */ r0, (0x10 // sixteen\n)");
+        let b = tokenize("mov r0,(16)");
+        assert!(semantically_eq(&a, &b) && !crate::structural_eq(&a, &b));
+        assert!(!semantically_eq(&a, &tokenize("mov r0, (16) nop")));
+
+        let (toks, _) = tokenize_spanned(&TokenizerConfig::default(), "// x\n(a /* y */");
+        let toks = normalize(toks);
+        assert!(crate::structural_eq(&toks, &tokenize("(a)")));
+        assert_eq!(toks[0].span, Span::new(5, 15));
+    }
+
+    #[test]
+    fn comments() {
+        let code = "mov r0, 1 // load\n/* a */ (b /* c */)\nnop";
+        let (toks, comments) = split_comments(tokenize_spanned(&TokenizerConfig::default(), code).0);
+        assert!(crate::structural_eq(&toks, &tokenize("mov r0, 1 (b) nop")));
+        assert_eq!(comments.iter().map(|comment| comment.as_str()).collect::<Vec<_>>(), vec![" load", " a ", " c "]);
+
+        let line = |i : usize| {
+            let start = code.split('\n').take(i).map(|line| line.len() + 1).sum::<usize>();
+            Span::new(start, start + code.split('\n').nth(i).unwrap().len())
+        };
+        assert_eq!(comments.within(line(0)).map(|comment| comment.span).collect::<Vec<_>>(), vec![Span::new(10, 17)]);
+        assert_eq!(comments.within(line(1)).count(), 2);
+        assert_eq!(comments.within(line(2)).count(), 0);
+    }
+}
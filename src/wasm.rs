@@ -0,0 +1,83 @@
+//! Exports for lexing from JavaScript when built for WebAssembly, with the `wasm` feature.
+//!
+//! The exports use only numbers, so a host loads the module with `WebAssembly.instantiate` and
+//! needs no generated glue: it copies the UTF-8 source into memory from `smpl_alloc`, calls
+//! `smpl_tokenize` and frees the source with `smpl_free`. The result is a little-endian `u32`
+//! length followed by that many bytes of the JSON of `tokenize_json`, freed with `smpl_free_result`.
+
+use crate::prelude::*;
+use crate::json::{to_json, tokens_to_json};
+use crate::{TokenizerConfig, tokenize_spanned};
+
+/// The tokens and diagnostics of `code` as the JSON object `{"tokens":[…],"diagnostics":[…]}`,
+/// each in the format of the `json` module.
+pub fn tokenize_json(config : &TokenizerConfig, code : &str) -> String {
+    let (toks, diagnostics) = tokenize_spanned(config, code);
+    format!(r#"{{"tokens":{},"diagnostics":{}}}"#, tokens_to_json(&toks), to_json(&diagnostics))
+}
+
+/// Allocates `len` bytes for the host to write a source into.
+#[no_mangle]
+pub extern "C" fn smpl_alloc(len : usize) -> *mut u8 {
+    Box::into_raw(vec![0u8; len].into_boxed_slice()).cast()
+}
+
+/// Frees the `len` bytes at `ptr`.
+///
+/// # Safety
+///
+/// `ptr` must come from `smpl_alloc(len)`, and not be freed already.
+#[no_mangle]
+pub unsafe extern "C" fn smpl_free(ptr : *mut u8, len : usize) {
+    drop(Box::from_raw(core::ptr::slice_from_raw_parts_mut(ptr, len)));
+}
+
+/// Tokenizes the `len` bytes of UTF-8 at `ptr` with the default config, invalid sequences
+/// being replaced by U+FFFD, returning the length-prefixed JSON of `tokenize_json`.
+///
+/// # Safety
+///
+/// `ptr` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn smpl_tokenize(ptr : *const u8, len : usize) -> *mut u8 {
+    let code = String::from_utf8_lossy(core::slice::from_raw_parts(ptr, len));
+    let json = tokenize_json(&TokenizerConfig::default(), &code);
+    let mut out = (json.len() as u32).to_le_bytes().to_vec();
+    out.extend(json.as_bytes());
+    Box::into_raw(out.into_boxed_slice()).cast()
+}
+
+/// Frees a result of `smpl_tokenize`.
+///
+/// # Safety
+///
+/// `ptr` must come from `smpl_tokenize`, and not be freed already.
+#[no_mangle]
+pub unsafe extern "C" fn smpl_free_result(ptr : *mut u8) {
+    let len = u32::from_le_bytes(*ptr.cast::<[u8; 4]>()) as usize;
+    smpl_free(ptr, len + 4);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exports() {
+        let code = "nop \"x";
+        let json = tokenize_json(&TokenizerConfig::default(), code);
+        assert!(json.starts_with(r#"{"tokens":[{"kind":"ident","value":"nop","span":{"start":0,"end":3}},"#));
+        assert!(json.contains(r#"],"diagnostics":[{"code":"unterminated-string","#));
+
+        unsafe {
+            let src = smpl_alloc(code.len());
+            src.copy_from_nonoverlapping(code.as_ptr(), code.len());
+            let result = smpl_tokenize(src, code.len());
+            smpl_free(src, code.len());
+
+            let len = u32::from_le_bytes(*result.cast::<[u8; 4]>()) as usize;
+            assert_eq!(core::slice::from_raw_parts(result.add(4), len), json.as_bytes());
+            smpl_free_result(result);
+        }
+    }
+}